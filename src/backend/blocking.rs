@@ -0,0 +1,106 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use tokio::sync::oneshot;
+
+use crate::cmd::BlockingCommand;
+use crate::{Client, RespFrame};
+
+// a client parked on one or more keys until a writer makes its command succeed
+#[derive(Debug)]
+pub struct Waiter {
+    // a snapshot of the blocked connection, so its command can run on its behalf
+    pub client: Client,
+    pub command: Box<dyn BlockingCommand>,
+    pub keys: Vec<Vec<u8>>,
+    pub reply: oneshot::Sender<RespFrame>,
+}
+
+// blocked clients by database index and key; each key keeps its waiters in
+// the order they blocked
+#[derive(Debug, Default)]
+pub struct Blocking {
+    next_id: u64,
+    queues: HashMap<(usize, Vec<u8>), VecDeque<u64>>,
+    waiters: HashMap<u64, Waiter>,
+}
+
+impl Blocking {
+    pub fn block(
+        &mut self,
+        client: Client,
+        command: Box<dyn BlockingCommand>,
+    ) -> (u64, oneshot::Receiver<RespFrame>) {
+        let (tx, rx) = oneshot::channel();
+        self.next_id += 1;
+        let id = self.next_id;
+        // a key given twice is waited on once, in the place it first took
+        let mut keys = command.keys();
+        let mut seen = HashSet::new();
+        keys.retain(|key| seen.insert(key.clone()));
+        for key in &keys {
+            self.queues
+                .entry((client.db, key.clone()))
+                .or_default()
+                .push_back(id);
+        }
+        self.waiters.insert(
+            id,
+            Waiter {
+                client,
+                command,
+                keys,
+                reply: tx,
+            },
+        );
+        (id, rx)
+    }
+
+    // forget a waiter, e.g. on timeout; None when it has already been served
+    pub fn unblock(&mut self, id: u64) -> Option<Waiter> {
+        let waiter = self.waiters.remove(&id)?;
+        self.dequeue(id, waiter.client.db, &waiter.keys);
+        Some(waiter)
+    }
+
+    pub fn is_blocked_on(&self, db: usize, key: &[u8]) -> bool {
+        self.queues.contains_key(&(db, key.to_vec()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    // the waiters of a key, oldest first
+    pub(crate) fn queue(&self, db: usize, key: &[u8]) -> Vec<u64> {
+        self.queues
+            .get(&(db, key.to_vec()))
+            .map(|q| q.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    // take a waiter out while its command runs; hand it back with `restore`
+    // if it is still not served
+    pub(crate) fn take(&mut self, id: u64) -> Option<Waiter> {
+        self.waiters.remove(&id)
+    }
+
+    pub(crate) fn restore(&mut self, id: u64, waiter: Waiter) {
+        self.waiters.insert(id, waiter);
+    }
+
+    pub(crate) fn dequeue(&mut self, id: u64, db: usize, keys: &[Vec<u8>]) {
+        for key in keys {
+            let entry = (db, key.clone());
+            if let Some(queue) = self.queues.get_mut(&entry) {
+                queue.retain(|&w| w != id);
+                if queue.is_empty() {
+                    self.queues.remove(&entry);
+                }
+            }
+        }
+    }
+}
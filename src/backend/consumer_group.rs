@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::StreamId;
+
+// an entry delivered to a consumer and not acknowledged yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEntry {
+    pub consumer: Vec<u8>,
+    // unix ms of the last delivery, what idle times are measured from
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Consumer {
+    // unix ms of the last command that named this consumer
+    pub seen_time: u64,
+    // unix ms of the last read or claim that got it entries
+    pub active_time: Option<u64>,
+    // the ids of its entries in the group's pending entries list
+    pub pending: BTreeSet<StreamId>,
+}
+
+// a consumer group: where it is in the stream, and which delivered entries
+// every consumer still has to acknowledge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumerGroup {
+    pub last_id: StreamId,
+    // how many entries the group has read, None when it can't be known
+    // (after the id was set explicitly to somewhere with deleted entries)
+    pub entries_read: Option<u64>,
+    pending: BTreeMap<StreamId, PendingEntry>,
+    consumers: BTreeMap<Vec<u8>, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_id: StreamId, entries_read: Option<u64>) -> Self {
+        ConsumerGroup {
+            last_id,
+            entries_read,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    // the pending entries list, ordered by id
+    pub fn pending(&self) -> &BTreeMap<StreamId, PendingEntry> {
+        &self.pending
+    }
+
+    pub fn consumers(&self) -> &BTreeMap<Vec<u8>, Consumer> {
+        &self.consumers
+    }
+
+    pub fn consumer(&self, name: &[u8]) -> Option<&Consumer> {
+        self.consumers.get(name)
+    }
+
+    // add a consumer, returning whether it is new
+    pub fn create_consumer(&mut self, name: &[u8], now: u64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        self.seen(name, now);
+        true
+    }
+
+    // remove a consumer and its pending entries, returning how many it had
+    pub fn delete_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    // note that a command named the consumer, creating it if needed
+    pub fn seen(&mut self, name: &[u8], now: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(name.to_vec()).or_default();
+        consumer.seen_time = now;
+        consumer
+    }
+
+    // record that the entry went to consumer: a first delivery, or a claim
+    // taking it over from whoever had it before
+    pub fn assign(
+        &mut self,
+        id: StreamId,
+        consumer: &[u8],
+        delivery_time: u64,
+        delivery_count: u64,
+    ) {
+        if let Some(old) = self.pending.get(&id) {
+            if let Some(owner) = self.consumers.get_mut(&old.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        self.pending.insert(
+            id,
+            PendingEntry {
+                consumer: consumer.to_vec(),
+                delivery_time,
+                delivery_count,
+            },
+        );
+        self.consumers
+            .entry(consumer.to_vec())
+            .or_default()
+            .pending
+            .insert(id);
+    }
+
+    // drop an entry from the pending list, returning whether it was there
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        let entry = match self.pending.remove(&id) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if let Some(owner) = self.consumers.get_mut(&entry.consumer) {
+            owner.pending.remove(&id);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_entries() {
+        let mut group = ConsumerGroup::new(StreamId::MIN, Some(0));
+        let (a, b) = (StreamId::new(1, 0), StreamId::new(2, 0));
+        group.assign(a, b"alice", 10, 1);
+        group.assign(b, b"alice", 10, 1);
+        // a claim moves the entry to the new owner
+        group.assign(a, b"bob", 20, 2);
+        assert_eq!(group.pending()[&a].consumer, b"bob");
+        assert_eq!(
+            group.consumer(b"alice").unwrap().pending,
+            BTreeSet::from([b])
+        );
+        assert_eq!(group.consumer(b"bob").unwrap().pending, BTreeSet::from([a]));
+
+        assert!(group.ack(a));
+        assert!(!group.ack(a));
+        assert!(group.consumer(b"bob").unwrap().pending.is_empty());
+        assert!(!group.create_consumer(b"alice", 30));
+        assert_eq!(group.delete_consumer(b"alice"), Some(1));
+        assert!(group.pending().is_empty());
+        assert_eq!(group.delete_consumer(b"alice"), None);
+    }
+}
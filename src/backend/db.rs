@@ -0,0 +1,369 @@
+use std::collections::VecDeque;
+
+use std::time::{Duration, Instant};
+
+use crate::cmd::CommandError;
+
+use super::{now_ms, Dict, DictSet, Expires, Stream, Value, ZSet};
+
+// keys with a TTL looked at per round of the active expiry cycle, and the
+// share of them, in percent, that has to be expired for another round
+const ACTIVE_EXPIRE_SAMPLES: usize = 20;
+const ACTIVE_EXPIRE_STALE_PERCENT: usize = 25;
+
+// one keyspace: binary keys mapped to typed values, plus the absolute
+// expire time in unix milliseconds of every key that has a TTL
+#[derive(Debug, Default)]
+pub struct Db {
+    entries: Dict<Vec<u8>, Value>,
+    expires: Expires,
+    // keys written since the last take_touched(), used to wake blocked clients
+    touched: Vec<Vec<u8>>,
+}
+
+// generates the typed accessors for one value kind:
+//   $get(key)            -> Ok(None) when missing, WRONGTYPE when the key holds another kind
+//   $get_mut(key)        -> same, mutable
+//   $get_or_create(key)  -> mutable access, inserting an empty value when missing
+// a change made in place through the mutable ones needs a touch() afterwards
+macro_rules! typed_accessors {
+    ($variant:ident, $ty:ty, $get:ident, $get_mut:ident, $get_or_create:ident) => {
+        pub fn $get(&mut self, key: &[u8]) -> Result<Option<&$ty>, CommandError> {
+            match self.get(key) {
+                Some(Value::$variant(v)) => Ok(Some(v)),
+                Some(_) => Err(CommandError::WrongType),
+                None => Ok(None),
+            }
+        }
+
+        pub fn $get_mut(&mut self, key: &[u8]) -> Result<Option<&mut $ty>, CommandError> {
+            match self.get_mut(key) {
+                Some(Value::$variant(v)) => Ok(Some(v)),
+                Some(_) => Err(CommandError::WrongType),
+                None => Ok(None),
+            }
+        }
+
+        pub fn $get_or_create(&mut self, key: &[u8]) -> Result<&mut $ty, CommandError> {
+            if !self.contains_key(key) {
+                self.insert(key.to_vec(), Value::$variant(Default::default()));
+            }
+            match self.get_mut(key) {
+                Some(Value::$variant(v)) => Ok(v),
+                _ => Err(CommandError::WrongType),
+            }
+        }
+    };
+}
+
+impl Db {
+    pub fn new() -> Self {
+        Db::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains_key(&mut self, key: &[u8]) -> bool {
+        self.expire_if_needed(key);
+        self.entries.contains_key(key)
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Option<&Value> {
+        self.expire_if_needed(key);
+        self.entries.get(key)
+    }
+
+    // read a live key without the side effects of get: an expired key reads as
+    // missing but is left for the next write to clean up
+    pub fn peek(&self, key: &[u8]) -> Option<&Value> {
+        match self.expires.get(key) {
+            Some(at) if at <= now_ms() => None,
+            _ => self.entries.get(key),
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Value> {
+        self.expire_if_needed(key);
+        self.entries.get_mut(key)
+    }
+
+    // store a new value under key; like SET, this discards any TTL the key had
+    pub fn insert(&mut self, key: Vec<u8>, value: Value) -> Option<Value> {
+        self.expires.remove(&key);
+        self.touched.push(key.clone());
+        self.entries.insert(key, value)
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Value> {
+        self.expire_if_needed(key);
+        self.expires.remove(key);
+        let value = self.entries.remove(key);
+        if value.is_some() {
+            self.touched.push(key.to_vec());
+        }
+        value
+    }
+
+    // mark a key as written, waking the clients blocked on it and failing the
+    // transactions watching it. Inserts and removals do this themselves; a
+    // command changing a value in place calls it once it really changed it
+    pub fn touch(&mut self, key: &[u8]) {
+        self.touched.push(key.to_vec());
+    }
+
+    // the keys written since the last call, in write order, possibly repeated
+    pub fn take_touched(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.touched)
+    }
+
+    // the keys go away like any deleted key would, so they count as touched
+    pub fn clear(&mut self) {
+        self.touch_all();
+        self.entries.clear();
+        self.expires.clear();
+    }
+
+    // mark every key as written, for when the whole keyspace goes away
+    fn touch_all(&mut self) {
+        let keys: Vec<_> = self.entries.keys().cloned().collect();
+        self.touched.extend(keys);
+    }
+
+    // the absolute expire time of a live key, in unix milliseconds
+    pub fn expire_at(&mut self, key: &[u8]) -> Option<u64> {
+        self.expire_if_needed(key);
+        self.expires.get(key)
+    }
+
+    // set the absolute expire time of an existing key; a time in the past deletes it
+    pub fn set_expire(&mut self, key: &[u8], at: u64) -> bool {
+        if !self.contains_key(key) {
+            return false;
+        }
+        if at <= now_ms() {
+            self.remove(key);
+        } else {
+            self.expires.insert(key.to_vec(), at);
+            self.touched.push(key.to_vec());
+        }
+        true
+    }
+
+    // drop the TTL of a key, returning whether it had one
+    pub fn persist(&mut self, key: &[u8]) -> bool {
+        self.expire_if_needed(key);
+        let had_ttl = self.expires.remove(key).is_some();
+        if had_ttl {
+            self.touched.push(key.to_vec());
+        }
+        had_ttl
+    }
+
+    // lazy expiry: a key whose time has come is deleted the moment it is looked at
+    fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        match self.expires.get(key) {
+            Some(at) if at <= now_ms() => {
+                self.expires.remove(key);
+                self.entries.remove(key);
+                self.touched.push(key.to_vec());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn is_expired(&self, key: &[u8], now: u64) -> bool {
+        self.expires.get(key).is_some_and(|at| at <= now)
+    }
+
+    // the live keys, in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &Vec<u8>> {
+        let now = now_ms();
+        self.entries
+            .keys()
+            .filter(move |key| !self.is_expired(key, now))
+    }
+
+    // one step of a SCAN walk over the live keys; see Dict::scan
+    pub fn scan(&self, cursor: u64, mut f: impl FnMut(&[u8], &Value)) -> u64 {
+        let now = now_ms();
+        self.entries.scan(cursor, |key, value| {
+            if !self.is_expired(key, now) {
+                f(key, value)
+            }
+        })
+    }
+
+    // a live key picked at random; expired keys met on the way are deleted
+    pub fn random_key(&mut self) -> Option<Vec<u8>> {
+        let mut rng = rand::thread_rng();
+        loop {
+            let key = self.entries.random(&mut rng)?.0.clone();
+            if !self.expire_if_needed(&key) {
+                return Some(key);
+            }
+        }
+    }
+
+    // move a key, TTL included, over whatever to held; false when from is missing
+    pub fn rename(&mut self, from: &[u8], to: &[u8]) -> bool {
+        let at = self.expire_at(from);
+        let value = match self.remove(from) {
+            Some(value) => value,
+            None => return false,
+        };
+        self.insert(to.to_vec(), value);
+        if let Some(at) = at {
+            self.expires.insert(to.to_vec(), at);
+        }
+        true
+    }
+
+    // active expiry, for keys nobody reads: sample keys with a TTL and delete
+    // the expired ones, going on while a good part of each sample turned out
+    // expired, until the time budget runs out. Returns how many were deleted
+    pub fn active_expire_cycle(&mut self, budget: Duration) -> usize {
+        let start = Instant::now();
+        let mut rng = rand::thread_rng();
+        let mut deleted = 0;
+        loop {
+            let samples = self.expires.len().min(ACTIVE_EXPIRE_SAMPLES);
+            if samples == 0 {
+                return deleted;
+            }
+            let now = now_ms();
+            let mut expired = 0;
+            for _ in 0..samples {
+                let key = match self.expires.random(&mut rng) {
+                    Some((key, at)) if at <= now => key.to_vec(),
+                    _ => continue,
+                };
+                self.expire_if_needed(&key);
+                expired += 1;
+            }
+            deleted += expired;
+            if expired * 100 <= samples * ACTIVE_EXPIRE_STALE_PERCENT || start.elapsed() >= budget {
+                return deleted;
+            }
+        }
+    }
+
+    // drop the key if a command left its container empty
+    pub fn remove_if_empty(&mut self, key: &[u8]) {
+        if matches!(self.entries.get(key), Some(v) if v.is_empty_container()) {
+            self.remove(key);
+        }
+    }
+
+    typed_accessors!(
+        String,
+        Vec<u8>,
+        get_string,
+        get_string_mut,
+        get_or_create_string
+    );
+    typed_accessors!(
+        List,
+        VecDeque<Vec<u8>>,
+        get_list,
+        get_list_mut,
+        get_or_create_list
+    );
+    typed_accessors!(
+        Hash,
+        Dict<Vec<u8>, Vec<u8>>,
+        get_hash,
+        get_hash_mut,
+        get_or_create_hash
+    );
+    typed_accessors!(
+        Set,
+        DictSet<Vec<u8>>,
+        get_set,
+        get_set_mut,
+        get_or_create_set
+    );
+    typed_accessors!(ZSet, ZSet, get_zset, get_zset_mut, get_or_create_zset);
+    typed_accessors!(
+        Stream,
+        Stream,
+        get_stream,
+        get_stream_mut,
+        get_or_create_stream
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_access() {
+        let mut db = Db::new();
+        db.insert(b"s".to_vec(), b"v".as_slice().into());
+        assert_eq!(db.get_string(b"s").unwrap(), Some(&b"v".to_vec()));
+        assert_eq!(db.get_list(b"s").unwrap_err(), CommandError::WrongType);
+        assert_eq!(
+            db.get_or_create_hash(b"s").unwrap_err(),
+            CommandError::WrongType
+        );
+        assert_eq!(db.get_set(b"missing").unwrap(), None);
+
+        db.get_or_create_list(b"l")
+            .unwrap()
+            .push_back(b"a".to_vec());
+        assert_eq!(db.get(b"l").unwrap().type_name(), "list");
+        db.get_list_mut(b"l").unwrap().unwrap().pop_front();
+        db.remove_if_empty(b"l");
+        assert!(!db.contains_key(b"l"));
+        assert_eq!(db.len(), 1);
+    }
+
+    #[test]
+    fn test_lazy_expire() {
+        let mut db = Db::new();
+        db.insert(b"k".to_vec(), b"v".as_slice().into());
+        assert!(db.set_expire(b"k", now_ms() + 100_000));
+        assert!(db.expire_at(b"k").is_some());
+
+        // overwriting the value drops the TTL
+        db.insert(b"k".to_vec(), b"v2".as_slice().into());
+        assert_eq!(db.expire_at(b"k"), None);
+
+        db.expires.insert(b"k".to_vec(), now_ms() - 1);
+        assert_eq!(db.get(b"k"), None);
+        assert!(db.is_empty());
+        assert!(!db.set_expire(b"k", now_ms() + 100_000));
+    }
+
+    #[test]
+    fn test_active_expire() {
+        let mut db = Db::new();
+        for i in 0..100 {
+            let key = format!("k{}", i).into_bytes();
+            db.insert(key.clone(), b"v".as_slice().into());
+            // every other key has expired already, the rest live on
+            let at = if i % 2 == 0 {
+                now_ms() - 1
+            } else {
+                now_ms() + 100_000
+            };
+            db.expires.insert(key, at);
+        }
+        db.insert(b"plain".to_vec(), b"v".as_slice().into());
+        let mut deleted = 0;
+        while db.len() > 51 {
+            deleted += db.active_expire_cycle(Duration::from_millis(100));
+        }
+        assert_eq!(deleted, 50);
+        assert_eq!(db.expires.len(), 50);
+        assert!(db.peek(b"plain").is_some());
+        assert_eq!(db.active_expire_cycle(Duration::from_millis(100)), 0);
+    }
+}
@@ -0,0 +1,425 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+use rand::Rng;
+
+// the smallest table a non-empty dict uses
+const MIN_BUCKETS: usize = 4;
+// shrink once fewer than one in this many buckets would be used
+const SHRINK_RATIO: usize = 8;
+
+// a chained hash table whose size is always a power of two, which is what
+// lets scan() walk it with a cursor that stays valid while it is resized
+// between calls: the cursor counts up with its bits reversed, so the buckets
+// it has visited are exactly those a grown or shrunk table maps them to.
+// Every element present from the first call to the last is returned at least
+// once; some may be returned twice.
+#[derive(Clone)]
+pub struct Dict<K, V> {
+    // empty, or a power of two long
+    buckets: Vec<Vec<(K, V)>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<K, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Dict {
+            buckets: Vec::new(),
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    pub fn new() -> Self {
+        Dict::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn bucket_of<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        self.hasher.hash_one(key) as usize & (self.buckets.len() - 1)
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<(usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let bucket = self.bucket_of(key);
+        let pos = self.buckets[bucket]
+            .iter()
+            .position(|(k, _)| k.borrow() == key)?;
+        Some((bucket, pos))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (bucket, pos) = self.find(key)?;
+        Some(&self.buckets[bucket][pos].1)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (bucket, pos) = self.find(key)?;
+        Some(&mut self.buckets[bucket][pos].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    // add or replace an element, returning the value it replaced
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some((bucket, pos)) = self.find(&key) {
+            return Some(std::mem::replace(&mut self.buckets[bucket][pos].1, value));
+        }
+        // grow once every bucket holds an element on average
+        if self.len >= self.buckets.len() {
+            self.resize((self.len + 1).next_power_of_two().max(MIN_BUCKETS));
+        }
+        let bucket = self.bucket_of(&key);
+        self.buckets[bucket].push((key, value));
+        self.len += 1;
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (bucket, pos) = self.find(key)?;
+        let (_, value) = self.buckets[bucket].swap_remove(pos);
+        self.len -= 1;
+        if self.len == 0 {
+            self.buckets = Vec::new();
+        } else if self.buckets.len() > MIN_BUCKETS && self.len * SHRINK_RATIO < self.buckets.len() {
+            self.resize(self.len.next_power_of_two().max(MIN_BUCKETS));
+        }
+        Some(value)
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets = Vec::new();
+        self.len = 0;
+    }
+
+    fn resize(&mut self, size: usize) {
+        let old = std::mem::replace(&mut self.buckets, (0..size).map(|_| Vec::new()).collect());
+        for (key, value) in old.into_iter().flatten() {
+            let bucket = self.bucket_of(&key);
+            self.buckets[bucket].push((key, value));
+        }
+    }
+
+    // the number of buckets, always a power of two once anything was stored
+    pub fn buckets(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn iter(&self) -> DictIter<'_, K, V> {
+        DictIter {
+            buckets: self.buckets.iter(),
+            bucket: [].iter(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    // keep only the elements f returns true for
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &mut V) -> bool) {
+        let before = self.len;
+        for bucket in &mut self.buckets {
+            bucket.retain_mut(|(k, v)| f(k, v));
+        }
+        self.len = self.buckets.iter().map(Vec::len).sum();
+        if self.len == 0 {
+            self.buckets = Vec::new();
+        } else if self.len < before && self.len * SHRINK_RATIO < self.buckets.len() {
+            self.resize(self.len.next_power_of_two().max(MIN_BUCKETS));
+        }
+    }
+
+    // an element picked at random: a random non-empty bucket, then a random
+    // element of its chain
+    pub fn random(&self, rng: &mut impl Rng) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        loop {
+            let bucket = &self.buckets[rng.gen_range(0..self.buckets.len())];
+            if !bucket.is_empty() {
+                let (k, v) = &bucket[rng.gen_range(0..bucket.len())];
+                return Some((k, v));
+            }
+        }
+    }
+
+    // call f on every element of the bucket the cursor points at, returning
+    // the cursor of the next bucket to visit; 0 once the walk is complete.
+    // A walk starts at cursor 0
+    pub fn scan(&self, cursor: u64, mut f: impl FnMut(&K, &V)) -> u64 {
+        if self.buckets.is_empty() {
+            return 0;
+        }
+        let mask = self.buckets.len() as u64 - 1;
+        for (k, v) in &self.buckets[(cursor & mask) as usize] {
+            f(k, v);
+        }
+        // increment the bits under the mask, starting from the highest one
+        let cursor = cursor | !mask;
+        cursor.reverse_bits().wrapping_add(1).reverse_bits()
+    }
+}
+
+impl<K: Hash + Eq, V: PartialEq> PartialEq for Dict<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for Dict<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.buckets.iter().flatten().map(|(k, v)| (k, v)))
+            .finish()
+    }
+}
+
+impl<K: Hash + Eq, V> FromIterator<(K, V)> for Dict<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut dict = Dict::new();
+        for (k, v) in iter {
+            dict.insert(k, v);
+        }
+        dict
+    }
+}
+
+pub struct DictIter<'a, K, V> {
+    buckets: std::slice::Iter<'a, Vec<(K, V)>>,
+    bucket: std::slice::Iter<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iterator for DictIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((k, v)) = self.bucket.next() {
+                return Some((k, v));
+            }
+            self.bucket = self.buckets.next()?.iter();
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V> IntoIterator for &'a Dict<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = DictIter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V> IntoIterator for Dict<K, V> {
+    type Item = (K, V);
+    type IntoIter = std::iter::Flatten<std::vec::IntoIter<Vec<(K, V)>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.buckets.into_iter().flatten()
+    }
+}
+
+// a Dict of keys alone, with the HashSet methods the set commands use
+#[derive(Clone, PartialEq)]
+pub struct DictSet<K: Hash + Eq>(Dict<K, ()>);
+
+impl<K: Hash + Eq> Default for DictSet<K> {
+    fn default() -> Self {
+        DictSet(Dict::new())
+    }
+}
+
+impl<K: Hash + Eq> DictSet<K> {
+    pub fn new() -> Self {
+        DictSet(Dict::new())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.contains_key(key)
+    }
+
+    // add a member, returning whether it is new
+    pub fn insert(&mut self, key: K) -> bool {
+        self.0.insert(key, ()).is_none()
+    }
+
+    // remove a member, returning whether it was there
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.remove(key).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &K> {
+        self.0.keys()
+    }
+
+    pub fn random(&self, rng: &mut impl Rng) -> Option<&K> {
+        self.0.random(rng).map(|(k, _)| k)
+    }
+
+    pub fn scan(&self, cursor: u64, mut f: impl FnMut(&K)) -> u64 {
+        self.0.scan(cursor, |k, _| f(k))
+    }
+}
+
+impl<K: Hash + Eq + fmt::Debug> fmt::Debug for DictSet<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Hash + Eq> FromIterator<K> for DictSet<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        DictSet(iter.into_iter().map(|k| (k, ())).collect())
+    }
+}
+
+impl<'a, K: Hash + Eq> IntoIterator for &'a DictSet<K> {
+    type Item = &'a K;
+    type IntoIter = std::iter::Map<DictIter<'a, K, ()>, fn((&'a K, &'a ())) -> &'a K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter().map(|(k, _)| k)
+    }
+}
+
+impl<K: Hash + Eq> IntoIterator for DictSet<K> {
+    type Item = K;
+    type IntoIter = std::iter::Map<<Dict<K, ()> as IntoIterator>::IntoIter, fn((K, ())) -> K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter().map(|(k, _)| k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    // a full walk, resizing the table to each size in turn between calls
+    fn scan_while_resizing(dict: &mut Dict<u32, ()>, sizes: &[usize]) -> Vec<u32> {
+        let mut seen = Vec::new();
+        let mut cursor = 0;
+        let mut sizes = sizes.iter().cycle();
+        loop {
+            cursor = dict.scan(cursor, |k, _| seen.push(*k));
+            if cursor == 0 {
+                return seen;
+            }
+            dict.resize(*sizes.next().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_insert_remove_resize() {
+        let mut dict = Dict::new();
+        for i in 0..100u32 {
+            assert_eq!(dict.insert(i, i * 2), None);
+        }
+        assert_eq!(dict.insert(7, 0), Some(14));
+        assert_eq!(dict.len(), 100);
+        assert_eq!(dict.buckets(), 128);
+        for i in 0..95 {
+            assert_eq!(dict.remove(&i), Some(if i == 7 { 0 } else { i * 2 }));
+        }
+        assert_eq!(dict.get(&99), Some(&198));
+        assert!(!dict.contains_key(&3));
+        // shrunk along the way, though not yet to fit the 5 left
+        assert_eq!(dict.buckets(), 16);
+        assert_eq!(dict.iter().count(), 5);
+        dict.retain(|k, _| k % 2 == 0);
+        assert_eq!(dict.len(), 2);
+        for i in [96, 98] {
+            dict.remove(&i);
+        }
+        assert_eq!(dict.buckets(), 0);
+        assert_eq!(dict.scan(0, |_, _| panic!("empty")), 0);
+    }
+
+    #[test]
+    fn test_scan_survives_resizing() {
+        let mut dict: Dict<u32, ()> = (0..300).map(|i| (i, ())).collect();
+        let all: HashSet<u32> = (0..300).collect();
+        // a plain walk returns every element exactly once
+        let plain = scan_while_resizing(&mut dict.clone(), &[512]);
+        assert_eq!(plain.len(), 300);
+        assert_eq!(plain.iter().copied().collect::<HashSet<_>>(), all);
+        // growing and shrinking under the cursor may repeat, but never miss
+        for sizes in [&[1024][..], &[16][..], &[512, 4, 2048, 64][..]] {
+            let seen = scan_while_resizing(&mut dict, sizes);
+            assert_eq!(seen.iter().copied().collect::<HashSet<_>>(), all);
+        }
+    }
+
+    #[test]
+    fn test_dict_set() {
+        let mut set: DictSet<Vec<u8>> = [b"a".to_vec(), b"b".to_vec()].into_iter().collect();
+        assert!(set.insert(b"c".to_vec()));
+        assert!(!set.insert(b"a".to_vec()));
+        assert!(set.remove(b"b".as_slice()));
+        assert!(!set.contains(b"b".as_slice()));
+        let mut members: Vec<_> = set.into_iter().collect();
+        members.sort();
+        assert_eq!(members, vec![b"a".to_vec(), b"c".to_vec()]);
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+// the expire times of the keys that have a TTL, kept so that a random key
+// can be picked in O(1): the active expiry cycle samples them
+#[derive(Debug, Default)]
+pub struct Expires {
+    // key -> (absolute unix ms, position in keys)
+    times: HashMap<Vec<u8>, (u64, usize)>,
+    keys: Vec<Vec<u8>>,
+}
+
+impl Expires {
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<u64> {
+        self.times.get(key).map(|&(at, _)| at)
+    }
+
+    pub fn insert(&mut self, key: Vec<u8>, at: u64) {
+        match self.times.get_mut(&key) {
+            Some(entry) => entry.0 = at,
+            None => {
+                self.times.insert(key.clone(), (at, self.keys.len()));
+                self.keys.push(key);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<u64> {
+        let (at, pos) = self.times.remove(key)?;
+        self.keys.swap_remove(pos);
+        // the last key took the place of the removed one
+        if let Some(moved) = self.keys.get(pos) {
+            self.times.get_mut(moved).expect("indexed key").1 = pos;
+        }
+        Some(at)
+    }
+
+    pub fn clear(&mut self) {
+        self.times.clear();
+        self.keys.clear();
+    }
+
+    // a key with a TTL picked at random, with its expire time
+    pub fn random(&self, rng: &mut impl Rng) -> Option<(&[u8], u64)> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let key = &self.keys[rng.gen_range(0..self.keys.len())];
+        Some((key, self.times[key].0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expires_index() {
+        let mut expires = Expires::default();
+        expires.insert(b"a".to_vec(), 1);
+        expires.insert(b"b".to_vec(), 2);
+        expires.insert(b"c".to_vec(), 3);
+        expires.insert(b"a".to_vec(), 10);
+        assert_eq!(expires.len(), 3);
+        assert_eq!(expires.remove(b"a"), Some(10));
+        assert_eq!(expires.remove(b"a"), None);
+        // c moved into the slot a left, and is still found there
+        assert_eq!(expires.remove(b"c"), Some(3));
+        assert_eq!(expires.get(b"b"), Some(2));
+
+        let mut rng = rand::thread_rng();
+        assert_eq!(expires.random(&mut rng), Some((b"b".as_slice(), 2)));
+        expires.clear();
+        assert!(expires.is_empty());
+        assert_eq!(expires.random(&mut rng), None);
+    }
+}
@@ -0,0 +1,604 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use mlua::{ChunkMode, HookTriggers, Lua, RegistryKey, Value, Variadic};
+
+use crate::cmd::CommandError;
+
+use super::{lua_error_message, new_lua, redis_table, sandbox};
+
+// the flags a function may be registered with; only no-writes changes
+// anything here, the others are accepted for compatibility
+const FUNCTION_FLAGS: [&str; 5] = [
+    "no-writes",
+    "allow-oom",
+    "allow-stale",
+    "no-cluster",
+    "allow-cross-slot-keys",
+];
+
+// the version byte a FUNCTION DUMP payload starts with
+const DUMP_VERSION: u8 = 1;
+
+// how long a library's code may run while it loads, as in Redis; it only
+// has to register functions, and holds the server while it does
+const LOAD_TIMEOUT: Duration = Duration::from_millis(500);
+
+// how many instructions a loading library runs between checks of the time
+const LOAD_CHECK_INSTRUCTIONS: u32 = 1000;
+
+// a function a library registered, callable with FCALL
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub description: Option<String>,
+    pub flags: Vec<String>,
+    // the Lua function itself, in the interpreter the library was loaded in
+    pub callback: RegistryKey,
+}
+
+impl Function {
+    pub fn no_writes(&self) -> bool {
+        self.flags.iter().any(|flag| flag == "no-writes")
+    }
+}
+
+// a library loaded with FUNCTION LOAD: its code, first line and all, and the
+// functions it registered, in the order it registered them
+#[derive(Debug)]
+pub struct Library {
+    pub name: String,
+    pub code: Vec<u8>,
+    pub functions: Vec<Function>,
+}
+
+// what FUNCTION RESTORE does with the libraries already loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestorePolicy {
+    // keep them, failing on a library that exists already
+    Append,
+    // keep them, but let the restored ones take their place
+    Replace,
+    // delete them all first
+    Flush,
+}
+
+// the function libraries, by name, and the interpreter their functions live in
+#[derive(Debug, Default)]
+pub struct Functions {
+    libraries: BTreeMap<String, Library>,
+    // the library of each function, by function name
+    index: HashMap<String, String>,
+    // created on first use, and lent out to the function that is running
+    lua: Option<Lua>,
+}
+
+impl Functions {
+    // load a library, returning its name; with replace a library of the same
+    // name gives way, otherwise it is an error
+    pub fn load(&mut self, code: Vec<u8>, replace: bool) -> Result<String, CommandError> {
+        let name = library_name(&code)?;
+        if !replace && self.libraries.contains_key(&name) {
+            return Err(CommandError::err(format!(
+                "Library '{}' already exists",
+                name
+            )));
+        }
+        let lua = self.take_lua()?;
+        let functions = load_library(&lua, &code);
+        self.put_lua(lua);
+        let functions = functions?;
+        for function in &functions {
+            if self
+                .index
+                .get(&function.name)
+                .is_some_and(|lib| *lib != name)
+            {
+                return Err(CommandError::err(format!(
+                    "Function {} already exists",
+                    function.name
+                )));
+            }
+        }
+        self.delete(&name);
+        for function in &functions {
+            self.index.insert(function.name.clone(), name.clone());
+        }
+        self.libraries.insert(
+            name.clone(),
+            Library {
+                name: name.clone(),
+                code,
+                functions,
+            },
+        );
+        Ok(name)
+    }
+
+    // delete a library and its functions, returning false if there was none
+    pub fn delete(&mut self, name: &str) -> bool {
+        let Some(library) = self.libraries.remove(name) else {
+            return false;
+        };
+        for function in library.functions {
+            self.index.remove(&function.name);
+            if let Some(lua) = &self.lua {
+                let _ = lua.remove_registry_value(function.callback);
+            }
+        }
+        true
+    }
+
+    // delete every library, and start over with a fresh interpreter
+    pub fn flush(&mut self) {
+        *self = Functions::default();
+    }
+
+    pub fn libraries(&self) -> impl Iterator<Item = &Library> {
+        self.libraries.values()
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        let library = self.libraries.get(self.index.get(name)?)?;
+        library.functions.iter().find(|f| f.name == name)
+    }
+
+    // every library's code, in a payload FUNCTION RESTORE takes back: a
+    // version byte, each code prefixed with its length, then a SHA1 of it all
+    pub fn dump(&self) -> Vec<u8> {
+        let mut payload = vec![DUMP_VERSION];
+        for library in self.libraries.values() {
+            payload.extend_from_slice(&(library.code.len() as u32).to_be_bytes());
+            payload.extend_from_slice(&library.code);
+        }
+        let checksum = sha1_smol::Sha1::from(&payload).digest().bytes();
+        payload.extend_from_slice(&checksum);
+        payload
+    }
+
+    // load the libraries of a FUNCTION DUMP payload; all or nothing, so they
+    // go into a fresh set that replaces this one once every library loaded
+    pub fn restore(&mut self, payload: &[u8], policy: RestorePolicy) -> Result<(), CommandError> {
+        let codes = parse_dump(payload)
+            .ok_or_else(|| CommandError::err("payload version or checksum are wrong"))?;
+        let mut restored = Functions::default();
+        if policy != RestorePolicy::Flush {
+            for library in self.libraries.values() {
+                restored.load(library.code.clone(), false)?;
+            }
+        }
+        for code in codes {
+            restored.load(code, policy == RestorePolicy::Replace)?;
+        }
+        *self = restored;
+        Ok(())
+    }
+
+    // the interpreter, for the function about to run; give it back with
+    // put_lua. A fresh one has none of the functions, so it loads every
+    // library again first
+    pub fn take_lua(&mut self) -> Result<Lua, CommandError> {
+        if let Some(lua) = self.lua.take() {
+            return Ok(lua);
+        }
+        let lua = new_lua().map_err(|e| CommandError::err(e.to_string()))?;
+        for library in self.libraries.values_mut() {
+            library.functions = load_library(&lua, &library.code)?;
+        }
+        Ok(lua)
+    }
+
+    pub fn put_lua(&mut self, lua: Lua) {
+        self.lua = Some(lua);
+    }
+}
+
+// the name a library gives itself on its first line, as in
+// "#!lua name=mylib"; lua is the only engine there is
+fn library_name(code: &[u8]) -> Result<String, CommandError> {
+    let first_line = code.split(|&b| b == b'\n').next().unwrap_or_default();
+    let shebang = String::from_utf8_lossy(first_line);
+    let Some(shebang) = shebang.strip_prefix("#!") else {
+        return Err(CommandError::err("Missing library metadata"));
+    };
+    let mut parts = shebang.split_whitespace();
+    let engine = parts.next().unwrap_or_default();
+    if !engine.eq_ignore_ascii_case("lua") {
+        return Err(CommandError::err(format!("Engine '{}' not found", engine)));
+    }
+    let mut name = None;
+    for part in parts {
+        match part.strip_prefix("name=") {
+            Some(value) => name = Some(value.to_string()),
+            None => {
+                return Err(CommandError::err(format!(
+                    "Invalid metadata value given: {}",
+                    part
+                )))
+            }
+        }
+    }
+    let name = name.ok_or_else(|| CommandError::err("Library name was not given"))?;
+    if !is_valid_name(&name) {
+        return Err(CommandError::err(
+            "Library names can only contain letters, numbers, or underscores(_) and must be at least one character long",
+        ));
+    }
+    Ok(name)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+// run a library's code in lua with redis.register_function available,
+// returning the functions it registered. The first line is blanked out
+// rather than dropped, so that errors point at the right line. Code still
+// running after LOAD_TIMEOUT is stopped. It runs in the read-only sandbox,
+// as do the functions it registers, so that libraries sharing the
+// interpreter can't change what the others see
+fn load_library(lua: &Lua, code: &[u8]) -> Result<Vec<Function>, CommandError> {
+    let body = match code.iter().position(|&b| b == b'\n') {
+        Some(newline) => &code[newline..],
+        None => &[],
+    };
+    let registered = RefCell::new(Vec::<Function>::new());
+    let started = Instant::now();
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(LOAD_CHECK_INSTRUCTIONS),
+        move |_, _| match started.elapsed() > LOAD_TIMEOUT {
+            true => Err(mlua::Error::RuntimeError(
+                "FUNCTION LOAD timeout".to_string(),
+            )),
+            false => Ok(()),
+        },
+    );
+    let result = lua.scope(|scope| {
+        let register = scope.create_function(|lua, args: Variadic<Value>| {
+            let function = registration(lua, args)?;
+            let mut registered = registered.borrow_mut();
+            if registered.iter().any(|f| f.name == function.name) {
+                return Err(mlua::Error::RuntimeError(
+                    "Function already exists in the library".to_string(),
+                ));
+            }
+            registered.push(function);
+            Ok(())
+        })?;
+        let redis = redis_table(lua)?;
+        redis.raw_set("register_function", register)?;
+        let chunk = lua
+            .load(body)
+            .set_name("@user_function")
+            .set_environment(sandbox(lua)?)
+            .set_mode(ChunkMode::Text)
+            .into_function();
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                return Ok(Err(CommandError::err(format!(
+                    "Error compiling function: {}",
+                    lua_error_message(&e)
+                ))))
+            }
+        };
+        Ok(chunk.call::<_, ()>(()).map_err(|e| {
+            CommandError::err(format!(
+                "Error registering functions: {}",
+                lua_error_message(&e)
+            ))
+        }))
+    });
+    lua.remove_hook();
+    result.map_err(|e| CommandError::err(e.to_string()))??;
+    let functions = registered.into_inner();
+    if functions.is_empty() {
+        return Err(CommandError::err("No functions registered"));
+    }
+    Ok(functions)
+}
+
+// the arguments of redis.register_function: a name and a callback, or a
+// table with function_name, callback, and optionally description and flags
+fn registration(lua: &Lua, args: Variadic<Value>) -> mlua::Result<Function> {
+    let fail = |message: &str| Err(mlua::Error::RuntimeError(message.to_string()));
+    let (mut name, mut callback, mut description, mut flags) = (None, None, None, Vec::new());
+    match args.as_slice() {
+        [Value::Table(table)] => {
+            for pair in table.clone().pairs::<String, Value>() {
+                let (key, value) = pair?;
+                match (key.as_str(), value) {
+                    ("function_name", Value::String(s)) => name = Some(s.to_str()?.to_string()),
+                    ("function_name", _) => {
+                        return fail(
+                            "function_name argument given to redis.register_function must be a string",
+                        )
+                    }
+                    ("callback", Value::Function(f)) => callback = Some(f),
+                    ("callback", _) => {
+                        return fail(
+                            "callback argument given to redis.register_function must be a function",
+                        )
+                    }
+                    ("description", Value::String(s)) => {
+                        description = Some(s.to_str()?.to_string())
+                    }
+                    ("description", _) => {
+                        return fail(
+                            "description argument given to redis.register_function must be a string",
+                        )
+                    }
+                    ("flags", Value::Table(t)) => {
+                        for flag in t.sequence_values::<String>() {
+                            let flag = flag?;
+                            if !FUNCTION_FLAGS.contains(&flag.as_str()) {
+                                return fail("unknown flag given");
+                            }
+                            flags.push(flag);
+                        }
+                    }
+                    ("flags", _) => {
+                        return fail(
+                            "flags argument to redis.register_function must be a table representing function flags",
+                        )
+                    }
+                    _ => return fail("unknown argument given to redis.register_function"),
+                }
+            }
+        }
+        [Value::String(s), Value::Function(f)] => {
+            name = Some(s.to_str()?.to_string());
+            callback = Some(f.clone());
+        }
+        [_, _] => {
+            return fail("function_name and callback given to redis.register_function must be a string and a function")
+        }
+        _ => return fail("wrong number of arguments to redis.register_function"),
+    }
+    let Some(name) = name else {
+        return fail("redis.register_function must get a function name argument");
+    };
+    let Some(callback) = callback else {
+        return fail("redis.register_function must get a callback argument");
+    };
+    if !is_valid_name(&name) {
+        return fail("Function names can only contain letters, numbers, or underscores(_) and must be at least one character long");
+    }
+    Ok(Function {
+        name,
+        description,
+        flags,
+        callback: lua.create_registry_value(callback)?,
+    })
+}
+
+// the library codes in a FUNCTION DUMP payload, None if it is not one
+fn parse_dump(payload: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let (content, checksum) = payload.split_at(payload.len().checked_sub(20)?);
+    if sha1_smol::Sha1::from(content).digest().bytes() != checksum {
+        return None;
+    }
+    let (&version, mut rest) = content.split_first()?;
+    if version != DUMP_VERSION {
+        return None;
+    }
+    let mut codes = Vec::new();
+    while !rest.is_empty() {
+        let (len, tail) = rest.split_at_checked(4)?;
+        let len = u32::from_be_bytes(len.try_into().ok()?) as usize;
+        let (code, tail) = tail.split_at_checked(len)?;
+        codes.push(code.to_vec());
+        rest = tail;
+    }
+    Some(codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY: &str = "#!lua name=mylib\n\
+        redis.register_function('hello', function(keys, args) return 'hi' end)\n\
+        redis.register_function{function_name = 'peek', callback = function() end, \
+        description = 'look', flags = {'no-writes'}}";
+
+    #[test]
+    fn test_library_name() {
+        assert_eq!(library_name(b"#!lua name=lib_1\nreturn").unwrap(), "lib_1");
+        assert_eq!(library_name(b"#!LUA name=x").unwrap(), "x");
+        assert_eq!(
+            library_name(b"return 1"),
+            Err(CommandError::err("Missing library metadata"))
+        );
+        assert_eq!(
+            library_name(b"#!js name=x"),
+            Err(CommandError::err("Engine 'js' not found"))
+        );
+        assert_eq!(
+            library_name(b"#!lua"),
+            Err(CommandError::err("Library name was not given"))
+        );
+        assert_eq!(
+            library_name(b"#!lua name=x foo=bar"),
+            Err(CommandError::err("Invalid metadata value given: foo=bar"))
+        );
+        assert!(library_name(b"#!lua name=a-b").is_err());
+    }
+
+    #[test]
+    fn test_load_and_delete() {
+        let mut functions = Functions::default();
+        assert_eq!(functions.load(LIBRARY.into(), false).unwrap(), "mylib");
+        let peek = functions.function("peek").unwrap();
+        assert!(peek.no_writes());
+        assert_eq!(peek.description.as_deref(), Some("look"));
+        assert!(!functions.function("hello").unwrap().no_writes());
+        assert_eq!(
+            functions.load(LIBRARY.into(), false),
+            Err(CommandError::err("Library 'mylib' already exists"))
+        );
+        assert!(functions.load(LIBRARY.into(), true).is_ok());
+
+        // function names are unique across libraries
+        assert_eq!(
+            functions.load(
+                b"#!lua name=other\nredis.register_function('hello', function() end)".to_vec(),
+                false
+            ),
+            Err(CommandError::err("Function hello already exists"))
+        );
+        assert_eq!(
+            functions.load(b"#!lua name=other\nlocal x = 1".to_vec(), false),
+            Err(CommandError::err("No functions registered"))
+        );
+        let Err(CommandError::Reply(e)) =
+            functions.load(b"#!lua name=other\nlocal = 1".to_vec(), false)
+        else {
+            panic!("compiled");
+        };
+        assert!(e.starts_with("ERR Error compiling function: user_function:2:"));
+        let Err(CommandError::Reply(e)) = functions.load(
+            b"#!lua name=other\nredis.register_function('f', function() end, 3)".to_vec(),
+            false,
+        ) else {
+            panic!("registered");
+        };
+        assert!(e.ends_with("wrong number of arguments to redis.register_function"));
+        // and nothing of a library that failed stays behind
+        assert!(functions.function("f").is_none());
+        assert_eq!(functions.libraries().count(), 1);
+
+        assert!(functions.delete("mylib"));
+        assert!(!functions.delete("mylib"));
+        assert!(functions.function("hello").is_none());
+    }
+
+    #[test]
+    fn test_library_refuses_bytecode() {
+        let mut functions = Functions::default();
+        let mut code = b"#!lua name=bin\n".to_vec();
+        code.extend(
+            Lua::new()
+                .load("return 1")
+                .into_function()
+                .unwrap()
+                .dump(false),
+        );
+        let Err(CommandError::Reply(e)) = functions.load(code, false) else {
+            panic!("bytecode loaded");
+        };
+        assert!(e.starts_with("ERR Error compiling function:"));
+        // nor can a library compile any while it loads
+        for body in [
+            "loadstring(string.dump(function() end))",
+            "load(function() return nil end)",
+            "string.dump(function() end)",
+        ] {
+            let code = format!("#!lua name=bin\n{}", body);
+            let Err(CommandError::Reply(e)) = functions.load(code.into(), false) else {
+                panic!("{} ran", body);
+            };
+            assert!(e.starts_with("ERR Error registering functions:"), "{}", e);
+        }
+        assert_eq!(functions.libraries().count(), 0);
+    }
+
+    #[test]
+    fn test_libraries_cannot_change_each_other() {
+        let mut functions = Functions::default();
+        for (name, body) in [
+            ("hijack", "tonumber = function() return 0 end"),
+            ("breaker", "string.upper = nil"),
+            ("raw", "rawset(_G, 'tonumber', print)"),
+        ] {
+            let code = format!(
+                "#!lua name={}\n{}\nredis.register_function('{}', function() end)",
+                name, body, name
+            );
+            let Err(CommandError::Reply(e)) = functions.load(code.into(), false) else {
+                panic!("{} loaded", name);
+            };
+            assert!(e.contains("Attempt to modify a readonly table"), "{}", e);
+        }
+        // nor can a function of theirs, once it runs
+        functions
+            .load(
+                b"#!lua name=late\nredis.register_function('late', \
+                function() string.upper = nil end)"
+                    .to_vec(),
+                false,
+            )
+            .unwrap();
+        functions
+            .load(
+                b"#!lua name=victim\nredis.register_function('victim', \
+                function() return string.upper(tostring(tonumber('1'))) .. 'a' end)"
+                    .to_vec(),
+                false,
+            )
+            .unwrap();
+        let lua = functions.take_lua().unwrap();
+        let call = |name: &str| {
+            lua.registry_value::<mlua::Function>(&functions.function(name).unwrap().callback)
+                .unwrap()
+                .call::<_, String>(())
+                .map_err(|e| lua_error_message(&e))
+        };
+        assert!(call("late").is_err());
+        assert_eq!(call("victim").unwrap(), "1a");
+    }
+
+    #[test]
+    fn test_load_timeout() {
+        let mut functions = Functions::default();
+        let Err(CommandError::Reply(e)) =
+            functions.load(b"#!lua name=loop\nwhile true do end".to_vec(), false)
+        else {
+            panic!("endless library loaded");
+        };
+        assert_eq!(e, "ERR Error registering functions: FUNCTION LOAD timeout");
+        assert_eq!(functions.libraries().count(), 0);
+        // the interpreter is fine afterwards, and its functions run unhurried
+        functions.load(LIBRARY.into(), false).unwrap();
+        let lua = functions.take_lua().unwrap();
+        let hello: mlua::Function = lua
+            .registry_value(&functions.function("hello").unwrap().callback)
+            .unwrap();
+        assert_eq!(hello.call::<_, String>(()).unwrap(), "hi");
+    }
+
+    #[test]
+    fn test_dump_and_restore() {
+        let mut functions = Functions::default();
+        functions.load(LIBRARY.into(), false).unwrap();
+        let payload = functions.dump();
+
+        let mut other = Functions::default();
+        other.restore(&payload, RestorePolicy::Append).unwrap();
+        assert!(other.function("hello").is_some());
+        assert_eq!(
+            other.restore(&payload, RestorePolicy::Append),
+            Err(CommandError::err("Library 'mylib' already exists"))
+        );
+        other.restore(&payload, RestorePolicy::Replace).unwrap();
+        other.restore(&payload, RestorePolicy::Flush).unwrap();
+        assert_eq!(other.libraries().count(), 1);
+
+        let mut corrupt = payload.clone();
+        corrupt[3] ^= 1;
+        assert_eq!(
+            other.restore(&corrupt, RestorePolicy::Flush),
+            Err(CommandError::err("payload version or checksum are wrong"))
+        );
+        assert!(other.function("hello").is_some());
+
+        // a fresh interpreter gets every library loaded again
+        other.lua = None;
+        let lua = other.take_lua().unwrap();
+        let hello: mlua::Function = lua
+            .registry_value(&other.function("hello").unwrap().callback)
+            .unwrap();
+        assert_eq!(hello.call::<_, String>(()).unwrap(), "hi");
+    }
+}
@@ -0,0 +1,386 @@
+// 52 bit geohashes, the sorted set scores geo commands index points by. The
+// latitude and longitude are each quantized to 26 bits and interleaved,
+// latitude in the even bits, so points close together tend to have close
+// scores and a cell at any precision is one contiguous score range.
+//
+// Latitudes are limited to what the web mercator projection covers, as in
+// Redis, so scores written by either can be read by the other.
+
+pub const GEO_STEP_MAX: u8 = 26;
+pub const GEO_LAT_MIN: f64 = -85.05112878;
+pub const GEO_LAT_MAX: f64 = 85.05112878;
+pub const GEO_LONG_MIN: f64 = -180.0;
+pub const GEO_LONG_MAX: f64 = 180.0;
+
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+const MERCATOR_MAX: f64 = 20037726.37;
+
+// a cell: its precision, and its bits with the latitude in the even ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeoCell {
+    pub bits: u64,
+    pub step: u8,
+}
+
+// the bounds of a cell
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoArea {
+    pub lat_min: f64,
+    pub lat_max: f64,
+    pub long_min: f64,
+    pub long_max: f64,
+}
+
+// what a search covers, centered on a point; sizes are in meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+pub fn valid_coordinates(long: f64, lat: f64) -> bool {
+    (GEO_LONG_MIN..=GEO_LONG_MAX).contains(&long) && (GEO_LAT_MIN..=GEO_LAT_MAX).contains(&lat)
+}
+
+// spread the low 32 bits of x into the even bits of the result
+fn spread(x: u64) -> u64 {
+    let mut x = x & 0xffff_ffff;
+    x = (x | (x << 16)) & 0x0000_ffff_0000_ffff;
+    x = (x | (x << 8)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+// the inverse of spread: gather the even bits of x
+fn squash(x: u64) -> u64 {
+    let mut x = x & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x >> 4)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x >> 8)) & 0x0000_ffff_0000_ffff;
+    (x | (x >> 16)) & 0xffff_ffff
+}
+
+fn interleave(lat: u64, long: u64) -> u64 {
+    spread(lat) | (spread(long) << 1)
+}
+
+// the latitude and longitude bits of a hash
+fn deinterleave(bits: u64) -> (u64, u64) {
+    (squash(bits), squash(bits >> 1))
+}
+
+// the cell of the given precision holding a point, within the given ranges
+fn encode_in(
+    long: f64,
+    lat: f64,
+    step: u8,
+    lat_range: (f64, f64),
+    long_range: (f64, f64),
+) -> GeoCell {
+    let cells = (1u64 << step) as f64;
+    let lat_offset = (lat - lat_range.0) / (lat_range.1 - lat_range.0);
+    let long_offset = (long - long_range.0) / (long_range.1 - long_range.0);
+    // the top edge belongs to the last cell
+    let lat_bits = ((lat_offset * cells) as u64).min((1 << step) - 1);
+    let long_bits = ((long_offset * cells) as u64).min((1 << step) - 1);
+    GeoCell {
+        bits: interleave(lat_bits, long_bits),
+        step,
+    }
+}
+
+pub fn encode(long: f64, lat: f64, step: u8) -> GeoCell {
+    encode_in(
+        long,
+        lat,
+        step,
+        (GEO_LAT_MIN, GEO_LAT_MAX),
+        (GEO_LONG_MIN, GEO_LONG_MAX),
+    )
+}
+
+pub fn decode(hash: GeoCell) -> GeoArea {
+    let (lat_bits, long_bits) = deinterleave(hash.bits);
+    let cells = (1u64 << hash.step) as f64;
+    let lat_scale = GEO_LAT_MAX - GEO_LAT_MIN;
+    let long_scale = GEO_LONG_MAX - GEO_LONG_MIN;
+    GeoArea {
+        lat_min: GEO_LAT_MIN + (lat_bits as f64 / cells) * lat_scale,
+        lat_max: GEO_LAT_MIN + ((lat_bits + 1) as f64 / cells) * lat_scale,
+        long_min: GEO_LONG_MIN + (long_bits as f64 / cells) * long_scale,
+        long_max: GEO_LONG_MIN + ((long_bits + 1) as f64 / cells) * long_scale,
+    }
+}
+
+// the score a point is stored under
+pub fn geo_score(long: f64, lat: f64) -> f64 {
+    encode(long, lat, GEO_STEP_MAX).bits as f64
+}
+
+// the (longitude, latitude) a score stands for: the center of its cell
+pub fn geo_point(score: f64) -> (f64, f64) {
+    let area = decode(GeoCell {
+        bits: score as u64,
+        step: GEO_STEP_MAX,
+    });
+    let long = ((area.long_min + area.long_max) / 2.0).clamp(GEO_LONG_MIN, GEO_LONG_MAX);
+    let lat = ((area.lat_min + area.lat_max) / 2.0).clamp(GEO_LAT_MIN, GEO_LAT_MAX);
+    (long, lat)
+}
+
+// the standard 11 character base32 geohash of a point, which unlike the
+// scores uses the full -90..90 latitude range
+pub fn geohash_string(long: f64, lat: f64) -> String {
+    const ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+    let hash = encode_in(long, lat, GEO_STEP_MAX, (-90.0, 90.0), (-180.0, 180.0));
+    // the 52 bits only fill 10 characters and 2 bits of the 11th
+    (0..11)
+        .map(|i| {
+            let index = if i == 10 {
+                0
+            } else {
+                (hash.bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            ALPHABET[index as usize] as char
+        })
+        .collect()
+}
+
+fn deg_rad(deg: f64) -> f64 {
+    deg * std::f64::consts::PI / 180.0
+}
+
+fn rad_deg(rad: f64) -> f64 {
+    rad * 180.0 / std::f64::consts::PI
+}
+
+fn lat_distance(lat1: f64, lat2: f64) -> f64 {
+    EARTH_RADIUS_IN_METERS * (deg_rad(lat2) - deg_rad(lat1)).abs()
+}
+
+// the great circle distance between two points in meters, by the haversine
+// formula
+pub fn geo_distance(long1: f64, lat1: f64, long2: f64, lat2: f64) -> f64 {
+    let v = ((deg_rad(long2) - deg_rad(long1)) / 2.0).sin();
+    // on the same meridian the latitudes alone give the distance
+    if v == 0.0 {
+        return lat_distance(lat1, lat2);
+    }
+    let u = ((deg_rad(lat2) - deg_rad(lat1)) / 2.0).sin();
+    let a = u * u + deg_rad(lat1).cos() * deg_rad(lat2).cos() * v * v;
+    2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+}
+
+impl GeoShape {
+    // the distance from the center to the point, if the shape holds it
+    pub fn distance_if_within(&self, center: (f64, f64), point: (f64, f64)) -> Option<f64> {
+        match *self {
+            GeoShape::Radius(radius) => {
+                let distance = geo_distance(center.0, center.1, point.0, point.1);
+                (distance <= radius).then_some(distance)
+            }
+            GeoShape::Box { width, height } => {
+                // the latitude distance is the cheaper one, so check it first
+                if lat_distance(point.1, center.1) > height / 2.0 {
+                    return None;
+                }
+                if geo_distance(point.0, point.1, center.0, point.1) > width / 2.0 {
+                    return None;
+                }
+                Some(geo_distance(center.0, center.1, point.0, point.1))
+            }
+        }
+    }
+
+    // half the width and height of the shape
+    fn extent(&self) -> (f64, f64) {
+        match *self {
+            GeoShape::Radius(radius) => (radius, radius),
+            GeoShape::Box { width, height } => (width / 2.0, height / 2.0),
+        }
+    }
+
+    // the radius of a circle around the whole shape
+    fn radius(&self) -> f64 {
+        let (x, y) = self.extent();
+        match self {
+            GeoShape::Radius(radius) => *radius,
+            GeoShape::Box { .. } => (x * x + y * y).sqrt(),
+        }
+    }
+
+    // the (long_min, lat_min, long_max, lat_max) of a box around the shape
+    fn bounding_box(&self, (long, lat): (f64, f64)) -> (f64, f64, f64, f64) {
+        let (width, height) = self.extent();
+        let lat_delta = rad_deg(height / EARTH_RADIUS_IN_METERS);
+        // the box is widest along its edge nearer to the pole
+        let edge = if lat < 0.0 {
+            lat - lat_delta
+        } else {
+            lat + lat_delta
+        };
+        let long_delta = rad_deg(width / EARTH_RADIUS_IN_METERS / deg_rad(edge).cos());
+        (
+            long - long_delta,
+            lat - lat_delta,
+            long + long_delta,
+            lat + lat_delta,
+        )
+    }
+}
+
+// the coarsest precision whose cells are still about as large as the range
+fn estimate_step(range: f64, lat: f64) -> u8 {
+    if range == 0.0 {
+        return GEO_STEP_MAX;
+    }
+    let (mut range, mut step) = (range, 1i32);
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+    // leave room so the range is covered by the cell and its neighbors
+    step -= 2;
+    // cells narrow towards the poles
+    if !(-66.0..=66.0).contains(&lat) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&lat) {
+            step -= 1;
+        }
+    }
+    step.clamp(1, GEO_STEP_MAX as i32) as u8
+}
+
+// move a cell by dx cells east and dy cells north, wrapping around
+fn neighbor(hash: GeoCell, dx: i64, dy: i64) -> GeoCell {
+    let (lat, long) = deinterleave(hash.bits);
+    let mask = (1u64 << hash.step) - 1;
+    let lat = (lat as i64 + dy) as u64 & mask;
+    let long = (long as i64 + dx) as u64 & mask;
+    GeoCell {
+        bits: interleave(lat, long),
+        step: hash.step,
+    }
+}
+
+// the score ranges, each [min, max), holding every point that may be
+// inside the shape: the cell of the center and those of its eight
+// neighbors that reach into the shape's bounding box
+pub fn search_ranges(center: (f64, f64), shape: &GeoShape) -> Vec<(f64, f64)> {
+    let (long_min, lat_min, long_max, lat_max) = shape.bounding_box(center);
+    let mut step = estimate_step(shape.radius(), center.1);
+    let mut hash = encode(center.0, center.1, step);
+    // when the neighbors don't reach the bounding box, use cells twice as big
+    let reaches = |hash: GeoCell| {
+        decode(neighbor(hash, 0, 1)).lat_max >= lat_max
+            && decode(neighbor(hash, 0, -1)).lat_min <= lat_min
+            && decode(neighbor(hash, 1, 0)).long_max >= long_max
+            && decode(neighbor(hash, -1, 0)).long_min <= long_min
+    };
+    if step > 1 && !reaches(hash) {
+        step -= 1;
+        hash = encode(center.0, center.1, step);
+    }
+    let area = decode(hash);
+
+    // the center first, then north, south, east, west and the corners
+    let moves = [
+        (0, 0),
+        (0, 1),
+        (0, -1),
+        (1, 0),
+        (-1, 0),
+        (1, 1),
+        (-1, 1),
+        (1, -1),
+        (-1, -1),
+    ];
+    let mut cells: Vec<GeoCell> = Vec::with_capacity(moves.len());
+    for (dx, dy) in moves {
+        // skip the neighbors lying wholly outside the bounding box
+        if step >= 2
+            && ((dy < 0 && area.lat_min < lat_min)
+                || (dy > 0 && area.lat_max > lat_max)
+                || (dx < 0 && area.long_min < long_min)
+                || (dx > 0 && area.long_max > long_max))
+        {
+            continue;
+        }
+        // with huge shapes the wrapped neighbors can be the same cell
+        let cell = neighbor(hash, dx, dy);
+        if !cells.contains(&cell) {
+            cells.push(cell);
+        }
+    }
+    cells
+        .into_iter()
+        .map(|cell| {
+            let shift = 2 * (GEO_STEP_MAX - cell.step) as u32;
+            (
+                (cell.bits << shift) as f64,
+                ((cell.bits + 1) << shift) as f64,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        // Palermo, as stored by Redis
+        let score = geo_score(13.361389, 38.115556);
+        assert_eq!(score, 3479099956230698.0);
+        let (long, lat) = geo_point(score);
+        assert!((long - 13.361389).abs() < 1e-5);
+        assert!((lat - 38.115556).abs() < 1e-5);
+        assert_eq!(geohash_string(13.361389, 38.115556), "sqc8b49rny0");
+        assert_eq!(geohash_string(15.087269, 37.502669), "sqdtr74hyu0");
+
+        let (x, y) = (0x1234_5678, 0x0fed_cba9);
+        assert_eq!(deinterleave(interleave(x, y)), (x, y));
+        assert!(valid_coordinates(180.0, GEO_LAT_MAX));
+        assert!(!valid_coordinates(180.1, 0.0));
+        assert!(!valid_coordinates(0.0, 86.0));
+    }
+
+    #[test]
+    fn test_distance() {
+        // between the stored points, which is what GEODIST measures
+        let (a, b) = (
+            geo_point(geo_score(13.361389, 38.115556)),
+            geo_point(geo_score(15.087269, 37.502669)),
+        );
+        let d = geo_distance(a.0, a.1, b.0, b.1);
+        assert!((d - 166274.1516).abs() < 0.01);
+        assert_eq!(geo_distance(1.0, 2.0, 1.0, 2.0), 0.0);
+        let box_ = GeoShape::Box {
+            width: 400_000.0,
+            height: 400_000.0,
+        };
+        assert!(box_
+            .distance_if_within((15.0, 37.0), (13.361389, 38.115556))
+            .is_some());
+        assert!(GeoShape::Radius(100_000.0)
+            .distance_if_within((15.0, 37.0), (13.361389, 38.115556))
+            .is_none());
+    }
+
+    #[test]
+    fn test_search_ranges_cover_nearby_points() {
+        let center = (15.0, 37.0);
+        let shape = GeoShape::Radius(200_000.0);
+        let ranges = search_ranges(center, &shape);
+        assert!(!ranges.is_empty() && ranges.len() <= 9);
+        for point in [(13.361389, 38.115556), (15.087269, 37.502669), (16.5, 36.0)] {
+            if shape.distance_if_within(center, point).is_some() {
+                let score = geo_score(point.0, point.1);
+                assert!(ranges.iter().any(|&(min, max)| min <= score && score < max));
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// Redis glob patterns, as KEYS and the MATCH option of SCAN take them:
+//   *       any run of bytes, empty included
+//   ?       any single byte
+//   [abc]   one of the listed bytes; [^abc] any other, [a-z] a range
+//   \x      the byte x itself
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // where to resume after the last *: the pattern past it, and the byte
+    // of the string it is made to swallow next
+    let mut star: Option<(usize, usize)> = None;
+    while s < string.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            // a run of stars is the same as one
+            while p < pattern.len() && pattern[p] == b'*' {
+                p += 1;
+            }
+            if p == pattern.len() {
+                return true;
+            }
+            star = Some((p, s));
+            continue;
+        }
+        if let Some(next) = match_byte(pattern, p, string[s], nocase) {
+            p = next;
+            s += 1;
+            continue;
+        }
+        match star {
+            Some((star_p, star_s)) => {
+                p = star_p;
+                s = star_s + 1;
+                star = Some((star_p, s));
+            }
+            None => return false,
+        }
+    }
+    pattern[p.min(pattern.len())..].iter().all(|&b| b == b'*')
+}
+
+// match one byte against the pattern element at p, returning where the
+// next element starts
+fn match_byte(pattern: &[u8], p: usize, c: u8, nocase: bool) -> Option<usize> {
+    let eq = |a: u8, b: u8| {
+        if nocase {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+    match *pattern.get(p)? {
+        b'?' => Some(p + 1),
+        b'[' => {
+            let mut i = p + 1;
+            let negate = pattern.get(i) == Some(&b'^');
+            if negate {
+                i += 1;
+            }
+            let mut found = false;
+            // an unterminated class runs to the end of the pattern
+            while i < pattern.len() && pattern[i] != b']' {
+                if pattern[i] == b'\\' && i + 1 < pattern.len() {
+                    found |= eq(pattern[i + 1], c);
+                    i += 2;
+                } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' {
+                    let (mut lo, mut hi) = (pattern[i], pattern[i + 2]);
+                    if lo > hi {
+                        std::mem::swap(&mut lo, &mut hi);
+                    }
+                    let c = if nocase { c.to_ascii_lowercase() } else { c };
+                    let (lo, hi) = if nocase {
+                        (lo.to_ascii_lowercase(), hi.to_ascii_lowercase())
+                    } else {
+                        (lo, hi)
+                    };
+                    found |= (lo..=hi).contains(&c);
+                    i += 3;
+                } else {
+                    found |= eq(pattern[i], c);
+                    i += 1;
+                }
+            }
+            (found != negate).then_some((i + 1).min(pattern.len()))
+        }
+        b'\\' if p + 1 < pattern.len() => eq(pattern[p + 1], c).then_some(p + 2),
+        b => eq(b, c).then_some(p + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, string: &str) -> bool {
+        glob_match(pattern.as_bytes(), string.as_bytes(), false)
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("h?llo", "hello"));
+        assert!(!matches("h?llo", "hllo"));
+        assert!(matches("h*llo", "hllo"));
+        assert!(matches("h*llo", "heeeello"));
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+        assert!(matches("h[a-b]llo", "hbllo"));
+        assert!(matches("h[b-a]llo", "hallo"));
+        assert!(!matches("h[a-b]llo", "hcllo"));
+        assert!(matches("h\\*llo", "h*llo"));
+        assert!(!matches("h\\*llo", "hello"));
+        assert!(matches("user:*:name", "user:42:name"));
+        assert!(!matches("user:*:name", "user:42:email"));
+        assert!(matches("*a*b*", "xxaxxbxx"));
+        assert!(!matches("*a*b", "xxaxxbxxc"));
+        assert!(matches("a**", "a"));
+        assert!(!matches("abc", "ab"));
+        assert!(glob_match(b"HELLO", b"hello", true));
+        assert!(glob_match(b"[A-C]x", b"bx", true));
+    }
+}
@@ -0,0 +1,502 @@
+// HyperLogLog kept in a string value, byte for byte in the layout Redis uses
+// so values can be moved between the two:
+//
+//   +------+----------+-------------+------------------+
+//   | HYLL | encoding | 3 unused    | cached count, LE |   16 byte header
+//   +------+----------+-------------+------------------+
+//
+// followed either by 16384 packed 6 bit registers (dense), or by a run length
+// encoding of the registers (sparse) made of three opcodes:
+//
+//   ZERO   00xxxxxx           1..64 registers set to 0
+//   XZERO  01xxxxxx yyyyyyyy  1..16384 registers set to 0
+//   VAL    1vvvvvxx           1..4 registers set to 1..32
+//
+// New values start sparse and turn dense once a register needs more than 32
+// or the sparse form would outgrow SPARSE_MAX_BYTES.
+
+use thiserror::Error;
+
+const P: u32 = 14;
+pub const HLL_REGISTERS: usize = 1 << P;
+// bits of the hash left after taking out the register index
+const Q: u32 = 64 - P;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << REGISTER_BITS) - 1;
+
+const HEADER_SIZE: usize = 16;
+const DENSE_SIZE: usize = HEADER_SIZE + (HLL_REGISTERS * REGISTER_BITS).div_ceil(8);
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+// the cache is invalid while the top bit of its last byte is set
+const CACHE_INVALID: u8 = 0x80;
+// Redis' hll-sparse-max-bytes default
+const SPARSE_MAX_BYTES: usize = 3000;
+
+const ZERO_MAX_LEN: usize = 64;
+const VAL_MAX_VALUE: u8 = 32;
+const VAL_MAX_LEN: usize = 4;
+
+const HASH_SEED: u64 = 0xadc83b19;
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HllError {
+    #[error("not a HyperLogLog")]
+    NotHll,
+    #[error("corrupted HyperLogLog")]
+    Corrupted,
+}
+
+// one decoded sparse opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Zero(usize),
+    XZero(usize),
+    Val(u8, usize),
+}
+
+impl Op {
+    fn decode(bytes: &[u8], pos: usize) -> Result<Op, HllError> {
+        let b = bytes[pos];
+        Ok(match b >> 6 {
+            0 => Op::Zero((b & 0x3f) as usize + 1),
+            1 => {
+                let next = *bytes.get(pos + 1).ok_or(HllError::Corrupted)?;
+                Op::XZero(((b as usize & 0x3f) << 8 | next as usize) + 1)
+            }
+            _ => Op::Val(((b >> 2) & 0x1f) + 1, (b & 0x3) as usize + 1),
+        })
+    }
+
+    fn encode(self, out: &mut Vec<u8>) {
+        match self {
+            Op::Zero(len) => out.push((len - 1) as u8),
+            Op::XZero(len) => {
+                let len = len - 1;
+                out.push(0x40 | (len >> 8) as u8);
+                out.push(len as u8);
+            }
+            Op::Val(value, len) => out.push(0x80 | (value - 1) << 2 | (len - 1) as u8),
+        }
+    }
+
+    // the opcode for a run of zeros, the short form when it fits
+    fn zeros(len: usize) -> Op {
+        match len > ZERO_MAX_LEN {
+            true => Op::XZero(len),
+            false => Op::Zero(len),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Op::XZero(_) => 2,
+            _ => 1,
+        }
+    }
+
+    fn span(self) -> usize {
+        match self {
+            Op::Zero(len) | Op::XZero(len) | Op::Val(_, len) => len,
+        }
+    }
+}
+
+// MurmurHash64A, the hash Redis feeds its HyperLogLogs with
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().expect("8 byte chunk"));
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, &b) in tail.iter().enumerate() {
+            h ^= (b as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+// the register an element lands in, and the length of the run of zeros in
+// the rest of its hash plus one
+fn register_of(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, HASH_SEED);
+    let index = (hash & (HLL_REGISTERS as u64 - 1)) as usize;
+    let rest = hash >> P | 1 << Q;
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+fn dense_get(registers: &[u8], index: usize) -> u8 {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let b0 = registers[byte] as u16;
+    let b1 = registers.get(byte + 1).copied().unwrap_or(0) as u16;
+    ((b0 >> shift | b1 << (8 - shift)) & REGISTER_MAX as u16) as u8
+}
+
+fn dense_set(registers: &mut [u8], index: usize, value: u8) {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let word = (registers[byte] as u16
+        | (registers.get(byte + 1).copied().unwrap_or(0) as u16) << 8)
+        & !((REGISTER_MAX as u16) << shift)
+        | (value as u16) << shift;
+    registers[byte] = word as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next = (word >> 8) as u8;
+    }
+}
+
+// an empty HyperLogLog: sparse, every register zero, a valid cached count of 0
+pub fn hll_new() -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + 2);
+    bytes.extend_from_slice(b"HYLL");
+    bytes.push(SPARSE);
+    bytes.resize(HEADER_SIZE, 0);
+    Op::XZero(HLL_REGISTERS).encode(&mut bytes);
+    bytes
+}
+
+// whether a string has a HyperLogLog header; the registers are only checked
+// once they are read
+pub fn hll_check(bytes: &[u8]) -> Result<(), HllError> {
+    if bytes.len() < HEADER_SIZE || &bytes[..4] != b"HYLL" {
+        return Err(HllError::NotHll);
+    }
+    match bytes[4] {
+        DENSE if bytes.len() == DENSE_SIZE => Ok(()),
+        SPARSE => Ok(()),
+        _ => Err(HllError::NotHll),
+    }
+}
+
+fn is_dense(bytes: &[u8]) -> bool {
+    bytes[4] == DENSE
+}
+
+// decode the sparse opcodes, checking they cover exactly every register
+fn sparse_ops(bytes: &[u8]) -> Result<Vec<Op>, HllError> {
+    let mut ops = Vec::new();
+    let mut pos = HEADER_SIZE;
+    let mut covered = 0;
+    while pos < bytes.len() {
+        let op = Op::decode(bytes, pos)?;
+        pos += op.size();
+        covered += op.span();
+        ops.push(op);
+    }
+    if covered != HLL_REGISTERS {
+        return Err(HllError::Corrupted);
+    }
+    Ok(ops)
+}
+
+// raise every register of max to at least the value it has in bytes
+pub fn hll_merge(max: &mut [u8], bytes: &[u8]) -> Result<(), HllError> {
+    hll_check(bytes)?;
+    if is_dense(bytes) {
+        for (i, m) in max.iter_mut().enumerate() {
+            *m = (*m).max(dense_get(&bytes[HEADER_SIZE..], i));
+        }
+        return Ok(());
+    }
+    let mut index = 0;
+    for op in sparse_ops(bytes)? {
+        if let Op::Val(value, len) = op {
+            for m in &mut max[index..index + len] {
+                *m = (*m).max(value);
+            }
+        }
+        index += op.span();
+    }
+    Ok(())
+}
+
+// all registers of a HyperLogLog, decoded
+pub fn hll_registers(bytes: &[u8]) -> Result<Vec<u8>, HllError> {
+    let mut registers = vec![0; HLL_REGISTERS];
+    hll_merge(&mut registers, bytes)?;
+    Ok(registers)
+}
+
+fn to_dense(bytes: &mut Vec<u8>) -> Result<(), HllError> {
+    if is_dense(bytes) {
+        return Ok(());
+    }
+    let registers = hll_registers(bytes)?;
+    bytes.truncate(HEADER_SIZE);
+    bytes[4] = DENSE;
+    bytes.resize(DENSE_SIZE, 0);
+    for (i, &value) in registers.iter().enumerate() {
+        if value > 0 {
+            dense_set(&mut bytes[HEADER_SIZE..], i, value);
+        }
+    }
+    Ok(())
+}
+
+// raise one register to value, returning whether it changed. A sparse value
+// is edited in place the way Redis does it, splitting the opcode that covers
+// the register and merging equal neighbours back, so both produce the same bytes
+fn set_register(bytes: &mut Vec<u8>, index: usize, value: u8) -> Result<bool, HllError> {
+    if is_dense(bytes) {
+        let registers = &mut bytes[HEADER_SIZE..];
+        if dense_get(registers, index) >= value {
+            return Ok(false);
+        }
+        dense_set(registers, index, value);
+        return Ok(true);
+    }
+    if value > VAL_MAX_VALUE {
+        to_dense(bytes)?;
+        return set_register(bytes, index, value);
+    }
+
+    // find the opcode covering the register, and the one before it
+    let mut pos = HEADER_SIZE;
+    let mut prev = None;
+    let mut first = 0;
+    let op = loop {
+        if pos >= bytes.len() {
+            return Err(HllError::Corrupted);
+        }
+        let op = Op::decode(bytes, pos)?;
+        if index < first + op.span() {
+            break op;
+        }
+        prev = Some(pos);
+        pos += op.size();
+        first += op.span();
+    };
+
+    let last = first + op.span() - 1;
+    let mut seq = Vec::with_capacity(5);
+    match op {
+        Op::Val(old, _) if old >= value => return Ok(false),
+        // a run of one register is simply overwritten
+        Op::Val(_, 1) | Op::Zero(1) => Op::Val(value, 1).encode(&mut seq),
+        Op::Val(old, _) => {
+            if index != first {
+                Op::Val(old, index - first).encode(&mut seq);
+            }
+            Op::Val(value, 1).encode(&mut seq);
+            if index != last {
+                Op::Val(old, last - index).encode(&mut seq);
+            }
+        }
+        Op::Zero(_) | Op::XZero(_) => {
+            if index != first {
+                Op::zeros(index - first).encode(&mut seq);
+            }
+            Op::Val(value, 1).encode(&mut seq);
+            if index != last {
+                Op::zeros(last - index).encode(&mut seq);
+            }
+        }
+    }
+    if seq.len() > op.size() && bytes.len() + seq.len() - op.size() > SPARSE_MAX_BYTES {
+        to_dense(bytes)?;
+        return set_register(bytes, index, value);
+    }
+    bytes.splice(pos..pos + op.size(), seq);
+
+    // merge adjacent VAL opcodes holding the same value, scanning up to five
+    // opcodes from the one before the edit
+    let mut pos = prev.unwrap_or(HEADER_SIZE);
+    let mut scan = 5;
+    while pos < bytes.len() && scan > 0 {
+        scan -= 1;
+        let op = Op::decode(bytes, pos)?;
+        let next = match bytes.get(pos + 1) {
+            Some(&next) if next & 0x80 != 0 => Some(Op::decode(&[next], 0)?),
+            _ => None,
+        };
+        if let (Op::Val(v1, l1), Some(Op::Val(v2, l2))) = (op, next) {
+            if v1 == v2 && l1 + l2 <= VAL_MAX_LEN {
+                let mut merged = Vec::with_capacity(1);
+                Op::Val(v1, l1 + l2).encode(&mut merged);
+                bytes.splice(pos..pos + 2, merged);
+                // try the merged run against its right neighbour too
+                continue;
+            }
+        }
+        pos += op.size();
+    }
+    Ok(true)
+}
+
+fn invalidate_cache(bytes: &mut [u8]) {
+    bytes[15] |= CACHE_INVALID;
+}
+
+// add an element, returning whether the estimate may have changed
+pub fn hll_add(bytes: &mut Vec<u8>, element: &[u8]) -> Result<bool, HllError> {
+    hll_check(bytes)?;
+    let (index, count) = register_of(element);
+    let changed = set_register(bytes, index, count)?;
+    if changed {
+        invalidate_cache(bytes);
+    }
+    Ok(changed)
+}
+
+// raise the registers of a HyperLogLog to those of max, making it dense
+// first if asked to
+pub fn hll_store(bytes: &mut Vec<u8>, max: &[u8], dense: bool) -> Result<(), HllError> {
+    hll_check(bytes)?;
+    if dense {
+        to_dense(bytes)?;
+    }
+    for (i, &value) in max.iter().enumerate() {
+        if value > 0 {
+            set_register(bytes, i, value)?;
+        }
+    }
+    invalidate_cache(bytes);
+    Ok(())
+}
+
+pub fn hll_is_dense(bytes: &[u8]) -> Result<bool, HllError> {
+    hll_check(bytes)?;
+    Ok(is_dense(bytes))
+}
+
+// the cardinality of a single HyperLogLog, from the cached count when it is
+// valid, otherwise estimated and cached
+pub fn hll_count(bytes: &mut [u8]) -> Result<u64, HllError> {
+    hll_check(bytes)?;
+    if bytes[15] & CACHE_INVALID == 0 {
+        let cached: [u8; 8] = bytes[8..16].try_into().expect("8 byte cache");
+        return Ok(u64::from_le_bytes(cached));
+    }
+    let count = hll_estimate(&hll_registers(bytes)?);
+    bytes[8..16].copy_from_slice(&count.to_le_bytes());
+    Ok(count)
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let prev = z;
+        z += x * y;
+        y += y;
+        if prev == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if prev == z {
+            return z / 3.0;
+        }
+    }
+}
+
+// estimate a cardinality from registers with the improved estimator of
+// Otmar Ertl, the one Redis uses, good to about 0.81%
+pub fn hll_estimate(registers: &[u8]) -> u64 {
+    let m = HLL_REGISTERS as f64;
+    let mut histogram = [0u32; 64];
+    for &r in registers {
+        histogram[r as usize] += 1;
+    }
+    let q = Q as usize;
+    let mut z = m * tau((m - histogram[q + 1] as f64) / m);
+    for j in (1..=q).rev() {
+        z += histogram[j] as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_layout() {
+        let mut hll = hll_new();
+        assert_eq!(&hll[HEADER_SIZE..], [0x7f, 0xff]);
+        assert_eq!(hll_count(&mut hll), Ok(0));
+
+        assert_eq!(set_register(&mut hll, 100, 1), Ok(true));
+        assert_eq!(set_register(&mut hll, 100, 1), Ok(false));
+        // XZERO(100) VAL(1, 1) XZERO(16283)
+        assert_eq!(&hll[HEADER_SIZE..], [0x40, 99, 0x80, 0x7f, 0x9a]);
+        // the neighbour joins the run
+        assert_eq!(set_register(&mut hll, 101, 1), Ok(true));
+        assert_eq!(&hll[HEADER_SIZE..], [0x40, 99, 0x81, 0x7f, 0x99]);
+        assert_eq!(set_register(&mut hll, 0, 3), Ok(true));
+        // VAL(3, 1) XZERO(99) VAL(1, 2) XZERO(16282)
+        assert_eq!(&hll[HEADER_SIZE..], [0x88, 0x40, 98, 0x81, 0x7f, 0x99]);
+
+        let registers = hll_registers(&hll).unwrap();
+        assert_eq!((registers[0], registers[100], registers[101]), (3, 1, 1));
+        assert_eq!(registers.iter().filter(|&&r| r > 0).count(), 3);
+
+        // a value over 32 needs the dense form
+        assert_eq!(set_register(&mut hll, 5000, 40), Ok(true));
+        assert_eq!(hll.len(), DENSE_SIZE);
+        let registers = hll_registers(&hll).unwrap();
+        assert_eq!((registers[0], registers[101], registers[5000]), (3, 1, 40));
+        assert_eq!(registers.iter().filter(|&&r| r > 0).count(), 4);
+    }
+
+    #[test]
+    fn test_estimate_error() {
+        let mut hll = hll_new();
+        let n = 100_000u64;
+        for i in 0..n {
+            hll_add(&mut hll, format!("element:{}", i).as_bytes()).unwrap();
+        }
+        assert!(is_dense(&hll));
+        let count = hll_count(&mut hll).unwrap() as f64;
+        // within a few standard errors of 0.81%
+        assert!((count - n as f64).abs() / (n as f64) < 0.03, "{}", count);
+        assert_eq!(
+            hll_registers(&hll).map(|r| hll_estimate(&r)),
+            Ok(count as u64)
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(hll_check(b"HYLL"), Err(HllError::NotHll));
+        let mut bad = hll_new();
+        bad[4] = DENSE;
+        assert_eq!(hll_check(&bad), Err(HllError::NotHll));
+        let mut short = hll_new();
+        short.pop();
+        assert_eq!(hll_registers(&short), Err(HllError::Corrupted));
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+mod blocking;
+mod consumer_group;
+mod db;
+mod dict;
+mod expires;
+mod function;
+mod geohash;
+mod glob;
+mod hyperloglog;
+mod pubsub;
+mod script;
+mod skiplist;
+mod slot;
+mod stream;
+mod value;
+mod watch;
+mod zset;
+
+use crate::cmd::Context;
+
+pub use blocking::*;
+pub use consumer_group::*;
+pub use db::*;
+pub use dict::*;
+pub use expires::*;
+pub use function::*;
+pub use geohash::*;
+pub use glob::*;
+pub use hyperloglog::*;
+pub use pubsub::*;
+pub use script::*;
+pub use slot::*;
+pub use stream::*;
+pub use value::*;
+pub use watch::*;
+pub use zset::*;
+
+// the shared server state; every connection holds a clone and runs its
+// commands while holding the lock, which keeps each command atomic. The
+// running script is shared outside of it, as a script holds the lock
+#[derive(Debug, Clone)]
+pub struct Backend {
+    inner: Arc<Mutex<BackendInner>>,
+    running_script: Arc<RunningScript>,
+}
+
+// how often a connection waiting for a script to give the lock back checks
+// again, and whether the server is busy meanwhile
+const SCRIPT_WAIT_INTERVAL: Duration = Duration::from_millis(1);
+
+// how many databases there are unless configured otherwise
+pub const DEFAULT_DATABASES: usize = 16;
+
+#[derive(Debug)]
+pub struct BackendInner {
+    // the logical databases SELECT picks from, by index
+    pub dbs: Vec<Db>,
+    pub blocking: Blocking,
+    pub watches: Watches,
+    pub subscriptions: Subscriptions,
+    pub scripts: Scripts,
+    pub functions: Functions,
+    pub running_script: Arc<RunningScript>,
+}
+
+impl Default for BackendInner {
+    fn default() -> Self {
+        BackendInner::new(DEFAULT_DATABASES)
+    }
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::new(DEFAULT_DATABASES, DEFAULT_BUSY_REPLY_THRESHOLD)
+    }
+}
+
+impl Backend {
+    pub fn new(databases: usize, busy_reply_threshold: Duration) -> Self {
+        let running_script = Arc::new(RunningScript::new(busy_reply_threshold));
+        let mut inner = BackendInner::new(databases);
+        inner.running_script = running_script.clone();
+        Backend {
+            inner: Arc::new(Mutex::new(inner)),
+            running_script,
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, BackendInner> {
+        // a panic inside one command must not take the whole server down
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // the lock if nobody holds it
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, BackendInner>> {
+        match self.inner.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    // the lock, for async code: a script may hold it for long, and waiting
+    // for it must not tie up a runtime thread other connections need
+    pub async fn lock_async(&self) -> MutexGuard<'_, BackendInner> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            self.wait_lock().await;
+        }
+    }
+
+    // a pause before trying the lock again
+    pub async fn wait_lock(&self) {
+        match self.running_script.is_running() {
+            true => tokio::time::sleep(SCRIPT_WAIT_INTERVAL).await,
+            false => tokio::task::yield_now().await,
+        }
+    }
+
+    pub fn running_script(&self) -> &RunningScript {
+        &self.running_script
+    }
+}
+
+// how often the active expiry cycle runs, and how long each run may hold the
+// lock: a quarter of the interval, as in Redis
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+const ACTIVE_EXPIRE_BUDGET: Duration = Duration::from_millis(25);
+
+// the background task deleting expired keys nobody reads anymore
+pub async fn active_expire(backend: Backend) {
+    let mut interval = tokio::time::interval(ACTIVE_EXPIRE_INTERVAL);
+    loop {
+        interval.tick().await;
+        // with the lock taken, most likely by a script, try again next tick
+        if let Some(mut inner) = backend.try_lock() {
+            inner.active_expire_cycle();
+        }
+    }
+}
+
+impl BackendInner {
+    pub fn new(databases: usize) -> Self {
+        BackendInner {
+            dbs: (0..databases).map(|_| Db::new()).collect(),
+            blocking: Blocking::default(),
+            watches: Watches::default(),
+            subscriptions: Subscriptions::default(),
+            scripts: Scripts::default(),
+            functions: Functions::default(),
+            running_script: Arc::default(),
+        }
+    }
+
+    // forget what a client that went away watched and subscribed to
+    pub fn disconnect(&mut self, client: u64) {
+        self.watches.unwatch_all(client);
+        self.subscriptions.remove_client(client);
+    }
+
+    // the databases share the time budget, each getting what the ones
+    // before it left
+    pub fn active_expire_cycle(&mut self) -> usize {
+        let start = Instant::now();
+        let mut deleted = 0;
+        for db in &mut self.dbs {
+            let budget = ACTIVE_EXPIRE_BUDGET.saturating_sub(start.elapsed());
+            if budget.is_zero() {
+                break;
+            }
+            deleted += db.active_expire_cycle(budget);
+        }
+        // the deletions count as writes; drain them like any command does
+        self.handle_touched_keys();
+        deleted
+    }
+
+    // react to the keys written by the last command: break the WATCH of the
+    // clients watching them, then serve clients blocked on them, the oldest
+    // waiter of each key first. Serving may write more keys (BLMOVE pushes to
+    // its destination), so keep going until nothing is left to wake
+    pub fn handle_touched_keys(&mut self) {
+        loop {
+            let mut ready = Vec::new();
+            for (index, db) in self.dbs.iter_mut().enumerate() {
+                ready.extend(db.take_touched().into_iter().map(|key| (index, key)));
+            }
+            if !self.watches.is_empty() {
+                for (index, key) in &ready {
+                    self.watches.touch(*index, key);
+                }
+            }
+            if self.blocking.is_empty() {
+                return;
+            }
+            ready.retain(|(index, key)| self.blocking.is_blocked_on(*index, key));
+            if ready.is_empty() {
+                return;
+            }
+            let mut seen = HashSet::new();
+            ready.retain(|ready| seen.insert(ready.clone()));
+            for (index, key) in ready {
+                self.serve_key(index, &key);
+            }
+        }
+    }
+
+    fn serve_key(&mut self, index: usize, key: &[u8]) {
+        for id in self.blocking.queue(index, key) {
+            let mut waiter = match self.blocking.take(id) {
+                Some(waiter) => waiter,
+                None => continue,
+            };
+            // the connection went away, don't hand it data it can't receive
+            if waiter.reply.is_closed() {
+                self.blocking.dequeue(id, waiter.client.db, &waiter.keys);
+                continue;
+            }
+            let result = {
+                let mut ctx = Context::new(&mut waiter.client, self);
+                waiter.command.try_serve(&mut ctx)
+            };
+            let reply = match result {
+                Ok(Some(reply)) => reply,
+                Ok(None) => {
+                    self.blocking.restore(id, waiter);
+                    continue;
+                }
+                Err(e) => e.into(),
+            };
+            self.blocking.dequeue(id, waiter.client.db, &waiter.keys);
+            let _ = waiter.reply.send(reply);
+        }
+    }
+}
+
+// current unix time in milliseconds, the unit every expire time is kept in
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,456 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+
+use super::{glob_match, key_hash_slot};
+
+// what a subscription is to: a channel by name, every channel matching a
+// glob pattern, or a sharded channel, which lives in the hash slot of its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionKind {
+    Channel,
+    Pattern,
+    ShardChannel,
+}
+
+// a published message on its way to one subscriber; the connection turns it
+// into a frame of the protocol it speaks at the time it writes it out
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    // the pattern the subscriber matched the channel with, if any
+    pub pattern: Option<Vec<u8>>,
+    // published with SPUBLISH to a sharded channel
+    pub sharded: bool,
+    pub channel: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    // what the message weighs in its subscriber's queue
+    fn size(&self) -> usize {
+        self.pattern.as_ref().map_or(0, Vec::len) + self.channel.len() + self.payload.len()
+    }
+}
+
+// how many bytes of messages may wait for a subscriber before it is cut off
+// for reading them too slowly, Redis' default hard client-output-buffer-limit
+// for pub/sub clients
+pub const PUBSUB_QUEUE_LIMIT: usize = 32 * 1024 * 1024;
+
+// a client's queue of published messages, bounded in bytes: a subscriber
+// that lets it grow past limit is disconnected, rather than the server
+// keeping every message for it
+pub fn message_queue(limit: usize) -> (MessageSender, MessageReceiver) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let size = Arc::new(QueueSize::default());
+    (
+        MessageSender {
+            tx,
+            size: size.clone(),
+            limit,
+        },
+        MessageReceiver { rx, size },
+    )
+}
+
+#[derive(Debug, Default)]
+struct QueueSize {
+    bytes: AtomicUsize,
+    overflowed: AtomicBool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageSender {
+    tx: mpsc::UnboundedSender<Message>,
+    size: Arc<QueueSize>,
+    limit: usize,
+}
+
+impl MessageSender {
+    // queue a message, unless the queue overflowed already. The one that
+    // overflows it still goes in, to wake the connection, which then finds
+    // out it has to go
+    fn send(&self, message: Message) {
+        if self.size.overflowed.load(Ordering::SeqCst) {
+            return;
+        }
+        let size = message.size();
+        if self.size.bytes.fetch_add(size, Ordering::SeqCst) + size > self.limit {
+            self.size.overflowed.store(true, Ordering::SeqCst);
+        }
+        // a closed connection is removed by its own task, nothing to do here
+        let _ = self.tx.send(message);
+    }
+}
+
+#[derive(Debug)]
+pub struct MessageReceiver {
+    rx: mpsc::UnboundedReceiver<Message>,
+    size: Arc<QueueSize>,
+}
+
+impl MessageReceiver {
+    // the next message; None once the queue overflowed, and the client is
+    // to be disconnected
+    pub async fn recv(&mut self) -> Option<Message> {
+        let message = self.rx.recv().await?;
+        self.take(message)
+    }
+
+    pub fn try_recv(&mut self) -> Result<Message, TryRecvError> {
+        let message = self.rx.try_recv()?;
+        self.take(message).ok_or(TryRecvError::Disconnected)
+    }
+
+    fn take(&self, message: Message) -> Option<Message> {
+        if self.size.overflowed.load(Ordering::SeqCst) {
+            return None;
+        }
+        self.size.bytes.fetch_sub(message.size(), Ordering::SeqCst);
+        Some(message)
+    }
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    // where to deliver; None for a client without a connection to write to
+    tx: Option<MessageSender>,
+    // in the order they were subscribed to, as UNSUBSCRIBE without arguments
+    // replies in that order
+    channels: Vec<Vec<u8>>,
+    patterns: Vec<Vec<u8>>,
+    shard_channels: Vec<Vec<u8>>,
+}
+
+impl Subscriber {
+    fn subscriptions(&mut self, kind: SubscriptionKind) -> &mut Vec<Vec<u8>> {
+        match kind {
+            SubscriptionKind::Channel => &mut self.channels,
+            SubscriptionKind::Pattern => &mut self.patterns,
+            SubscriptionKind::ShardChannel => &mut self.shard_channels,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.channels.is_empty() && self.patterns.is_empty() && self.shard_channels.is_empty()
+    }
+}
+
+// the clients subscribed to each channel or pattern
+type Subscribers = HashMap<Vec<u8>, HashSet<u64>>;
+
+// the pub/sub subscriptions of every client, by channel, by pattern, and by
+// hash slot then sharded channel: keeping the channels of a slot together
+// lets a cluster hand them over along with the slot
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    channels: Subscribers,
+    patterns: Subscribers,
+    shard_channels: HashMap<u16, Subscribers>,
+    subscribers: HashMap<u64, Subscriber>,
+}
+
+impl Subscriptions {
+    // the subscribers of the targets of a kind that live where target does
+    fn by_target(&mut self, kind: SubscriptionKind, target: &[u8]) -> &mut Subscribers {
+        match kind {
+            SubscriptionKind::Channel => &mut self.channels,
+            SubscriptionKind::Pattern => &mut self.patterns,
+            SubscriptionKind::ShardChannel => self
+                .shard_channels
+                .entry(key_hash_slot(target))
+                .or_default(),
+        }
+    }
+
+    fn subscribers_of(&self, kind: SubscriptionKind, target: &[u8]) -> Option<&HashSet<u64>> {
+        match kind {
+            SubscriptionKind::Channel => self.channels.get(target),
+            SubscriptionKind::Pattern => self.patterns.get(target),
+            SubscriptionKind::ShardChannel => self
+                .shard_channels
+                .get(&key_hash_slot(target))
+                .and_then(|channels| channels.get(target)),
+        }
+    }
+
+    // subscribe client, returning false when it already was
+    pub fn subscribe(
+        &mut self,
+        client: u64,
+        tx: Option<&MessageSender>,
+        kind: SubscriptionKind,
+        target: &[u8],
+    ) -> bool {
+        if !self
+            .by_target(kind, target)
+            .entry(target.to_vec())
+            .or_default()
+            .insert(client)
+        {
+            return false;
+        }
+        let subscriber = self
+            .subscribers
+            .entry(client)
+            .or_insert_with(|| Subscriber {
+                tx: tx.cloned(),
+                channels: Vec::new(),
+                patterns: Vec::new(),
+                shard_channels: Vec::new(),
+            });
+        subscriber.subscriptions(kind).push(target.to_vec());
+        true
+    }
+
+    // unsubscribe client, returning false when it was not subscribed
+    pub fn unsubscribe(&mut self, client: u64, kind: SubscriptionKind, target: &[u8]) -> bool {
+        let subscribed = self
+            .subscribers_of(kind, target)
+            .is_some_and(|clients| clients.contains(&client));
+        if !subscribed {
+            return false;
+        }
+        let targets = self.by_target(kind, target);
+        if let Some(clients) = targets.get_mut(target) {
+            clients.remove(&client);
+            if clients.is_empty() {
+                targets.remove(target);
+            }
+        }
+        if kind == SubscriptionKind::ShardChannel {
+            let slot = key_hash_slot(target);
+            if self
+                .shard_channels
+                .get(&slot)
+                .is_some_and(HashMap::is_empty)
+            {
+                self.shard_channels.remove(&slot);
+            }
+        }
+        if let Some(subscriber) = self.subscribers.get_mut(&client) {
+            subscriber.subscriptions(kind).retain(|t| t != target);
+            if subscriber.is_empty() {
+                self.subscribers.remove(&client);
+            }
+        }
+        true
+    }
+
+    // what client is subscribed to, in the order it subscribed
+    pub fn client_targets(&self, client: u64, kind: SubscriptionKind) -> Vec<Vec<u8>> {
+        match self.subscribers.get(&client) {
+            Some(subscriber) => match kind {
+                SubscriptionKind::Channel => subscriber.channels.clone(),
+                SubscriptionKind::Pattern => subscriber.patterns.clone(),
+                SubscriptionKind::ShardChannel => subscriber.shard_channels.clone(),
+            },
+            None => Vec::new(),
+        }
+    }
+
+    // the subscription count the confirmations of a kind report: channels
+    // and patterns are counted together, sharded channels on their own
+    pub fn count(&self, client: u64, kind: SubscriptionKind) -> usize {
+        self.subscribers.get(&client).map_or(0, |s| match kind {
+            SubscriptionKind::ShardChannel => s.shard_channels.len(),
+            _ => s.channels.len() + s.patterns.len(),
+        })
+    }
+
+    pub fn is_subscribed(&self, client: u64) -> bool {
+        self.subscribers.contains_key(&client)
+    }
+
+    // forget a client that went away
+    pub fn remove_client(&mut self, client: u64) {
+        for kind in [
+            SubscriptionKind::Channel,
+            SubscriptionKind::Pattern,
+            SubscriptionKind::ShardChannel,
+        ] {
+            for target in self.client_targets(client, kind) {
+                self.unsubscribe(client, kind, &target);
+            }
+        }
+    }
+
+    // deliver payload to the subscribers of channel and of every pattern
+    // matching it, returning how many deliveries that made
+    pub fn publish(&self, channel: &[u8], payload: &[u8]) -> usize {
+        let mut receivers = 0;
+        if let Some(clients) = self.channels.get(channel) {
+            for client in clients {
+                self.deliver(*client, None, false, channel, payload);
+                receivers += 1;
+            }
+        }
+        for (pattern, clients) in &self.patterns {
+            if !glob_match(pattern, channel, false) {
+                continue;
+            }
+            for client in clients {
+                self.deliver(*client, Some(pattern), false, channel, payload);
+                receivers += 1;
+            }
+        }
+        receivers
+    }
+
+    // deliver payload to the subscribers of a sharded channel, looked up in
+    // the channel's slot alone; patterns never match sharded channels
+    pub fn spublish(&self, channel: &[u8], payload: &[u8]) -> usize {
+        let Some(clients) = self.subscribers_of(SubscriptionKind::ShardChannel, channel) else {
+            return 0;
+        };
+        for client in clients {
+            self.deliver(*client, None, true, channel, payload);
+        }
+        clients.len()
+    }
+
+    fn deliver(
+        &self,
+        client: u64,
+        pattern: Option<&Vec<u8>>,
+        sharded: bool,
+        channel: &[u8],
+        payload: &[u8],
+    ) {
+        let tx = self.subscribers.get(&client).and_then(|s| s.tx.as_ref());
+        if let Some(tx) = tx {
+            tx.send(Message {
+                pattern: pattern.cloned(),
+                sharded,
+                channel: channel.to_vec(),
+                payload: payload.to_vec(),
+            });
+        }
+    }
+
+    // the channels of a kind with at least one subscriber; patterns are not
+    // channels, there are never any of those
+    pub fn channels(&self, kind: SubscriptionKind) -> Vec<&Vec<u8>> {
+        match kind {
+            SubscriptionKind::Channel => self.channels.keys().collect(),
+            SubscriptionKind::Pattern => Vec::new(),
+            SubscriptionKind::ShardChannel => self
+                .shard_channels
+                .values()
+                .flat_map(HashMap::keys)
+                .collect(),
+        }
+    }
+
+    pub fn num_subscribers(&self, kind: SubscriptionKind, channel: &[u8]) -> usize {
+        self.subscribers_of(kind, channel).map_or(0, HashSet::len)
+    }
+
+    // the number of distinct patterns subscribed to
+    pub fn num_patterns(&self) -> usize {
+        self.patterns.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_to_channels_and_patterns() {
+        let mut subs = Subscriptions::default();
+        let (tx, mut rx) = message_queue(PUBSUB_QUEUE_LIMIT);
+        assert!(subs.subscribe(1, Some(&tx), SubscriptionKind::Channel, b"news"));
+        assert!(!subs.subscribe(1, Some(&tx), SubscriptionKind::Channel, b"news"));
+        assert!(subs.subscribe(1, Some(&tx), SubscriptionKind::Pattern, b"n*"));
+        assert!(subs.subscribe(2, None, SubscriptionKind::Channel, b"news"));
+        assert_eq!(subs.count(1, SubscriptionKind::Channel), 2);
+        assert_eq!(subs.num_subscribers(SubscriptionKind::Channel, b"news"), 2);
+        assert_eq!(subs.num_patterns(), 1);
+
+        assert_eq!(subs.publish(b"news", b"hi"), 3);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Message {
+                pattern: None,
+                sharded: false,
+                channel: b"news".to_vec(),
+                payload: b"hi".to_vec(),
+            }
+        );
+        assert_eq!(rx.try_recv().unwrap().pattern, Some(b"n*".to_vec()));
+        assert!(rx.try_recv().is_err());
+        assert_eq!(subs.publish(b"other", b"hi"), 0);
+
+        assert!(subs.unsubscribe(1, SubscriptionKind::Channel, b"news"));
+        assert!(!subs.unsubscribe(1, SubscriptionKind::Channel, b"news"));
+        assert!(subs.is_subscribed(1));
+        subs.remove_client(1);
+        subs.remove_client(2);
+        assert!(!subs.is_subscribed(1));
+        assert_eq!(subs.num_patterns(), 0);
+        assert!(subs.channels(SubscriptionKind::Channel).is_empty());
+    }
+
+    #[test]
+    fn test_sharded_channels() {
+        let mut subs = Subscriptions::default();
+        let (tx, mut rx) = message_queue(PUBSUB_QUEUE_LIMIT);
+        subs.subscribe(
+            1,
+            Some(&tx),
+            SubscriptionKind::ShardChannel,
+            b"{tenant1}.orders",
+        );
+        subs.subscribe(
+            1,
+            Some(&tx),
+            SubscriptionKind::ShardChannel,
+            b"{tenant1}.users",
+        );
+        subs.subscribe(1, Some(&tx), SubscriptionKind::Pattern, b"*");
+        assert_eq!(subs.count(1, SubscriptionKind::ShardChannel), 2);
+        assert_eq!(subs.count(1, SubscriptionKind::Channel), 1);
+        // both channels share the slot of their hash tag
+        let slot = key_hash_slot(b"tenant1");
+        assert_eq!(subs.shard_channels.len(), 1);
+        assert_eq!(subs.shard_channels[&slot].len(), 2);
+
+        // PUBLISH and SPUBLISH don't cross over
+        assert_eq!(subs.publish(b"{tenant1}.orders", b"x"), 1);
+        assert_eq!(rx.try_recv().unwrap().pattern, Some(b"*".to_vec()));
+        assert_eq!(subs.spublish(b"{tenant1}.orders", b"new"), 1);
+        let message = rx.try_recv().unwrap();
+        assert!(message.sharded);
+        assert_eq!(message.pattern, None);
+        assert_eq!(subs.spublish(b"{tenant2}.orders", b"new"), 0);
+        assert!(rx.try_recv().is_err());
+
+        assert_eq!(subs.channels(SubscriptionKind::ShardChannel).len(), 2);
+        assert!(subs.unsubscribe(1, SubscriptionKind::ShardChannel, b"{tenant1}.orders"));
+        subs.remove_client(1);
+        assert!(subs.shard_channels.is_empty());
+        assert!(!subs.is_subscribed(1));
+    }
+
+    #[test]
+    fn test_message_queue_limit() {
+        let mut subs = Subscriptions::default();
+        let (tx, mut rx) = message_queue(10);
+        subs.subscribe(1, Some(&tx), SubscriptionKind::Channel, b"news");
+        // messages read in time free their room in the queue
+        for _ in 0..3 {
+            subs.publish(b"news", b"12345");
+            assert_eq!(rx.try_recv().unwrap().payload, b"12345".to_vec());
+        }
+        // left unread, they fill it up, and the subscriber is cut off
+        subs.publish(b"news", b"1");
+        subs.publish(b"news", b"12345");
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        subs.publish(b"news", b"1");
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+}
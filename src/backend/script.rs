@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use mlua::{Lua, LuaOptions, StdLib, Table, Variadic};
+
+use super::now_ms;
+
+// how long a script may hold the server before other clients are told it is
+// busy, unless configured otherwise
+pub const DEFAULT_BUSY_REPLY_THRESHOLD: Duration = Duration::from_secs(5);
+
+// the named registry values of the environment scripts and libraries run
+// in, and of the redis table behind the read-only one they see
+const SANDBOX_KEY: &str = "sandbox";
+const REDIS_KEY: &str = "redis";
+
+// run once in a fresh interpreter, after the redis table is in place, and
+// returns the environment code runs in. Everything the code can reach is read
+// only: the interpreter outlives the code, so anything it changed would be
+// seen by every script or library after it. Each table among the globals is
+// swapped for an empty proxy reading through to it, with its metatable
+// hidden, and the functions writing tables raw refuse the proxies. Reading a
+// global nobody set, or setting one, almost always is a typo
+const SANDBOX: &str = r#"
+local globals = _G
+local proxies = {}
+local raw_set = rawset
+
+local function refuse_proxy(t)
+    if proxies[t] then
+        error("Attempt to modify a readonly table", 3)
+    end
+end
+
+local function readonly(t)
+    local proxy = setmetatable({}, {
+        __index = t,
+        __newindex = function()
+            error("Attempt to modify a readonly table", 2)
+        end,
+        __metatable = false,
+    })
+    proxies[proxy] = true
+    return proxy
+end
+
+rawset = function(t, k, v)
+    refuse_proxy(t)
+    return raw_set(t, k, v)
+end
+for _, name in ipairs({"insert", "remove", "sort", "setn"}) do
+    local f = table[name]
+    if f then
+        table[name] = function(t, ...)
+            refuse_proxy(t)
+            return f(t, ...)
+        end
+    end
+end
+for name, value in pairs(globals) do
+    if type(value) == "table" and name ~= "_G" then
+        globals[name] = readonly(value)
+    end
+end
+
+local string_meta = getmetatable("")
+string_meta.__index = globals.string
+string_meta.__metatable = false
+
+local env = setmetatable({}, {
+    __newindex = function(_, name)
+        if globals[name] ~= nil then
+            error("Attempt to modify a readonly table", 2)
+        end
+        error("Script attempted to create global variable '" .. tostring(name) .. "'", 2)
+    end,
+    __index = function(_, name)
+        local value = globals[name]
+        if value == nil then
+            error("Script attempted to access nonexistent global variable '" .. tostring(name) .. "'", 2)
+        end
+        return value
+    end,
+    __metatable = false,
+})
+proxies[env] = true
+globals._G = env
+return env
+"#;
+
+// the scripts EVAL and SCRIPT LOAD were given, by the SHA1 of their body,
+// and the Lua interpreter they run in
+#[derive(Debug, Default)]
+pub struct Scripts {
+    bodies: HashMap<String, Vec<u8>>,
+    // created on first use, and lent out to the script that is running
+    lua: Option<Lua>,
+}
+
+impl Scripts {
+    // remember a script, returning the SHA1 EVALSHA knows it by
+    pub fn load(&mut self, body: Vec<u8>) -> String {
+        let sha = sha1_hex(&body);
+        self.bodies.entry(sha.clone()).or_insert(body);
+        sha
+    }
+
+    pub fn get(&self, sha: &str) -> Option<&Vec<u8>> {
+        self.bodies.get(&sha.to_ascii_lowercase())
+    }
+
+    pub fn contains(&self, sha: &str) -> bool {
+        self.get(sha).is_some()
+    }
+
+    // forget every script, and start the next one in a fresh interpreter
+    pub fn flush(&mut self) {
+        self.bodies.clear();
+        self.lua = None;
+    }
+
+    // the interpreter, for the script about to run; give it back with put_lua
+    pub fn take_lua(&mut self) -> mlua::Result<Lua> {
+        match self.lua.take() {
+            Some(lua) => Ok(lua),
+            None => new_lua(),
+        }
+    }
+
+    pub fn put_lua(&mut self, lua: Lua) {
+        self.lua = Some(lua);
+    }
+}
+
+// the script running right now, if any. It lives outside the server lock the
+// script holds, so that other connections can tell the server is busy and
+// SCRIPT KILL can reach the script
+#[derive(Debug)]
+pub struct RunningScript {
+    busy_threshold: Duration,
+    // when the script started, in unix milliseconds; 0 while none runs
+    started: AtomicU64,
+    // whether it ran a write command, after which it can't be killed
+    wrote: AtomicBool,
+    killed: AtomicBool,
+}
+
+impl Default for RunningScript {
+    fn default() -> Self {
+        RunningScript::new(DEFAULT_BUSY_REPLY_THRESHOLD)
+    }
+}
+
+impl RunningScript {
+    pub fn new(busy_threshold: Duration) -> Self {
+        RunningScript {
+            busy_threshold,
+            started: AtomicU64::new(0),
+            wrote: AtomicBool::new(false),
+            killed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn start(&self) {
+        self.wrote.store(false, Ordering::SeqCst);
+        self.killed.store(false, Ordering::SeqCst);
+        self.started.store(now_ms().max(1), Ordering::SeqCst);
+    }
+
+    pub fn finish(&self) {
+        self.started.store(0, Ordering::SeqCst);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started.load(Ordering::SeqCst) != 0
+    }
+
+    // whether a script has been running for longer than the busy threshold
+    pub fn is_busy(&self) -> bool {
+        match self.started.load(Ordering::SeqCst) {
+            0 => false,
+            started => now_ms().saturating_sub(started) >= self.busy_threshold.as_millis() as u64,
+        }
+    }
+
+    pub fn mark_write(&self) {
+        self.wrote.store(true, Ordering::SeqCst);
+    }
+
+    // ask the script to stop at its next check; false when it wrote already,
+    // as stopping it halfway would leave its writes half done
+    pub fn kill(&self) -> bool {
+        if self.wrote.load(Ordering::SeqCst) {
+            return false;
+        }
+        self.killed.store(true, Ordering::SeqCst);
+        true
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst)
+    }
+}
+
+// the hex SHA1 of a script body, as EVALSHA takes it
+pub fn sha1_hex(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
+
+// the message of a Lua error, without the traceback mlua adds to some
+pub fn lua_error_message(e: &mlua::Error) -> String {
+    match e {
+        mlua::Error::CallbackError { cause, .. } => lua_error_message(cause),
+        mlua::Error::SyntaxError { message, .. } => message.clone(),
+        mlua::Error::RuntimeError(message) => match message.split_once("\nstack traceback:") {
+            Some((message, _)) => message.to_string(),
+            None => message.clone(),
+        },
+        e => e.to_string(),
+    }
+}
+
+// an interpreter with the libraries Redis gives scripts and the parts of the
+// redis table that don't reach into the keyspace; redis.call and redis.pcall
+// are bound for each run, to the client running the script
+pub(crate) fn new_lua() -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::default(),
+    )?;
+    let globals = lua.globals();
+    // no reaching into the server's filesystem, and no compiling code at run
+    // time: the load functions take bytecode, which the VM runs unchecked,
+    // and string.dump is where bytecode comes from. Nor swapping the
+    // environment of a function for one that is not read only
+    for name in [
+        "dofile",
+        "loadfile",
+        "load",
+        "loadstring",
+        "getfenv",
+        "setfenv",
+    ] {
+        globals.raw_set(name, mlua::Nil)?;
+    }
+    globals
+        .raw_get::<_, Table>("string")?
+        .raw_set("dump", mlua::Nil)?;
+
+    let redis = lua.create_table()?;
+    redis.set(
+        "status_reply",
+        lua.create_function(|lua, status: mlua::String| reply_table(lua, "ok", status))?,
+    )?;
+    redis.set(
+        "error_reply",
+        lua.create_function(|lua, error: mlua::String| reply_table(lua, "err", error))?,
+    )?;
+    redis.set(
+        "sha1hex",
+        lua.create_function(|_, data: mlua::String| Ok(sha1_hex(data.as_bytes())))?,
+    )?;
+    for (level, name) in ["LOG_DEBUG", "LOG_VERBOSE", "LOG_NOTICE", "LOG_WARNING"]
+        .into_iter()
+        .enumerate()
+    {
+        redis.set(name, level)?;
+    }
+    redis.set(
+        "log",
+        lua.create_function(|_, (level, message): (u8, Variadic<mlua::String>)| {
+            let message = message
+                .iter()
+                .map(|part| part.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" ");
+            match level {
+                0 => tracing::debug!("{}", message),
+                1 | 2 => tracing::info!("{}", message),
+                _ => tracing::warn!("{}", message),
+            }
+            Ok(())
+        })?,
+    )?;
+    globals.raw_set("redis", redis.clone())?;
+    lua.set_named_registry_value(REDIS_KEY, redis)?;
+    let env: Table = lua.load(SANDBOX).set_name("@sandbox").eval()?;
+    lua.set_named_registry_value(SANDBOX_KEY, env)?;
+    drop(globals);
+    Ok(lua)
+}
+
+// the read-only environment a new_lua interpreter runs code in
+pub(crate) fn sandbox(lua: &Lua) -> mlua::Result<Table<'_>> {
+    lua.named_registry_value(SANDBOX_KEY)
+}
+
+// the redis table itself, where the interpreter's owner puts what the code
+// it runs may only read, like redis.call
+pub(crate) fn redis_table(lua: &Lua) -> mlua::Result<Table<'_>> {
+    lua.named_registry_value(REDIS_KEY)
+}
+
+// {ok = status} or {err = message}, the tables scripts return for the
+// replies that are not bulk strings
+fn reply_table<'lua>(lua: &'lua Lua, field: &str, text: mlua::String) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set(field, text)?;
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts() {
+        let mut scripts = Scripts::default();
+        let sha = scripts.load(b"return 1".to_vec());
+        assert_eq!(sha, "e0e1f9fabfc9d4800c877a703b823ac0578ff8db");
+        assert!(scripts.contains(&sha.to_uppercase()));
+        assert!(!scripts.contains("ffffffffffffffffffffffffffffffffffffffff"));
+
+        let lua = scripts.take_lua().unwrap();
+        let run = |code: &str| {
+            lua.load(code)
+                .set_environment(sandbox(&lua).unwrap())
+                .eval::<mlua::Value>()
+                .map_err(|e| lua_error_message(&e))
+        };
+        assert_eq!(run("return math.max(1, 2)"), Ok(mlua::Value::Integer(2)));
+        // globals are off limits, and so is the filesystem
+        assert!(run("x = 1").is_err());
+        assert!(run("return undefined_thing").is_err());
+        assert!(run("return dofile('/etc/passwd')").is_err());
+        assert!(run("return loadstring('return 1')").is_err());
+        assert!(run("return string.dump(print)").is_err());
+        assert_eq!(
+            run("return redis.status_reply('OK').ok").unwrap().as_str(),
+            Some("OK")
+        );
+        scripts.put_lua(lua);
+
+        scripts.flush();
+        assert!(!scripts.contains(&sha));
+        assert!(scripts.lua.is_none());
+    }
+
+    #[test]
+    fn test_sandbox_is_read_only() {
+        let lua = new_lua().unwrap();
+        let run = |code: &str| {
+            lua.load(code)
+                .set_environment(sandbox(&lua).unwrap())
+                .exec()
+                .map_err(|e| lua_error_message(&e))
+        };
+        for code in [
+            "string.upper = function() return 'pwned' end",
+            "tonumber = function() return 0 end",
+            "_G.tonumber = nil",
+            "rawset(_G, 'tonumber', print)",
+            "rawset(string, 'upper', print)",
+            "rawset(redis, 'sha1hex', print)",
+            "table.insert(_G, 1)",
+            "table.insert(math, 1)",
+        ] {
+            let Err(e) = run(code) else {
+                panic!("{} ran", code);
+            };
+            assert!(
+                e.contains("Attempt to modify a readonly table"),
+                "{}: {}",
+                code,
+                e
+            );
+        }
+        for code in [
+            "setmetatable(_G, nil)",
+            "setmetatable(string, nil)",
+            "setfenv(1, {})",
+            "getmetatable('').__index.upper = print",
+        ] {
+            assert!(run(code).is_err(), "{} ran", code);
+        }
+        // tables of the script's own are as writable as ever
+        run("local t = {} rawset(t, 1, 2) table.insert(t, 3) setmetatable(t, nil)").unwrap();
+        run("assert(('x'):upper() == 'X' and string.upper('y') == 'Y')").unwrap();
+        run("assert(tonumber('1') == 1 and getmetatable('') == false)").unwrap();
+    }
+
+    #[test]
+    fn test_running_script() {
+        let running = RunningScript::new(Duration::ZERO);
+        assert!(!running.is_running());
+        assert!(!running.is_busy());
+        running.start();
+        assert!(running.is_busy());
+        assert!(running.kill());
+        assert!(running.is_killed());
+
+        // a script that wrote has to run to its end
+        running.start();
+        assert!(!running.is_killed());
+        running.mark_write();
+        assert!(!running.kill());
+        assert!(!running.is_killed());
+        running.finish();
+        assert!(!running.is_running());
+
+        let running = RunningScript::new(Duration::from_secs(60));
+        running.start();
+        assert!(running.is_running());
+        assert!(!running.is_busy());
+    }
+}
@@ -0,0 +1,395 @@
+use std::cmp::Ordering;
+
+use rand::Rng;
+
+use super::{LexRange, ScoreRange};
+
+const MAX_LEVEL: usize = 32;
+// the chance that a node reaches one level up
+const LEVEL_P: f64 = 0.25;
+// the head node lives at index 0 of the arena and holds no element
+const HEAD: usize = 0;
+
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    forward: Option<usize>,
+    // how many level 0 links the forward pointer skips, which is what makes
+    // rank lookups O(log n)
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: Vec<u8>,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Level>,
+}
+
+// the ordered half of a sorted set: elements sorted by (score, member), kept in
+// an arena of nodes linked by index, the layout Redis uses for its zsets
+#[derive(Debug, Clone)]
+pub(crate) struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+    len: usize,
+    level: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let head = Node {
+            member: Vec::new(),
+            score: 0.0,
+            backward: None,
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0,
+                };
+                MAX_LEVEL
+            ],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: Vec::new(),
+            tail: None,
+            len: 0,
+            level: 1,
+        }
+    }
+}
+
+fn cmp_element(score: f64, member: &[u8], other_score: f64, other_member: &[u8]) -> Ordering {
+    score
+        .partial_cmp(&other_score)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| member.cmp(other_member))
+}
+
+fn random_level() -> usize {
+    let mut rng = rand::thread_rng();
+    let mut level = 1;
+    while level < MAX_LEVEL && rng.gen_bool(LEVEL_P) {
+        level += 1;
+    }
+    level
+}
+
+impl SkipList {
+    fn forward(&self, node: usize, level: usize) -> Option<usize> {
+        self.nodes[node].levels[level].forward
+    }
+
+    fn span(&self, node: usize, level: usize) -> usize {
+        self.nodes[node].levels[level].span
+    }
+
+    // whether the element at node sorts before (score, member)
+    fn is_before(&self, node: usize, score: f64, member: &[u8]) -> bool {
+        let n = &self.nodes[node];
+        cmp_element(n.score, &n.member, score, member) == Ordering::Less
+    }
+
+    pub fn element(&self, node: usize) -> (&[u8], f64) {
+        let n = &self.nodes[node];
+        (&n.member, n.score)
+    }
+
+    pub fn next(&self, node: usize) -> Option<usize> {
+        self.forward(node, 0)
+    }
+
+    pub fn prev(&self, node: usize) -> Option<usize> {
+        self.nodes[node].backward
+    }
+
+    // insert an element that is not in the list yet
+    pub fn insert(&mut self, score: f64, member: Vec<u8>) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0usize; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.forward(x, i) {
+                if !self.is_before(next, score, &member) {
+                    break;
+                }
+                rank[i] += self.span(x, i);
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            member,
+            score,
+            backward: (update[0] != HEAD).then_some(update[0]),
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0,
+                };
+                level
+            ],
+        };
+        let x = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let prev = self.nodes[update[i]].levels[i];
+            self.nodes[x].levels[i] = Level {
+                forward: prev.forward,
+                span: prev.span - (rank[0] - rank[i]),
+            };
+            self.nodes[update[i]].levels[i] = Level {
+                forward: Some(x),
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, &prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[prev].levels[i].span += 1;
+        }
+
+        match self.forward(x, 0) {
+            Some(next) => self.nodes[next].backward = Some(x),
+            None => self.tail = Some(x),
+        }
+        self.len += 1;
+    }
+
+    // remove an element, returning whether it was there
+    pub fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if !self.is_before(next, score, member) {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+        let x = match self.forward(x, 0) {
+            Some(x) if self.nodes[x].score == score && self.nodes[x].member == member => x,
+            _ => return false,
+        };
+
+        for (i, &prev) in update.iter().enumerate().take(self.level) {
+            if self.forward(prev, i) == Some(x) {
+                let removed = self.nodes[x].levels[i];
+                let level = &mut self.nodes[prev].levels[i];
+                level.span = level.span + removed.span - 1;
+                level.forward = removed.forward;
+            } else {
+                self.nodes[prev].levels[i].span -= 1;
+            }
+        }
+        let backward = self.nodes[x].backward;
+        match self.forward(x, 0) {
+            Some(next) => self.nodes[next].backward = backward,
+            None => self.tail = backward,
+        }
+        while self.level > 1 && self.forward(HEAD, self.level - 1).is_none() {
+            self.level -= 1;
+        }
+        self.len -= 1;
+
+        // release the slot, keeping no member bytes alive in it
+        self.nodes[x].member = Vec::new();
+        self.nodes[x].levels = Vec::new();
+        self.free.push(x);
+        true
+    }
+
+    // the 0-based rank of an element
+    pub fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let mut x = HEAD;
+        let mut rank = 0;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                let n = &self.nodes[next];
+                if cmp_element(n.score, &n.member, score, member) == Ordering::Greater {
+                    break;
+                }
+                rank += self.span(x, i);
+                x = next;
+            }
+            if x != HEAD && self.nodes[x].member == member {
+                return Some(rank - 1);
+            }
+        }
+        None
+    }
+
+    // the node at a 0-based rank
+    pub fn by_rank(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len {
+            return None;
+        }
+        let target = rank + 1;
+        let mut x = HEAD;
+        let mut traversed = 0;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if traversed + self.span(x, i) > target {
+                    break;
+                }
+                traversed += self.span(x, i);
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    pub fn first(&self) -> Option<usize> {
+        self.forward(HEAD, 0)
+    }
+
+    pub fn last(&self) -> Option<usize> {
+        self.tail
+    }
+
+    // the last node for which `before` holds, walking down from the top level;
+    // `before` must be true for a prefix of the list and false after it
+    fn last_matching(&self, before: impl Fn(&Node) -> bool) -> usize {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if !before(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        x
+    }
+
+    pub fn first_in_score_range(&self, range: &ScoreRange) -> Option<usize> {
+        if range.is_empty() {
+            return None;
+        }
+        let x = self.last_matching(|n| !range.above_min(n.score));
+        self.forward(x, 0)
+            .filter(|&x| range.below_max(self.nodes[x].score))
+    }
+
+    pub fn last_in_score_range(&self, range: &ScoreRange) -> Option<usize> {
+        if range.is_empty() {
+            return None;
+        }
+        let x = self.last_matching(|n| range.below_max(n.score));
+        (x != HEAD && range.above_min(self.nodes[x].score)).then_some(x)
+    }
+
+    pub fn first_in_lex_range(&self, range: &LexRange) -> Option<usize> {
+        if range.is_empty() {
+            return None;
+        }
+        let x = self.last_matching(|n| !range.above_min(&n.member));
+        self.forward(x, 0)
+            .filter(|&x| range.below_max(&self.nodes[x].member))
+    }
+
+    pub fn last_in_lex_range(&self, range: &LexRange) -> Option<usize> {
+        if range.is_empty() {
+            return None;
+        }
+        let x = self.last_matching(|n| range.below_max(&n.member));
+        (x != HEAD && range.above_min(&self.nodes[x].member)).then_some(x)
+    }
+
+    // the 0-based rank of a node, O(log n) through the element it holds
+    pub fn node_rank(&self, node: usize) -> usize {
+        let (member, score) = self.element(node);
+        self.rank(score, member).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // walk level 0 and check order, back links and every span
+    fn check(list: &SkipList) -> Vec<(Vec<u8>, f64)> {
+        let mut items = Vec::new();
+        let mut positions = vec![(HEAD, 0usize)];
+        let mut x = list.first();
+        let mut prev = None;
+        while let Some(node) = x {
+            assert_eq!(list.prev(node), prev);
+            positions.push((node, positions.len()));
+            let (member, score) = list.element(node);
+            items.push((member.to_vec(), score));
+            prev = Some(node);
+            x = list.next(node);
+        }
+        assert_eq!(list.last(), prev);
+        assert_eq!(items.len(), list.len);
+        for &(node, pos) in &positions {
+            for i in 0..list.nodes[node].levels.len().min(list.level) {
+                if let Some(next) = list.forward(node, i) {
+                    let next_pos = positions.iter().find(|p| p.0 == next).unwrap().1;
+                    assert_eq!(list.span(node, i), next_pos - pos);
+                }
+            }
+        }
+        items
+    }
+
+    #[test]
+    fn test_skiplist_order_and_rank() {
+        let mut list = SkipList::default();
+        for i in 0..200 {
+            // insert out of order, with ties on score broken by member
+            let score = ((i * 37) % 50) as f64;
+            list.insert(score, format!("m{:03}", i).into_bytes());
+        }
+        let items = check(&list);
+        assert!(items
+            .windows(2)
+            .all(|w| cmp_element(w[0].1, &w[0].0, w[1].1, &w[1].0) == Ordering::Less));
+        for (rank, (member, score)) in items.iter().enumerate() {
+            assert_eq!(list.rank(*score, member), Some(rank));
+            assert_eq!(
+                list.by_rank(rank).map(|n| list.element(n).0),
+                Some(&member[..])
+            );
+        }
+        assert_eq!(list.by_rank(200), None);
+
+        for i in (0..200).step_by(3) {
+            let score = ((i * 37) % 50) as f64;
+            assert!(list.remove(score, format!("m{:03}", i).as_bytes()));
+        }
+        assert!(!list.remove(0.0, b"missing"));
+        let items = check(&list);
+        assert_eq!(items.len(), 133);
+        for (rank, (member, score)) in items.iter().enumerate() {
+            assert_eq!(list.rank(*score, member), Some(rank));
+        }
+    }
+}
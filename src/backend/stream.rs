@@ -0,0 +1,585 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::ConsumerGroup;
+
+// a chunk is closed to appends once it holds this many entries or bytes,
+// the defaults of Redis' stream-node-max-entries and stream-node-max-bytes
+const CHUNK_MAX_ENTRIES: usize = 100;
+const CHUNK_MAX_BYTES: usize = 4096;
+
+// entry flags
+const DELETED: u8 = 1;
+// the entry has the same field names as the first entry of its chunk, so only
+// its values are stored
+const SAME_FIELDS: u8 = 2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    // the id right after this one, None past the last possible id
+    pub fn next(&self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => self.ms.checked_add(1).map(|ms| StreamId::new(ms, 0)),
+        }
+    }
+
+    // the id right before this one, None before 0-0
+    pub fn prev(&self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => self.ms.checked_sub(1).map(|ms| StreamId::new(ms, u64::MAX)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+pub type StreamFields = Vec<(Vec<u8>, Vec<u8>)>;
+
+// how XADD and XTRIM cut a stream down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimStrategy {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn get_varint(buf: &[u8], pos: &mut usize) -> u64 {
+    let mut v = 0;
+    let mut shift = 0;
+    loop {
+        let b = buf[*pos];
+        *pos += 1;
+        v |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return v;
+        }
+        shift += 7;
+    }
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn get_bytes(buf: &[u8], pos: &mut usize) -> Vec<u8> {
+    let len = get_varint(buf, pos) as usize;
+    let bytes = buf[*pos..*pos + len].to_vec();
+    *pos += len;
+    bytes
+}
+
+// a run of consecutive entries packed into one buffer, like a Redis listpack:
+// each entry is its flags, its id as a delta from the chunk's first id, and
+// its fields as length-prefixed strings. Deleting only flags an entry; the
+// chunk is dropped once all of its entries are deleted
+#[derive(Debug, Clone, PartialEq)]
+struct Chunk {
+    first: StreamId,
+    last: StreamId,
+    fields: Vec<Vec<u8>>,
+    data: Vec<u8>,
+    count: usize,
+    live: usize,
+}
+
+// one decoded entry and where it starts in its chunk
+struct RawEntry {
+    offset: usize,
+    deleted: bool,
+    id: StreamId,
+    fields: StreamFields,
+}
+
+impl Chunk {
+    fn new(id: StreamId, fields: &StreamFields) -> Self {
+        Chunk {
+            first: id,
+            last: id,
+            fields: fields.iter().map(|(field, _)| field.clone()).collect(),
+            data: Vec::new(),
+            count: 0,
+            live: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.count >= CHUNK_MAX_ENTRIES || self.data.len() >= CHUNK_MAX_BYTES
+    }
+
+    fn push(&mut self, id: StreamId, fields: &StreamFields) {
+        let same_fields = fields.len() == self.fields.len()
+            && fields
+                .iter()
+                .zip(&self.fields)
+                .all(|((field, _), master)| field == master);
+        self.data.push(if same_fields { SAME_FIELDS } else { 0 });
+        put_varint(&mut self.data, id.ms - self.first.ms);
+        put_varint(&mut self.data, id.seq);
+        put_varint(&mut self.data, fields.len() as u64);
+        for (field, value) in fields {
+            if !same_fields {
+                put_bytes(&mut self.data, field);
+            }
+            put_bytes(&mut self.data, value);
+        }
+        self.last = id;
+        self.count += 1;
+        self.live += 1;
+    }
+
+    fn decode_at(&self, pos: &mut usize) -> RawEntry {
+        let offset = *pos;
+        let flags = self.data[*pos];
+        *pos += 1;
+        let ms = self.first.ms + get_varint(&self.data, pos);
+        let seq = get_varint(&self.data, pos);
+        let n = get_varint(&self.data, pos) as usize;
+        let fields = (0..n)
+            .map(|i| {
+                let field = match flags & SAME_FIELDS {
+                    0 => get_bytes(&self.data, pos),
+                    _ => self.fields[i].clone(),
+                };
+                (field, get_bytes(&self.data, pos))
+            })
+            .collect();
+        RawEntry {
+            offset,
+            deleted: flags & DELETED != 0,
+            id: StreamId::new(ms, seq),
+            fields,
+        }
+    }
+
+    // every entry, deleted ones included, oldest first
+    fn entries(&self) -> Vec<RawEntry> {
+        let mut pos = 0;
+        let mut entries = Vec::with_capacity(self.count);
+        while pos < self.data.len() {
+            entries.push(self.decode_at(&mut pos));
+        }
+        entries
+    }
+
+    fn mark_deleted(&mut self, offset: usize) {
+        self.data[offset] |= DELETED;
+        self.live -= 1;
+    }
+}
+
+// append-only log of field/value entries ordered by id, kept in packed chunks
+// indexed by the id of their first entry
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stream {
+    chunks: BTreeMap<StreamId, Chunk>,
+    len: usize,
+    last_id: StreamId,
+    // every entry ever added, deleted ones included
+    entries_added: u64,
+    max_deleted_id: StreamId,
+    groups: BTreeMap<Vec<u8>, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Stream::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // the highest id ever added, even if that entry is gone since
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    // the id XADD * generates: the current time, or a bump of the sequence when
+    // the clock has not moved past the last id; None once ids are exhausted
+    pub fn next_id(&self, now_ms: u64) -> Option<StreamId> {
+        if now_ms > self.last_id.ms {
+            Some(StreamId::new(now_ms, 0))
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    // append an entry; the id must be greater than last_id
+    pub fn add(&mut self, id: StreamId, fields: StreamFields) {
+        match self.chunks.values_mut().next_back() {
+            Some(chunk) if !chunk.is_full() => chunk.push(id, &fields),
+            _ => {
+                let mut chunk = Chunk::new(id, &fields);
+                chunk.push(id, &fields);
+                self.chunks.insert(id, chunk);
+            }
+        }
+        self.len += 1;
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    // the entries with start <= id <= end, oldest first or, with rev, newest first
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    ) -> Vec<(StreamId, StreamFields)> {
+        let count = count.unwrap_or(usize::MAX);
+        if start > end || count == 0 {
+            return vec![];
+        }
+        // the chunk holding start may begin before it
+        let from = self
+            .chunks
+            .range(..=start)
+            .next_back()
+            .map_or(start, |(&first, _)| first);
+        let chunks = self.chunks.range(from..=end).map(|(_, chunk)| chunk);
+        let chunks: Box<dyn Iterator<Item = &Chunk>> = match rev {
+            false => Box::new(chunks),
+            true => Box::new(chunks.rev()),
+        };
+
+        let mut result = Vec::new();
+        for chunk in chunks {
+            let mut entries = chunk.entries();
+            if rev {
+                entries.reverse();
+            }
+            for entry in entries {
+                if entry.deleted || entry.id < start || entry.id > end {
+                    continue;
+                }
+                result.push((entry.id, entry.fields));
+                if result.len() >= count {
+                    return result;
+                }
+            }
+        }
+        result
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<StreamFields> {
+        self.range(id, id, Some(1), false)
+            .pop()
+            .map(|(_, fields)| fields)
+    }
+
+    pub fn first_entry(&self) -> Option<(StreamId, StreamFields)> {
+        self.range(StreamId::MIN, StreamId::MAX, Some(1), false)
+            .pop()
+    }
+
+    pub fn last_entry(&self) -> Option<(StreamId, StreamFields)> {
+        self.range(StreamId::MIN, StreamId::MAX, Some(1), true)
+            .pop()
+    }
+
+    // the id of the oldest entry still in the stream, 0-0 when empty
+    pub fn first_id(&self) -> StreamId {
+        self.first_entry().map_or(StreamId::MIN, |(id, _)| id)
+    }
+
+    // the chunks entries are packed into
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn groups(&self) -> &BTreeMap<Vec<u8>, ConsumerGroup> {
+        &self.groups
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    // add a group, returning false if one by that name exists already
+    pub fn create_group(&mut self, name: Vec<u8>, group: ConsumerGroup) -> bool {
+        if self.groups.contains_key(&name) {
+            return false;
+        }
+        self.groups.insert(name, group);
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    // whether an entry after id was deleted, which makes counting the entries
+    // between id and the end unreliable
+    fn has_tombstones_after(&self, id: StreamId) -> bool {
+        !self.is_empty() && self.max_deleted_id != StreamId::MIN && id <= self.max_deleted_id
+    }
+
+    // how many entries were added up to and including id, when that can still
+    // be worked out from the counters, like Redis' distance estimate
+    pub fn entries_read_at(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if self.is_empty() && id <= self.last_id {
+            return Some(self.entries_added);
+        }
+        if id == self.last_id {
+            return Some(self.entries_added);
+        }
+        if id > self.last_id {
+            return None;
+        }
+        let first = self.first_id();
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first {
+            let before_first = self.entries_added - self.len as u64;
+            if id < first {
+                return Some(before_first);
+            }
+            if id == first {
+                return Some(before_first + 1);
+            }
+        }
+        None
+    }
+
+    // how many entries a group has yet to read, None when unknown
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(read) if !self.has_tombstones_after(group.last_id) => Some(read),
+            _ => self.entries_read_at(group.last_id),
+        };
+        entries_read.map(|read| self.entries_added.saturating_sub(read))
+    }
+
+    // hand the entries after a group's last id to one of its consumers, moving
+    // the group forward and, unless noack, adding them to its pending list;
+    // None if there is no such group
+    pub fn read_group(
+        &mut self,
+        group: &[u8],
+        consumer: &[u8],
+        count: Option<usize>,
+        noack: bool,
+        now: u64,
+    ) -> Option<Vec<(StreamId, StreamFields)>> {
+        let after = self.groups.get(group)?.last_id;
+        let entries = match after.next() {
+            Some(start) => self.range(start, StreamId::MAX, count, false),
+            None => vec![],
+        };
+        // work out the read counter before borrowing the group mutably
+        let mut entries_read = self.groups[group].entries_read;
+        for (id, _) in &entries {
+            entries_read = match entries_read {
+                Some(read) if !self.has_tombstones_after(*id) => Some(read + 1),
+                _ if self.entries_added > 0 => self.entries_read_at(*id),
+                read => read,
+            };
+        }
+
+        let cg = self.groups.get_mut(group)?;
+        let reader = cg.seen(consumer, now);
+        if !entries.is_empty() {
+            reader.active_time = Some(now);
+        }
+        if let Some((last, _)) = entries.last() {
+            cg.last_id = *last;
+            cg.entries_read = entries_read;
+        }
+        if !noack {
+            for (id, _) in &entries {
+                cg.assign(*id, consumer, now, 1);
+            }
+        }
+        Some(entries)
+    }
+
+    // delete one entry, returning whether it was there
+    pub fn delete(&mut self, id: StreamId) -> bool {
+        let first = match self.chunks.range(..=id).next_back() {
+            Some((&first, _)) => first,
+            None => return false,
+        };
+        let chunk = self.chunks.get_mut(&first).expect("chunk just found");
+        let offset = match chunk
+            .entries()
+            .into_iter()
+            .find(|entry| entry.id == id && !entry.deleted)
+        {
+            Some(entry) => entry.offset,
+            None => return false,
+        };
+        chunk.mark_deleted(offset);
+        if chunk.live == 0 {
+            self.chunks.remove(&first);
+        }
+        self.len -= 1;
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    // drop the oldest entries per the strategy, returning how many went. An
+    // approximate trim only drops whole chunks, which is much cheaper, and stops
+    // before removing more than limit entries (0 for no limit)
+    pub fn trim(&mut self, strategy: TrimStrategy, approx: bool, limit: usize) -> usize {
+        let mut removed = 0;
+        while let Some((&first, chunk)) = self.chunks.iter_mut().next() {
+            if limit > 0 && removed + chunk.live > limit {
+                break;
+            }
+            let whole = match strategy {
+                TrimStrategy::MaxLen(max) => self.len - chunk.live >= max,
+                TrimStrategy::MinId(min) => chunk.last < min,
+            };
+            if whole {
+                removed += chunk.live;
+                self.len -= chunk.live;
+                self.chunks.remove(&first);
+                continue;
+            }
+            if approx {
+                break;
+            }
+            // an exact trim flags entries one by one in the chunk it stopped at
+            for entry in chunk.entries() {
+                if entry.deleted {
+                    continue;
+                }
+                let drop = match strategy {
+                    TrimStrategy::MaxLen(max) => self.len > max,
+                    TrimStrategy::MinId(min) => entry.id < min,
+                };
+                if !drop {
+                    break;
+                }
+                chunk.mark_deleted(entry.offset);
+                self.len -= 1;
+                removed += 1;
+            }
+            if chunk.live == 0 {
+                self.chunks.remove(&first);
+            }
+            break;
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(n: u64) -> StreamFields {
+        vec![(b"n".to_vec(), n.to_string().into_bytes())]
+    }
+
+    fn ids(entries: &[(StreamId, StreamFields)]) -> Vec<u64> {
+        entries.iter().map(|(id, _)| id.ms).collect()
+    }
+
+    #[test]
+    fn test_stream_chunks() {
+        let mut stream = Stream::new();
+        for ms in 1..=250 {
+            stream.add(StreamId::new(ms, 0), fields(ms));
+        }
+        // an entry with other field names is stored with them
+        stream.add(
+            StreamId::new(251, 0),
+            vec![(b"other".to_vec(), b"x".to_vec())],
+        );
+        assert_eq!(stream.chunks.len(), 3);
+        assert_eq!(stream.len(), 251);
+        assert_eq!(
+            stream.get(StreamId::new(251, 0)),
+            Some(vec![(b"other".to_vec(), b"x".to_vec())])
+        );
+        assert_eq!(stream.get(StreamId::new(120, 0)), Some(fields(120)));
+
+        let found = stream.range(StreamId::new(99, 0), StreamId::new(102, 0), None, false);
+        assert_eq!(ids(&found), [99, 100, 101, 102]);
+        let found = stream.range(StreamId::MIN, StreamId::MAX, Some(3), true);
+        assert_eq!(ids(&found), [251, 250, 249]);
+
+        assert!(stream.delete(StreamId::new(100, 0)));
+        assert!(!stream.delete(StreamId::new(100, 0)));
+        let found = stream.range(StreamId::new(99, 0), StreamId::new(101, 0), None, false);
+        assert_eq!(ids(&found), [99, 101]);
+        assert_eq!(stream.max_deleted_id(), StreamId::new(100, 0));
+    }
+
+    #[test]
+    fn test_stream_trim() {
+        let mut stream = Stream::new();
+        for ms in 1..=250 {
+            stream.add(StreamId::new(ms, 0), fields(ms));
+        }
+        // approximate trims keep whole chunks
+        assert_eq!(stream.trim(TrimStrategy::MaxLen(180), true, 0), 0);
+        assert_eq!(stream.trim(TrimStrategy::MaxLen(120), true, 0), 100);
+        assert_eq!(stream.trim(TrimStrategy::MaxLen(120), false, 0), 30);
+        assert_eq!(stream.len(), 120);
+        assert_eq!(stream.first_entry().map(|(id, _)| id.ms), Some(131));
+
+        assert_eq!(
+            stream.trim(TrimStrategy::MinId(StreamId::new(201, 0)), true, 50),
+            0
+        );
+        assert_eq!(
+            stream.trim(TrimStrategy::MinId(StreamId::new(240, 0)), false, 0),
+            109
+        );
+        assert_eq!(stream.first_entry().map(|(id, _)| id.ms), Some(240));
+        assert_eq!(stream.last_entry().map(|(id, _)| id.ms), Some(250));
+        assert_eq!(stream.entries_added(), 250);
+    }
+}
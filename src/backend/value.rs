@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+
+use super::{Dict, DictSet, Stream, ZSet};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(Vec<u8>),
+    List(VecDeque<Vec<u8>>),
+    Hash(Dict<Vec<u8>, Vec<u8>>),
+    Set(DictSet<Vec<u8>>),
+    ZSet(ZSet),
+    Stream(Stream),
+}
+
+impl Value {
+    // the name reported by TYPE
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    // containers are removed from the keyspace once they become empty;
+    // strings and streams may legitimately be empty
+    pub fn is_empty_container(&self) -> bool {
+        match self {
+            Value::String(_) | Value::Stream(_) => false,
+            Value::List(l) => l.is_empty(),
+            Value::Hash(h) => h.is_empty(),
+            Value::Set(s) => s.is_empty(),
+            Value::ZSet(z) => z.is_empty(),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(v: Vec<u8>) -> Self {
+        Value::String(v)
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(v: &[u8]) -> Self {
+        Value::String(v.to_vec())
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::{HashMap, HashSet};
+
+// the keys clients WATCH, by database index and key. A write to a watched key
+// marks every client watching it dirty, and a dirty client's EXEC fails
+#[derive(Debug, Default)]
+pub struct Watches {
+    watchers: HashMap<(usize, Vec<u8>), HashSet<u64>>,
+    // what each client watches, to forget it all at once
+    watched: HashMap<u64, Vec<(usize, Vec<u8>)>>,
+    dirty: HashSet<u64>,
+}
+
+impl Watches {
+    pub fn is_empty(&self) -> bool {
+        self.watched.is_empty()
+    }
+
+    pub fn watch(&mut self, client: u64, db: usize, key: Vec<u8>) {
+        let entry = (db, key);
+        if self
+            .watchers
+            .entry(entry.clone())
+            .or_default()
+            .insert(client)
+        {
+            self.watched.entry(client).or_default().push(entry);
+        }
+    }
+
+    // a write to key, breaking the transactions of the clients watching it
+    pub fn touch(&mut self, db: usize, key: &[u8]) {
+        if let Some(clients) = self.watchers.get(&(db, key.to_vec())) {
+            self.dirty.extend(clients);
+        }
+    }
+
+    pub fn is_dirty(&self, client: u64) -> bool {
+        self.dirty.contains(&client)
+    }
+
+    // the keys client watches, in the order it watched them
+    pub fn keys(&self, client: u64) -> &[(usize, Vec<u8>)] {
+        self.watched.get(&client).map_or(&[], Vec::as_slice)
+    }
+
+    // forget everything client watches, as EXEC, DISCARD and UNWATCH do
+    pub fn unwatch_all(&mut self, client: u64) {
+        self.dirty.remove(&client);
+        for entry in self.watched.remove(&client).unwrap_or_default() {
+            if let Some(clients) = self.watchers.get_mut(&entry) {
+                clients.remove(&client);
+                if clients.is_empty() {
+                    self.watchers.remove(&entry);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watches() {
+        let mut watches = Watches::default();
+        watches.watch(1, 0, b"a".to_vec());
+        watches.watch(1, 0, b"a".to_vec());
+        watches.watch(1, 0, b"b".to_vec());
+        watches.watch(2, 0, b"b".to_vec());
+        assert_eq!(watches.keys(1).len(), 2);
+
+        // the same key in another database is another key
+        watches.touch(1, b"a");
+        assert!(!watches.is_dirty(1));
+        watches.touch(0, b"a");
+        assert!(watches.is_dirty(1));
+        assert!(!watches.is_dirty(2));
+
+        watches.unwatch_all(1);
+        assert!(!watches.is_dirty(1));
+        assert!(watches.keys(1).is_empty());
+        watches.touch(0, b"b");
+        assert!(watches.is_dirty(2));
+        watches.unwatch_all(2);
+        assert!(watches.is_empty());
+        assert!(watches.watchers.is_empty());
+    }
+}
@@ -0,0 +1,284 @@
+use super::skiplist::SkipList;
+use super::Dict;
+
+// sorted set: a member -> score index for O(1) lookups, plus a skiplist ordered
+// by (score, member) for ranks and ranges in O(log n)
+#[derive(Debug, Clone, Default)]
+pub struct ZSet {
+    scores: Dict<Vec<u8>, f64>,
+    list: SkipList,
+}
+
+impl PartialEq for ZSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.scores == other.scores
+    }
+}
+
+// an interval of scores; either end may be exclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreRange {
+    pub min: f64,
+    pub min_exclusive: bool,
+    pub max: f64,
+    pub max_exclusive: bool,
+}
+
+impl ScoreRange {
+    pub fn above_min(&self, score: f64) -> bool {
+        if self.min_exclusive {
+            score > self.min
+        } else {
+            score >= self.min
+        }
+    }
+
+    pub fn below_max(&self, score: f64) -> bool {
+        if self.max_exclusive {
+            score < self.max
+        } else {
+            score <= self.max
+        }
+    }
+
+    pub fn contains(&self, score: f64) -> bool {
+        self.above_min(score) && self.below_max(score)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min > self.max || (self.min == self.max && (self.min_exclusive || self.max_exclusive))
+    }
+}
+
+// one end of a lexicographic range: - and + are the open ends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexBound {
+    NegInf,
+    PosInf,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+impl LexRange {
+    pub fn above_min(&self, member: &[u8]) -> bool {
+        match &self.min {
+            LexBound::NegInf => true,
+            LexBound::PosInf => false,
+            LexBound::Inclusive(min) => member >= min.as_slice(),
+            LexBound::Exclusive(min) => member > min.as_slice(),
+        }
+    }
+
+    pub fn below_max(&self, member: &[u8]) -> bool {
+        match &self.max {
+            LexBound::NegInf => false,
+            LexBound::PosInf => true,
+            LexBound::Inclusive(max) => member <= max.as_slice(),
+            LexBound::Exclusive(max) => member < max.as_slice(),
+        }
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        self.above_min(member) && self.below_max(member)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        use LexBound::*;
+        match (&self.min, &self.max) {
+            (PosInf, _) | (_, NegInf) => true,
+            (NegInf, _) | (_, PosInf) => false,
+            (Inclusive(min), Inclusive(max)) => min > max,
+            (Inclusive(min) | Exclusive(min), Inclusive(max) | Exclusive(max)) => min >= max,
+        }
+    }
+}
+
+// walks the skiplist from one node towards the tail, or towards the head when rev
+pub struct ZSetIter<'a> {
+    list: &'a SkipList,
+    node: Option<usize>,
+    rev: bool,
+}
+
+impl<'a> Iterator for ZSetIter<'a> {
+    type Item = (&'a [u8], f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.node?;
+        self.node = match self.rev {
+            false => self.list.next(node),
+            true => self.list.prev(node),
+        };
+        Some(self.list.element(node))
+    }
+}
+
+impl ZSet {
+    pub fn new() -> Self {
+        ZSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    // add a member or update its score, returning the previous score
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> Option<f64> {
+        match self.scores.get_mut(&member) {
+            Some(old) => {
+                let prev = *old;
+                if prev != score {
+                    *old = score;
+                    self.list.remove(prev, &member);
+                    self.list.insert(score, member);
+                }
+                Some(prev)
+            }
+            None => {
+                self.scores.insert(member.clone(), score);
+                self.list.insert(score, member);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.list.remove(score, member);
+        Some(score)
+    }
+
+    // the 0-based rank of a member, counting from the lowest score or, with
+    // rev, from the highest
+    pub fn rank(&self, member: &[u8], rev: bool) -> Option<usize> {
+        let score = self.score(member)?;
+        let rank = self.list.rank(score, member)?;
+        Some(if rev { self.len() - 1 - rank } else { rank })
+    }
+
+    // iterate from the element at a rank, in the given direction; ranks count
+    // from the highest score when rev
+    pub fn iter_from_rank(&self, rank: usize, rev: bool) -> ZSetIter<'_> {
+        let node = match rev {
+            false => self.list.by_rank(rank),
+            true => self
+                .len()
+                .checked_sub(rank + 1)
+                .and_then(|rank| self.list.by_rank(rank)),
+        };
+        ZSetIter {
+            list: &self.list,
+            node,
+            rev,
+        }
+    }
+
+    pub fn iter(&self) -> ZSetIter<'_> {
+        ZSetIter {
+            list: &self.list,
+            node: self.list.first(),
+            rev: false,
+        }
+    }
+
+    pub fn iter_rev(&self) -> ZSetIter<'_> {
+        ZSetIter {
+            list: &self.list,
+            node: self.list.last(),
+            rev: true,
+        }
+    }
+
+    // the rank of the first element inside the range, in the given direction,
+    // and how many elements the range holds
+    pub fn score_range(&self, range: &ScoreRange, rev: bool) -> Option<(usize, usize)> {
+        let first = self.list.first_in_score_range(range)?;
+        let last = self.list.last_in_score_range(range)?;
+        self.span_of(first, last, rev)
+    }
+
+    pub fn lex_range(&self, range: &LexRange, rev: bool) -> Option<(usize, usize)> {
+        let first = self.list.first_in_lex_range(range)?;
+        let last = self.list.last_in_lex_range(range)?;
+        self.span_of(first, last, rev)
+    }
+
+    fn span_of(&self, first: usize, last: usize, rev: bool) -> Option<(usize, usize)> {
+        let start = self.list.node_rank(first);
+        let end = self.list.node_rank(last);
+        if start > end {
+            return None;
+        }
+        let rank = if rev { self.len() - 1 - end } else { start };
+        Some((rank, end - start + 1))
+    }
+
+    pub fn count_in_score_range(&self, range: &ScoreRange) -> usize {
+        self.score_range(range, false).map_or(0, |(_, count)| count)
+    }
+
+    // one step of a ZSCAN walk over the members; see Dict::scan
+    pub fn scan(&self, cursor: u64, mut f: impl FnMut(&[u8], f64)) -> u64 {
+        self.scores.scan(cursor, |member, &score| f(member, score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(iter: ZSetIter) -> Vec<String> {
+        iter.map(|(m, _)| String::from_utf8_lossy(m).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_zset_ranges() {
+        let mut zset = ZSet::new();
+        for (i, member) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+            assert_eq!(zset.insert(member.as_bytes().to_vec(), i as f64), None);
+        }
+        assert_eq!(zset.insert(b"a".to_vec(), 10.0), Some(0.0));
+        assert_eq!(members(zset.iter()), ["b", "c", "d", "e", "a"]);
+        assert_eq!(zset.rank(b"a", false), Some(4));
+        assert_eq!(zset.rank(b"a", true), Some(0));
+        assert_eq!(members(zset.iter_from_rank(1, true)), ["e", "d", "c", "b"]);
+
+        let range = ScoreRange {
+            min: 2.0,
+            min_exclusive: true,
+            max: 10.0,
+            max_exclusive: false,
+        };
+        assert_eq!(zset.score_range(&range, false), Some((2, 3)));
+        assert_eq!(zset.score_range(&range, true), Some((0, 3)));
+        assert_eq!(zset.count_in_score_range(&range), 3);
+
+        let lex = LexRange {
+            min: LexBound::Inclusive(b"c".to_vec()),
+            max: LexBound::PosInf,
+        };
+        let mut all_same = ZSet::new();
+        for member in ["a", "b", "c", "d"] {
+            all_same.insert(member.as_bytes().to_vec(), 0.0);
+        }
+        assert_eq!(all_same.lex_range(&lex, false), Some((2, 2)));
+        assert_eq!(zset.remove(b"c"), Some(2.0));
+        assert_eq!(zset.remove(b"c"), None);
+        assert_eq!(members(zset.iter_rev()), ["a", "e", "d", "b"]);
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::backend::MessageSender;
+use crate::cmd::Transaction;
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+// per-connection state, owned by the connection task and handed to commands through the Context
+#[derive(Debug)]
+pub struct Client {
+    pub id: u64,
+    pub name: Option<String>,
+    pub protocol: Protocol,
+    // the index of the database SELECT picked
+    pub db: usize,
+    // the commands queued since MULTI, None outside a transaction
+    pub transaction: Option<Transaction>,
+    // where the messages of the channels it subscribes to go
+    pub messages: Option<MessageSender>,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Client {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            name: None,
+            protocol: Protocol::default(),
+            db: 0,
+            transaction: None,
+            messages: None,
+        }
+    }
+
+    // a copy of who the client is and what it selected, for running a
+    // command on its behalf later; a transaction in progress is not part of it
+    pub fn snapshot(&self) -> Client {
+        Client {
+            id: self.id,
+            name: self.name.clone(),
+            protocol: self.protocol,
+            db: self.db,
+            transaction: None,
+            messages: None,
+        }
+    }
+
+    pub fn is_resp3(&self) -> bool {
+        self.protocol == Protocol::Resp3
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client::new()
+    }
+}
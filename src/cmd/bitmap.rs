@@ -0,0 +1,725 @@
+use crate::{RespArray, RespFrame};
+
+use super::{
+    check_string_size, nil, string_range, CommandArgs, CommandError, CommandExecutor, CommandSpec,
+    Context, MAX_STRING_SIZE,
+};
+
+// bitmaps are plain strings addressed bit by bit, most significant bit of
+// the first byte first
+
+// SETBIT key offset value
+#[derive(Debug)]
+pub struct SetBit {
+    key: Vec<u8>,
+    offset: u64,
+    value: bool,
+}
+
+// GETBIT key offset
+#[derive(Debug)]
+pub struct GetBit {
+    key: Vec<u8>,
+    offset: u64,
+}
+
+// BITCOUNT key [start end [BYTE | BIT]]
+#[derive(Debug)]
+pub struct BitCount {
+    key: Vec<u8>,
+    range: Option<BitRange>,
+}
+
+// BITPOS key bit [start [end [BYTE | BIT]]]
+#[derive(Debug)]
+pub struct BitPos {
+    key: Vec<u8>,
+    bit: bool,
+    start: i64,
+    end: Option<i64>,
+    unit: Unit,
+}
+
+// BITOP AND | OR | XOR | NOT destkey key [key ...]
+#[derive(Debug)]
+pub struct BitOp {
+    op: BitOperation,
+    dest: Vec<u8>,
+    keys: Vec<Vec<u8>>,
+}
+
+// BITFIELD key [GET encoding offset | [OVERFLOW WRAP | SAT | FAIL]
+//   SET encoding offset value | INCRBY encoding offset increment ...]
+#[derive(Debug)]
+pub struct BitField {
+    key: Vec<u8>,
+    ops: Vec<FieldOp>,
+}
+
+// BITFIELD_RO key [GET encoding offset [GET encoding offset ...]]
+#[derive(Debug)]
+pub struct BitFieldRo {
+    key: Vec<u8>,
+    ops: Vec<FieldOp>,
+}
+
+// whether BITCOUNT and BITPOS ranges count bytes or bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Byte,
+    Bit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BitRange {
+    start: i64,
+    end: i64,
+    unit: Unit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+// a BITFIELD integer type: signed up to 64 bits, unsigned up to 63
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Encoding {
+    signed: bool,
+    bits: u32,
+}
+
+// what BITFIELD does when SET or INCRBY leave the range of the type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldOp {
+    Get(Encoding, u64),
+    Set(Encoding, u64, i64, Overflow),
+    IncrBy(Encoding, u64, i64, Overflow),
+}
+
+fn bit_offset_error() -> CommandError {
+    CommandError::err("bit offset is not an integer or out of range")
+}
+
+// a bit offset within the largest string allowed
+fn parse_bit_offset(buf: &[u8]) -> Result<u64, CommandError> {
+    let offset = std::str::from_utf8(buf)
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .ok_or_else(bit_offset_error)?;
+    if offset >= MAX_STRING_SIZE as u64 * 8 {
+        return Err(bit_offset_error());
+    }
+    Ok(offset)
+}
+
+fn get_bit(bytes: &[u8], offset: u64) -> bool {
+    let byte = (offset / 8) as usize;
+    byte < bytes.len() && bytes[byte] & (0x80 >> (offset % 8)) != 0
+}
+
+fn set_bit(bytes: &mut Vec<u8>, offset: u64, value: bool) {
+    let byte = (offset / 8) as usize;
+    if bytes.len() <= byte {
+        bytes.resize(byte + 1, 0);
+    }
+    let mask = 0x80 >> (offset % 8);
+    if value {
+        bytes[byte] |= mask;
+    } else {
+        bytes[byte] &= !mask;
+    }
+}
+
+// the bits [start, end] of a string in the given unit, as an inclusive bit range
+fn bit_range(len: usize, start: i64, end: i64, unit: Unit) -> Option<(u64, u64)> {
+    match unit {
+        Unit::Byte => string_range(len, start, end).map(|(s, e)| (s as u64 * 8, e as u64 * 8 + 7)),
+        Unit::Bit => string_range(len * 8, start, end).map(|(s, e)| (s as u64, e as u64)),
+    }
+}
+
+fn count_bits(bytes: &[u8], start: u64, end: u64) -> u64 {
+    let (first, last) = ((start / 8) as usize, (end / 8) as usize);
+    let mut count: u64 = bytes[first..=last]
+        .iter()
+        .map(|b| b.count_ones() as u64)
+        .sum();
+    // take off the bits of the edge bytes that fall outside the range
+    count -= (bytes[first] as u32 >> (8 - start % 8)).count_ones() as u64;
+    count -= (bytes[last] as u32 & (0xff >> (end % 8 + 1))).count_ones() as u64;
+    count
+}
+
+fn parse_unit(args: &mut CommandArgs) -> Result<Unit, CommandError> {
+    let unit = match args.next_keyword().as_deref() {
+        None => Unit::Byte,
+        Some("BYTE") => Unit::Byte,
+        Some("BIT") => Unit::Bit,
+        Some(_) => return Err(CommandError::Syntax),
+    };
+    args.finish()?;
+    Ok(unit)
+}
+
+impl CommandSpec for SetBit {
+    const NAME: &'static str = "setbit";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let offset = parse_bit_offset(&args.next_bytes()?)?;
+        let value = match args.next_bytes()?.as_slice() {
+            b"0" => false,
+            b"1" => true,
+            _ => return Err(CommandError::err("bit is not an integer or out of range")),
+        };
+        Ok(SetBit { key, offset, value })
+    }
+}
+
+impl CommandExecutor for SetBit {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let bytes = ctx.db().get_or_create_string(&self.key)?;
+        let old = get_bit(bytes, self.offset);
+        set_bit(bytes, self.offset, self.value);
+        ctx.db().touch(&self.key);
+        Ok(RespFrame::Integer(old as i64))
+    }
+}
+
+impl CommandSpec for GetBit {
+    const NAME: &'static str = "getbit";
+    const ARITY: i64 = 3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(GetBit {
+            key: args.next_bytes()?,
+            offset: parse_bit_offset(&args.next_bytes()?)?,
+        })
+    }
+}
+
+impl CommandExecutor for GetBit {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let bit = match ctx.db().get_string(&self.key)? {
+            Some(bytes) => get_bit(bytes, self.offset),
+            None => false,
+        };
+        Ok(RespFrame::Integer(bit as i64))
+    }
+}
+
+impl CommandSpec for BitCount {
+    const NAME: &'static str = "bitcount";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let range = match args.len() {
+            0 => None,
+            1 => return Err(CommandError::Syntax),
+            _ => Some(BitRange {
+                start: args.next_i64()?,
+                end: args.next_i64()?,
+                unit: parse_unit(args)?,
+            }),
+        };
+        Ok(BitCount { key, range })
+    }
+}
+
+impl CommandExecutor for BitCount {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let bytes = match ctx.db().get_string(&self.key)? {
+            Some(bytes) => bytes,
+            None => return Ok(RespFrame::Integer(0)),
+        };
+        let count = match self.range {
+            None => bytes.iter().map(|b| b.count_ones() as u64).sum(),
+            Some(range) => match bit_range(bytes.len(), range.start, range.end, range.unit) {
+                Some((start, end)) => count_bits(bytes, start, end),
+                None => 0,
+            },
+        };
+        Ok(RespFrame::Integer(count as i64))
+    }
+}
+
+impl CommandSpec for BitPos {
+    const NAME: &'static str = "bitpos";
+    const ARITY: i64 = -3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let bit = match args.next_i64()? {
+            0 => false,
+            1 => true,
+            _ => return Err(CommandError::err("The bit argument must be 1 or 0.")),
+        };
+        let start = match args.is_empty() {
+            true => 0,
+            false => args.next_i64()?,
+        };
+        let end = match args.is_empty() {
+            true => None,
+            false => Some(args.next_i64()?),
+        };
+        let unit = parse_unit(args)?;
+        Ok(BitPos {
+            key,
+            bit,
+            start,
+            end,
+            unit,
+        })
+    }
+}
+
+impl CommandExecutor for BitPos {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let bytes = match ctx.db().get_string(&self.key)? {
+            Some(bytes) => bytes,
+            // a missing key is all clear bits
+            None => return Ok(RespFrame::Integer(if self.bit { -1 } else { 0 })),
+        };
+        let (start, end) =
+            match bit_range(bytes.len(), self.start, self.end.unwrap_or(-1), self.unit) {
+                Some(range) => range,
+                None => return Ok(RespFrame::Integer(-1)),
+            };
+        let found = (start..=end).find(|&offset| get_bit(bytes, offset) == self.bit);
+        let pos = match found {
+            Some(pos) => pos as i64,
+            // looking for a clear bit with no explicit end, the string is as
+            // if padded with zeros, so the first one is right after it
+            None if !self.bit && self.end.is_none() => bytes.len() as i64 * 8,
+            None => -1,
+        };
+        Ok(RespFrame::Integer(pos))
+    }
+}
+
+impl CommandSpec for BitOp {
+    const NAME: &'static str = "bitop";
+    const ARITY: i64 = -4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let op = match args.next_keyword().as_deref() {
+            Some("AND") => BitOperation::And,
+            Some("OR") => BitOperation::Or,
+            Some("XOR") => BitOperation::Xor,
+            Some("NOT") => BitOperation::Not,
+            _ => return Err(CommandError::Syntax),
+        };
+        let dest = args.next_bytes()?;
+        let keys = args.rest();
+        if op == BitOperation::Not && keys.len() != 1 {
+            return Err(CommandError::err(
+                "BITOP NOT must be called with a single source key.",
+            ));
+        }
+        Ok(BitOp { op, dest, keys })
+    }
+}
+
+impl CommandExecutor for BitOp {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        // missing keys count as empty strings, shorter ones as zero padded
+        let mut sources = Vec::with_capacity(self.keys.len());
+        for key in &self.keys {
+            sources.push(db.get_string(key)?.cloned().unwrap_or_default());
+        }
+        let len = sources.iter().map(Vec::len).max().unwrap_or(0);
+        let byte = |src: &Vec<u8>, i: usize| src.get(i).copied().unwrap_or(0);
+        let result: Vec<u8> = (0..len)
+            .map(|i| {
+                let mut bytes = sources.iter().map(|src| byte(src, i));
+                let first = bytes.next().unwrap_or(0);
+                match self.op {
+                    BitOperation::And => bytes.fold(first, |acc, b| acc & b),
+                    BitOperation::Or => bytes.fold(first, |acc, b| acc | b),
+                    BitOperation::Xor => bytes.fold(first, |acc, b| acc ^ b),
+                    BitOperation::Not => !first,
+                }
+            })
+            .collect();
+
+        if result.is_empty() {
+            db.remove(&self.dest);
+        } else {
+            db.insert(self.dest, result.into());
+        }
+        Ok(RespFrame::Integer(len as i64))
+    }
+}
+
+impl Encoding {
+    fn parse(buf: &[u8]) -> Result<Encoding, CommandError> {
+        let error = || {
+            CommandError::err(
+                "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.",
+            )
+        };
+        let (signed, bits) = match buf.split_first() {
+            Some((b'i' | b'I', bits)) => (true, bits),
+            Some((b'u' | b'U', bits)) => (false, bits),
+            _ => return Err(error()),
+        };
+        let bits = std::str::from_utf8(bits)
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .ok_or_else(error)?;
+        let max = if signed { 64 } else { 63 };
+        if bits == 0 || bits > max {
+            return Err(error());
+        }
+        Ok(Encoding { signed, bits })
+    }
+
+    fn min(&self) -> i128 {
+        match self.signed {
+            true => -(1i128 << (self.bits - 1)),
+            false => 0,
+        }
+    }
+
+    fn max(&self) -> i128 {
+        match self.signed {
+            true => (1i128 << (self.bits - 1)) - 1,
+            false => (1i128 << self.bits) - 1,
+        }
+    }
+
+    // read the field at a bit offset; bits past the end of the string are 0
+    fn get(&self, bytes: &[u8], offset: u64) -> i64 {
+        let mut raw: u64 = 0;
+        for i in 0..self.bits as u64 {
+            raw = raw << 1 | get_bit(bytes, offset + i) as u64;
+        }
+        self.wrap(raw)
+    }
+
+    fn set(&self, bytes: &mut Vec<u8>, offset: u64, value: i64) {
+        let raw = value as u64;
+        for i in 0..self.bits as u64 {
+            let bit = raw >> (self.bits as u64 - 1 - i) & 1 == 1;
+            set_bit(bytes, offset + i, bit);
+        }
+    }
+
+    // keep only the low bits of raw, sign extending signed types
+    fn wrap(&self, raw: u64) -> i64 {
+        let shift = 64 - self.bits;
+        match self.signed {
+            true => ((raw << shift) as i64) >> shift,
+            false => ((raw << shift) >> shift) as i64,
+        }
+    }
+
+    // fit a value into the type per the overflow mode, None when it fails
+    fn fit(&self, value: i128, overflow: Overflow) -> Option<i64> {
+        if value >= self.min() && value <= self.max() {
+            return Some(value as i64);
+        }
+        match overflow {
+            Overflow::Wrap => Some(self.wrap(value as u64)),
+            Overflow::Sat if value > self.max() => Some(self.max() as i64),
+            Overflow::Sat => Some(self.min() as i64),
+            Overflow::Fail => None,
+        }
+    }
+}
+
+// a field offset: plain bits, or with a # prefix a multiple of the type width
+fn parse_field_offset(buf: &[u8], encoding: Encoding) -> Result<u64, CommandError> {
+    let offset = match buf.strip_prefix(b"#") {
+        Some(index) => parse_bit_offset(index)?
+            .checked_mul(encoding.bits as u64)
+            .ok_or_else(bit_offset_error)?,
+        None => parse_bit_offset(buf)?,
+    };
+    if offset + encoding.bits as u64 > MAX_STRING_SIZE as u64 * 8 {
+        return Err(bit_offset_error());
+    }
+    Ok(offset)
+}
+
+// the subcommands of BITFIELD, or of BITFIELD_RO when read_only
+fn parse_field_ops(args: &mut CommandArgs, read_only: bool) -> Result<Vec<FieldOp>, CommandError> {
+    let mut ops = Vec::new();
+    let mut overflow = Overflow::Wrap;
+    while let Some(op) = args.next_keyword() {
+        if read_only && op != "GET" {
+            return Err(CommandError::err(
+                "BITFIELD_RO only supports the GET subcommand",
+            ));
+        }
+        if op == "OVERFLOW" {
+            overflow = match args.next_keyword().as_deref() {
+                Some("WRAP") => Overflow::Wrap,
+                Some("SAT") => Overflow::Sat,
+                Some("FAIL") => Overflow::Fail,
+                _ => return Err(CommandError::err("Invalid OVERFLOW type specified")),
+            };
+            continue;
+        }
+        let encoding = Encoding::parse(&args.next_bytes()?)?;
+        let offset = parse_field_offset(&args.next_bytes()?, encoding)?;
+        ops.push(match op.as_str() {
+            "GET" => FieldOp::Get(encoding, offset),
+            "SET" => FieldOp::Set(encoding, offset, args.next_i64()?, overflow),
+            "INCRBY" => FieldOp::IncrBy(encoding, offset, args.next_i64()?, overflow),
+            _ => return Err(CommandError::Syntax),
+        });
+    }
+    Ok(ops)
+}
+
+// run the field operations in order, one reply per GET, SET or INCRBY
+fn run_field_ops(
+    ctx: &mut Context,
+    key: &[u8],
+    ops: Vec<FieldOp>,
+) -> Result<RespFrame, CommandError> {
+    let db = ctx.db();
+    let writes = ops.iter().any(|op| !matches!(op, FieldOp::Get(..)));
+    if !writes {
+        // reads see a missing key as all zeros and leave it missing
+        let empty = Vec::new();
+        let bytes = db.get_string(key)?.unwrap_or(&empty);
+        let values = ops
+            .iter()
+            .map(|op| match *op {
+                FieldOp::Get(encoding, offset) => RespFrame::Integer(encoding.get(bytes, offset)),
+                _ => unreachable!("only reads here"),
+            })
+            .collect::<Vec<_>>();
+        return Ok(RespArray::new(values).into());
+    }
+
+    let end = ops
+        .iter()
+        .filter(|op| !matches!(op, FieldOp::Get(..)))
+        .map(|op| match *op {
+            FieldOp::Get(e, offset)
+            | FieldOp::Set(e, offset, ..)
+            | FieldOp::IncrBy(e, offset, ..) => offset + e.bits as u64,
+        })
+        .max()
+        .unwrap_or(0);
+    check_string_size(end.div_ceil(8) as usize)?;
+    let bytes = db.get_or_create_string(key)?;
+    let mut values = Vec::with_capacity(ops.len());
+    for op in ops {
+        values.push(match op {
+            FieldOp::Get(encoding, offset) => RespFrame::Integer(encoding.get(bytes, offset)),
+            FieldOp::Set(encoding, offset, value, overflow) => {
+                let old = encoding.get(bytes, offset);
+                match encoding.fit(value as i128, overflow) {
+                    Some(value) => {
+                        encoding.set(bytes, offset, value);
+                        RespFrame::Integer(old)
+                    }
+                    None => nil(),
+                }
+            }
+            FieldOp::IncrBy(encoding, offset, incr, overflow) => {
+                let old = encoding.get(bytes, offset);
+                match encoding.fit(old as i128 + incr as i128, overflow) {
+                    Some(value) => {
+                        encoding.set(bytes, offset, value);
+                        RespFrame::Integer(value)
+                    }
+                    None => nil(),
+                }
+            }
+        });
+    }
+    db.touch(key);
+    Ok(RespArray::new(values).into())
+}
+
+impl CommandSpec for BitField {
+    const NAME: &'static str = "bitfield";
+    const ARITY: i64 = -2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(BitField {
+            key: args.next_bytes()?,
+            ops: parse_field_ops(args, false)?,
+        })
+    }
+}
+
+impl CommandExecutor for BitField {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        run_field_ops(ctx, &self.key, self.ops)
+    }
+}
+
+impl CommandSpec for BitFieldRo {
+    const NAME: &'static str = "bitfield_ro";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(BitFieldRo {
+            key: args.next_bytes()?,
+            ops: parse_field_ops(args, true)?,
+        })
+    }
+}
+
+impl CommandExecutor for BitFieldRo {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        run_field_ops(ctx, &self.key, self.ops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::TestContext;
+    use crate::BulkString;
+
+    fn int(n: i64) -> RespFrame {
+        RespFrame::Integer(n)
+    }
+
+    fn ints(values: &[i64]) -> RespFrame {
+        RespArray::new(values.iter().map(|&n| int(n)).collect::<Vec<_>>()).into()
+    }
+
+    #[test]
+    fn test_setbit_getbit() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("setbit k 7 1"), int(0));
+        assert_eq!(t.run("setbit k 7 1"), int(1));
+        assert_eq!(t.run("get k"), BulkString::new("\x01").into());
+        assert_eq!(t.run("setbit k 9 1"), int(0));
+        assert_eq!(t.run("strlen k"), int(2));
+        assert_eq!(t.run("getbit k 9"), int(1));
+        assert_eq!(t.run("getbit k 8"), int(0));
+        assert_eq!(t.run("getbit k 1000"), int(0));
+        assert_eq!(t.run("getbit missing 0"), int(0));
+        assert_eq!(
+            t.run("setbit k 0 2"),
+            CommandError::err("bit is not an integer or out of range").into()
+        );
+        assert_eq!(t.run("setbit k -1 1"), bit_offset_error().into());
+        assert_eq!(t.run("setbit k 4294967296 1"), bit_offset_error().into());
+    }
+
+    #[test]
+    fn test_bitcount_bitpos() {
+        let mut t = TestContext::default();
+        t.run("set k foobar");
+        assert_eq!(t.run("bitcount k"), int(26));
+        assert_eq!(t.run("bitcount k 0 0"), int(4));
+        assert_eq!(t.run("bitcount k 1 1"), int(6));
+        assert_eq!(t.run("bitcount k 1 1 byte"), int(6));
+        assert_eq!(t.run("bitcount k 5 30 bit"), int(17));
+        assert_eq!(t.run("bitcount k -2 -1"), int(7));
+        assert_eq!(t.run("bitcount k 0"), CommandError::Syntax.into());
+        assert_eq!(t.run("bitcount missing"), int(0));
+
+        assert_eq!(t.run("bitpos missing 0"), int(0));
+        assert_eq!(t.run("bitpos missing 1"), int(-1));
+        t.run("setbit b 8 1");
+        t.run("setbit b 9 1");
+        t.run("setbit b 10 1");
+        // b is 00000000 11100000
+        assert_eq!(t.run("bitpos b 1"), int(8));
+        assert_eq!(t.run("bitpos b 0 1"), int(11));
+        assert_eq!(t.run("bitpos b 1 9 15 bit"), int(9));
+        assert_eq!(t.run("bitpos b 1 11 15 bit"), int(-1));
+        for i in 0..8 {
+            t.run(&format!("setbit ones {} 1", i));
+        }
+        // all ones: with no end the first clear bit is past the string
+        assert_eq!(t.run("bitpos ones 0"), int(8));
+        assert_eq!(t.run("bitpos ones 0 0 -1"), int(-1));
+        assert_eq!(
+            t.run("bitpos b 2"),
+            CommandError::err("The bit argument must be 1 or 0.").into()
+        );
+    }
+
+    #[test]
+    fn test_bitop() {
+        let mut t = TestContext::default();
+        t.run("set a abc");
+        t.run("set b a");
+        assert_eq!(t.run("bitop and d a b"), int(3));
+        assert_eq!(
+            t.run("get d"),
+            BulkString::new(b"a\x00\x00".to_vec()).into()
+        );
+        assert_eq!(t.run("bitop or d a b missing"), int(3));
+        assert_eq!(t.run("get d"), BulkString::new("abc").into());
+        assert_eq!(t.run("bitop xor d a a"), int(3));
+        assert_eq!(t.run("get d"), BulkString::new(vec![0, 0, 0]).into());
+        assert_eq!(t.run("bitop not d b"), int(1));
+        assert_eq!(t.run("get d"), BulkString::new(vec![!b'a']).into());
+        assert_eq!(
+            t.run("bitop not d a b"),
+            CommandError::err("BITOP NOT must be called with a single source key.").into()
+        );
+        // an empty result deletes the destination
+        assert_eq!(t.run("bitop and d missing"), int(0));
+        assert_eq!(t.run("get d"), nil());
+    }
+
+    #[test]
+    fn test_bitfield() {
+        let mut t = TestContext::default();
+        assert_eq!(
+            t.run("bitfield k set i8 0 100 get i8 0 incrby i8 0 100"),
+            RespArray::new(vec![int(0), int(100), int(-56)]).into()
+        );
+        assert_eq!(
+            t.run("bitfield k overflow sat incrby i8 0 -100 overflow fail incrby i8 0 -1"),
+            RespArray::new(vec![int(-128), nil()]).into()
+        );
+        assert_eq!(
+            t.run("bitfield k set u4 #1 15 get u4 8 get u4 #1 incrby u4 #1 1"),
+            ints(&[0, 0, 15, 0])
+        );
+        assert_eq!(
+            t.run("bitfield k overflow sat incrby u2 100 5 overflow fail set u2 100 4"),
+            RespArray::new(vec![int(3), nil()]).into()
+        );
+        assert_eq!(t.run("bitfield k get i64 0"), ints(&[i64::MIN]));
+        assert_eq!(t.run("bitfield_ro k get u8 0"), ints(&[128]));
+        assert_eq!(
+            t.run("bitfield_ro k set u8 0 1"),
+            CommandError::err("BITFIELD_RO only supports the GET subcommand").into()
+        );
+        assert_eq!(
+            t.run("bitfield k get u64 0"),
+            CommandError::err("Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.").into()
+        );
+        assert_eq!(
+            t.run("bitfield k overflow nope"),
+            CommandError::err("Invalid OVERFLOW type specified").into()
+        );
+        // reads alone don't create the key
+        assert_eq!(t.run("bitfield missing get u8 0"), ints(&[0]));
+        assert_eq!(t.run("get missing"), nil());
+        assert_eq!(t.run("bitfield k"), ints(&[]));
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{BulkString, Protocol, RespArray, RespFrame, RespMap, SimpleString};
+
+use super::{ok, CommandArgs, CommandError, CommandExecutor, CommandSpec, Context};
+
+// PING [message]
+#[derive(Debug)]
+pub struct Ping {
+    message: Option<Vec<u8>>,
+}
+
+// ECHO message
+#[derive(Debug)]
+pub struct Echo {
+    message: Vec<u8>,
+}
+
+// SELECT index
+#[derive(Debug)]
+pub struct Select {
+    index: i64,
+}
+
+// HELLO [protover [AUTH username password] [SETNAME clientname]]
+#[derive(Debug)]
+pub struct Hello {
+    protocol: Option<i64>,
+    name: Option<String>,
+}
+
+// QUIT
+#[derive(Debug)]
+pub struct Quit;
+
+// RESET
+#[derive(Debug)]
+pub struct Reset;
+
+impl CommandSpec for Ping {
+    const NAME: &'static str = "ping";
+    const ARITY: i64 = -1;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        if args.len() > 1 {
+            return Err(CommandError::WrongArity(Self::NAME.to_string()));
+        }
+        Ok(Ping {
+            message: args.next_bytes().ok(),
+        })
+    }
+}
+
+impl CommandExecutor for Ping {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        // a subscribed RESP2 client gets a reply shaped like its messages
+        if !ctx.client.is_resp3() && ctx.backend.subscriptions.is_subscribed(ctx.client.id) {
+            let message = self.message.unwrap_or_default();
+            return Ok(RespArray::new(vec![
+                BulkString::new("pong").into(),
+                BulkString::new(message).into(),
+            ])
+            .into());
+        }
+        Ok(match self.message {
+            Some(message) => BulkString::new(message).into(),
+            None => SimpleString::new("PONG").into(),
+        })
+    }
+}
+
+impl CommandSpec for Echo {
+    const NAME: &'static str = "echo";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Echo {
+            message: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Echo {
+    fn execute(self, _ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(BulkString::new(self.message).into())
+    }
+}
+
+impl CommandSpec for Select {
+    const NAME: &'static str = "select";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Select {
+            index: args.next_i64()?,
+        })
+    }
+}
+
+impl CommandExecutor for Select {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        ctx.client.db = ctx.db_index(self.index)?;
+        Ok(ok())
+    }
+}
+
+impl CommandSpec for Hello {
+    const NAME: &'static str = "hello";
+    const ARITY: i64 = -1;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let mut hello = Hello {
+            protocol: None,
+            name: None,
+        };
+        if args.is_empty() {
+            return Ok(hello);
+        }
+        hello.protocol = Some(args.next_i64().map_err(|_| {
+            CommandError::err("Protocol version is not an integer or out of range")
+        })?);
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                // there is no ACL yet, so credentials are accepted and ignored
+                "AUTH" if args.len() >= 2 => {
+                    args.next_bytes()?;
+                    args.next_bytes()?;
+                }
+                "SETNAME" if !args.is_empty() => hello.name = Some(args.next_string()?),
+                _ => {
+                    return Err(CommandError::err(format!(
+                        "Syntax error in HELLO option '{}'",
+                        opt
+                    )))
+                }
+            }
+        }
+        Ok(hello)
+    }
+}
+
+impl CommandExecutor for Hello {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        match self.protocol {
+            None => {}
+            Some(2) => ctx.client.protocol = Protocol::Resp2,
+            Some(3) => ctx.client.protocol = Protocol::Resp3,
+            Some(_) => {
+                return Err(CommandError::Reply(
+                    "NOPROTO unsupported protocol version".to_string(),
+                ))
+            }
+        }
+        if let Some(name) = self.name {
+            ctx.client.name = Some(name);
+        }
+
+        let proto = if ctx.client.is_resp3() { 3 } else { 2 };
+        let info: Vec<(&str, RespFrame)> = vec![
+            ("server", BulkString::new("redis").into()),
+            ("version", BulkString::new(env!("CARGO_PKG_VERSION")).into()),
+            ("proto", RespFrame::Integer(proto)),
+            ("id", RespFrame::Integer(ctx.client.id as i64)),
+            ("mode", BulkString::new("standalone").into()),
+            ("role", BulkString::new("master").into()),
+            ("modules", RespArray::new(vec![]).into()),
+        ];
+        if ctx.client.is_resp3() {
+            let mut map = RespMap::new();
+            for (k, v) in info {
+                map.insert(k.into(), v);
+            }
+            Ok(map.into())
+        } else {
+            let mut arr = Vec::with_capacity(info.len() * 2);
+            for (k, v) in info {
+                arr.push(BulkString::new(k).into());
+                arr.push(v);
+            }
+            Ok(RespArray::new(arr).into())
+        }
+    }
+}
+
+impl CommandSpec for Quit {
+    const NAME: &'static str = "quit";
+    const ARITY: i64 = -1;
+
+    fn parse(_args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Quit)
+    }
+}
+
+impl CommandExecutor for Quit {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        ctx.close();
+        Ok(ok())
+    }
+}
+
+impl CommandSpec for Reset {
+    const NAME: &'static str = "reset";
+    const ARITY: i64 = 1;
+
+    fn parse(_args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Reset)
+    }
+}
+
+// the connection goes back to how it was when it opened, though it keeps
+// its id and name: no transaction, no watches, no subscriptions, RESP2 and
+// database 0
+impl CommandExecutor for Reset {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        ctx.backend.disconnect(ctx.client.id);
+        ctx.client.transaction = None;
+        ctx.client.protocol = Protocol::Resp2;
+        ctx.client.db = 0;
+        Ok(SimpleString::new("RESET").into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::{nil, Reply, TestContext};
+    use crate::SimpleError;
+
+    #[test]
+    fn test_ping_echo() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("ping"), SimpleString::new("PONG").into());
+        assert_eq!(t.run("ping hi"), BulkString::new("hi").into());
+        assert_eq!(t.run("echo hi"), BulkString::new("hi").into());
+    }
+
+    #[test]
+    fn test_hello_switches_protocol() {
+        let mut t = TestContext::default();
+        assert!(matches!(t.run("hello"), RespFrame::Array(_)));
+        assert!(matches!(t.run("hello 3 setname app"), RespFrame::Map(_)));
+        assert!(t.client.is_resp3());
+        assert_eq!(t.client.name.as_deref(), Some("app"));
+        assert!(matches!(t.run("hello 4"), RespFrame::Error(_)));
+        assert!(t.client.is_resp3());
+        t.run("hello 2");
+        assert!(!t.client.is_resp3());
+    }
+
+    #[test]
+    fn test_select() {
+        let mut t = TestContext::default();
+        t.run("set k db0");
+        assert_eq!(t.run("select 3"), SimpleString::new("OK").into());
+        assert_eq!(t.client.db, 3);
+        assert_eq!(t.run("get k"), nil());
+        t.run("set k db3");
+        t.run("select 0");
+        assert_eq!(t.run("get k"), BulkString::new("db0").into());
+
+        let out_of_range = SimpleError::new("ERR DB index is out of range").into();
+        assert_eq!(t.run("select 16"), out_of_range);
+        assert_eq!(t.run("select -1"), out_of_range);
+        assert_eq!(t.run("select x"), CommandError::NotInteger.into());
+        assert_eq!(t.client.db, 0);
+    }
+
+    #[test]
+    fn test_quit_and_reset() {
+        let mut t = TestContext::default();
+        t.run("hello 3 setname app");
+        t.run("select 2");
+        t.run("watch k");
+        t.run("subscribe news");
+        t.run("multi");
+        assert_eq!(t.run("reset"), SimpleString::new("RESET").into());
+        assert!(!t.client.is_resp3());
+        assert_eq!(t.client.db, 0);
+        assert!(t.client.transaction.is_none());
+        assert!(t.backend.watches.is_empty());
+        assert!(!t.backend.subscriptions.is_subscribed(t.client.id));
+        assert_eq!(t.client.name.as_deref(), Some("app"));
+
+        // a subscribed RESP2 client may still quit, and so may one in MULTI
+        for setup in ["subscribe news", "multi"] {
+            t.run("reset");
+            t.run(setup);
+            match t.exec("quit") {
+                Reply::Close(reply) => assert_eq!(reply, ok()),
+                reply => panic!("quit replied {:?}", reply),
+            }
+        }
+    }
+}
@@ -0,0 +1,1560 @@
+use std::time::Duration;
+
+use crate::backend::{now_ms, ConsumerGroup, Db, Stream, StreamFields, StreamId};
+use crate::{BulkString, RespArray, RespFrame, RespMap, RespNullArray};
+
+use super::{
+    check_subcommand_arity, entries_reply, entry_reply, nil, ok, parse_block, parse_id,
+    parse_range_id, parse_streams, streams_reply, BlockingCommand, CommandArgs, CommandError,
+    CommandExecutor, CommandSpec, Context,
+};
+
+// XAUTOCLAIM looks at up to this many pending entries per entry it may claim
+const AUTOCLAIM_ATTEMPTS_FACTOR: i64 = 10;
+
+// XGROUP CREATE key group id | $ [MKSTREAM] [ENTRIESREAD entries-read]
+// XGROUP SETID key group id | $ [ENTRIESREAD entries-read]
+// XGROUP DESTROY key group
+// XGROUP CREATECONSUMER key group consumer
+// XGROUP DELCONSUMER key group consumer
+#[derive(Debug)]
+pub enum XGroup {
+    Create {
+        key: Vec<u8>,
+        group: Vec<u8>,
+        id: GroupStart,
+        mkstream: bool,
+        entries_read: Option<u64>,
+    },
+    SetId {
+        key: Vec<u8>,
+        group: Vec<u8>,
+        id: GroupStart,
+        entries_read: Option<u64>,
+    },
+    Destroy {
+        key: Vec<u8>,
+        group: Vec<u8>,
+    },
+    CreateConsumer {
+        key: Vec<u8>,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+    },
+    DelConsumer {
+        key: Vec<u8>,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+    },
+}
+
+// where a group starts reading: after an id, or after the last id ($)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupStart {
+    Id(StreamId),
+    Last,
+}
+
+// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key [key ...] id [id ...]
+#[derive(Debug)]
+pub struct XReadGroup {
+    group: Vec<u8>,
+    consumer: Vec<u8>,
+    count: Option<usize>,
+    block: Option<Option<Duration>>,
+    noack: bool,
+    keys: Vec<Vec<u8>>,
+    from: Vec<GroupRead>,
+}
+
+// > reads entries never delivered to the group, an id reads the consumer's
+// own pending entries after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupRead {
+    New,
+    History(StreamId),
+}
+
+// XACK key group id [id ...]
+#[derive(Debug)]
+pub struct XAck {
+    key: Vec<u8>,
+    group: Vec<u8>,
+    ids: Vec<StreamId>,
+}
+
+// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+#[derive(Debug)]
+pub struct XPending {
+    key: Vec<u8>,
+    group: Vec<u8>,
+    range: Option<PendingRange>,
+}
+
+#[derive(Debug)]
+struct PendingRange {
+    min_idle: u64,
+    start: StreamId,
+    end: StreamId,
+    count: usize,
+    consumer: Option<Vec<u8>>,
+}
+
+// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds]
+//   [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID lastid]
+#[derive(Debug)]
+pub struct XClaim {
+    key: Vec<u8>,
+    group: Vec<u8>,
+    consumer: Vec<u8>,
+    min_idle: u64,
+    ids: Vec<StreamId>,
+    idle: Option<u64>,
+    time: Option<u64>,
+    retry_count: Option<u64>,
+    force: bool,
+    just_id: bool,
+    last_id: Option<StreamId>,
+}
+
+// XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]
+#[derive(Debug)]
+pub struct XAutoClaim {
+    key: Vec<u8>,
+    group: Vec<u8>,
+    consumer: Vec<u8>,
+    min_idle: u64,
+    start: StreamId,
+    count: usize,
+    just_id: bool,
+}
+
+// XINFO STREAM key [FULL [COUNT count]]
+// XINFO GROUPS key
+// XINFO CONSUMERS key group
+#[derive(Debug)]
+pub enum XInfo {
+    Stream { key: Vec<u8>, full: Option<usize> },
+    Groups { key: Vec<u8> },
+    Consumers { key: Vec<u8>, group: Vec<u8> },
+}
+
+fn no_group(key: &[u8], group: &[u8]) -> CommandError {
+    CommandError::Reply(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        String::from_utf8_lossy(key),
+        String::from_utf8_lossy(group)
+    ))
+}
+
+fn no_group_for_key(key: &[u8], group: &[u8]) -> CommandError {
+    CommandError::Reply(format!(
+        "NOGROUP No such consumer group '{}' for key name '{}'",
+        String::from_utf8_lossy(group),
+        String::from_utf8_lossy(key)
+    ))
+}
+
+// the stream at key and one of its groups, or NOGROUP if either is missing
+fn group_mut<'a>(db: &'a mut Db, key: &[u8], group: &[u8]) -> Result<&'a mut Stream, CommandError> {
+    match db.get_stream_mut(key)? {
+        Some(stream) if stream.group(group).is_some() => Ok(stream),
+        _ => Err(no_group(key, group)),
+    }
+}
+
+// a field/value reply: a map on RESP3, a flat array of pairs on RESP2
+pub(crate) fn info_reply(resp3: bool, info: Vec<(&str, RespFrame)>) -> RespFrame {
+    if resp3 {
+        let mut map = RespMap::new();
+        for (k, v) in info {
+            map.insert(k.into(), v);
+        }
+        map.into()
+    } else {
+        let mut arr = Vec::with_capacity(info.len() * 2);
+        for (k, v) in info {
+            arr.push(BulkString::new(k).into());
+            arr.push(v);
+        }
+        RespArray::new(arr).into()
+    }
+}
+
+fn id_reply(id: StreamId) -> RespFrame {
+    BulkString::new(id.to_string()).into()
+}
+
+fn ids_reply(ids: Vec<StreamId>) -> RespFrame {
+    RespArray::new(ids.into_iter().map(id_reply).collect::<Vec<_>>()).into()
+}
+
+// a millisecond duration argument; negative ones count as 0 like in Redis
+fn parse_ms(args: &mut CommandArgs, error: &str) -> Result<u64, CommandError> {
+    let ms = args.next_i64().map_err(|_| CommandError::err(error))?;
+    Ok(ms.max(0) as u64)
+}
+
+impl GroupStart {
+    fn parse(buf: &[u8]) -> Result<GroupStart, CommandError> {
+        match buf {
+            b"$" => Ok(GroupStart::Last),
+            id => parse_id(id, 0).map(GroupStart::Id),
+        }
+    }
+
+    fn resolve(self, stream: &Stream) -> StreamId {
+        match self {
+            GroupStart::Id(id) => id,
+            GroupStart::Last => stream.last_id(),
+        }
+    }
+}
+
+// ENTRIESREAD entries-read, where -1 stands for unknown
+fn parse_entries_read(args: &mut CommandArgs) -> Result<Option<u64>, CommandError> {
+    match args.next_keyword().as_deref() {
+        Some("ENTRIESREAD") => {}
+        _ => return Err(CommandError::Syntax),
+    }
+    let n = args.next_i64()?;
+    if n < -1 {
+        return Err(CommandError::err(
+            "value for ENTRIESREAD must be positive or -1",
+        ));
+    }
+    Ok((n >= 0).then_some(n as u64))
+}
+
+impl CommandSpec for XGroup {
+    const NAME: &'static str = "xgroup";
+    const ARITY: i64 = -2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let sub = args.next_string()?.to_ascii_lowercase();
+        let arity = match sub.as_str() {
+            "create" | "setid" => -5,
+            "destroy" => 4,
+            "createconsumer" | "delconsumer" => 5,
+            _ => {
+                return Err(CommandError::UnknownSubcommand(
+                    sub,
+                    Self::NAME.to_uppercase(),
+                ))
+            }
+        };
+        check_subcommand_arity(args, &sub, arity)?;
+        let key = args.next_bytes()?;
+        let group = args.next_bytes()?;
+        let cmd = match sub.as_str() {
+            "create" => {
+                let id = GroupStart::parse(&args.next_bytes()?)?;
+                let mut mkstream = false;
+                let mut entries_read = None;
+                while let Some(opt) = args.peek_keyword() {
+                    match opt.as_str() {
+                        "MKSTREAM" => {
+                            args.next_bytes()?;
+                            mkstream = true;
+                        }
+                        _ => entries_read = parse_entries_read(args)?,
+                    }
+                }
+                XGroup::Create {
+                    key,
+                    group,
+                    id,
+                    mkstream,
+                    entries_read,
+                }
+            }
+            "setid" => {
+                let id = GroupStart::parse(&args.next_bytes()?)?;
+                let entries_read = match args.is_empty() {
+                    true => None,
+                    false => parse_entries_read(args)?,
+                };
+                XGroup::SetId {
+                    key,
+                    group,
+                    id,
+                    entries_read,
+                }
+            }
+            "destroy" => XGroup::Destroy { key, group },
+            "createconsumer" => XGroup::CreateConsumer {
+                key,
+                group,
+                consumer: args.next_bytes()?,
+            },
+            _ => XGroup::DelConsumer {
+                key,
+                group,
+                consumer: args.next_bytes()?,
+            },
+        };
+        args.finish()?;
+        Ok(cmd)
+    }
+}
+
+impl XGroup {
+    fn key(&self) -> &[u8] {
+        match self {
+            XGroup::Create { key, .. }
+            | XGroup::SetId { key, .. }
+            | XGroup::Destroy { key, .. }
+            | XGroup::CreateConsumer { key, .. }
+            | XGroup::DelConsumer { key, .. } => key,
+        }
+    }
+}
+
+impl CommandExecutor for XGroup {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        if let XGroup::Create {
+            key,
+            mkstream: true,
+            ..
+        } = &self
+        {
+            db.get_or_create_stream(key)?;
+        }
+        let key = self.key().to_vec();
+        let stream = db.get_stream_mut(&key)?.ok_or_else(|| {
+            CommandError::err(
+                "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.",
+            )
+        })?;
+        let now = now_ms();
+        // the reply, and whether the stream changed
+        let (reply, changed) = match self {
+            XGroup::Create {
+                group,
+                id,
+                entries_read,
+                ..
+            } => {
+                let last_id = id.resolve(stream);
+                if !stream.create_group(group, ConsumerGroup::new(last_id, entries_read)) {
+                    return Err(CommandError::Reply(
+                        "BUSYGROUP Consumer Group name already exists".to_string(),
+                    ));
+                }
+                (ok(), true)
+            }
+            XGroup::SetId {
+                key,
+                group,
+                id,
+                entries_read,
+            } => {
+                let last_id = id.resolve(stream);
+                let cg = stream
+                    .group_mut(&group)
+                    .ok_or_else(|| no_group_for_key(&key, &group))?;
+                cg.last_id = last_id;
+                cg.entries_read = entries_read;
+                (ok(), true)
+            }
+            XGroup::Destroy { group, .. } => {
+                let destroyed = stream.destroy_group(&group);
+                (RespFrame::Integer(destroyed as i64), destroyed)
+            }
+            XGroup::CreateConsumer {
+                key,
+                group,
+                consumer,
+            } => {
+                let cg = stream
+                    .group_mut(&group)
+                    .ok_or_else(|| no_group_for_key(&key, &group))?;
+                let created = cg.create_consumer(&consumer, now);
+                (RespFrame::Integer(created as i64), created)
+            }
+            XGroup::DelConsumer {
+                key,
+                group,
+                consumer,
+            } => {
+                let cg = stream
+                    .group_mut(&group)
+                    .ok_or_else(|| no_group_for_key(&key, &group))?;
+                let deleted = cg.delete_consumer(&consumer);
+                (
+                    RespFrame::Integer(deleted.unwrap_or(0) as i64),
+                    deleted.is_some(),
+                )
+            }
+        };
+        if changed {
+            db.touch(&key);
+        }
+        Ok(reply)
+    }
+}
+
+impl CommandSpec for XReadGroup {
+    const NAME: &'static str = "xreadgroup";
+    const ARITY: i64 = -7;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        if args.next_keyword().as_deref() != Some("GROUP") {
+            return Err(CommandError::Syntax);
+        }
+        let group = args.next_bytes()?;
+        let consumer = args.next_bytes()?;
+        let mut count = None;
+        let mut block = None;
+        let mut noack = false;
+        loop {
+            match args.next_keyword().as_deref() {
+                Some("COUNT") => count = Some(args.next_i64()?.max(0) as usize),
+                Some("BLOCK") => block = Some(parse_block(args)?),
+                Some("NOACK") => noack = true,
+                Some("STREAMS") => break,
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        let (keys, ids) = parse_streams(args)?;
+        let from = ids
+            .iter()
+            .map(|id| match id.as_slice() {
+                b">" => Ok(GroupRead::New),
+                b"$" => Err(CommandError::err(
+                    "The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.",
+                )),
+                id => parse_id(id, 0).map(GroupRead::History),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(XReadGroup {
+            group,
+            consumer,
+            count,
+            block,
+            noack,
+            keys,
+            from,
+        })
+    }
+}
+
+impl XReadGroup {
+    fn no_group(&self, key: &[u8]) -> CommandError {
+        CommandError::Reply(format!(
+            "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+            String::from_utf8_lossy(key),
+            String::from_utf8_lossy(&self.group)
+        ))
+    }
+
+    // the consumer's pending entries after an id, delivered again; entries
+    // deleted from the stream since come back with no fields
+    fn read_history(&self, stream: &mut Stream, after: StreamId, now: u64) -> Vec<RespFrame> {
+        let count = self.count.filter(|&n| n > 0).unwrap_or(usize::MAX);
+        let cg = stream
+            .group(&self.group)
+            .expect("group checked by the caller");
+        let ids: Vec<StreamId> = cg
+            .consumer(&self.consumer)
+            .map(|c| {
+                c.pending
+                    .range(after..)
+                    .filter(|&&id| id > after)
+                    .take(count)
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut entries = Vec::with_capacity(ids.len());
+        for id in ids {
+            match stream.get(id) {
+                Some(fields) => {
+                    let cg = stream
+                        .group_mut(&self.group)
+                        .expect("group checked by the caller");
+                    let count = cg.pending()[&id].delivery_count;
+                    cg.assign(id, &self.consumer, now, count + 1);
+                    entries.push(entry_reply(id, fields));
+                }
+                None => {
+                    entries.push(RespArray::new(vec![id_reply(id), RespNullArray.into()]).into())
+                }
+            }
+        }
+        entries
+    }
+}
+
+impl BlockingCommand for XReadGroup {
+    fn keys(&self) -> Vec<Vec<u8>> {
+        self.keys.clone()
+    }
+
+    fn try_serve(&self, ctx: &mut Context) -> Result<Option<RespFrame>, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let now = now_ms();
+        let mut found = Vec::new();
+        for (key, from) in self.keys.iter().zip(&self.from) {
+            // look before writing: a blocked read must not touch keys it can't serve
+            let has_new = match ctx.db().get_stream(key)? {
+                Some(stream) => match stream.group(&self.group) {
+                    Some(cg) => cg.last_id.next().is_some_and(|start| {
+                        !stream
+                            .range(start, StreamId::MAX, Some(1), false)
+                            .is_empty()
+                    }),
+                    None => return Err(self.no_group(key)),
+                },
+                None => return Err(self.no_group(key)),
+            };
+            match from {
+                GroupRead::New if has_new => {
+                    let stream = ctx.db().get_stream_mut(key)?.expect("stream checked above");
+                    let entries = stream
+                        .read_group(&self.group, &self.consumer, self.count, self.noack, now)
+                        .unwrap_or_default();
+                    ctx.db().touch(key);
+                    found.push((key.clone(), entries_reply(entries)));
+                }
+                GroupRead::New => {}
+                GroupRead::History(after) => {
+                    let stream = ctx.db().get_stream_mut(key)?.expect("stream checked above");
+                    let entries = self.read_history(stream, *after, now);
+                    if !entries.is_empty() {
+                        ctx.db().touch(key);
+                    }
+                    found.push((key.clone(), RespArray::new(entries).into()));
+                }
+            }
+        }
+        Ok(streams_reply(resp3, found))
+    }
+
+    fn timeout_reply(&self) -> RespFrame {
+        RespNullArray.into()
+    }
+}
+
+impl CommandExecutor for XReadGroup {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        // every group has to exist before anything is read, and naming a
+        // consumer creates it even if nothing gets delivered
+        let now = now_ms();
+        for key in &self.keys {
+            match ctx
+                .db()
+                .get_stream_mut(key)?
+                .and_then(|s| s.group_mut(&self.group))
+            {
+                Some(cg) => {
+                    cg.seen(&self.consumer, now);
+                }
+                None => return Err(self.no_group(key)),
+            }
+        }
+        if let Some(reply) = self.try_serve(ctx)? {
+            return Ok(reply);
+        }
+        match self.block {
+            Some(timeout) => ctx.block(self, timeout),
+            None => Ok(RespNullArray.into()),
+        }
+    }
+}
+
+impl CommandSpec for XAck {
+    const NAME: &'static str = "xack";
+    const ARITY: i64 = -4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let group = args.next_bytes()?;
+        let ids = args
+            .rest()
+            .iter()
+            .map(|id| parse_id(id, 0))
+            .collect::<Result<_, _>>()?;
+        Ok(XAck { key, group, ids })
+    }
+}
+
+impl CommandExecutor for XAck {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let acked = match ctx
+            .db()
+            .get_stream_mut(&self.key)?
+            .and_then(|s| s.group_mut(&self.group))
+        {
+            Some(cg) => self.ids.iter().filter(|id| cg.ack(**id)).count(),
+            None => 0,
+        };
+        if acked > 0 {
+            ctx.db().touch(&self.key);
+        }
+        Ok(RespFrame::Integer(acked as i64))
+    }
+}
+
+impl CommandSpec for XPending {
+    const NAME: &'static str = "xpending";
+    const ARITY: i64 = -3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let group = args.next_bytes()?;
+        if args.is_empty() {
+            return Ok(XPending {
+                key,
+                group,
+                range: None,
+            });
+        }
+        let min_idle = match args.peek_keyword().as_deref() {
+            Some("IDLE") => {
+                args.next_bytes()?;
+                args.next_i64()?.max(0) as u64
+            }
+            _ => 0,
+        };
+        if !(3..=4).contains(&args.len()) {
+            return Err(CommandError::Syntax);
+        }
+        let start = parse_range_id(&args.next_bytes()?, true)?;
+        let end = parse_range_id(&args.next_bytes()?, false)?;
+        let count = args.next_i64()?.max(0) as usize;
+        let consumer = match args.is_empty() {
+            true => None,
+            false => Some(args.next_bytes()?),
+        };
+        Ok(XPending {
+            key,
+            group,
+            range: Some(PendingRange {
+                min_idle,
+                start,
+                end,
+                count,
+                consumer,
+            }),
+        })
+    }
+}
+
+impl CommandExecutor for XPending {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let cg = ctx
+            .db()
+            .get_stream(&self.key)?
+            .and_then(|s| s.group(&self.group))
+            .ok_or_else(|| no_group(&self.key, &self.group))?;
+        let pending = cg.pending();
+
+        let range = match self.range {
+            Some(range) => range,
+            // the summary: how many, the id range and the count per consumer
+            None => {
+                let (first, last) = match (pending.keys().next(), pending.keys().next_back()) {
+                    (Some(first), Some(last)) => (*first, *last),
+                    _ => {
+                        return Ok(RespArray::new(vec![
+                            RespFrame::Integer(0),
+                            nil(),
+                            nil(),
+                            RespNullArray.into(),
+                        ])
+                        .into())
+                    }
+                };
+                let consumers = cg
+                    .consumers()
+                    .iter()
+                    .filter(|(_, c)| !c.pending.is_empty())
+                    .map(|(name, c)| {
+                        RespArray::new(vec![
+                            BulkString::new(name.clone()).into(),
+                            BulkString::new(c.pending.len().to_string()).into(),
+                        ])
+                        .into()
+                    })
+                    .collect::<Vec<RespFrame>>();
+                return Ok(RespArray::new(vec![
+                    RespFrame::Integer(pending.len() as i64),
+                    id_reply(first),
+                    id_reply(last),
+                    RespArray::new(consumers).into(),
+                ])
+                .into());
+            }
+        };
+
+        if range.start > range.end {
+            return Ok(RespArray::new(vec![]).into());
+        }
+        let now = now_ms();
+        let items = pending
+            .range(range.start..=range.end)
+            .filter(|(_, entry)| {
+                range
+                    .consumer
+                    .as_ref()
+                    .is_none_or(|consumer| &entry.consumer == consumer)
+            })
+            .filter(|(_, entry)| now.saturating_sub(entry.delivery_time) >= range.min_idle)
+            .take(range.count)
+            .map(|(id, entry)| {
+                RespArray::new(vec![
+                    id_reply(*id),
+                    BulkString::new(entry.consumer.clone()).into(),
+                    RespFrame::Integer(now.saturating_sub(entry.delivery_time) as i64),
+                    RespFrame::Integer(entry.delivery_count as i64),
+                ])
+                .into()
+            })
+            .collect::<Vec<RespFrame>>();
+        Ok(RespArray::new(items).into())
+    }
+}
+
+// the claiming common to XCLAIM and XAUTOCLAIM: hand one pending entry to a
+// consumer if it has been idle long enough. An entry deleted from the stream
+// since can't be claimed and is dropped from the pending list instead
+struct Claim<'a> {
+    group: &'a [u8],
+    consumer: &'a [u8],
+    min_idle: u64,
+    now: u64,
+}
+
+enum Claimed {
+    Entry(StreamFields),
+    Deleted,
+    Skipped,
+}
+
+impl Claim<'_> {
+    fn claim(
+        &self,
+        stream: &mut Stream,
+        id: StreamId,
+        delivery_time: u64,
+        retry_count: Option<u64>,
+        just_id: bool,
+    ) -> Claimed {
+        let fields = stream.get(id);
+        let cg = match stream.group_mut(self.group) {
+            Some(cg) => cg,
+            None => return Claimed::Skipped,
+        };
+        let entry = match cg.pending().get(&id) {
+            Some(entry) => entry,
+            None => return Claimed::Skipped,
+        };
+        if self.now.saturating_sub(entry.delivery_time) < self.min_idle {
+            return Claimed::Skipped;
+        }
+        let fields = match fields {
+            Some(fields) => fields,
+            None => {
+                cg.ack(id);
+                return Claimed::Deleted;
+            }
+        };
+        let count = match (retry_count, just_id) {
+            (Some(count), _) => count,
+            (None, true) => entry.delivery_count,
+            (None, false) => entry.delivery_count + 1,
+        };
+        cg.assign(id, self.consumer, delivery_time, count);
+        cg.seen(self.consumer, self.now).active_time = Some(self.now);
+        Claimed::Entry(fields)
+    }
+}
+
+impl CommandSpec for XClaim {
+    const NAME: &'static str = "xclaim";
+    const ARITY: i64 = -6;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let group = args.next_bytes()?;
+        let consumer = args.next_bytes()?;
+        let min_idle = parse_ms(args, "Invalid min-idle-time argument for XCLAIM")?;
+        // ids run up to the first argument that isn't one
+        let mut ids = vec![parse_id(&args.next_bytes()?, 0)?];
+        while let Some(id) = args.peek_keyword() {
+            match parse_id(id.as_bytes(), 0) {
+                Ok(id) => {
+                    args.next_bytes()?;
+                    ids.push(id);
+                }
+                Err(_) => break,
+            }
+        }
+        let mut cmd = XClaim {
+            key,
+            group,
+            consumer,
+            min_idle,
+            ids,
+            idle: None,
+            time: None,
+            retry_count: None,
+            force: false,
+            just_id: false,
+            last_id: None,
+        };
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                "IDLE" => {
+                    cmd.idle = Some(parse_ms(args, "Invalid IDLE option argument for XCLAIM")?)
+                }
+                "TIME" => {
+                    cmd.time = Some(parse_ms(args, "Invalid TIME option argument for XCLAIM")?)
+                }
+                "RETRYCOUNT" => {
+                    cmd.retry_count = Some(parse_ms(
+                        args,
+                        "Invalid RETRYCOUNT option argument for XCLAIM",
+                    )?)
+                }
+                "FORCE" => cmd.force = true,
+                "JUSTID" => cmd.just_id = true,
+                "LASTID" => cmd.last_id = Some(parse_id(&args.next_bytes()?, 0)?),
+                _ => {
+                    return Err(CommandError::err(format!(
+                        "Unrecognized XCLAIM option '{}'",
+                        opt
+                    )))
+                }
+            }
+        }
+        Ok(cmd)
+    }
+}
+
+impl CommandExecutor for XClaim {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let now = now_ms();
+        let stream = group_mut(ctx.db(), &self.key, &self.group)?;
+        let delivery_time = match (self.time, self.idle) {
+            (Some(time), _) => time,
+            (None, Some(idle)) => now.saturating_sub(idle),
+            (None, None) => now,
+        };
+        let claim = Claim {
+            group: &self.group,
+            consumer: &self.consumer,
+            min_idle: self.min_idle,
+            now,
+        };
+
+        let cg = stream.group_mut(&self.group).expect("group checked above");
+        cg.seen(&self.consumer, now);
+        if let Some(last_id) = self.last_id {
+            cg.last_id = cg.last_id.max(last_id);
+        }
+        let mut claimed = Vec::new();
+        let mut deleted = false;
+        for id in self.ids {
+            // FORCE puts entries of the stream nobody was delivered in the
+            // pending list, as if they had been
+            let is_pending = stream
+                .group(&self.group)
+                .is_some_and(|cg| cg.pending().contains_key(&id));
+            if !is_pending && self.force && stream.get(id).is_some() {
+                let cg = stream.group_mut(&self.group).expect("group checked above");
+                cg.assign(id, &self.consumer, 0, 0);
+            }
+            match claim.claim(stream, id, delivery_time, self.retry_count, self.just_id) {
+                Claimed::Entry(fields) => claimed.push(match self.just_id {
+                    true => id_reply(id),
+                    false => entry_reply(id, fields),
+                }),
+                Claimed::Deleted => deleted = true,
+                Claimed::Skipped => {}
+            }
+        }
+        if !claimed.is_empty() || deleted {
+            ctx.db().touch(&self.key);
+        }
+        Ok(RespArray::new(claimed).into())
+    }
+}
+
+impl CommandSpec for XAutoClaim {
+    const NAME: &'static str = "xautoclaim";
+    const ARITY: i64 = -6;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let group = args.next_bytes()?;
+        let consumer = args.next_bytes()?;
+        let min_idle = parse_ms(args, "Invalid min-idle-time argument for XAUTOCLAIM")?;
+        let start = parse_range_id(&args.next_bytes()?, true)?;
+        let mut count = 100;
+        let mut just_id = false;
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                "COUNT" => {
+                    let n = args.next_i64()?;
+                    if !(1..=i64::MAX / AUTOCLAIM_ATTEMPTS_FACTOR).contains(&n) {
+                        return Err(CommandError::err("COUNT must be > 0"));
+                    }
+                    count = n as usize;
+                }
+                "JUSTID" => just_id = true,
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok(XAutoClaim {
+            key,
+            group,
+            consumer,
+            min_idle,
+            start,
+            count,
+            just_id,
+        })
+    }
+}
+
+impl CommandExecutor for XAutoClaim {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let now = now_ms();
+        let stream = group_mut(ctx.db(), &self.key, &self.group)?;
+        let claim = Claim {
+            group: &self.group,
+            consumer: &self.consumer,
+            min_idle: self.min_idle,
+            now,
+        };
+
+        let cg = stream.group_mut(&self.group).expect("group checked above");
+        cg.seen(&self.consumer, now);
+        let attempts = self.count * AUTOCLAIM_ATTEMPTS_FACTOR as usize;
+        // scan one entry past the attempts, which becomes the next cursor
+        let mut scan: Vec<StreamId> = cg
+            .pending()
+            .range(self.start..)
+            .take(attempts + 1)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut cursor = match scan.len() > attempts {
+            true => scan.pop().unwrap_or(StreamId::MIN),
+            false => StreamId::MIN,
+        };
+
+        let mut claimed = Vec::new();
+        let mut deleted = Vec::new();
+        for (i, &id) in scan.iter().enumerate() {
+            if claimed.len() >= self.count {
+                cursor = scan[i];
+                break;
+            }
+            match claim.claim(stream, id, now, None, self.just_id) {
+                Claimed::Entry(fields) => claimed.push(match self.just_id {
+                    true => id_reply(id),
+                    false => entry_reply(id, fields),
+                }),
+                Claimed::Deleted => deleted.push(id),
+                Claimed::Skipped => {}
+            }
+        }
+        if !claimed.is_empty() || !deleted.is_empty() {
+            ctx.db().touch(&self.key);
+        }
+        Ok(RespArray::new(vec![
+            id_reply(cursor),
+            RespArray::new(claimed).into(),
+            ids_reply(deleted),
+        ])
+        .into())
+    }
+}
+
+impl CommandSpec for XInfo {
+    const NAME: &'static str = "xinfo";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let sub = args.next_string()?.to_ascii_lowercase();
+        let arity = match sub.as_str() {
+            "stream" => -3,
+            "groups" => 3,
+            "consumers" => 4,
+            _ => {
+                return Err(CommandError::UnknownSubcommand(
+                    sub,
+                    Self::NAME.to_uppercase(),
+                ))
+            }
+        };
+        check_subcommand_arity(args, &sub, arity)?;
+        let key = args.next_bytes()?;
+        let cmd = match sub.as_str() {
+            "stream" => {
+                let full = match args.next_keyword().as_deref() {
+                    None => None,
+                    Some("FULL") => match args.next_keyword().as_deref() {
+                        None => Some(10),
+                        Some("COUNT") => Some(args.next_i64()?.max(0) as usize),
+                        Some(_) => return Err(CommandError::Syntax),
+                    },
+                    Some(_) => return Err(CommandError::Syntax),
+                };
+                XInfo::Stream { key, full }
+            }
+            "groups" => XInfo::Groups { key },
+            _ => XInfo::Consumers {
+                key,
+                group: args.next_bytes()?,
+            },
+        };
+        args.finish()?;
+        Ok(cmd)
+    }
+}
+
+fn optional_count_reply(id: Option<u64>) -> RespFrame {
+    id.map_or_else(nil, |n| RespFrame::Integer(n as i64))
+}
+
+// the group fields XINFO GROUPS and XINFO STREAM FULL share
+fn group_info<'a>(stream: &Stream, name: &[u8], cg: &ConsumerGroup) -> Vec<(&'a str, RespFrame)> {
+    vec![
+        ("name", BulkString::new(name.to_vec()).into()),
+        ("last-delivered-id", id_reply(cg.last_id)),
+        ("entries-read", optional_count_reply(cg.entries_read)),
+        ("lag", optional_count_reply(stream.lag(cg))),
+    ]
+}
+
+fn stream_info(stream: &Stream, full: Option<usize>, resp3: bool) -> RespFrame {
+    // the chunk index stands in for the radix tree Redis reports on
+    let mut info = vec![
+        ("length", RespFrame::Integer(stream.len() as i64)),
+        (
+            "radix-tree-keys",
+            RespFrame::Integer(stream.chunk_count() as i64),
+        ),
+        (
+            "radix-tree-nodes",
+            RespFrame::Integer(stream.chunk_count() as i64),
+        ),
+        ("last-generated-id", id_reply(stream.last_id())),
+        ("max-deleted-entry-id", id_reply(stream.max_deleted_id())),
+        (
+            "entries-added",
+            RespFrame::Integer(stream.entries_added() as i64),
+        ),
+        ("recorded-first-entry-id", id_reply(stream.first_id())),
+    ];
+    let count = match full {
+        Some(count) => count,
+        None => {
+            let entry = |e: Option<(StreamId, _)>| e.map_or_else(nil, |(id, f)| entry_reply(id, f));
+            info.push(("groups", RespFrame::Integer(stream.groups().len() as i64)));
+            info.push(("first-entry", entry(stream.first_entry())));
+            info.push(("last-entry", entry(stream.last_entry())));
+            return info_reply(resp3, info);
+        }
+    };
+
+    // FULL lists the entries, 0 meaning all of them, and every group in detail
+    let count = (count > 0).then_some(count);
+    info.push((
+        "entries",
+        entries_reply(stream.range(StreamId::MIN, StreamId::MAX, count, false)),
+    ));
+    let take = count.unwrap_or(usize::MAX);
+    let groups = stream
+        .groups()
+        .iter()
+        .map(|(name, cg)| {
+            let mut group = group_info(stream, name, cg);
+            group.push(("pel-count", RespFrame::Integer(cg.pending().len() as i64)));
+            let pending = cg
+                .pending()
+                .iter()
+                .take(take)
+                .map(|(id, entry)| {
+                    RespArray::new(vec![
+                        id_reply(*id),
+                        BulkString::new(entry.consumer.clone()).into(),
+                        RespFrame::Integer(entry.delivery_time as i64),
+                        RespFrame::Integer(entry.delivery_count as i64),
+                    ])
+                    .into()
+                })
+                .collect::<Vec<RespFrame>>();
+            group.push(("pending", RespArray::new(pending).into()));
+            let consumers = cg
+                .consumers()
+                .iter()
+                .map(|(name, consumer)| {
+                    let pending = consumer
+                        .pending
+                        .iter()
+                        .take(take)
+                        .map(|id| {
+                            let entry = &cg.pending()[id];
+                            RespArray::new(vec![
+                                id_reply(*id),
+                                RespFrame::Integer(entry.delivery_time as i64),
+                                RespFrame::Integer(entry.delivery_count as i64),
+                            ])
+                            .into()
+                        })
+                        .collect::<Vec<RespFrame>>();
+                    info_reply(
+                        resp3,
+                        vec![
+                            ("name", BulkString::new(name.clone()).into()),
+                            ("seen-time", RespFrame::Integer(consumer.seen_time as i64)),
+                            (
+                                "active-time",
+                                RespFrame::Integer(consumer.active_time.map_or(-1, |t| t as i64)),
+                            ),
+                            (
+                                "pel-count",
+                                RespFrame::Integer(consumer.pending.len() as i64),
+                            ),
+                            ("pending", RespArray::new(pending).into()),
+                        ],
+                    )
+                })
+                .collect::<Vec<RespFrame>>();
+            group.push(("consumers", RespArray::new(consumers).into()));
+            info_reply(resp3, group)
+        })
+        .collect::<Vec<RespFrame>>();
+    info.push(("groups", RespArray::new(groups).into()));
+    info_reply(resp3, info)
+}
+
+impl CommandExecutor for XInfo {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let now = now_ms();
+        let key = match &self {
+            XInfo::Stream { key, .. } | XInfo::Groups { key } | XInfo::Consumers { key, .. } => {
+                key.clone()
+            }
+        };
+        let stream = ctx
+            .db()
+            .get_stream(&key)?
+            .ok_or_else(|| CommandError::err("no such key"))?;
+        match self {
+            XInfo::Stream { full, .. } => Ok(stream_info(stream, full, resp3)),
+            XInfo::Groups { .. } => {
+                let groups = stream
+                    .groups()
+                    .iter()
+                    .map(|(name, cg)| {
+                        let mut info = group_info(stream, name, cg);
+                        info.insert(
+                            1,
+                            ("consumers", RespFrame::Integer(cg.consumers().len() as i64)),
+                        );
+                        info.insert(
+                            2,
+                            ("pending", RespFrame::Integer(cg.pending().len() as i64)),
+                        );
+                        info_reply(resp3, info)
+                    })
+                    .collect::<Vec<RespFrame>>();
+                Ok(RespArray::new(groups).into())
+            }
+            XInfo::Consumers { group, .. } => {
+                let cg = stream
+                    .group(&group)
+                    .ok_or_else(|| no_group_for_key(&key, &group))?;
+                let consumers = cg
+                    .consumers()
+                    .iter()
+                    .map(|(name, consumer)| {
+                        let inactive = consumer
+                            .active_time
+                            .map_or(-1, |t| now.saturating_sub(t) as i64);
+                        info_reply(
+                            resp3,
+                            vec![
+                                ("name", BulkString::new(name.clone()).into()),
+                                ("pending", RespFrame::Integer(consumer.pending.len() as i64)),
+                                (
+                                    "idle",
+                                    RespFrame::Integer(
+                                        now.saturating_sub(consumer.seen_time) as i64
+                                    ),
+                                ),
+                                ("inactive", RespFrame::Integer(inactive)),
+                            ],
+                        )
+                    })
+                    .collect::<Vec<RespFrame>>();
+                Ok(RespArray::new(consumers).into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::TestContext;
+    use crate::Client;
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    fn int(n: i64) -> RespFrame {
+        RespFrame::Integer(n)
+    }
+
+    fn array(items: Vec<RespFrame>) -> RespFrame {
+        RespArray::new(items).into()
+    }
+
+    fn entry(id: &str, field: &str, value: &str) -> RespFrame {
+        array(vec![bulk(id), array(vec![bulk(field), bulk(value)])])
+    }
+
+    fn read_reply(key: &str, entries: Vec<RespFrame>) -> RespFrame {
+        array(vec![array(vec![bulk(key), array(entries)])])
+    }
+
+    // an extended XPENDING reply with the idle times zeroed, as they depend
+    // on how long the test took
+    fn without_idle(reply: RespFrame) -> RespFrame {
+        let RespFrame::Array(mut rows) = reply else {
+            panic!("not an array: {:?}", reply);
+        };
+        for row in rows.iter_mut() {
+            let RespFrame::Array(row) = row else {
+                panic!("not an array: {:?}", row);
+            };
+            row[2] = int(0);
+        }
+        rows.into()
+    }
+
+    // the value of one field of a RESP2 info reply
+    fn field(info: &RespFrame, name: &str) -> RespFrame {
+        let RespFrame::Array(arr) = info else {
+            panic!("not an array: {:?}", info);
+        };
+        let items = &arr[..];
+        let pos = items
+            .iter()
+            .position(|item| *item == bulk(name))
+            .unwrap_or_else(|| panic!("no field {}", name));
+        match &items[pos + 1] {
+            RespFrame::Integer(n) => int(*n),
+            RespFrame::BulkString(b) => BulkString::new(b.to_vec()).into(),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_xgroup() {
+        let mut t = TestContext::default();
+        assert_eq!(
+            t.run("xgroup create s g $"),
+            CommandError::err("The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.").into()
+        );
+        assert_eq!(t.run("xgroup create s g $ mkstream"), ok());
+        assert_eq!(
+            t.run("xgroup create s g 0"),
+            CommandError::Reply("BUSYGROUP Consumer Group name already exists".to_string()).into()
+        );
+        assert_eq!(t.run("xgroup createconsumer s g alice"), int(1));
+        assert_eq!(t.run("xgroup createconsumer s g alice"), int(0));
+        assert_eq!(t.run("xgroup delconsumer s g alice"), int(0));
+        assert_eq!(
+            t.run("xgroup setid s nope 0"),
+            no_group_for_key(b"s", b"nope").into()
+        );
+        assert_eq!(t.run("xgroup setid s g 0 entriesread 0"), ok());
+        assert_eq!(t.run("xgroup destroy s g"), int(1));
+        assert_eq!(t.run("xgroup destroy s g"), int(0));
+        assert_eq!(
+            t.run("xgroup frob s g"),
+            CommandError::UnknownSubcommand("frob".to_string(), "XGROUP".to_string()).into()
+        );
+        assert_eq!(
+            t.run("xgroup destroy s"),
+            CommandError::WrongArity("xgroup|destroy".to_string()).into()
+        );
+    }
+
+    #[test]
+    fn test_xreadgroup_and_pending() {
+        let mut t = TestContext::default();
+        for i in 1..=3 {
+            t.run(&format!("xadd s {}-0 n {}", i, i));
+        }
+        t.run("xgroup create s g 0");
+        assert_eq!(
+            t.run("xreadgroup group g alice count 2 streams s >"),
+            read_reply("s", vec![entry("1-0", "n", "1"), entry("2-0", "n", "2")])
+        );
+        assert_eq!(
+            t.run("xpending s g"),
+            array(vec![
+                int(2),
+                bulk("1-0"),
+                bulk("2-0"),
+                array(vec![array(vec![bulk("alice"), bulk("2")])]),
+            ])
+        );
+        // reading the history delivers the entries again
+        assert_eq!(
+            t.run("xreadgroup group g alice streams s 1-0"),
+            read_reply("s", vec![entry("2-0", "n", "2")])
+        );
+        assert_eq!(
+            without_idle(t.run("xpending s g - + 10 alice")),
+            array(vec![
+                array(vec![bulk("1-0"), bulk("alice"), int(0), int(1)]),
+                array(vec![bulk("2-0"), bulk("alice"), int(0), int(2)]),
+            ])
+        );
+        // a deleted entry stays pending, without its fields
+        t.run("xdel s 1-0");
+        assert_eq!(
+            t.run("xreadgroup group g alice count 1 streams s 0"),
+            read_reply("s", vec![array(vec![bulk("1-0"), RespNullArray.into()])])
+        );
+        assert_eq!(t.run("xack s g 1-0 2-0 9-0"), int(2));
+        assert_eq!(
+            t.run("xpending s g"),
+            array(vec![int(0), nil(), nil(), RespNullArray.into()])
+        );
+
+        assert_eq!(
+            t.run("xreadgroup group g bob noack streams s >"),
+            read_reply("s", vec![entry("3-0", "n", "3")])
+        );
+        assert_eq!(
+            t.run("xreadgroup group g bob streams s >"),
+            RespNullArray.into()
+        );
+        assert_eq!(t.run("xpending s g - + 10"), array(vec![]));
+        assert_eq!(
+            t.run("xreadgroup group nope bob streams s >"),
+            CommandError::Reply(
+                "NOGROUP No such key 's' or consumer group 'nope' in XREADGROUP with GROUP option"
+                    .to_string()
+            )
+            .into()
+        );
+
+        let RespFrame::Array(groups) = t.run("xinfo groups s") else {
+            panic!("xinfo groups is an array");
+        };
+        let group = &groups[0];
+        assert_eq!(field(group, "consumers"), int(2));
+        assert_eq!(field(group, "pending"), int(0));
+        assert_eq!(field(group, "last-delivered-id"), bulk("3-0"));
+        assert_eq!(field(group, "lag"), int(0));
+        let info = t.run("xinfo stream s");
+        assert_eq!(field(&info, "length"), int(2));
+        assert_eq!(field(&info, "groups"), int(1));
+        assert_eq!(field(&info, "max-deleted-entry-id"), bulk("1-0"));
+        assert_eq!(
+            t.run("xinfo stream nope"),
+            CommandError::err("no such key").into()
+        );
+    }
+
+    #[test]
+    fn test_lag() {
+        let mut t = TestContext::default();
+        for i in 1..=5 {
+            t.run(&format!("xadd s {}-0 n {}", i, i));
+        }
+        t.run("xgroup create s g 0");
+        t.run("xreadgroup group g alice count 2 streams s >");
+        let RespFrame::Array(groups) = t.run("xinfo groups s") else {
+            panic!("xinfo groups is an array");
+        };
+        let group = &groups[0];
+        assert_eq!(field(group, "entries-read"), int(2));
+        assert_eq!(field(group, "lag"), int(3));
+    }
+
+    #[test]
+    fn test_xclaim_and_xautoclaim() {
+        let mut t = TestContext::default();
+        for i in 1..=4 {
+            t.run(&format!("xadd s {}-0 n {}", i, i));
+        }
+        t.run("xgroup create s g 0");
+        t.run("xreadgroup group g alice streams s >");
+
+        // entries delivered just now aren't idle long enough
+        assert_eq!(t.run("xclaim s g bob 60000 1-0"), array(vec![]));
+        assert_eq!(
+            t.run("xclaim s g bob 0 1-0 2-0 justid"),
+            array(vec![bulk("1-0"), bulk("2-0")])
+        );
+        assert_eq!(
+            t.run("xclaim s g bob 0 3-0 retrycount 7"),
+            array(vec![entry("3-0", "n", "3")])
+        );
+        assert_eq!(
+            without_idle(t.run("xpending s g - + 10 bob")),
+            array(vec![
+                array(vec![bulk("1-0"), bulk("bob"), int(0), int(1)]),
+                array(vec![bulk("2-0"), bulk("bob"), int(0), int(1)]),
+                array(vec![bulk("3-0"), bulk("bob"), int(0), int(7)]),
+            ])
+        );
+        t.run("xack s g 4-0");
+        assert_eq!(t.run("xclaim s g bob 0 4-0"), array(vec![]));
+        assert_eq!(
+            t.run("xclaim s g bob 0 4-0 force justid"),
+            array(vec![bulk("4-0")])
+        );
+
+        // XAUTOCLAIM walks the pending list, dropping deleted entries
+        t.run("xdel s 2-0");
+        assert_eq!(
+            t.run("xautoclaim s g carol 0 - count 1"),
+            array(vec![
+                bulk("2-0"),
+                array(vec![entry("1-0", "n", "1")]),
+                array(vec![])
+            ])
+        );
+        assert_eq!(
+            t.run("xautoclaim s g carol 0 2-0 count 1 justid"),
+            array(vec![
+                bulk("4-0"),
+                array(vec![bulk("3-0")]),
+                array(vec![bulk("2-0")])
+            ])
+        );
+        assert_eq!(
+            t.run("xautoclaim s g carol 0 4-0"),
+            array(vec![
+                bulk("0-0"),
+                array(vec![entry("4-0", "n", "4")]),
+                array(vec![])
+            ])
+        );
+        assert_eq!(
+            t.run("xautoclaim s g carol 0 - count 0"),
+            CommandError::err("COUNT must be > 0").into()
+        );
+        assert_eq!(
+            t.run("xclaim s nope bob 0 1-0"),
+            no_group(b"s", b"nope").into()
+        );
+
+        let RespFrame::Array(consumers) = t.run("xinfo consumers s g") else {
+            panic!("xinfo consumers is an array");
+        };
+        let consumers = &consumers[..];
+        assert_eq!(consumers.len(), 3);
+        assert_eq!(field(&consumers[2], "name"), bulk("carol"));
+        assert_eq!(field(&consumers[2], "pending"), int(3));
+    }
+
+    #[test]
+    fn test_xpending_idle() {
+        let mut t = TestContext::default();
+        for i in 1..=3 {
+            t.run(&format!("xadd s {}-0 n {}", i, i));
+        }
+        t.run("xgroup create s g 0");
+        t.run("xreadgroup group g alice streams s >");
+        // entries claimed with IDLE look that long idle, whenever the test
+        // runs; the others were delivered just now
+        t.run("xclaim s g alice 0 1-0 idle 100000 justid");
+        t.run("xclaim s g bob 0 3-0 idle 200000 justid");
+
+        let RespFrame::Array(rows) = t.run("xpending s g idle 50000 - + 10") else {
+            panic!("xpending is an array");
+        };
+        assert_eq!(rows.len(), 2);
+        for (row, id, consumer, idle) in [
+            (&rows[0], "1-0", "alice", 100000),
+            (&rows[1], "3-0", "bob", 200000),
+        ] {
+            let RespFrame::Array(row) = row else {
+                panic!("not an array: {:?}", row);
+            };
+            assert_eq!(row[0], bulk(id));
+            assert_eq!(row[1], bulk(consumer));
+            let RespFrame::Integer(reported) = row[2] else {
+                panic!("idle is an integer: {:?}", row[2]);
+            };
+            assert!((idle..idle + 10000).contains(&reported));
+            assert_eq!(row[3], int(1));
+        }
+        assert_eq!(
+            without_idle(t.run("xpending s g idle 150000 - + 10")),
+            array(vec![array(vec![bulk("3-0"), bulk("bob"), int(0), int(1)])])
+        );
+        assert_eq!(t.run("xpending s g idle 300000 - + 10 bob"), array(vec![]));
+        // the consumer filter applies on top of the idle one
+        assert_eq!(
+            without_idle(t.run("xpending s g idle 50000 - + 10 alice")),
+            array(vec![array(vec![
+                bulk("1-0"),
+                bulk("alice"),
+                int(0),
+                int(1)
+            ])])
+        );
+    }
+
+    #[test]
+    fn test_xreadgroup_block() {
+        let mut t = TestContext::default();
+        t.run("xgroup create s g $ mkstream");
+        let mut blocked = t.block("xreadgroup group g alice block 0 streams s >");
+        t.client = Client::new();
+        // moving the group's id touches the stream without giving the
+        // reader anything
+        t.run("xgroup setid s g $");
+        assert!(blocked.rx.try_recv().is_err());
+        t.run("xadd s 1-0 n 1");
+        assert_eq!(
+            blocked.rx.try_recv().unwrap(),
+            read_reply("s", vec![entry("1-0", "n", "1")])
+        );
+        assert_eq!(
+            without_idle(t.run("xpending s g - + 10")),
+            array(vec![array(vec![
+                bulk("1-0"),
+                bulk("alice"),
+                int(0),
+                int(1)
+            ])])
+        );
+
+        // destroying the group fails a waiting reader
+        let mut blocked = t.block("xreadgroup group g alice block 0 streams s >");
+        t.client = Client::new();
+        t.run("xgroup destroy s g");
+        assert_eq!(
+            blocked.rx.try_recv().unwrap(),
+            CommandError::Reply(
+                "NOGROUP No such key 's' or consumer group 'g' in XREADGROUP with GROUP option"
+                    .to_string()
+            )
+            .into()
+        );
+    }
+}
@@ -0,0 +1,422 @@
+use crate::backend::{now_ms, Db};
+use crate::RespFrame;
+
+use super::{CommandArgs, CommandError, CommandExecutor, CommandSpec, Context};
+
+// EXPIRE key seconds [NX | XX | GT | LT]
+#[derive(Debug)]
+pub struct Expire {
+    inner: SetExpire,
+}
+
+// PEXPIRE key milliseconds [NX | XX | GT | LT]
+#[derive(Debug)]
+pub struct PExpire {
+    inner: SetExpire,
+}
+
+// EXPIREAT key unix-time-seconds [NX | XX | GT | LT]
+#[derive(Debug)]
+pub struct ExpireAt {
+    inner: SetExpire,
+}
+
+// PEXPIREAT key unix-time-milliseconds [NX | XX | GT | LT]
+#[derive(Debug)]
+pub struct PExpireAt {
+    inner: SetExpire,
+}
+
+// TTL key
+#[derive(Debug)]
+pub struct Ttl {
+    key: Vec<u8>,
+}
+
+// PTTL key
+#[derive(Debug)]
+pub struct PTtl {
+    key: Vec<u8>,
+}
+
+// EXPIRETIME key
+#[derive(Debug)]
+pub struct ExpireTime {
+    key: Vec<u8>,
+}
+
+// PEXPIRETIME key
+#[derive(Debug)]
+pub struct PExpireTime {
+    key: Vec<u8>,
+}
+
+// PERSIST key
+#[derive(Debug)]
+pub struct Persist {
+    key: Vec<u8>,
+}
+
+// when the new expire time may replace the current one; a key without TTL
+// counts as never expiring for GT and LT
+#[derive(Debug, Default, Clone, Copy)]
+struct ExpireFlags {
+    // only if the key has no TTL
+    nx: bool,
+    // only if it has one
+    xx: bool,
+    // only if later than the current one
+    gt: bool,
+    // only if earlier
+    lt: bool,
+}
+
+// what the EXPIRE family shares: the time is turned into an absolute one
+// when the command runs
+#[derive(Debug)]
+struct SetExpire {
+    name: &'static str,
+    key: Vec<u8>,
+    time: i64,
+    // milliseconds per unit of time
+    unit: i64,
+    absolute: bool,
+    flags: ExpireFlags,
+}
+
+impl SetExpire {
+    fn parse(
+        name: &'static str,
+        args: &mut CommandArgs,
+        unit: i64,
+        absolute: bool,
+    ) -> Result<SetExpire, CommandError> {
+        let key = args.next_bytes()?;
+        let time = args.next_i64()?;
+        let mut flags = ExpireFlags::default();
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                "NX" => flags.nx = true,
+                "XX" => flags.xx = true,
+                "GT" => flags.gt = true,
+                "LT" => flags.lt = true,
+                _ => {
+                    return Err(CommandError::err(format!(
+                        "Unsupported option {}",
+                        opt.to_ascii_lowercase()
+                    )))
+                }
+            }
+        }
+        if flags.nx && (flags.xx || flags.gt || flags.lt) {
+            return Err(CommandError::err(
+                "NX and XX, GT or LT options at the same time are not compatible",
+            ));
+        }
+        if flags.gt && flags.lt {
+            return Err(CommandError::err(
+                "GT and LT options at the same time are not compatible",
+            ));
+        }
+        Ok(SetExpire {
+            name,
+            key,
+            time,
+            unit,
+            absolute,
+            flags,
+        })
+    }
+
+    // the absolute expire time in unix milliseconds
+    fn deadline(&self) -> Result<i64, CommandError> {
+        let invalid =
+            || CommandError::err(format!("invalid expire time in '{}' command", self.name));
+        let ms = self.time.checked_mul(self.unit).ok_or_else(invalid)?;
+        if self.absolute {
+            return Ok(ms);
+        }
+        ms.checked_add(now_ms() as i64).ok_or_else(invalid)
+    }
+
+    fn execute(self, db: &mut Db) -> Result<RespFrame, CommandError> {
+        let at = self.deadline()?;
+        if !db.contains_key(&self.key) {
+            return Ok(RespFrame::Integer(0));
+        }
+        let current = db.expire_at(&self.key).map(|at| at as i64);
+        let flags = self.flags;
+        let refused = match current {
+            None => flags.xx || flags.gt,
+            Some(current) => flags.nx || (flags.gt && at <= current) || (flags.lt && at >= current),
+        };
+        if refused {
+            return Ok(RespFrame::Integer(0));
+        }
+        // a time in the past deletes the key
+        db.set_expire(&self.key, at.max(0) as u64);
+        Ok(RespFrame::Integer(1))
+    }
+}
+
+// -2 for a missing key, -1 for a key without TTL, or the expire time
+// turned into a reply by f
+fn expire_reply(db: &mut Db, key: &[u8], f: impl Fn(u64) -> i64) -> RespFrame {
+    if db.get(key).is_none() {
+        return RespFrame::Integer(-2);
+    }
+    RespFrame::Integer(db.expire_at(key).map_or(-1, f))
+}
+
+// the milliseconds left until at, which is in the future for a live key
+fn ms_left(at: u64) -> i64 {
+    at.saturating_sub(now_ms()) as i64
+}
+
+impl CommandSpec for Expire {
+    const NAME: &'static str = "expire";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Expire {
+            inner: SetExpire::parse(Self::NAME, args, 1000, false)?,
+        })
+    }
+}
+
+impl CommandExecutor for Expire {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        self.inner.execute(ctx.db())
+    }
+}
+
+impl CommandSpec for PExpire {
+    const NAME: &'static str = "pexpire";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(PExpire {
+            inner: SetExpire::parse(Self::NAME, args, 1, false)?,
+        })
+    }
+}
+
+impl CommandExecutor for PExpire {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        self.inner.execute(ctx.db())
+    }
+}
+
+impl CommandSpec for ExpireAt {
+    const NAME: &'static str = "expireat";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(ExpireAt {
+            inner: SetExpire::parse(Self::NAME, args, 1000, true)?,
+        })
+    }
+}
+
+impl CommandExecutor for ExpireAt {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        self.inner.execute(ctx.db())
+    }
+}
+
+impl CommandSpec for PExpireAt {
+    const NAME: &'static str = "pexpireat";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(PExpireAt {
+            inner: SetExpire::parse(Self::NAME, args, 1, true)?,
+        })
+    }
+}
+
+impl CommandExecutor for PExpireAt {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        self.inner.execute(ctx.db())
+    }
+}
+
+impl CommandSpec for Ttl {
+    const NAME: &'static str = "ttl";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Ttl {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Ttl {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        // rounded to the nearest second
+        Ok(expire_reply(ctx.db(), &self.key, |at| {
+            (ms_left(at) + 500) / 1000
+        }))
+    }
+}
+
+impl CommandSpec for PTtl {
+    const NAME: &'static str = "pttl";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(PTtl {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for PTtl {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(expire_reply(ctx.db(), &self.key, ms_left))
+    }
+}
+
+impl CommandSpec for ExpireTime {
+    const NAME: &'static str = "expiretime";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(ExpireTime {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for ExpireTime {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(expire_reply(ctx.db(), &self.key, |at| (at / 1000) as i64))
+    }
+}
+
+impl CommandSpec for PExpireTime {
+    const NAME: &'static str = "pexpiretime";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(PExpireTime {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for PExpireTime {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(expire_reply(ctx.db(), &self.key, |at| at as i64))
+    }
+}
+
+impl CommandSpec for Persist {
+    const NAME: &'static str = "persist";
+    const ARITY: i64 = 2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Persist {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Persist {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(RespFrame::Integer(ctx.db().persist(&self.key) as i64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::{nil, TestContext};
+
+    fn int(n: i64) -> RespFrame {
+        RespFrame::Integer(n)
+    }
+
+    #[test]
+    fn test_expire_and_ttl() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("expire k 100"), int(0));
+        assert_eq!(t.run("ttl k"), int(-2));
+        t.run("set k v");
+        assert_eq!(t.run("ttl k"), int(-1));
+        assert_eq!(t.run("pexpiretime k"), int(-1));
+        assert_eq!(t.run("expire k 100"), int(1));
+        assert_eq!(t.run("ttl k"), int(100));
+        let RespFrame::Integer(pttl) = t.run("pttl k") else {
+            panic!("pttl is an integer");
+        };
+        assert!(pttl > 99_000 && pttl <= 100_000);
+
+        assert_eq!(t.run("expireat k 4000000000"), int(1));
+        assert_eq!(t.run("expiretime k"), int(4000000000));
+        assert_eq!(t.run("pexpireat k 4000000000123"), int(1));
+        assert_eq!(t.run("pexpiretime k"), int(4000000000123));
+        assert_eq!(t.run("expiretime k"), int(4000000000));
+
+        assert_eq!(t.run("persist k"), int(1));
+        assert_eq!(t.run("persist k"), int(0));
+        assert_eq!(t.run("ttl k"), int(-1));
+
+        // a time in the past deletes the key
+        assert_eq!(t.run("pexpire k -1"), int(1));
+        assert_eq!(t.run("get k"), nil());
+    }
+
+    #[test]
+    fn test_expire_conditions() {
+        let mut t = TestContext::default();
+        t.run("set k v");
+        assert_eq!(t.run("expire k 100 xx"), int(0));
+        assert_eq!(t.run("expire k 100 gt"), int(0));
+        assert_eq!(t.run("expire k 100 lt"), int(1));
+        assert_eq!(t.run("expire k 200 nx"), int(0));
+        assert_eq!(t.run("expire k 50 gt"), int(0));
+        assert_eq!(t.run("expire k 200 gt"), int(1));
+        assert_eq!(t.run("expire k 300 xx lt"), int(0));
+        assert_eq!(t.run("expire k 150 xx lt"), int(1));
+        t.run("set other v");
+        assert_eq!(t.run("expire other 100 xx lt"), int(0));
+        assert_eq!(t.run("ttl k"), int(150));
+
+        assert_eq!(
+            t.run("expire k 1 nx xx"),
+            CommandError::err("NX and XX, GT or LT options at the same time are not compatible")
+                .into()
+        );
+        assert_eq!(
+            t.run("expire k 1 gt lt"),
+            CommandError::err("GT and LT options at the same time are not compatible").into()
+        );
+        assert_eq!(
+            t.run("expire k 1 foo"),
+            CommandError::err("Unsupported option foo").into()
+        );
+        assert_eq!(
+            t.run("expire k 9223372036854775807"),
+            CommandError::err("invalid expire time in 'expire' command").into()
+        );
+        assert_eq!(t.run("expire k abc"), CommandError::NotInteger.into());
+    }
+
+    #[test]
+    fn test_active_expiry() {
+        let mut t = TestContext::default();
+        t.run("set k v");
+        t.run("set plain v");
+        t.run("pexpire k 1");
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(t.backend.active_expire_cycle(), 1);
+        assert_eq!(t.backend.dbs[0].len(), 1);
+    }
+}
@@ -0,0 +1,460 @@
+use crate::backend::{glob_match, RestorePolicy};
+use crate::{BulkString, RespArray, RespFrame};
+
+use super::{
+    check_subcommand_arity, info_reply, nil, ok, parse_flush_mode, run_script, script_error,
+    set_reply, string_table, CommandArgs, CommandError, CommandExecutor, CommandSpec, Context,
+    ScriptArgs,
+};
+
+// FUNCTION LOAD [REPLACE] code | DELETE library | FLUSH [ASYNC|SYNC]
+//        | LIST [LIBRARYNAME pattern] [WITHCODE] | DUMP
+//        | RESTORE payload [FLUSH|APPEND|REPLACE]
+#[derive(Debug)]
+pub enum FunctionCommand {
+    Load {
+        code: Vec<u8>,
+        replace: bool,
+    },
+    Delete {
+        library: String,
+    },
+    Flush,
+    List {
+        pattern: Option<Vec<u8>>,
+        with_code: bool,
+    },
+    Dump,
+    Restore {
+        payload: Vec<u8>,
+        policy: RestorePolicy,
+    },
+}
+
+// FCALL and FCALL_RO: call a function a library registered, with the keys
+// it works on and the rest of its arguments
+macro_rules! fcall_command {
+    ($ty:ident, $name:literal, $read_only:literal) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            function: String,
+            args: ScriptArgs,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -3;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                Ok($ty {
+                    function: args.next_string()?,
+                    args: ScriptArgs::parse(args)?,
+                })
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                fcall(ctx, &self.function, self.args, $read_only)
+            }
+        }
+    };
+}
+
+fcall_command!(FCall, "fcall", false);
+fcall_command!(FCallRo, "fcall_ro", true);
+
+// a function flagged no-writes runs as a read-only script whichever command
+// called it, and FCALL_RO only calls those
+fn fcall(
+    ctx: &mut Context,
+    name: &str,
+    args: ScriptArgs,
+    read_only: bool,
+) -> Result<RespFrame, CommandError> {
+    let lua = ctx.backend.functions.take_lua()?;
+    let callback = match ctx.backend.functions.function(name) {
+        None => Err(CommandError::err("Function not found")),
+        Some(function) if read_only && !function.no_writes() => Err(CommandError::err(
+            "Can not execute a script with write flag using *_ro command.",
+        )),
+        Some(function) => lua
+            .registry_value::<mlua::Function>(&function.callback)
+            .map(|callback| (callback, function.no_writes()))
+            .map_err(|e| CommandError::err(e.to_string())),
+    };
+    let result = callback.and_then(|(callback, no_writes)| {
+        run_script(ctx, &lua, no_writes, |lua| {
+            let keys = string_table(lua, &args.keys)?;
+            let args = string_table(lua, &args.args)?;
+            callback
+                .call((keys, args))
+                .map_err(|e| script_error(&e, name))
+        })
+    });
+    ctx.backend.functions.put_lua(lua);
+    result
+}
+
+impl CommandSpec for FunctionCommand {
+    const NAME: &'static str = "function";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let sub = args.next_string()?.to_ascii_lowercase();
+        let arity = match sub.as_str() {
+            "load" => -3,
+            "delete" => 3,
+            "flush" => -2,
+            "list" => -2,
+            "dump" => 2,
+            "restore" => -3,
+            _ => {
+                return Err(CommandError::UnknownSubcommand(
+                    sub,
+                    Self::NAME.to_uppercase(),
+                ))
+            }
+        };
+        check_subcommand_arity(args, &sub, arity)?;
+        Ok(match sub.as_str() {
+            "load" => {
+                let replace = args.len() > 1 && args.peek_keyword().as_deref() == Some("REPLACE");
+                if replace {
+                    args.next_keyword();
+                }
+                let code = args.next_bytes()?;
+                args.finish()?;
+                FunctionCommand::Load { code, replace }
+            }
+            "delete" => FunctionCommand::Delete {
+                library: args.next_string()?,
+            },
+            "flush" => {
+                parse_flush_mode(args)?;
+                FunctionCommand::Flush
+            }
+            "list" => {
+                let (mut pattern, mut with_code) = (None, false);
+                while let Some(option) = args.next_keyword() {
+                    match option.as_str() {
+                        "WITHCODE" if !with_code => with_code = true,
+                        "LIBRARYNAME" if pattern.is_none() => {
+                            pattern = Some(args.next_bytes().map_err(|_| {
+                                CommandError::err("library name argument was not given")
+                            })?)
+                        }
+                        _ => return Err(CommandError::err("Unknown argument")),
+                    }
+                }
+                FunctionCommand::List { pattern, with_code }
+            }
+            "dump" => FunctionCommand::Dump,
+            _ => {
+                let payload = args.next_bytes()?;
+                let policy = match args.next_keyword().as_deref() {
+                    None | Some("APPEND") => RestorePolicy::Append,
+                    Some("REPLACE") => RestorePolicy::Replace,
+                    Some("FLUSH") => RestorePolicy::Flush,
+                    Some(_) => {
+                        return Err(CommandError::err(
+                            "Wrong restore policy given, value should be either FLUSH, APPEND or REPLACE.",
+                        ))
+                    }
+                };
+                args.finish()?;
+                FunctionCommand::Restore { payload, policy }
+            }
+        })
+    }
+}
+
+impl CommandExecutor for FunctionCommand {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let functions = &mut ctx.backend.functions;
+        Ok(match self {
+            FunctionCommand::Load { code, replace } => {
+                BulkString::new(functions.load(code, replace)?).into()
+            }
+            FunctionCommand::Delete { library } => {
+                if !functions.delete(&library) {
+                    return Err(CommandError::err("Library not found"));
+                }
+                ok()
+            }
+            FunctionCommand::Flush => {
+                functions.flush();
+                ok()
+            }
+            FunctionCommand::List { pattern, with_code } => {
+                let libraries = functions
+                    .libraries()
+                    .filter(|library| {
+                        pattern
+                            .as_ref()
+                            .is_none_or(|p| glob_match(p, library.name.as_bytes(), false))
+                    })
+                    .map(|library| {
+                        let list = library
+                            .functions
+                            .iter()
+                            .map(|function| {
+                                let description = match &function.description {
+                                    Some(description) => {
+                                        BulkString::new(description.as_str()).into()
+                                    }
+                                    None => nil(),
+                                };
+                                let flags = function.flags.iter().map(|f| f.as_bytes().to_vec());
+                                info_reply(
+                                    resp3,
+                                    vec![
+                                        ("name", BulkString::new(function.name.as_str()).into()),
+                                        ("description", description),
+                                        ("flags", set_reply(resp3, flags)),
+                                    ],
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        let mut info = vec![
+                            (
+                                "library_name",
+                                BulkString::new(library.name.as_str()).into(),
+                            ),
+                            ("engine", BulkString::new("LUA").into()),
+                            ("functions", RespArray::new(list).into()),
+                        ];
+                        if with_code {
+                            info.push((
+                                "library_code",
+                                BulkString::new(library.code.clone()).into(),
+                            ));
+                        }
+                        info_reply(resp3, info)
+                    })
+                    .collect::<Vec<_>>();
+                RespArray::new(libraries).into()
+            }
+            FunctionCommand::Dump => BulkString::new(functions.dump()).into(),
+            FunctionCommand::Restore { payload, policy } => {
+                functions.restore(&payload, policy)?;
+                ok()
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::{run, Command, TestContext};
+    use crate::{Reply, SimpleError, SimpleString};
+
+    const LIBRARY: &str = "#!lua name=counters\n\
+        redis.register_function('bump', function(keys, args) \
+            return redis.call('incrby', keys[1], args[1]) end)\n\
+        redis.register_function{function_name = 'peek', flags = {'no-writes'}, \
+            callback = function(keys) return redis.call('get', keys[1]) end}\n\
+        redis.register_function{function_name = 'sneaky', flags = {'no-writes'}, \
+            callback = function(keys) return redis.call('del', keys[1]) end}";
+
+    fn run_args(t: &mut TestContext, args: &[&str]) -> RespFrame {
+        let cmd = RespArray::new(
+            args.iter()
+                .map(|arg| BulkString::new(*arg).into())
+                .collect::<Vec<RespFrame>>(),
+        );
+        match Command::try_from(cmd) {
+            Ok(cmd) => match run(cmd, &mut t.client, &mut t.backend) {
+                Reply::Frame(frame) => frame,
+                reply => panic!("unexpected reply {:?}", reply),
+            },
+            Err(e) => e.into(),
+        }
+    }
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    #[test]
+    fn test_function_load_and_fcall() {
+        let mut t = TestContext::default();
+        assert_eq!(
+            run_args(&mut t, &["function", "load", LIBRARY]),
+            bulk("counters")
+        );
+        assert_eq!(
+            run_args(&mut t, &["function", "load", LIBRARY]),
+            CommandError::err("Library 'counters' already exists").into()
+        );
+        assert_eq!(
+            run_args(&mut t, &["function", "load", "replace", LIBRARY]),
+            bulk("counters")
+        );
+        assert_eq!(
+            run_args(&mut t, &["function", "load", "frob", LIBRARY]),
+            CommandError::Syntax.into()
+        );
+        // a library that never finishes loading is stopped, and the server
+        // goes on answering
+        assert_eq!(
+            run_args(
+                &mut t,
+                &["function", "load", "#!lua name=loop\nwhile true do end"]
+            ),
+            CommandError::err("Error registering functions: FUNCTION LOAD timeout").into()
+        );
+        assert_eq!(t.run("ping"), SimpleString::new("PONG").into());
+
+        assert_eq!(t.run("fcall bump 1 n 5"), RespFrame::Integer(5));
+        assert_eq!(t.run("fcall bump 1 n 5"), RespFrame::Integer(10));
+        assert_eq!(t.run("fcall_ro peek 1 n"), bulk("10"));
+        assert_eq!(t.run("fcall peek 1 n"), bulk("10"));
+        assert_eq!(
+            t.run("fcall_ro bump 1 n 1"),
+            CommandError::err("Can not execute a script with write flag using *_ro command.")
+                .into()
+        );
+        // no-writes holds whichever command calls the function
+        assert_eq!(
+            t.run("fcall sneaky 1 n"),
+            CommandError::err("Write commands are not allowed from read-only scripts.").into()
+        );
+        assert_eq!(
+            t.run("fcall missing 0"),
+            CommandError::err("Function not found").into()
+        );
+        assert_eq!(
+            t.run("fcall bump 2 n"),
+            CommandError::err("Number of keys can't be greater than number of args").into()
+        );
+        // scripts can't reach functions, nor the other way around
+        assert_eq!(
+            run_args(
+                &mut t,
+                &["eval", "return redis.call('fcall', 'bump', 1, 'n', 1)", "0"]
+            ),
+            CommandError::err("This Redis command is not allowed from script").into()
+        );
+        assert_eq!(t.run("script flush"), ok());
+        assert_eq!(t.run("fcall peek 1 n"), bulk("10"));
+
+        assert_eq!(t.run("function delete counters"), ok());
+        assert_eq!(
+            t.run("function delete counters"),
+            CommandError::err("Library not found").into()
+        );
+        assert_eq!(
+            t.run("fcall bump 1 n 5"),
+            CommandError::err("Function not found").into()
+        );
+    }
+
+    #[test]
+    fn test_function_list() {
+        let mut t = TestContext::default();
+        run_args(&mut t, &["function", "load", LIBRARY]);
+        run_args(
+            &mut t,
+            &[
+                "function",
+                "load",
+                "#!lua name=other\nredis.register_function{function_name = 'f', \
+                 description = 'does f', callback = function() return 1 end}",
+            ],
+        );
+        let RespFrame::Array(libraries) = t.run("function list") else {
+            panic!("not an array");
+        };
+        assert_eq!(libraries.len(), 2);
+        assert_eq!(
+            t.run("function list libraryname oth* withcode"),
+            RespArray::new(vec![RespArray::new(vec![
+                bulk("library_name"),
+                bulk("other"),
+                bulk("engine"),
+                bulk("LUA"),
+                bulk("functions"),
+                RespArray::new(vec![RespArray::new(vec![
+                    bulk("name"),
+                    bulk("f"),
+                    bulk("description"),
+                    bulk("does f"),
+                    bulk("flags"),
+                    RespArray::new(vec![]).into(),
+                ])
+                .into()])
+                .into(),
+                bulk("library_code"),
+                bulk(
+                    "#!lua name=other\nredis.register_function{function_name = 'f', \
+                     description = 'does f', callback = function() return 1 end}"
+                ),
+            ])
+            .into()])
+            .into()
+        );
+        assert_eq!(
+            t.run("function list libraryname"),
+            CommandError::err("library name argument was not given").into()
+        );
+        assert_eq!(
+            t.run("function list withcode withcode"),
+            CommandError::err("Unknown argument").into()
+        );
+    }
+
+    #[test]
+    fn test_function_dump_restore_flush() {
+        let mut t = TestContext::default();
+        run_args(&mut t, &["function", "load", LIBRARY]);
+        let RespFrame::BulkString(payload) = t.run("function dump") else {
+            panic!("not a bulk string");
+        };
+        assert_eq!(t.run("function flush sync"), ok());
+        assert_eq!(
+            t.run("fcall bump 1 n 1"),
+            CommandError::err("Function not found").into()
+        );
+
+        let mut restore = |policy: &str| {
+            let mut cmd = vec![
+                BulkString::new("function").into(),
+                BulkString::new("restore").into(),
+                BulkString::new(payload.0.clone()).into(),
+            ];
+            if !policy.is_empty() {
+                cmd.push(BulkString::new(policy).into());
+            }
+            let cmd = Command::try_from(RespArray::new(cmd));
+            match cmd {
+                Ok(cmd) => match run(cmd, &mut t.client, &mut t.backend) {
+                    Reply::Frame(frame) => frame,
+                    reply => panic!("unexpected reply {:?}", reply),
+                },
+                Err(e) => e.into(),
+            }
+        };
+        assert_eq!(restore(""), ok());
+        assert_eq!(
+            restore("append"),
+            CommandError::err("Library 'counters' already exists").into()
+        );
+        assert_eq!(restore("replace"), ok());
+        assert_eq!(restore("flush"), ok());
+        assert_eq!(
+            restore("merge"),
+            CommandError::err(
+                "Wrong restore policy given, value should be either FLUSH, APPEND or REPLACE."
+            )
+            .into()
+        );
+        assert_eq!(t.run("fcall bump 1 n 1"), RespFrame::Integer(1));
+        assert_eq!(
+            t.run("function restore garbage"),
+            SimpleError::new("ERR payload version or checksum are wrong").into()
+        );
+    }
+}
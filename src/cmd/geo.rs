@@ -0,0 +1,696 @@
+use crate::backend::{
+    geo_distance, geo_point, geo_score, geohash_string, search_ranges, valid_coordinates, GeoShape,
+    ScoreRange, ZSet,
+};
+use crate::{BulkString, RespArray, RespFrame, RespNullArray};
+
+use super::{
+    nil, score_reply, store_zset, zadd, CommandArgs, CommandError, CommandExecutor, CommandSpec,
+    Context, ScoredMembers, ZAddFlags,
+};
+
+// GEOADD key [NX | XX] [CH] longitude latitude member [longitude latitude member ...]
+#[derive(Debug)]
+pub struct GeoAdd {
+    key: Vec<u8>,
+    flags: ZAddFlags,
+    ch: bool,
+    pairs: Vec<(f64, Vec<u8>)>,
+}
+
+// GEOPOS key [member ...]
+#[derive(Debug)]
+pub struct GeoPos {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+}
+
+// GEODIST key member1 member2 [M | KM | FT | MI]
+#[derive(Debug)]
+pub struct GeoDist {
+    key: Vec<u8>,
+    member1: Vec<u8>,
+    member2: Vec<u8>,
+    unit: f64,
+}
+
+// GEOHASH key [member ...]
+#[derive(Debug)]
+pub struct GeoHash {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+}
+
+// GEOSEARCH key FROMMEMBER member | FROMLONLAT longitude latitude
+//   BYRADIUS radius unit | BYBOX width height unit [ASC | DESC]
+//   [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+#[derive(Debug)]
+pub struct GeoSearch {
+    key: Vec<u8>,
+    query: GeoQuery,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+}
+
+// GEOSEARCHSTORE destination source FROMMEMBER member | FROMLONLAT longitude latitude
+//   BYRADIUS radius unit | BYBOX width height unit [ASC | DESC]
+//   [COUNT count [ANY]] [STOREDIST]
+#[derive(Debug)]
+pub struct GeoSearchStore {
+    destination: Vec<u8>,
+    source: Vec<u8>,
+    query: GeoQuery,
+    store_dist: bool,
+}
+
+// where a search is centered
+#[derive(Debug, Clone, PartialEq)]
+enum GeoFrom {
+    Member(Vec<u8>),
+    LonLat(f64, f64),
+}
+
+// the options GEOSEARCH and GEOSEARCHSTORE share
+#[derive(Debug, Clone, PartialEq)]
+struct GeoQuery {
+    from: GeoFrom,
+    shape: GeoShape,
+    // meters per unit of the shape, which distances are replied in
+    unit: f64,
+    // Some(true) for descending distances
+    desc: Option<bool>,
+    count: Option<usize>,
+    any: bool,
+}
+
+// a point found by a search
+struct GeoMatch<'a> {
+    member: &'a [u8],
+    score: f64,
+    // in the unit of the search
+    dist: f64,
+    point: (f64, f64),
+}
+
+// meters per unit
+fn parse_unit(unit: &[u8]) -> Result<f64, CommandError> {
+    match String::from_utf8_lossy(unit).to_ascii_lowercase().as_str() {
+        "m" => Ok(1.0),
+        "km" => Ok(1000.0),
+        "ft" => Ok(0.3048),
+        "mi" => Ok(1609.34),
+        _ => Err(CommandError::err(
+            "unsupported unit provided. please use M, KM, FT, MI",
+        )),
+    }
+}
+
+fn parse_coordinates(args: &mut CommandArgs) -> Result<(f64, f64), CommandError> {
+    let (long, lat) = (args.next_f64()?, args.next_f64()?);
+    if !valid_coordinates(long, lat) {
+        return Err(CommandError::err(format!(
+            "invalid longitude,latitude pair {:.6},{:.6}",
+            long, lat
+        )));
+    }
+    Ok((long, lat))
+}
+
+// a distance: rounded to 4 decimals, as a double on RESP3 and a bulk string on RESP2
+fn distance_reply(resp3: bool, dist: f64) -> RespFrame {
+    if resp3 {
+        RespFrame::Double((dist * 10000.0).round() / 10000.0)
+    } else {
+        BulkString::new(format!("{:.4}", dist)).into()
+    }
+}
+
+fn point_reply(resp3: bool, (long, lat): (f64, f64)) -> RespFrame {
+    RespArray::new(vec![score_reply(resp3, long), score_reply(resp3, lat)]).into()
+}
+
+impl CommandSpec for GeoAdd {
+    const NAME: &'static str = "geoadd";
+    const ARITY: i64 = -5;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let mut flags = ZAddFlags::default();
+        let mut ch = false;
+        while let Some(opt) = args.peek_keyword() {
+            match opt.as_str() {
+                "NX" => flags.nx = true,
+                "XX" => flags.xx = true,
+                "CH" => ch = true,
+                _ => break,
+            }
+            args.next_bytes()?;
+        }
+        if args.is_empty() || !args.len().is_multiple_of(3) || (flags.nx && flags.xx) {
+            return Err(CommandError::Syntax);
+        }
+        let mut pairs = Vec::with_capacity(args.len() / 3);
+        while !args.is_empty() {
+            let (long, lat) = parse_coordinates(args)?;
+            pairs.push((geo_score(long, lat), args.next_bytes()?));
+        }
+        Ok(GeoAdd {
+            key,
+            flags,
+            ch,
+            pairs,
+        })
+    }
+}
+
+impl CommandExecutor for GeoAdd {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let (added, changed, _) = zadd(ctx.db(), &self.key, self.flags, false, &self.pairs)?;
+        let count = if self.ch { added + changed } else { added };
+        Ok(RespFrame::Integer(count as i64))
+    }
+}
+
+impl CommandSpec for GeoPos {
+    const NAME: &'static str = "geopos";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(GeoPos {
+            key: args.next_bytes()?,
+            members: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for GeoPos {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let zset = ctx.db().get_zset(&self.key)?;
+        let points = self
+            .members
+            .iter()
+            .map(|member| match zset.and_then(|zset| zset.score(member)) {
+                Some(score) => point_reply(resp3, geo_point(score)),
+                None => RespNullArray.into(),
+            })
+            .collect::<Vec<_>>();
+        Ok(RespArray::new(points).into())
+    }
+}
+
+impl CommandSpec for GeoDist {
+    const NAME: &'static str = "geodist";
+    const ARITY: i64 = -4;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let member1 = args.next_bytes()?;
+        let member2 = args.next_bytes()?;
+        let unit = match args.len() {
+            0 => 1.0,
+            1 => parse_unit(&args.next_bytes()?)?,
+            _ => return Err(CommandError::Syntax),
+        };
+        Ok(GeoDist {
+            key,
+            member1,
+            member2,
+            unit,
+        })
+    }
+}
+
+impl CommandExecutor for GeoDist {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let zset = match ctx.db().get_zset(&self.key)? {
+            Some(zset) => zset,
+            None => return Ok(nil()),
+        };
+        let (a, b) = match (zset.score(&self.member1), zset.score(&self.member2)) {
+            (Some(a), Some(b)) => (geo_point(a), geo_point(b)),
+            _ => return Ok(nil()),
+        };
+        let dist = geo_distance(a.0, a.1, b.0, b.1) / self.unit;
+        Ok(distance_reply(resp3, dist))
+    }
+}
+
+impl CommandSpec for GeoHash {
+    const NAME: &'static str = "geohash";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(GeoHash {
+            key: args.next_bytes()?,
+            members: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for GeoHash {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let zset = ctx.db().get_zset(&self.key)?;
+        let hashes = self
+            .members
+            .iter()
+            .map(|member| match zset.and_then(|zset| zset.score(member)) {
+                Some(score) => {
+                    let (long, lat) = geo_point(score);
+                    BulkString::new(geohash_string(long, lat)).into()
+                }
+                None => nil(),
+            })
+            .collect::<Vec<_>>();
+        Ok(RespArray::new(hashes).into())
+    }
+}
+
+// the WITH* options of GEOSEARCH
+#[derive(Debug, Default)]
+struct WithOptions {
+    coord: bool,
+    dist: bool,
+    hash: bool,
+}
+
+impl GeoQuery {
+    // parse the search options; WITH* are only accepted when with is given,
+    // and STOREDIST only when store_dist is
+    fn parse(
+        name: &str,
+        args: &mut CommandArgs,
+        mut with: Option<&mut WithOptions>,
+        mut store_dist: Option<&mut bool>,
+    ) -> Result<GeoQuery, CommandError> {
+        let (mut from, mut shape) = (None, None);
+        let mut unit = 1.0;
+        let (mut desc, mut count, mut any) = (None, None, false);
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                "FROMMEMBER" if from.is_none() && !args.is_empty() => {
+                    from = Some(GeoFrom::Member(args.next_bytes()?));
+                }
+                "FROMLONLAT" if from.is_none() && args.len() >= 2 => {
+                    let (long, lat) = parse_coordinates(args)?;
+                    from = Some(GeoFrom::LonLat(long, lat));
+                }
+                "BYRADIUS" if shape.is_none() && args.len() >= 2 => {
+                    let radius = args.next_f64()?;
+                    if radius < 0.0 {
+                        return Err(CommandError::err("radius cannot be negative"));
+                    }
+                    unit = parse_unit(&args.next_bytes()?)?;
+                    shape = Some(GeoShape::Radius(radius * unit));
+                }
+                "BYBOX" if shape.is_none() && args.len() >= 3 => {
+                    let (width, height) = (args.next_f64()?, args.next_f64()?);
+                    if width < 0.0 || height < 0.0 {
+                        return Err(CommandError::err("height or width cannot be negative"));
+                    }
+                    unit = parse_unit(&args.next_bytes()?)?;
+                    shape = Some(GeoShape::Box {
+                        width: width * unit,
+                        height: height * unit,
+                    });
+                }
+                "ASC" => desc = Some(false),
+                "DESC" => desc = Some(true),
+                "COUNT" if !args.is_empty() => {
+                    let n = args.next_i64()?;
+                    if n <= 0 {
+                        return Err(CommandError::err("COUNT must be > 0"));
+                    }
+                    count = Some(n as usize);
+                    // ANY only counts right after COUNT
+                    if args.peek_keyword().as_deref() == Some("ANY") {
+                        args.next_bytes()?;
+                        any = true;
+                    }
+                }
+                "WITHCOORD" | "WITHDIST" | "WITHHASH" if with.is_some() => {
+                    let with = with.as_deref_mut().expect("checked above");
+                    match opt.as_str() {
+                        "WITHCOORD" => with.coord = true,
+                        "WITHDIST" => with.dist = true,
+                        _ => with.hash = true,
+                    }
+                }
+                "STOREDIST" if store_dist.is_some() => {
+                    **store_dist.as_mut().expect("checked above") = true;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        let from = from.ok_or_else(|| {
+            CommandError::err(format!(
+                "exactly one of FROMMEMBER or FROMLONLAT can be specified for {}",
+                name
+            ))
+        })?;
+        let shape = shape.ok_or_else(|| {
+            CommandError::err(format!(
+                "exactly one of BYRADIUS and BYBOX can be specified for {}",
+                name
+            ))
+        })?;
+        // a limited search keeps the nearest points unless ANY says otherwise
+        if count.is_some() && !any && desc.is_none() {
+            desc = Some(false);
+        }
+        Ok(GeoQuery {
+            from,
+            shape,
+            unit,
+            desc,
+            count,
+            any,
+        })
+    }
+
+    // the points of the sorted set inside the shape
+    fn run<'a>(&self, zset: &'a ZSet) -> Result<Vec<GeoMatch<'a>>, CommandError> {
+        let center = match &self.from {
+            GeoFrom::LonLat(long, lat) => (*long, *lat),
+            GeoFrom::Member(member) => match zset.score(member) {
+                Some(score) => geo_point(score),
+                None => return Err(CommandError::err("could not decode requested zset member")),
+            },
+        };
+        let mut found = Vec::new();
+        'ranges: for (min, max) in search_ranges(center, &self.shape) {
+            let range = ScoreRange {
+                min,
+                min_exclusive: false,
+                max,
+                max_exclusive: true,
+            };
+            let (rank, len) = match zset.score_range(&range, false) {
+                Some(span) => span,
+                None => continue,
+            };
+            for (member, score) in zset.iter_from_rank(rank, false).take(len) {
+                let point = geo_point(score);
+                if let Some(dist) = self.shape.distance_if_within(center, point) {
+                    found.push(GeoMatch {
+                        member,
+                        score,
+                        dist: dist / self.unit,
+                        point,
+                    });
+                    // with ANY the first matches found are good enough
+                    if self.any && Some(found.len()) == self.count {
+                        break 'ranges;
+                    }
+                }
+            }
+        }
+        match self.desc {
+            Some(false) => found.sort_by(|a, b| a.dist.total_cmp(&b.dist)),
+            Some(true) => found.sort_by(|a, b| b.dist.total_cmp(&a.dist)),
+            None => {}
+        }
+        if let Some(count) = self.count {
+            found.truncate(count);
+        }
+        Ok(found)
+    }
+}
+
+impl CommandSpec for GeoSearch {
+    const NAME: &'static str = "geosearch";
+    const ARITY: i64 = -7;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let mut with = WithOptions::default();
+        let query = GeoQuery::parse(Self::NAME, args, Some(&mut with), None)?;
+        Ok(GeoSearch {
+            key,
+            query,
+            with_coord: with.coord,
+            with_dist: with.dist,
+            with_hash: with.hash,
+        })
+    }
+}
+
+impl CommandExecutor for GeoSearch {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let zset = match ctx.db().get_zset(&self.key)? {
+            Some(zset) => zset,
+            None => return Ok(RespArray::new(Vec::new()).into()),
+        };
+        let plain = !(self.with_coord || self.with_dist || self.with_hash);
+        let items = self
+            .query
+            .run(zset)?
+            .into_iter()
+            .map(|found| {
+                let member: RespFrame = BulkString::new(found.member.to_vec()).into();
+                if plain {
+                    return member;
+                }
+                let mut item = vec![member];
+                if self.with_dist {
+                    item.push(distance_reply(resp3, found.dist));
+                }
+                if self.with_hash {
+                    item.push(RespFrame::Integer(found.score as i64));
+                }
+                if self.with_coord {
+                    item.push(point_reply(resp3, found.point));
+                }
+                RespArray::new(item).into()
+            })
+            .collect::<Vec<_>>();
+        Ok(RespArray::new(items).into())
+    }
+}
+
+impl CommandSpec for GeoSearchStore {
+    const NAME: &'static str = "geosearchstore";
+    const ARITY: i64 = -8;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let destination = args.next_bytes()?;
+        let source = args.next_bytes()?;
+        let mut store_dist = false;
+        let query = GeoQuery::parse(Self::NAME, args, None, Some(&mut store_dist))?;
+        Ok(GeoSearchStore {
+            destination,
+            source,
+            query,
+            store_dist,
+        })
+    }
+}
+
+impl CommandExecutor for GeoSearchStore {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let items: ScoredMembers = match db.get_zset(&self.source)? {
+            Some(zset) => self
+                .query
+                .run(zset)?
+                .into_iter()
+                .map(|found| {
+                    let score = if self.store_dist {
+                        found.dist
+                    } else {
+                        found.score
+                    };
+                    (found.member.to_vec(), score)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let count = items.len();
+        store_zset(db, self.destination, items);
+        Ok(RespFrame::Integer(count as i64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Protocol;
+    use crate::cmd::TestContext;
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    fn bulks(items: &[&str]) -> RespFrame {
+        RespArray::new(items.iter().map(|s| bulk(s)).collect::<Vec<_>>()).into()
+    }
+
+    fn sicily() -> TestContext {
+        let mut t = TestContext::default();
+        assert_eq!(
+            t.run("geoadd Sicily 13.361389 38.115556 Palermo 15.087269 37.502669 Catania"),
+            RespFrame::Integer(2)
+        );
+        t
+    }
+
+    #[test]
+    fn test_geoadd() {
+        let mut t = sicily();
+        assert_eq!(t.run("zscore Sicily Palermo"), bulk("3479099956230698"));
+        assert_eq!(
+            t.run("geoadd Sicily nx 0 0 Palermo 12.758489 38.788135 edge1"),
+            RespFrame::Integer(1)
+        );
+        assert_eq!(
+            t.run("geoadd Sicily xx ch 13.361389 38.115556 Palermo 0 0 Catania 1 1 new"),
+            RespFrame::Integer(1)
+        );
+        assert_eq!(t.run("zcard Sicily"), RespFrame::Integer(3));
+        assert_eq!(
+            t.run("geoadd Sicily 181 10 x"),
+            CommandError::err("invalid longitude,latitude pair 181.000000,10.000000").into()
+        );
+        assert_eq!(t.run("geoadd Sicily 1 1 a 2"), CommandError::Syntax.into());
+        assert_eq!(
+            t.run("geoadd Sicily nx xx 1 1 a"),
+            CommandError::Syntax.into()
+        );
+        t.run("set s x");
+        assert_eq!(t.run("geoadd s 1 1 a"), CommandError::WrongType.into());
+    }
+
+    #[test]
+    fn test_geopos_geodist_geohash() {
+        let mut t = sicily();
+        assert_eq!(
+            t.run("geopos Sicily Palermo missing"),
+            RespArray::new(vec![
+                bulks(&["13.361389338970184", "38.1155563954963"]),
+                RespNullArray.into(),
+            ])
+            .into()
+        );
+        assert_eq!(t.run("geodist Sicily Palermo Catania"), bulk("166274.1516"));
+        assert_eq!(t.run("geodist Sicily Palermo Catania km"), bulk("166.2742"));
+        assert_eq!(t.run("geodist Sicily Palermo Catania mi"), bulk("103.3182"));
+        assert_eq!(t.run("geodist Sicily Palermo missing"), nil());
+        assert_eq!(
+            t.run("geodist Sicily Palermo Catania parsec"),
+            CommandError::err("unsupported unit provided. please use M, KM, FT, MI").into()
+        );
+        assert_eq!(
+            t.run("geohash Sicily Palermo Catania missing"),
+            RespArray::new(vec![bulk("sqc8b49rny0"), bulk("sqdtr74hyu0"), nil()]).into()
+        );
+
+        t.client.protocol = Protocol::Resp3;
+        assert_eq!(
+            t.run("geodist Sicily Palermo Catania km"),
+            RespFrame::Double(166.2742)
+        );
+    }
+
+    #[test]
+    fn test_geosearch() {
+        let mut t = sicily();
+        t.run("geoadd Sicily 12.758489 38.788135 edge1 17.241510 38.788135 edge2");
+        assert_eq!(
+            t.run("geosearch Sicily fromlonlat 15 37 byradius 200 km asc"),
+            bulks(&["Catania", "Palermo"])
+        );
+        assert_eq!(
+            t.run("geosearch Sicily fromlonlat 15 37 bybox 400 400 km desc"),
+            bulks(&["edge1", "edge2", "Palermo", "Catania"])
+        );
+        assert_eq!(
+            t.run("geosearch Sicily frommember Palermo byradius 200 km count 2 withdist"),
+            RespArray::new(vec![
+                RespArray::new(vec![bulk("Palermo"), bulk("0.0000")]).into(),
+                RespArray::new(vec![bulk("edge1"), bulk("91.4007")]).into(),
+            ])
+            .into()
+        );
+        assert_eq!(
+            t.run("geosearch Sicily fromlonlat 15 37 byradius 100 km withhash withcoord"),
+            RespArray::new(vec![RespArray::new(vec![
+                bulk("Catania"),
+                RespFrame::Integer(3479447370796909),
+                bulks(&["15.087267458438873", "37.50266842333162"]),
+            ])
+            .into()])
+            .into()
+        );
+        let RespFrame::Array(any) =
+            t.run("geosearch Sicily fromlonlat 15 37 bybox 400 400 km count 3 any")
+        else {
+            panic!("expected an array");
+        };
+        assert_eq!(any.len(), 3);
+        assert_eq!(
+            t.run("geosearch missing fromlonlat 15 37 byradius 1 km"),
+            RespArray::new(Vec::new()).into()
+        );
+
+        assert_eq!(
+            t.run("geosearch Sicily frommember nobody byradius 1 km"),
+            CommandError::err("could not decode requested zset member").into()
+        );
+        assert_eq!(
+            t.run("geosearch Sicily byradius 1 km asc withdist"),
+            CommandError::err(
+                "exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearch"
+            )
+            .into()
+        );
+        assert_eq!(
+            t.run("geosearch Sicily fromlonlat 15 37 asc withdist"),
+            CommandError::err("exactly one of BYRADIUS and BYBOX can be specified for geosearch")
+                .into()
+        );
+        assert_eq!(
+            t.run("geosearch Sicily fromlonlat 15 37 byradius 1 km any"),
+            CommandError::Syntax.into()
+        );
+        assert_eq!(
+            t.run("geosearch Sicily fromlonlat 15 37 byradius 1 km count 0"),
+            CommandError::err("COUNT must be > 0").into()
+        );
+        assert_eq!(
+            t.run("geosearch Sicily fromlonlat 15 37 byradius 1 km storedist"),
+            CommandError::Syntax.into()
+        );
+    }
+
+    #[test]
+    fn test_geosearchstore() {
+        let mut t = sicily();
+        assert_eq!(
+            t.run("geosearchstore dst Sicily fromlonlat 15 37 byradius 200 km"),
+            RespFrame::Integer(2)
+        );
+        assert_eq!(t.run("zscore dst Palermo"), bulk("3479099956230698"));
+        assert_eq!(
+            t.run("geosearchstore dst Sicily fromlonlat 15 37 byradius 200 km count 1 storedist"),
+            RespFrame::Integer(1)
+        );
+        assert_eq!(
+            t.run("zrange dst 0 -1 withscores"),
+            bulks(&["Catania", "56.4412578701582"])
+        );
+        assert_eq!(
+            t.run("geosearchstore dst Sicily fromlonlat 0 0 byradius 1 km"),
+            RespFrame::Integer(0)
+        );
+        assert_eq!(t.run("zcard dst"), RespFrame::Integer(0));
+        assert_eq!(
+            t.run("geosearchstore dst Sicily fromlonlat 15 37 byradius 1 km withdist"),
+            CommandError::Syntax.into()
+        );
+    }
+}
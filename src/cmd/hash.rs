@@ -0,0 +1,688 @@
+use crate::{BulkString, RespArray, RespFrame, RespMap};
+
+use super::{
+    float_sum, nil, parse_f64, parse_i64, random_sample, scan_reply, CommandArgs, CommandError,
+    CommandExecutor, CommandSpec, Context, Pairs, ScanArgs,
+};
+
+// HSET key field value [field value ...]
+#[derive(Debug)]
+pub struct HSet {
+    key: Vec<u8>,
+    pairs: Pairs,
+}
+
+// HGET key field
+#[derive(Debug)]
+pub struct HGet {
+    key: Vec<u8>,
+    field: Vec<u8>,
+}
+
+// HMGET key field [field ...]
+#[derive(Debug)]
+pub struct HMGet {
+    key: Vec<u8>,
+    fields: Vec<Vec<u8>>,
+}
+
+// HGETALL key
+#[derive(Debug)]
+pub struct HGetAll {
+    key: Vec<u8>,
+}
+
+// HDEL key field [field ...]
+#[derive(Debug)]
+pub struct HDel {
+    key: Vec<u8>,
+    fields: Vec<Vec<u8>>,
+}
+
+// HEXISTS key field
+#[derive(Debug)]
+pub struct HExists {
+    key: Vec<u8>,
+    field: Vec<u8>,
+}
+
+// HLEN key
+#[derive(Debug)]
+pub struct HLen {
+    key: Vec<u8>,
+}
+
+// HKEYS key
+#[derive(Debug)]
+pub struct HKeys {
+    key: Vec<u8>,
+}
+
+// HVALS key
+#[derive(Debug)]
+pub struct HVals {
+    key: Vec<u8>,
+}
+
+// HINCRBY key field increment
+#[derive(Debug)]
+pub struct HIncrBy {
+    key: Vec<u8>,
+    field: Vec<u8>,
+    increment: i64,
+}
+
+// HINCRBYFLOAT key field increment
+#[derive(Debug)]
+pub struct HIncrByFloat {
+    key: Vec<u8>,
+    field: Vec<u8>,
+    increment: f64,
+    // as given, for float_sum to add exactly
+    increment_text: Vec<u8>,
+}
+
+// HSETNX key field value
+#[derive(Debug)]
+pub struct HSetNx {
+    key: Vec<u8>,
+    field: Vec<u8>,
+    value: Vec<u8>,
+}
+
+// HSTRLEN key field
+#[derive(Debug)]
+pub struct HStrlen {
+    key: Vec<u8>,
+    field: Vec<u8>,
+}
+
+// HRANDFIELD key [count [WITHVALUES]]
+#[derive(Debug)]
+pub struct HRandField {
+    key: Vec<u8>,
+    count: Option<i64>,
+    with_values: bool,
+}
+
+// HSCAN key cursor [MATCH pattern] [COUNT count]
+#[derive(Debug)]
+pub struct HScan {
+    key: Vec<u8>,
+    args: ScanArgs,
+}
+
+impl CommandSpec for HSet {
+    const NAME: &'static str = "hset";
+    const ARITY: i64 = -4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        if !args.len().is_multiple_of(2) {
+            return Err(CommandError::WrongArity(Self::NAME.to_string()));
+        }
+        let mut pairs = Vec::with_capacity(args.len() / 2);
+        while !args.is_empty() {
+            pairs.push((args.next_bytes()?, args.next_bytes()?));
+        }
+        Ok(HSet { key, pairs })
+    }
+}
+
+impl CommandExecutor for HSet {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let hash = ctx.db().get_or_create_hash(&self.key)?;
+        let mut added = 0;
+        for (field, value) in self.pairs {
+            if hash.insert(field, value).is_none() {
+                added += 1;
+            }
+        }
+        ctx.db().touch(&self.key);
+        Ok(RespFrame::Integer(added))
+    }
+}
+
+impl CommandSpec for HGet {
+    const NAME: &'static str = "hget";
+    const ARITY: i64 = 3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HGet {
+            key: args.next_bytes()?,
+            field: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for HGet {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let value = ctx
+            .db()
+            .get_hash(&self.key)?
+            .and_then(|hash| hash.get(&self.field));
+        Ok(match value {
+            Some(v) => BulkString::new(v.clone()).into(),
+            None => nil(),
+        })
+    }
+}
+
+impl CommandSpec for HMGet {
+    const NAME: &'static str = "hmget";
+    const ARITY: i64 = -3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HMGet {
+            key: args.next_bytes()?,
+            fields: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for HMGet {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let hash = ctx.db().get_hash(&self.key)?;
+        let values = self
+            .fields
+            .iter()
+            .map(|field| match hash.and_then(|h| h.get(field)) {
+                Some(v) => BulkString::new(v.clone()).into(),
+                None => nil(),
+            })
+            .collect::<Vec<RespFrame>>();
+        Ok(RespArray::new(values).into())
+    }
+}
+
+impl CommandSpec for HGetAll {
+    const NAME: &'static str = "hgetall";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HGetAll {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for HGetAll {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let hash = ctx.db().get_hash(&self.key)?;
+        let entries = hash.into_iter().flatten();
+        if resp3 {
+            let mut map = RespMap::new();
+            for (field, value) in entries {
+                map.insert(field.clone(), BulkString::new(value.clone()).into());
+            }
+            Ok(map.into())
+        } else {
+            let mut arr = Vec::new();
+            for (field, value) in entries {
+                arr.push(BulkString::new(field.clone()).into());
+                arr.push(BulkString::new(value.clone()).into());
+            }
+            Ok(RespArray::new(arr).into())
+        }
+    }
+}
+
+impl CommandSpec for HDel {
+    const NAME: &'static str = "hdel";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HDel {
+            key: args.next_bytes()?,
+            fields: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for HDel {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let hash = match db.get_hash_mut(&self.key)? {
+            Some(hash) => hash,
+            None => return Ok(RespFrame::Integer(0)),
+        };
+        let removed = self
+            .fields
+            .iter()
+            .filter(|field| hash.remove(*field).is_some())
+            .count();
+        if removed > 0 {
+            db.touch(&self.key);
+            db.remove_if_empty(&self.key);
+        }
+        Ok(RespFrame::Integer(removed as i64))
+    }
+}
+
+impl CommandSpec for HExists {
+    const NAME: &'static str = "hexists";
+    const ARITY: i64 = 3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HExists {
+            key: args.next_bytes()?,
+            field: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for HExists {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let exists = ctx
+            .db()
+            .get_hash(&self.key)?
+            .is_some_and(|hash| hash.contains_key(&self.field));
+        Ok(RespFrame::Integer(exists as i64))
+    }
+}
+
+impl CommandSpec for HLen {
+    const NAME: &'static str = "hlen";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HLen {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for HLen {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let len = ctx.db().get_hash(&self.key)?.map(|h| h.len()).unwrap_or(0);
+        Ok(RespFrame::Integer(len as i64))
+    }
+}
+
+impl CommandSpec for HKeys {
+    const NAME: &'static str = "hkeys";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HKeys {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for HKeys {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let keys = ctx
+            .db()
+            .get_hash(&self.key)?
+            .into_iter()
+            .flat_map(|h| h.keys())
+            .map(|k| BulkString::new(k.clone()).into())
+            .collect::<Vec<RespFrame>>();
+        Ok(RespArray::new(keys).into())
+    }
+}
+
+impl CommandSpec for HVals {
+    const NAME: &'static str = "hvals";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HVals {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for HVals {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let values = ctx
+            .db()
+            .get_hash(&self.key)?
+            .into_iter()
+            .flat_map(|h| h.values())
+            .map(|v| BulkString::new(v.clone()).into())
+            .collect::<Vec<RespFrame>>();
+        Ok(RespArray::new(values).into())
+    }
+}
+
+impl CommandSpec for HIncrBy {
+    const NAME: &'static str = "hincrby";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HIncrBy {
+            key: args.next_bytes()?,
+            field: args.next_bytes()?,
+            increment: args.next_i64()?,
+        })
+    }
+}
+
+impl CommandExecutor for HIncrBy {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let current = match db.get_hash(&self.key)?.and_then(|h| h.get(&self.field)) {
+            Some(v) => {
+                parse_i64(v).map_err(|_| CommandError::err("hash value is not an integer"))?
+            }
+            None => 0,
+        };
+        let value = current
+            .checked_add(self.increment)
+            .ok_or_else(|| CommandError::err("increment or decrement would overflow"))?;
+        db.get_or_create_hash(&self.key)?
+            .insert(self.field, value.to_string().into_bytes());
+        db.touch(&self.key);
+        Ok(RespFrame::Integer(value))
+    }
+}
+
+impl CommandSpec for HIncrByFloat {
+    const NAME: &'static str = "hincrbyfloat";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let field = args.next_bytes()?;
+        let increment_text = args.next_bytes()?;
+        Ok(HIncrByFloat {
+            key,
+            field,
+            increment: parse_f64(&increment_text)?,
+            increment_text,
+        })
+    }
+}
+
+impl CommandExecutor for HIncrByFloat {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let (current, current_text) = match db.get_hash(&self.key)?.and_then(|h| h.get(&self.field))
+        {
+            Some(v) => (
+                parse_f64(v).map_err(|_| CommandError::err("hash value is not a float"))?,
+                v.clone(),
+            ),
+            None => (0.0, b"0".to_vec()),
+        };
+        let value = current + self.increment;
+        if !value.is_finite() {
+            return Err(CommandError::err("increment would produce NaN or Infinity"));
+        }
+        let formatted = float_sum(&current_text, &self.increment_text, value).into_bytes();
+        db.get_or_create_hash(&self.key)?
+            .insert(self.field, formatted.clone());
+        db.touch(&self.key);
+        Ok(BulkString::new(formatted).into())
+    }
+}
+
+impl CommandSpec for HSetNx {
+    const NAME: &'static str = "hsetnx";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HSetNx {
+            key: args.next_bytes()?,
+            field: args.next_bytes()?,
+            value: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for HSetNx {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let hash = ctx.db().get_or_create_hash(&self.key)?;
+        if hash.contains_key(&self.field) {
+            return Ok(RespFrame::Integer(0));
+        }
+        hash.insert(self.field, self.value);
+        ctx.db().touch(&self.key);
+        Ok(RespFrame::Integer(1))
+    }
+}
+
+impl CommandSpec for HStrlen {
+    const NAME: &'static str = "hstrlen";
+    const ARITY: i64 = 3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HStrlen {
+            key: args.next_bytes()?,
+            field: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for HStrlen {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let len = ctx
+            .db()
+            .get_hash(&self.key)?
+            .and_then(|h| h.get(&self.field))
+            .map(|v| v.len())
+            .unwrap_or(0);
+        Ok(RespFrame::Integer(len as i64))
+    }
+}
+
+impl CommandSpec for HRandField {
+    const NAME: &'static str = "hrandfield";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let count = match args.is_empty() {
+            true => None,
+            false => Some(args.next_i64()?),
+        };
+        let with_values = match args.next_keyword() {
+            Some(opt) if opt == "WITHVALUES" => true,
+            Some(_) => return Err(CommandError::Syntax),
+            None => false,
+        };
+        args.finish()?;
+        Ok(HRandField {
+            key,
+            count,
+            with_values,
+        })
+    }
+}
+
+impl CommandExecutor for HRandField {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let hash = ctx.db().get_hash(&self.key)?;
+        let entries = hash
+            .map(|h| h.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let count = match self.count {
+            None => {
+                return Ok(match random_sample(&entries, 1)?.first() {
+                    Some((field, _)) => BulkString::new(field.to_vec()).into(),
+                    None => nil(),
+                })
+            }
+            Some(count) => count,
+        };
+
+        let mut arr = Vec::new();
+        for (field, value) in random_sample(&entries, count)? {
+            let field: RespFrame = BulkString::new(field.to_vec()).into();
+            match (self.with_values, resp3) {
+                (false, _) => arr.push(field),
+                // RESP3 groups each field with its value
+                (true, true) => arr.push(
+                    RespArray::new(vec![field, BulkString::new(value.to_vec()).into()]).into(),
+                ),
+                (true, false) => {
+                    arr.push(field);
+                    arr.push(BulkString::new(value.to_vec()).into());
+                }
+            }
+        }
+        Ok(RespArray::new(arr).into())
+    }
+}
+
+impl CommandSpec for HScan {
+    const NAME: &'static str = "hscan";
+    const ARITY: i64 = -3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(HScan {
+            key: args.next_bytes()?,
+            args: ScanArgs::parse(args, false)?,
+        })
+    }
+}
+
+impl CommandExecutor for HScan {
+    // fields and values, interleaved
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let hash = match ctx.db().get_hash(&self.key)? {
+            Some(hash) => hash,
+            None => return Ok(scan_reply(0, Vec::new())),
+        };
+        let args = &self.args;
+        let (cursor, found) = args.walk(|cursor, found| {
+            hash.scan(cursor, |field, value| {
+                if args.matches(field) {
+                    found.push(field.clone());
+                    found.push(value.clone());
+                }
+            })
+        });
+        Ok(scan_reply(cursor, found))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::{ok, TestContext};
+    use crate::RespEncode;
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    #[test]
+    fn test_hset_hget() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("hset h a 1 b 2"), RespFrame::Integer(2));
+        assert_eq!(t.run("hset h a 3 c 4"), RespFrame::Integer(1));
+        assert_eq!(t.run("hget h a"), bulk("3"));
+        assert_eq!(t.run("hget h x"), nil());
+        assert_eq!(t.run("hget missing a"), nil());
+        assert_eq!(
+            t.run("hmget h a x c"),
+            RespArray::new(vec![bulk("3"), nil(), bulk("4")]).into()
+        );
+        assert_eq!(
+            t.run("hset h a"),
+            CommandError::WrongArity("hset".to_string()).into()
+        );
+        assert_eq!(t.run("hlen h"), RespFrame::Integer(3));
+        assert_eq!(t.run("hstrlen h a"), RespFrame::Integer(1));
+        assert_eq!(t.run("hexists h a"), RespFrame::Integer(1));
+        assert_eq!(t.run("hexists h x"), RespFrame::Integer(0));
+    }
+
+    #[test]
+    fn test_hgetall_by_protocol() {
+        let mut t = TestContext::default();
+        t.run("hset h a 1");
+        assert_eq!(
+            t.run("hgetall h"),
+            RespArray::new(vec![bulk("a"), bulk("1")]).into()
+        );
+        t.run("hello 3");
+        let mut map = RespMap::new();
+        map.insert(b"a".to_vec(), bulk("1"));
+        assert_eq!(t.run("hgetall h"), map.into());
+        assert_eq!(t.run("hgetall missing"), RespMap::new().into());
+
+        // fields are binary on RESP3 too, and go out as bulk strings
+        t.backend.dbs[0]
+            .get_or_create_hash(b"b")
+            .unwrap()
+            .insert(b"x\r\n\xff".to_vec(), b"v".to_vec());
+        assert_eq!(
+            t.run("hgetall b").encode(),
+            b"%1\r\n$4\r\nx\r\n\xff\r\n$1\r\nv\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_hdel_removes_empty_hash() {
+        let mut t = TestContext::default();
+        t.run("hset h a 1 b 2");
+        assert_eq!(t.run("hdel h a x"), RespFrame::Integer(1));
+        assert_eq!(t.run("hdel h b"), RespFrame::Integer(1));
+        assert!(!t.backend.dbs[0].contains_key(b"h"));
+    }
+
+    #[test]
+    fn test_hincrby() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("hincrby h n 5"), RespFrame::Integer(5));
+        assert_eq!(t.run("hincrby h n -7"), RespFrame::Integer(-2));
+        assert_eq!(t.run("hincrbyfloat h f 1.5"), bulk("1.5"));
+        assert_eq!(t.run("hincrbyfloat h n 0.5"), bulk("-1.5"));
+        // decimals add up as decimals, not as doubles do
+        assert_eq!(t.run("hincrbyfloat h d 0.1"), bulk("0.1"));
+        assert_eq!(t.run("hincrbyfloat h d 0.2"), bulk("0.3"));
+        assert_eq!(t.run("hget h d"), bulk("0.3"));
+        assert_eq!(
+            t.run("hincrby h n 1"),
+            CommandError::err("hash value is not an integer").into()
+        );
+        t.run("hset h s abc");
+        assert_eq!(
+            t.run("hincrbyfloat h s 1"),
+            CommandError::err("hash value is not a float").into()
+        );
+    }
+
+    #[test]
+    fn test_hsetnx_keys_vals() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("hsetnx h a 1"), RespFrame::Integer(1));
+        assert_eq!(t.run("hsetnx h a 2"), RespFrame::Integer(0));
+        assert_eq!(t.run("hkeys h"), RespArray::new(vec![bulk("a")]).into());
+        assert_eq!(t.run("hvals h"), RespArray::new(vec![bulk("1")]).into());
+        assert_eq!(t.run("set s v"), ok());
+        assert_eq!(t.run("hget s a"), CommandError::WrongType.into());
+    }
+
+    #[test]
+    fn test_hrandfield() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("hrandfield h"), nil());
+        assert_eq!(t.run("hrandfield h 3"), RespArray::new(vec![]).into());
+        t.run("hset h a 1 b 2 c 3");
+        assert!(matches!(t.run("hrandfield h"), RespFrame::BulkString(_)));
+
+        let len = |frame: RespFrame| match frame {
+            RespFrame::Array(arr) => arr.len(),
+            _ => panic!("expected array"),
+        };
+        assert_eq!(len(t.run("hrandfield h 10")), 3);
+        assert_eq!(len(t.run("hrandfield h -10")), 10);
+        assert_eq!(len(t.run("hrandfield h 2 withvalues")), 4);
+        t.run("hello 3");
+        assert_eq!(len(t.run("hrandfield h 2 withvalues")), 2);
+        assert_eq!(t.run("hrandfield h 2 foo"), CommandError::Syntax.into());
+    }
+}
@@ -0,0 +1,200 @@
+use crate::backend::{
+    hll_add, hll_check, hll_count, hll_estimate, hll_is_dense, hll_merge, hll_new, hll_store,
+    HllError, HLL_REGISTERS,
+};
+use crate::RespFrame;
+
+use super::{ok, CommandArgs, CommandError, CommandExecutor, CommandSpec, Context};
+
+// PFADD key [element [element ...]]
+#[derive(Debug)]
+pub struct PfAdd {
+    key: Vec<u8>,
+    elements: Vec<Vec<u8>>,
+}
+
+// PFCOUNT key [key ...]
+#[derive(Debug)]
+pub struct PfCount {
+    keys: Vec<Vec<u8>>,
+}
+
+// PFMERGE destkey [sourcekey [sourcekey ...]]
+#[derive(Debug)]
+pub struct PfMerge {
+    dest: Vec<u8>,
+    sources: Vec<Vec<u8>>,
+}
+
+impl From<HllError> for CommandError {
+    fn from(e: HllError) -> Self {
+        match e {
+            HllError::NotHll => CommandError::Reply(
+                "WRONGTYPE Key is not a valid HyperLogLog string value.".to_string(),
+            ),
+            HllError::Corrupted => {
+                CommandError::Reply("INVALIDOBJ Corrupted HLL object detected".to_string())
+            }
+        }
+    }
+}
+
+impl CommandSpec for PfAdd {
+    const NAME: &'static str = "pfadd";
+    const ARITY: i64 = -2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(PfAdd {
+            key: args.next_bytes()?,
+            elements: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for PfAdd {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        // creating the key counts as a change even with no elements
+        let mut changed = false;
+        if db.get_string(&self.key)?.is_none() {
+            db.insert(self.key.clone(), hll_new().into());
+            changed = true;
+        }
+        let hll = db.get_string_mut(&self.key)?.expect("key just checked");
+        hll_check(hll)?;
+        for element in &self.elements {
+            changed |= hll_add(hll, element)?;
+        }
+        if changed {
+            db.touch(&self.key);
+        }
+        Ok(RespFrame::Integer(changed as i64))
+    }
+}
+
+impl CommandSpec for PfCount {
+    const NAME: &'static str = "pfcount";
+    const ARITY: i64 = -2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(PfCount { keys: args.rest() })
+    }
+}
+
+impl CommandExecutor for PfCount {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        // a single key can use and refresh its cached count
+        if let [key] = self.keys.as_slice() {
+            let count = match db.get_string_mut(key)? {
+                Some(hll) => hll_count(hll)?,
+                None => 0,
+            };
+            return Ok(RespFrame::Integer(count as i64));
+        }
+        // several are counted as their union, without touching any of them
+        let mut registers = vec![0; HLL_REGISTERS];
+        for key in &self.keys {
+            if let Some(hll) = db.get_string(key)? {
+                hll_merge(&mut registers, hll)?;
+            }
+        }
+        Ok(RespFrame::Integer(hll_estimate(&registers) as i64))
+    }
+}
+
+impl CommandSpec for PfMerge {
+    const NAME: &'static str = "pfmerge";
+    const ARITY: i64 = -2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(PfMerge {
+            dest: args.next_bytes()?,
+            sources: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for PfMerge {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        // the destination is one of the inputs, and the result is dense as
+        // soon as any input is
+        let mut registers = vec![0; HLL_REGISTERS];
+        let mut dense = false;
+        for key in std::iter::once(&self.dest).chain(&self.sources) {
+            if let Some(hll) = db.get_string(key)? {
+                dense |= hll_is_dense(hll)?;
+                hll_merge(&mut registers, hll)?;
+            }
+        }
+        if db.get_string(&self.dest)?.is_none() {
+            db.insert(self.dest.clone(), hll_new().into());
+        }
+        let hll = db.get_string_mut(&self.dest)?.expect("key just checked");
+        hll_store(hll, &registers, dense)?;
+        db.touch(&self.dest);
+        Ok(ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::TestContext;
+
+    fn int(n: i64) -> RespFrame {
+        RespFrame::Integer(n)
+    }
+
+    #[test]
+    fn test_pfadd_pfcount() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("pfadd h"), int(1));
+        assert_eq!(t.run("pfadd h"), int(0));
+        assert_eq!(t.run("pfcount h"), int(0));
+        assert_eq!(t.run("pfadd h a b c d e f g"), int(1));
+        assert_eq!(t.run("pfadd h a b"), int(0));
+        assert_eq!(t.run("pfcount h"), int(7));
+        assert_eq!(t.run("pfcount missing"), int(0));
+        // the value is an ordinary string starting with the magic
+        let RespFrame::BulkString(raw) = t.run("get h") else {
+            panic!("a HyperLogLog is a string");
+        };
+        assert_eq!(&raw[..5], b"HYLL\x01");
+
+        t.run("set s hello");
+        assert_eq!(
+            t.run("pfadd s a"),
+            CommandError::Reply(
+                "WRONGTYPE Key is not a valid HyperLogLog string value.".to_string()
+            )
+            .into()
+        );
+        assert_eq!(t.run("pfcount h s"), t.run("pfadd s a"));
+        t.backend.dbs[0].insert(b"empty".to_vec(), Vec::new().into());
+        assert_eq!(t.run("pfcount empty"), t.run("pfadd s a"));
+        t.run("lpush l a");
+        assert_eq!(t.run("pfcount l"), CommandError::WrongType.into());
+    }
+
+    #[test]
+    fn test_pfmerge() {
+        let mut t = TestContext::default();
+        t.run("pfadd a 1 2 3 4");
+        t.run("pfadd b 3 4 5 6");
+        assert_eq!(t.run("pfcount a b"), int(6));
+        assert_eq!(t.run("pfmerge c a b"), ok());
+        assert_eq!(t.run("pfcount c"), int(6));
+        assert_eq!(t.run("pfadd c 7"), int(1));
+        assert_eq!(t.run("pfmerge c"), ok());
+        assert_eq!(t.run("pfcount c"), int(7));
+        // the inputs are left alone
+        assert_eq!(t.run("pfcount a"), int(4));
+        assert_eq!(t.run("pfmerge d missing"), ok());
+        assert_eq!(t.run("pfcount d"), int(0));
+    }
+}
@@ -0,0 +1,862 @@
+use std::collections::HashSet;
+
+use crate::backend::glob_match;
+use crate::{BulkString, RespArray, RespFrame, SimpleString};
+
+use super::{nil, ok, CommandArgs, CommandError, CommandExecutor, CommandSpec, Context};
+
+// DEL key [key ...]
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<Vec<u8>>,
+}
+
+// UNLINK key [key ...]
+#[derive(Debug)]
+pub struct Unlink {
+    keys: Vec<Vec<u8>>,
+}
+
+// EXISTS key [key ...]
+#[derive(Debug)]
+pub struct Exists {
+    keys: Vec<Vec<u8>>,
+}
+
+// TYPE key
+#[derive(Debug)]
+pub struct Type {
+    key: Vec<u8>,
+}
+
+// RENAME key newkey
+#[derive(Debug)]
+pub struct Rename {
+    key: Vec<u8>,
+    new_key: Vec<u8>,
+}
+
+// RENAMENX key newkey
+#[derive(Debug)]
+pub struct RenameNx {
+    key: Vec<u8>,
+    new_key: Vec<u8>,
+}
+
+// COPY source destination [DB destination-db] [REPLACE]
+#[derive(Debug)]
+pub struct Copy {
+    source: Vec<u8>,
+    destination: Vec<u8>,
+    db: Option<i64>,
+    replace: bool,
+}
+
+// MOVE key db
+#[derive(Debug)]
+pub struct Move {
+    key: Vec<u8>,
+    db: i64,
+}
+
+// SWAPDB index1 index2
+#[derive(Debug)]
+pub struct SwapDb {
+    first: i64,
+    second: i64,
+}
+
+// FLUSHDB [ASYNC | SYNC]
+#[derive(Debug)]
+pub struct FlushDb;
+
+// FLUSHALL [ASYNC | SYNC]
+#[derive(Debug)]
+pub struct FlushAll;
+
+// TOUCH key [key ...]
+#[derive(Debug)]
+pub struct Touch {
+    keys: Vec<Vec<u8>>,
+}
+
+// RANDOMKEY
+#[derive(Debug)]
+pub struct RandomKey;
+
+// DBSIZE
+#[derive(Debug)]
+pub struct DbSize;
+
+// KEYS pattern
+#[derive(Debug)]
+pub struct Keys {
+    pattern: Vec<u8>,
+}
+
+// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+#[derive(Debug)]
+pub struct Scan {
+    args: ScanArgs,
+}
+
+// the arguments SCAN, HSCAN, SSCAN and ZSCAN share
+#[derive(Debug)]
+pub(crate) struct ScanArgs {
+    cursor: u64,
+    pattern: Option<Vec<u8>>,
+    count: usize,
+    // the TYPE filter, SCAN only
+    type_name: Option<String>,
+}
+
+impl ScanArgs {
+    pub(crate) fn parse(args: &mut CommandArgs, with_type: bool) -> Result<ScanArgs, CommandError> {
+        let cursor = String::from_utf8_lossy(&args.next_bytes()?)
+            .parse()
+            .map_err(|_| CommandError::err("invalid cursor"))?;
+        let mut scan = ScanArgs {
+            cursor,
+            pattern: None,
+            count: 10,
+            type_name: None,
+        };
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                "MATCH" if !args.is_empty() => scan.pattern = Some(args.next_bytes()?),
+                "COUNT" if !args.is_empty() => {
+                    let count = args.next_i64()?;
+                    if count < 1 {
+                        return Err(CommandError::Syntax);
+                    }
+                    scan.count = count as usize;
+                }
+                "TYPE" if with_type && !args.is_empty() => {
+                    scan.type_name = Some(args.next_string()?.to_ascii_lowercase());
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok(scan)
+    }
+
+    pub(crate) fn matches(&self, element: &[u8]) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| glob_match(pattern, element, false))
+    }
+
+    // walk from the cursor until count elements turned up, giving up after
+    // ten times as many buckets; step visits one bucket and returns the
+    // next cursor
+    pub(crate) fn walk<T>(&self, mut step: impl FnMut(u64, &mut Vec<T>) -> u64) -> (u64, Vec<T>) {
+        let mut found = Vec::new();
+        let mut cursor = self.cursor;
+        let mut budget = self.count.saturating_mul(10);
+        loop {
+            cursor = step(cursor, &mut found);
+            budget -= 1;
+            if cursor == 0 || budget == 0 || found.len() >= self.count {
+                return (cursor, found);
+            }
+        }
+    }
+}
+
+// the [cursor, elements] reply of the SCAN family
+pub(crate) fn scan_reply(cursor: u64, elements: Vec<Vec<u8>>) -> RespFrame {
+    let elements = elements
+        .into_iter()
+        .map(|element| BulkString::new(element).into())
+        .collect::<Vec<RespFrame>>();
+    RespArray::new(vec![
+        BulkString::new(cursor.to_string()).into(),
+        RespArray::new(elements).into(),
+    ])
+    .into()
+}
+
+impl CommandSpec for Del {
+    const NAME: &'static str = "del";
+    const ARITY: i64 = -2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Del { keys: args.rest() })
+    }
+}
+
+impl CommandExecutor for Del {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let removed = self
+            .keys
+            .iter()
+            .filter(|key| db.remove(key).is_some())
+            .count();
+        Ok(RespFrame::Integer(removed as i64))
+    }
+}
+
+impl CommandSpec for Unlink {
+    const NAME: &'static str = "unlink";
+    const ARITY: i64 = -2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Unlink { keys: args.rest() })
+    }
+}
+
+impl CommandExecutor for Unlink {
+    // values are freed where they are dropped, so this is DEL
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Del { keys: self.keys }.execute(ctx)
+    }
+}
+
+impl CommandSpec for Exists {
+    const NAME: &'static str = "exists";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Exists { keys: args.rest() })
+    }
+}
+
+impl CommandExecutor for Exists {
+    // a key named twice counts twice
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let found = self.keys.iter().filter(|key| db.contains_key(key)).count();
+        Ok(RespFrame::Integer(found as i64))
+    }
+}
+
+impl CommandSpec for Type {
+    const NAME: &'static str = "type";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Type {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Type {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let name = ctx.db().get(&self.key).map_or("none", |v| v.type_name());
+        Ok(SimpleString::new(name).into())
+    }
+}
+
+impl CommandSpec for Rename {
+    const NAME: &'static str = "rename";
+    const ARITY: i64 = 3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Rename {
+            key: args.next_bytes()?,
+            new_key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Rename {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        if !db.contains_key(&self.key) {
+            return Err(CommandError::err("no such key"));
+        }
+        if self.key != self.new_key {
+            db.rename(&self.key, &self.new_key);
+        }
+        Ok(ok())
+    }
+}
+
+impl CommandSpec for RenameNx {
+    const NAME: &'static str = "renamenx";
+    const ARITY: i64 = 3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(RenameNx {
+            key: args.next_bytes()?,
+            new_key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for RenameNx {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        if !db.contains_key(&self.key) {
+            return Err(CommandError::err("no such key"));
+        }
+        if db.contains_key(&self.new_key) {
+            return Ok(RespFrame::Integer(0));
+        }
+        db.rename(&self.key, &self.new_key);
+        Ok(RespFrame::Integer(1))
+    }
+}
+
+impl CommandSpec for Copy {
+    const NAME: &'static str = "copy";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let source = args.next_bytes()?;
+        let destination = args.next_bytes()?;
+        let mut copy = Copy {
+            source,
+            destination,
+            db: None,
+            replace: false,
+        };
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                "DB" if !args.is_empty() => copy.db = Some(args.next_i64()?),
+                "REPLACE" => copy.replace = true,
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok(copy)
+    }
+}
+
+impl CommandExecutor for Copy {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let from = ctx.client.db;
+        let to = match self.db {
+            Some(index) => ctx.db_index(index)?,
+            None => from,
+        };
+        if from == to && self.source == self.destination {
+            return Err(CommandError::err(
+                "source and destination objects are the same",
+            ));
+        }
+        let db = &mut ctx.backend.dbs[from];
+        let value = match db.get(&self.source) {
+            Some(value) => value.clone(),
+            None => return Ok(RespFrame::Integer(0)),
+        };
+        let at = db.expire_at(&self.source);
+        let db = &mut ctx.backend.dbs[to];
+        if !self.replace && db.contains_key(&self.destination) {
+            return Ok(RespFrame::Integer(0));
+        }
+        db.insert(self.destination.clone(), value);
+        if let Some(at) = at {
+            db.set_expire(&self.destination, at);
+        }
+        Ok(RespFrame::Integer(1))
+    }
+}
+
+impl CommandSpec for Move {
+    const NAME: &'static str = "move";
+    const ARITY: i64 = 3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Move {
+            key: args.next_bytes()?,
+            db: args.next_i64()?,
+        })
+    }
+}
+
+impl CommandExecutor for Move {
+    // the key keeps its TTL; nothing moves when the target already has the key
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let from = ctx.client.db;
+        let to = ctx.db_index(self.db)?;
+        if from == to {
+            return Err(CommandError::err(
+                "source and destination objects are the same",
+            ));
+        }
+        if !ctx.backend.dbs[from].contains_key(&self.key)
+            || ctx.backend.dbs[to].contains_key(&self.key)
+        {
+            return Ok(RespFrame::Integer(0));
+        }
+        let source = &mut ctx.backend.dbs[from];
+        let at = source.expire_at(&self.key);
+        let value = source.remove(&self.key).expect("checked above");
+        let target = &mut ctx.backend.dbs[to];
+        target.insert(self.key.clone(), value);
+        if let Some(at) = at {
+            target.set_expire(&self.key, at);
+        }
+        Ok(RespFrame::Integer(1))
+    }
+}
+
+impl CommandSpec for SwapDb {
+    const NAME: &'static str = "swapdb";
+    const ARITY: i64 = 3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let first = args
+            .next_i64()
+            .map_err(|_| CommandError::err("invalid first DB index"))?;
+        let second = args
+            .next_i64()
+            .map_err(|_| CommandError::err("invalid second DB index"))?;
+        Ok(SwapDb { first, second })
+    }
+}
+
+impl CommandExecutor for SwapDb {
+    // clients keep their index and so see the other keyspace from now on.
+    // A key of either keyspace changes in both, appearing in one and going
+    // away from the other or taking another value, so it is touched in both:
+    // watches on it fail, and the clients blocked on it get served
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let first = ctx.db_index(self.first)?;
+        let second = ctx.db_index(self.second)?;
+        if first != second {
+            let dbs = &mut ctx.backend.dbs;
+            let keys: HashSet<Vec<u8>> = dbs[first]
+                .keys()
+                .chain(dbs[second].keys())
+                .cloned()
+                .collect();
+            dbs.swap(first, second);
+            for key in &keys {
+                dbs[first].touch(key);
+                dbs[second].touch(key);
+            }
+        }
+        Ok(ok())
+    }
+}
+
+// the optional ASYNC or SYNC of the FLUSH commands; both free the memory
+// right away here, there is no background thread to hand it to
+pub(crate) fn parse_flush_mode(args: &mut CommandArgs) -> Result<(), CommandError> {
+    match args.next_keyword() {
+        None => Ok(()),
+        Some(mode) if (mode == "ASYNC" || mode == "SYNC") && args.is_empty() => Ok(()),
+        Some(_) => Err(CommandError::Syntax),
+    }
+}
+
+impl CommandSpec for FlushDb {
+    const NAME: &'static str = "flushdb";
+    const ARITY: i64 = -1;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        parse_flush_mode(args)?;
+        Ok(FlushDb)
+    }
+}
+
+impl CommandExecutor for FlushDb {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        ctx.db().clear();
+        Ok(ok())
+    }
+}
+
+impl CommandSpec for FlushAll {
+    const NAME: &'static str = "flushall";
+    const ARITY: i64 = -1;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        parse_flush_mode(args)?;
+        Ok(FlushAll)
+    }
+}
+
+impl CommandExecutor for FlushAll {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        for db in &mut ctx.backend.dbs {
+            db.clear();
+        }
+        Ok(ok())
+    }
+}
+
+impl CommandSpec for Touch {
+    const NAME: &'static str = "touch";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Touch { keys: args.rest() })
+    }
+}
+
+impl CommandExecutor for Touch {
+    // there is no access time to update, so this only counts
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Exists { keys: self.keys }.execute(ctx)
+    }
+}
+
+impl CommandSpec for RandomKey {
+    const NAME: &'static str = "randomkey";
+    const ARITY: i64 = 1;
+
+    fn parse(_args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(RandomKey)
+    }
+}
+
+impl CommandExecutor for RandomKey {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(ctx
+            .db()
+            .random_key()
+            .map_or_else(nil, |key| BulkString::new(key).into()))
+    }
+}
+
+impl CommandSpec for DbSize {
+    const NAME: &'static str = "dbsize";
+    const ARITY: i64 = 1;
+
+    fn parse(_args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(DbSize)
+    }
+}
+
+impl CommandExecutor for DbSize {
+    // like Redis, this counts expired keys not reclaimed yet
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(RespFrame::Integer(ctx.db().len() as i64))
+    }
+}
+
+impl CommandSpec for Keys {
+    const NAME: &'static str = "keys";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Keys {
+            pattern: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Keys {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let keys = ctx
+            .db()
+            .keys()
+            .filter(|key| glob_match(&self.pattern, key, false))
+            .map(|key| BulkString::new(key.clone()).into())
+            .collect::<Vec<RespFrame>>();
+        Ok(RespArray::new(keys).into())
+    }
+}
+
+impl CommandSpec for Scan {
+    const NAME: &'static str = "scan";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Scan {
+            args: ScanArgs::parse(args, true)?,
+        })
+    }
+}
+
+impl CommandExecutor for Scan {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let args = &self.args;
+        let (cursor, keys) = args.walk(|cursor, found| {
+            db.scan(cursor, |key, value| {
+                let type_ok = args
+                    .type_name
+                    .as_ref()
+                    .is_none_or(|name| name == value.type_name());
+                if type_ok && args.matches(key) {
+                    found.push(key.to_vec());
+                }
+            })
+        });
+        Ok(scan_reply(cursor, keys))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::TestContext;
+    use crate::RespNullArray;
+
+    fn int(n: i64) -> RespFrame {
+        RespFrame::Integer(n)
+    }
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    // page through a SCAN-like command to the end, collecting what it returns
+    fn scan_all(t: &mut TestContext, command: &str) -> Vec<String> {
+        let mut found = Vec::new();
+        let mut cursor = "0".to_string();
+        loop {
+            let RespFrame::Array(reply) = t.run(&command.replace("{}", &cursor)) else {
+                panic!("scan replies with an array");
+            };
+            let [RespFrame::BulkString(next), RespFrame::Array(elements)] = &reply[..] else {
+                panic!("scan replies with a cursor and elements");
+            };
+            for element in elements.iter() {
+                let RespFrame::BulkString(element) = element else {
+                    panic!("elements are bulk strings");
+                };
+                found.push(String::from_utf8_lossy(element).into_owned());
+            }
+            cursor = String::from_utf8_lossy(next).into_owned();
+            if cursor == "0" {
+                return found;
+            }
+        }
+    }
+
+    #[test]
+    fn test_del_exists_type() {
+        let mut t = TestContext::default();
+        t.run("set a 1");
+        t.run("lpush l x");
+        assert_eq!(t.run("exists a a l missing"), int(3));
+        assert_eq!(t.run("type a"), SimpleString::new("string").into());
+        assert_eq!(t.run("type l"), SimpleString::new("list").into());
+        assert_eq!(t.run("type missing"), SimpleString::new("none").into());
+        assert_eq!(t.run("touch a missing"), int(1));
+        assert_eq!(t.run("del a l missing"), int(2));
+        assert_eq!(t.run("unlink a"), int(0));
+        assert_eq!(t.run("dbsize"), int(0));
+        assert_eq!(t.run("randomkey"), nil());
+        t.run("set b 2");
+        assert_eq!(t.run("randomkey"), bulk("b"));
+        assert_eq!(t.run("dbsize"), int(1));
+    }
+
+    #[test]
+    fn test_rename_and_copy() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("rename a b"), CommandError::err("no such key").into());
+        t.run("set a 1");
+        t.run("expire a 100");
+        assert_eq!(t.run("rename a a"), ok());
+        assert_eq!(t.run("rename a b"), ok());
+        assert_eq!(t.run("exists a"), int(0));
+        assert_eq!(t.run("ttl b"), int(100));
+        t.run("set c 3");
+        assert_eq!(t.run("renamenx b c"), int(0));
+        assert_eq!(t.run("renamenx b d"), int(1));
+        assert_eq!(t.run("get d"), bulk("1"));
+
+        assert_eq!(t.run("copy d c"), int(0));
+        assert_eq!(t.run("copy d c replace"), int(1));
+        assert_eq!(t.run("get c"), bulk("1"));
+        assert_eq!(t.run("ttl c"), int(100));
+        assert_eq!(t.run("copy missing e"), int(0));
+        assert_eq!(
+            t.run("copy c c"),
+            CommandError::err("source and destination objects are the same").into()
+        );
+        // the copy is independent of the original
+        t.run("rpush l1 a b");
+        t.run("copy l1 l2");
+        t.run("rpush l1 c");
+        assert_eq!(t.run("llen l2"), int(2));
+    }
+
+    #[test]
+    fn test_copy_and_move_across_dbs() {
+        let mut t = TestContext::default();
+        t.run("set a 1");
+        t.run("expire a 100");
+        assert_eq!(t.run("copy a a db 1"), int(1));
+        assert_eq!(t.run("copy a a db 1"), int(0));
+        assert_eq!(
+            t.run("copy a a db 16"),
+            CommandError::err("DB index is out of range").into()
+        );
+
+        assert_eq!(t.run("move a 1"), int(0));
+        assert_eq!(t.run("move a 2"), int(1));
+        assert_eq!(t.run("move a 2"), int(0));
+        assert_eq!(t.run("exists a"), int(0));
+        assert_eq!(
+            t.run("move a 0"),
+            CommandError::err("source and destination objects are the same").into()
+        );
+        t.run("select 2");
+        assert_eq!(t.run("get a"), bulk("1"));
+        assert_eq!(t.run("ttl a"), int(100));
+        t.run("select 1");
+        assert_eq!(t.run("ttl a"), int(100));
+    }
+
+    #[test]
+    fn test_swapdb_and_flush() {
+        let mut t = TestContext::default();
+        t.run("set a 0");
+        t.run("select 1");
+        t.run("set b 1");
+        t.run("set c 1");
+        assert_eq!(t.run("swapdb 0 1"), ok());
+        assert_eq!(t.run("dbsize"), int(1));
+        assert_eq!(t.run("get a"), bulk("0"));
+        assert_eq!(
+            t.run("swapdb x 1"),
+            CommandError::err("invalid first DB index").into()
+        );
+        assert_eq!(
+            t.run("swapdb 0 -1"),
+            CommandError::err("DB index is out of range").into()
+        );
+
+        // a client blocked on a key is served when a swap brings the key in
+        t.run("rpush l x");
+        t.client.db = 2;
+        let mut blocked = t.block("blpop l 0");
+        t.client.db = 1;
+        t.run("swapdb 1 2");
+        let served = RespArray::new(vec![bulk("l"), bulk("x")]).into();
+        assert_eq!(blocked.rx.try_recv().unwrap(), served);
+
+        // a key the swap takes away fails the watches on it, and so does
+        // one it brings in: the first swap takes gone out of db 0, the
+        // second brings it back
+        t.run("select 0");
+        t.run("set gone 1");
+        for _ in 0..2 {
+            t.run("watch gone");
+            t.run("swapdb 0 1");
+            t.run("multi");
+            t.run("ping");
+            assert_eq!(t.run("exec"), RespNullArray.into());
+        }
+        t.run("del gone");
+        t.run("select 1");
+
+        assert_eq!(t.run("flushdb async"), ok());
+        assert_eq!(t.run("select 0"), ok());
+        assert_eq!(t.run("dbsize"), int(2));
+        assert_eq!(t.run("flushdb sync"), ok());
+        assert_eq!(t.run("dbsize"), int(0));
+        t.run("set a 0");
+        t.run("select 5");
+        t.run("set a 5");
+        assert_eq!(t.run("flushall"), ok());
+        assert_eq!(t.run("dbsize"), int(0));
+        t.run("select 0");
+        assert_eq!(t.run("dbsize"), int(0));
+        assert_eq!(t.run("flushall lazy"), CommandError::Syntax.into());
+        assert_eq!(t.run("flushdb sync async"), CommandError::Syntax.into());
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut t = TestContext::default();
+        for key in ["user:1", "user:2", "user:10", "order:1"] {
+            t.run(&format!("set {} v", key));
+        }
+        let RespFrame::Array(keys) = t.run("keys user:?") else {
+            panic!("keys replies with an array");
+        };
+        assert_eq!(keys.len(), 2);
+        let RespFrame::Array(keys) = t.run("keys *") else {
+            panic!("keys replies with an array");
+        };
+        assert_eq!(keys.len(), 4);
+        t.run("pexpire order:1 -1");
+        assert_eq!(t.run("keys order*"), RespArray::new(Vec::new()).into());
+    }
+
+    #[test]
+    fn test_scan() {
+        let mut t = TestContext::default();
+        for i in 0..100 {
+            t.run(&format!("set key:{} v", i));
+        }
+        t.run("sadd set:0 a");
+        let keys = scan_all(&mut t, "scan {} count 7");
+        let unique = keys.iter().collect::<HashSet<_>>();
+        assert_eq!(keys.len(), 101);
+        assert_eq!(unique.len(), 101);
+        assert_eq!(scan_all(&mut t, "scan {} match key:1? count 5").len(), 10);
+        assert_eq!(scan_all(&mut t, "scan {} type set"), vec!["set:0"]);
+
+        // keys there from start to end are all returned even though the table
+        // grows and shrinks under the cursor
+        let mut found = HashSet::new();
+        let mut cursor = "0".to_string();
+        let mut round = 0;
+        loop {
+            let RespFrame::Array(reply) = t.run(&format!("scan {} count 3", cursor)) else {
+                panic!("scan replies with an array");
+            };
+            let [RespFrame::BulkString(next), RespFrame::Array(elements)] = &reply[..] else {
+                panic!("scan replies with a cursor and elements");
+            };
+            for element in elements.iter() {
+                if let RespFrame::BulkString(element) = element {
+                    found.insert(String::from_utf8_lossy(element).into_owned());
+                }
+            }
+            cursor = String::from_utf8_lossy(next).into_owned();
+            if cursor == "0" {
+                break;
+            }
+            round += 1;
+            if round % 2 == 0 {
+                for i in 0..40 {
+                    t.run(&format!("set tmp:{}:{} v", round, i));
+                }
+            } else {
+                for i in 0..40 {
+                    t.run(&format!("del tmp:{}:{}", round - 1, i));
+                }
+            }
+        }
+        for i in 0..100 {
+            assert!(found.contains(&format!("key:{}", i)));
+        }
+
+        assert_eq!(t.run("scan x"), CommandError::err("invalid cursor").into());
+        assert_eq!(t.run("scan -1"), CommandError::err("invalid cursor").into());
+        assert_eq!(t.run("scan 0 count 0"), CommandError::Syntax.into());
+        assert_eq!(t.run("scan 0 foo"), CommandError::Syntax.into());
+    }
+
+    #[test]
+    fn test_container_scans() {
+        let mut t = TestContext::default();
+        for i in 0..50 {
+            t.run(&format!("hset h f{} {}", i, i));
+            t.run(&format!("sadd s m{}", i));
+            t.run(&format!("zadd z {} m{}", i, i));
+        }
+        let mut fields = scan_all(&mut t, "hscan h {} match f1*");
+        fields.sort();
+        assert_eq!(fields.len(), 22);
+        assert!(fields.contains(&"f12".to_string()) && fields.contains(&"12".to_string()));
+        let members = scan_all(&mut t, "sscan s {} count 3");
+        assert_eq!(members.iter().collect::<HashSet<_>>().len(), 50);
+        let pairs = scan_all(&mut t, "zscan z {} match m7");
+        assert_eq!(pairs, vec!["m7", "7"]);
+        assert_eq!(scan_all(&mut t, "zscan missing {}"), Vec::<String>::new());
+        assert_eq!(t.run("sscan s 0 type set"), CommandError::Syntax.into());
+        assert_eq!(t.run("hscan s 0"), CommandError::WrongType.into());
+    }
+}
@@ -0,0 +1,1110 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::backend::Db;
+use crate::{BulkString, RespArray, RespFrame, RespNullArray};
+
+use super::{
+    nil, ok, parse_timeout, BlockingCommand, CommandArgs, CommandError, CommandExecutor,
+    CommandSpec, Context,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Left,
+    Right,
+}
+
+// LRANGE key start stop
+#[derive(Debug)]
+pub struct LRange {
+    key: Vec<u8>,
+    start: i64,
+    stop: i64,
+}
+
+// LINDEX key index
+#[derive(Debug)]
+pub struct LIndex {
+    key: Vec<u8>,
+    index: i64,
+}
+
+// LSET key index element
+#[derive(Debug)]
+pub struct LSet {
+    key: Vec<u8>,
+    index: i64,
+    element: Vec<u8>,
+}
+
+// LINSERT key BEFORE | AFTER pivot element
+#[derive(Debug)]
+pub struct LInsert {
+    key: Vec<u8>,
+    after: bool,
+    pivot: Vec<u8>,
+    element: Vec<u8>,
+}
+
+// LREM key count element
+#[derive(Debug)]
+pub struct LRem {
+    key: Vec<u8>,
+    count: i64,
+    element: Vec<u8>,
+}
+
+// LTRIM key start stop
+#[derive(Debug)]
+pub struct LTrim {
+    key: Vec<u8>,
+    start: i64,
+    stop: i64,
+}
+
+// LLEN key
+#[derive(Debug)]
+pub struct LLen {
+    key: Vec<u8>,
+}
+
+// LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]
+#[derive(Debug)]
+pub struct LPos {
+    key: Vec<u8>,
+    element: Vec<u8>,
+    rank: i64,
+    count: Option<usize>,
+    max_len: usize,
+}
+
+// LMOVE source destination LEFT | RIGHT LEFT | RIGHT
+#[derive(Debug)]
+pub struct LMove {
+    source: Vec<u8>,
+    destination: Vec<u8>,
+    from: End,
+    to: End,
+}
+
+// LMPOP numkeys key [key ...] LEFT | RIGHT [COUNT count]
+#[derive(Debug)]
+pub struct LMPop {
+    keys: Vec<Vec<u8>>,
+    end: End,
+    count: usize,
+}
+
+// BLMOVE source destination LEFT | RIGHT LEFT | RIGHT timeout
+#[derive(Debug)]
+pub struct BLMove {
+    inner: LMove,
+    timeout: Option<Duration>,
+}
+
+// BLMPOP timeout numkeys key [key ...] LEFT | RIGHT [COUNT count]
+#[derive(Debug)]
+pub struct BLMPop {
+    inner: LMPop,
+    timeout: Option<Duration>,
+}
+
+impl End {
+    pub(crate) fn parse(args: &mut CommandArgs) -> Result<End, CommandError> {
+        match args.next_keyword().as_deref() {
+            Some("LEFT") => Ok(End::Left),
+            Some("RIGHT") => Ok(End::Right),
+            _ => Err(CommandError::Syntax),
+        }
+    }
+}
+
+// resolve a Redis style inclusive range over a sequence of len items,
+// negative indexes counting from the end; None when the range is empty
+pub(crate) fn list_range(len: usize, start: i64, stop: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+fn list_index(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (index >= 0 && index < len as i64).then_some(index as usize)
+}
+
+fn bulk_array(items: impl IntoIterator<Item = Vec<u8>>) -> RespFrame {
+    RespArray::new(
+        items
+            .into_iter()
+            .map(|item| BulkString::new(item).into())
+            .collect::<Vec<RespFrame>>(),
+    )
+    .into()
+}
+
+// pop up to count elements from one end of the list at key, dropping the key once
+// it is empty; None when the key does not exist
+pub(crate) fn pop_elements(
+    db: &mut Db,
+    key: &[u8],
+    end: End,
+    count: usize,
+) -> Result<Option<Vec<Vec<u8>>>, CommandError> {
+    let list = match db.get_list_mut(key)? {
+        Some(list) => list,
+        None => return Ok(None),
+    };
+    let count = count.min(list.len());
+    let popped = match end {
+        End::Left => list.drain(..count).collect(),
+        End::Right => list.drain(list.len() - count..).rev().collect(),
+    };
+    if count > 0 {
+        db.touch(key);
+        db.remove_if_empty(key);
+    }
+    Ok(Some(popped))
+}
+
+// move one element between two lists, checking the destination type up front
+// so that nothing is lost when it holds the wrong kind of value
+pub(crate) fn move_element(
+    db: &mut Db,
+    source: &[u8],
+    destination: &[u8],
+    from: End,
+    to: End,
+) -> Result<Option<Vec<u8>>, CommandError> {
+    if db.get_list(source)?.is_none() {
+        return Ok(None);
+    }
+    db.get_list(destination)?;
+    let element = match pop_elements(db, source, from, 1)? {
+        Some(mut popped) => popped.pop(),
+        None => None,
+    };
+    if let Some(element) = &element {
+        let list = db.get_or_create_list(destination)?;
+        match to {
+            End::Left => list.push_front(element.clone()),
+            End::Right => list.push_back(element.clone()),
+        }
+        db.touch(destination);
+    }
+    Ok(element)
+}
+
+// LPUSH, RPUSH, LPUSHX and RPUSHX only differ in the end they push to and
+// whether the list has to exist already
+macro_rules! push_command {
+    ($ty:ident, $name:literal, $end:expr, $only_existing:literal) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            key: Vec<u8>,
+            elements: Vec<Vec<u8>>,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -3;
+            const WRITE: bool = true;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                Ok($ty {
+                    key: args.next_bytes()?,
+                    elements: args.rest(),
+                })
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                push_elements(ctx.db(), &self.key, self.elements, $end, $only_existing)
+            }
+        }
+    };
+}
+
+// LPOP and RPOP
+macro_rules! pop_command {
+    ($ty:ident, $name:literal, $end:expr) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            key: Vec<u8>,
+            count: Option<usize>,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -2;
+            const WRITE: bool = true;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                let key = args.next_bytes()?;
+                let count = match args.is_empty() {
+                    true => None,
+                    false => Some(parse_positive_count(args)?),
+                };
+                if !args.is_empty() {
+                    return Err(CommandError::WrongArity(Self::NAME.to_string()));
+                }
+                Ok($ty { key, count })
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                let popped = pop_elements(ctx.db(), &self.key, $end, self.count.unwrap_or(1))?;
+                Ok(match (popped, self.count) {
+                    (Some(mut popped), None) => match popped.pop() {
+                        Some(element) => BulkString::new(element).into(),
+                        None => nil(),
+                    },
+                    (None, None) => nil(),
+                    (Some(popped), Some(_)) => bulk_array(popped),
+                    (None, Some(_)) => RespNullArray.into(),
+                })
+            }
+        }
+    };
+}
+
+// LPUSH key element [element ...]
+push_command!(LPush, "lpush", End::Left, false);
+// RPUSH key element [element ...]
+push_command!(RPush, "rpush", End::Right, false);
+// LPUSHX key element [element ...]
+push_command!(LPushX, "lpushx", End::Left, true);
+// RPUSHX key element [element ...]
+push_command!(RPushX, "rpushx", End::Right, true);
+// LPOP key [count]
+pop_command!(LPop, "lpop", End::Left);
+// RPOP key [count]
+pop_command!(RPop, "rpop", End::Right);
+
+fn push_elements(
+    db: &mut Db,
+    key: &[u8],
+    elements: Vec<Vec<u8>>,
+    end: End,
+    only_existing: bool,
+) -> Result<RespFrame, CommandError> {
+    if only_existing && db.get_list(key)?.is_none() {
+        return Ok(RespFrame::Integer(0));
+    }
+    let list = db.get_or_create_list(key)?;
+    for element in elements {
+        match end {
+            End::Left => list.push_front(element),
+            End::Right => list.push_back(element),
+        }
+    }
+    let len = list.len();
+    db.touch(key);
+    Ok(RespFrame::Integer(len as i64))
+}
+
+fn parse_positive_count(args: &mut CommandArgs) -> Result<usize, CommandError> {
+    let count = args.next_i64()?;
+    if count < 0 {
+        return Err(CommandError::err("value is out of range, must be positive"));
+    }
+    Ok(count as usize)
+}
+
+impl CommandSpec for LRange {
+    const NAME: &'static str = "lrange";
+    const ARITY: i64 = 4;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(LRange {
+            key: args.next_bytes()?,
+            start: args.next_i64()?,
+            stop: args.next_i64()?,
+        })
+    }
+}
+
+impl CommandExecutor for LRange {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let list = match ctx.db().get_list(&self.key)? {
+            Some(list) => list,
+            None => return Ok(bulk_array(vec![])),
+        };
+        let items = match list_range(list.len(), self.start, self.stop) {
+            Some((start, stop)) => list.range(start..=stop).cloned().collect(),
+            None => vec![],
+        };
+        Ok(bulk_array(items))
+    }
+}
+
+impl CommandSpec for LIndex {
+    const NAME: &'static str = "lindex";
+    const ARITY: i64 = 3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(LIndex {
+            key: args.next_bytes()?,
+            index: args.next_i64()?,
+        })
+    }
+}
+
+impl CommandExecutor for LIndex {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let element = ctx
+            .db()
+            .get_list(&self.key)?
+            .and_then(|list| list_index(list.len(), self.index).map(|i| list[i].clone()));
+        Ok(match element {
+            Some(element) => BulkString::new(element).into(),
+            None => nil(),
+        })
+    }
+}
+
+impl CommandSpec for LSet {
+    const NAME: &'static str = "lset";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(LSet {
+            key: args.next_bytes()?,
+            index: args.next_i64()?,
+            element: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for LSet {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let list = ctx
+            .db()
+            .get_list_mut(&self.key)?
+            .ok_or_else(|| CommandError::err("no such key"))?;
+        let index = list_index(list.len(), self.index)
+            .ok_or_else(|| CommandError::err("index out of range"))?;
+        list[index] = self.element;
+        ctx.db().touch(&self.key);
+        Ok(ok())
+    }
+}
+
+impl CommandSpec for LInsert {
+    const NAME: &'static str = "linsert";
+    const ARITY: i64 = 5;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let after = match args.next_keyword().as_deref() {
+            Some("BEFORE") => false,
+            Some("AFTER") => true,
+            _ => return Err(CommandError::Syntax),
+        };
+        Ok(LInsert {
+            key,
+            after,
+            pivot: args.next_bytes()?,
+            element: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for LInsert {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let list = match ctx.db().get_list_mut(&self.key)? {
+            Some(list) => list,
+            None => return Ok(RespFrame::Integer(0)),
+        };
+        match list.iter().position(|e| *e == self.pivot) {
+            Some(pos) => {
+                let at = if self.after { pos + 1 } else { pos };
+                list.insert(at, self.element);
+                let len = list.len();
+                ctx.db().touch(&self.key);
+                Ok(RespFrame::Integer(len as i64))
+            }
+            None => Ok(RespFrame::Integer(-1)),
+        }
+    }
+}
+
+impl CommandSpec for LRem {
+    const NAME: &'static str = "lrem";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(LRem {
+            key: args.next_bytes()?,
+            count: args.next_i64()?,
+            element: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for LRem {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let list = match db.get_list_mut(&self.key)? {
+            Some(list) => list,
+            None => return Ok(RespFrame::Integer(0)),
+        };
+        // a negative count removes matches starting from the tail
+        let limit = match self.count {
+            0 => usize::MAX,
+            n => n.unsigned_abs() as usize,
+        };
+        let mut removed = 0;
+        let mut kept: VecDeque<Vec<u8>> = VecDeque::with_capacity(list.len());
+        if self.count >= 0 {
+            for e in list.drain(..) {
+                if removed < limit && e == self.element {
+                    removed += 1;
+                } else {
+                    kept.push_back(e);
+                }
+            }
+        } else {
+            for e in list.drain(..).rev() {
+                if removed < limit && e == self.element {
+                    removed += 1;
+                } else {
+                    kept.push_front(e);
+                }
+            }
+        }
+        *list = kept;
+        if removed > 0 {
+            db.touch(&self.key);
+            db.remove_if_empty(&self.key);
+        }
+        Ok(RespFrame::Integer(removed as i64))
+    }
+}
+
+impl CommandSpec for LTrim {
+    const NAME: &'static str = "ltrim";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(LTrim {
+            key: args.next_bytes()?,
+            start: args.next_i64()?,
+            stop: args.next_i64()?,
+        })
+    }
+}
+
+impl CommandExecutor for LTrim {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        if let Some(list) = db.get_list_mut(&self.key)? {
+            let len = list.len();
+            match list_range(len, self.start, self.stop) {
+                Some((start, stop)) => {
+                    list.truncate(stop + 1);
+                    list.drain(..start);
+                }
+                None => list.clear(),
+            }
+            if list.len() < len {
+                db.touch(&self.key);
+                db.remove_if_empty(&self.key);
+            }
+        }
+        Ok(ok())
+    }
+}
+
+impl CommandSpec for LLen {
+    const NAME: &'static str = "llen";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(LLen {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for LLen {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let len = ctx.db().get_list(&self.key)?.map(|l| l.len()).unwrap_or(0);
+        Ok(RespFrame::Integer(len as i64))
+    }
+}
+
+impl CommandSpec for LPos {
+    const NAME: &'static str = "lpos";
+    const ARITY: i64 = -3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let mut cmd = LPos {
+            key: args.next_bytes()?,
+            element: args.next_bytes()?,
+            rank: 1,
+            count: None,
+            max_len: 0,
+        };
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                "RANK" => {
+                    cmd.rank = args.next_i64()?;
+                    if cmd.rank == 0 {
+                        return Err(CommandError::err(
+                            "RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the last match",
+                        ));
+                    }
+                    if cmd.rank == i64::MIN {
+                        return Err(CommandError::err("value is out of range"));
+                    }
+                }
+                "COUNT" => {
+                    let count = args.next_i64()?;
+                    if count < 0 {
+                        return Err(CommandError::err("COUNT can't be negative"));
+                    }
+                    cmd.count = Some(count as usize);
+                }
+                "MAXLEN" => {
+                    let max_len = args.next_i64()?;
+                    if max_len < 0 {
+                        return Err(CommandError::err("MAXLEN can't be negative"));
+                    }
+                    cmd.max_len = max_len as usize;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok(cmd)
+    }
+}
+
+impl CommandExecutor for LPos {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let list = ctx.db().get_list(&self.key)?;
+        let len = list.map(|l| l.len()).unwrap_or(0);
+        // COUNT 0 asks for every match, MAXLEN 0 scans the whole list
+        let wanted = match self.count {
+            Some(0) => usize::MAX,
+            Some(n) => n,
+            None => 1,
+        };
+        let scan = if self.max_len == 0 {
+            len
+        } else {
+            self.max_len.min(len)
+        };
+        let skip = self.rank.unsigned_abs() as usize - 1;
+
+        let mut matches = Vec::new();
+        if let Some(list) = list {
+            let positions: Box<dyn Iterator<Item = usize>> = if self.rank > 0 {
+                Box::new(0..len)
+            } else {
+                Box::new((0..len).rev())
+            };
+            for i in positions
+                .take(scan)
+                .filter(|&i| list[i] == self.element)
+                .skip(skip)
+            {
+                matches.push(RespFrame::Integer(i as i64));
+                if matches.len() >= wanted {
+                    break;
+                }
+            }
+        }
+        Ok(match self.count {
+            Some(_) => RespArray::new(matches).into(),
+            None => matches.pop().unwrap_or_else(nil),
+        })
+    }
+}
+
+impl CommandSpec for LMove {
+    const NAME: &'static str = "lmove";
+    const ARITY: i64 = 5;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(LMove {
+            source: args.next_bytes()?,
+            destination: args.next_bytes()?,
+            from: End::parse(args)?,
+            to: End::parse(args)?,
+        })
+    }
+}
+
+impl CommandExecutor for LMove {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let element = move_element(
+            ctx.db(),
+            &self.source,
+            &self.destination,
+            self.from,
+            self.to,
+        )?;
+        Ok(match element {
+            Some(element) => BulkString::new(element).into(),
+            None => nil(),
+        })
+    }
+}
+
+// the numkeys key [key ...] prefix shared by the *MPOP family
+pub(crate) fn parse_numkeys(args: &mut CommandArgs) -> Result<Vec<Vec<u8>>, CommandError> {
+    let numkeys = args
+        .next_i64()
+        .map_err(|_| CommandError::err("numkeys should be greater than 0"))?;
+    if numkeys <= 0 {
+        return Err(CommandError::err("numkeys should be greater than 0"));
+    }
+    if numkeys as usize > args.len() {
+        return Err(CommandError::Syntax);
+    }
+    (0..numkeys).map(|_| args.next_bytes()).collect()
+}
+
+// the optional COUNT count suffix of the *MPOP family
+pub(crate) fn parse_mpop_count(args: &mut CommandArgs) -> Result<usize, CommandError> {
+    let mut count = None;
+    while let Some(opt) = args.next_keyword() {
+        match opt.as_str() {
+            "COUNT" if count.is_none() => {
+                let n = args
+                    .next_i64()
+                    .map_err(|_| CommandError::err("count should be greater than 0"))?;
+                if n <= 0 {
+                    return Err(CommandError::err("count should be greater than 0"));
+                }
+                count = Some(n as usize);
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+    Ok(count.unwrap_or(1))
+}
+
+impl LMPop {
+    // pop from the first non-empty list, replying [key, [elements]]
+    pub(crate) fn try_pop(&self, db: &mut Db) -> Result<Option<RespFrame>, CommandError> {
+        for key in &self.keys {
+            if let Some(popped) = pop_elements(db, key, self.end, self.count)? {
+                return Ok(Some(
+                    RespArray::new(vec![
+                        BulkString::new(key.clone()).into(),
+                        bulk_array(popped),
+                    ])
+                    .into(),
+                ));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl CommandSpec for LMPop {
+    const NAME: &'static str = "lmpop";
+    const ARITY: i64 = -4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let keys = parse_numkeys(args)?;
+        let end = End::parse(args)?;
+        let count = parse_mpop_count(args)?;
+        Ok(LMPop { keys, end, count })
+    }
+}
+
+impl CommandExecutor for LMPop {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(self
+            .try_pop(ctx.db())?
+            .unwrap_or_else(|| RespNullArray.into()))
+    }
+}
+
+// BLPOP and BRPOP: pop from the first non-empty list, or wait for one
+macro_rules! blocking_pop_command {
+    ($ty:ident, $name:literal, $end:expr) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            keys: Vec<Vec<u8>>,
+            timeout: Option<Duration>,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -3;
+            const WRITE: bool = true;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                let mut keys = args.rest();
+                let timeout = parse_timeout(&keys.pop().unwrap_or_default())?;
+                Ok($ty { keys, timeout })
+            }
+        }
+
+        impl BlockingCommand for $ty {
+            fn keys(&self) -> Vec<Vec<u8>> {
+                self.keys.clone()
+            }
+
+            fn try_serve(&self, ctx: &mut Context) -> Result<Option<RespFrame>, CommandError> {
+                for key in &self.keys {
+                    if let Some(mut popped) = pop_elements(ctx.db(), key, $end, 1)? {
+                        let element = popped.pop().unwrap_or_default();
+                        return Ok(Some(bulk_array([key.clone(), element])));
+                    }
+                }
+                Ok(None)
+            }
+
+            fn timeout_reply(&self) -> RespFrame {
+                RespNullArray.into()
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                match self.try_serve(ctx)? {
+                    Some(reply) => Ok(reply),
+                    None => {
+                        let timeout = self.timeout;
+                        ctx.block(self, timeout)
+                    }
+                }
+            }
+        }
+    };
+}
+
+blocking_pop_command!(BLPop, "blpop", End::Left);
+blocking_pop_command!(BRPop, "brpop", End::Right);
+
+impl CommandSpec for BLMove {
+    const NAME: &'static str = "blmove";
+    const ARITY: i64 = 6;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let inner = LMove::parse(args)?;
+        let timeout = parse_timeout(&args.next_bytes()?)?;
+        Ok(BLMove { inner, timeout })
+    }
+}
+
+impl BlockingCommand for BLMove {
+    fn keys(&self) -> Vec<Vec<u8>> {
+        vec![self.inner.source.clone()]
+    }
+
+    fn try_serve(&self, ctx: &mut Context) -> Result<Option<RespFrame>, CommandError> {
+        let LMove {
+            source,
+            destination,
+            from,
+            to,
+        } = &self.inner;
+        Ok(move_element(ctx.db(), source, destination, *from, *to)?
+            .map(|element| BulkString::new(element).into()))
+    }
+
+    fn timeout_reply(&self) -> RespFrame {
+        nil()
+    }
+}
+
+impl CommandExecutor for BLMove {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        match self.try_serve(ctx)? {
+            Some(reply) => Ok(reply),
+            None => {
+                let timeout = self.timeout;
+                ctx.block(self, timeout)
+            }
+        }
+    }
+}
+
+impl CommandSpec for BLMPop {
+    const NAME: &'static str = "blmpop";
+    const ARITY: i64 = -5;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let timeout = parse_timeout(&args.next_bytes()?)?;
+        let inner = LMPop::parse(args)?;
+        Ok(BLMPop { inner, timeout })
+    }
+}
+
+impl BlockingCommand for BLMPop {
+    fn keys(&self) -> Vec<Vec<u8>> {
+        self.inner.keys.clone()
+    }
+
+    fn try_serve(&self, ctx: &mut Context) -> Result<Option<RespFrame>, CommandError> {
+        self.inner.try_pop(ctx.db())
+    }
+
+    fn timeout_reply(&self) -> RespFrame {
+        RespNullArray.into()
+    }
+}
+
+impl CommandExecutor for BLMPop {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        match self.try_serve(ctx)? {
+            Some(reply) => Ok(reply),
+            None => {
+                let timeout = self.timeout;
+                ctx.block(self, timeout)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::TestContext;
+    use crate::Client;
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    fn bulks(items: &[&str]) -> RespFrame {
+        RespArray::new(items.iter().map(|s| bulk(s)).collect::<Vec<_>>()).into()
+    }
+
+    fn ints(items: &[i64]) -> RespFrame {
+        RespArray::new(
+            items
+                .iter()
+                .map(|i| RespFrame::Integer(*i))
+                .collect::<Vec<_>>(),
+        )
+        .into()
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("rpush l a b c"), RespFrame::Integer(3));
+        assert_eq!(t.run("lpush l x y"), RespFrame::Integer(5));
+        assert_eq!(t.run("lrange l 0 -1"), bulks(&["y", "x", "a", "b", "c"]));
+        assert_eq!(t.run("lpushx missing a"), RespFrame::Integer(0));
+        assert_eq!(t.run("rpushx l d"), RespFrame::Integer(6));
+
+        assert_eq!(t.run("lpop l"), bulk("y"));
+        assert_eq!(t.run("rpop l 2"), bulks(&["d", "c"]));
+        assert_eq!(t.run("lpop l 0"), bulks(&[]));
+        assert_eq!(t.run("lpop l 10"), bulks(&["x", "a", "b"]));
+        assert!(!t.backend.dbs[0].contains_key(b"l"));
+        assert_eq!(t.run("lpop l"), nil());
+        assert_eq!(t.run("lpop l 2"), RespNullArray.into());
+        assert_eq!(
+            t.run("lpop l -1"),
+            CommandError::err("value is out of range, must be positive").into()
+        );
+    }
+
+    #[test]
+    fn test_lrange_lindex_lset() {
+        let mut t = TestContext::default();
+        t.run("rpush l a b c d");
+        assert_eq!(t.run("lrange l 1 2"), bulks(&["b", "c"]));
+        assert_eq!(t.run("lrange l -3 -2"), bulks(&["b", "c"]));
+        assert_eq!(t.run("lrange l -100 100"), bulks(&["a", "b", "c", "d"]));
+        assert_eq!(t.run("lrange l 5 10"), bulks(&[]));
+        assert_eq!(t.run("lrange l 2 1"), bulks(&[]));
+
+        assert_eq!(t.run("lindex l -1"), bulk("d"));
+        assert_eq!(t.run("lindex l 4"), nil());
+        assert_eq!(t.run("lset l -2 x"), ok());
+        assert_eq!(t.run("lindex l 2"), bulk("x"));
+        assert_eq!(
+            t.run("lset l 4 x"),
+            CommandError::err("index out of range").into()
+        );
+        assert_eq!(
+            t.run("lset missing 0 x"),
+            CommandError::err("no such key").into()
+        );
+    }
+
+    #[test]
+    fn test_linsert_lrem_ltrim() {
+        let mut t = TestContext::default();
+        t.run("rpush l a b a c a");
+        assert_eq!(t.run("linsert l before c x"), RespFrame::Integer(6));
+        assert_eq!(t.run("linsert l after c y"), RespFrame::Integer(7));
+        assert_eq!(t.run("linsert l after z y"), RespFrame::Integer(-1));
+        assert_eq!(t.run("linsert missing after z y"), RespFrame::Integer(0));
+        assert_eq!(
+            t.run("lrange l 0 -1"),
+            bulks(&["a", "b", "a", "x", "c", "y", "a"])
+        );
+
+        assert_eq!(t.run("lrem l -1 a"), RespFrame::Integer(1));
+        assert_eq!(
+            t.run("lrange l 0 -1"),
+            bulks(&["a", "b", "a", "x", "c", "y"])
+        );
+        assert_eq!(t.run("lrem l 1 a"), RespFrame::Integer(1));
+        assert_eq!(t.run("lrange l 0 -1"), bulks(&["b", "a", "x", "c", "y"]));
+        assert_eq!(t.run("lrem l 0 a"), RespFrame::Integer(1));
+
+        assert_eq!(t.run("ltrim l 1 -2"), ok());
+        assert_eq!(t.run("lrange l 0 -1"), bulks(&["x", "c"]));
+        assert_eq!(t.run("llen l"), RespFrame::Integer(2));
+        assert_eq!(t.run("ltrim l 5 10"), ok());
+        assert!(!t.backend.dbs[0].contains_key(b"l"));
+    }
+
+    #[test]
+    fn test_lpos() {
+        let mut t = TestContext::default();
+        t.run("rpush l a b c 1 2 3 c c");
+        assert_eq!(t.run("lpos l c"), RespFrame::Integer(2));
+        assert_eq!(t.run("lpos l x"), nil());
+        assert_eq!(t.run("lpos l c rank 2"), RespFrame::Integer(6));
+        assert_eq!(t.run("lpos l c rank -1"), RespFrame::Integer(7));
+        assert_eq!(t.run("lpos l c count 2"), ints(&[2, 6]));
+        assert_eq!(t.run("lpos l c count 0"), ints(&[2, 6, 7]));
+        assert_eq!(t.run("lpos l c rank -1 count 2"), ints(&[7, 6]));
+        assert_eq!(t.run("lpos l c count 0 maxlen 3"), ints(&[2]));
+        assert_eq!(t.run("lpos missing c count 0"), ints(&[]));
+        assert!(matches!(t.run("lpos l c rank 0"), RespFrame::Error(_)));
+        assert_eq!(
+            t.run("lpos l c count -1"),
+            CommandError::err("COUNT can't be negative").into()
+        );
+    }
+
+    #[test]
+    fn test_lmove() {
+        let mut t = TestContext::default();
+        t.run("rpush src a b c");
+        assert_eq!(t.run("lmove src dst right left"), bulk("c"));
+        assert_eq!(t.run("lmove src dst left right"), bulk("a"));
+        assert_eq!(t.run("lrange dst 0 -1"), bulks(&["c", "a"]));
+        // rotation on a single list
+        assert_eq!(t.run("lmove dst dst left right"), bulk("c"));
+        assert_eq!(t.run("lrange dst 0 -1"), bulks(&["a", "c"]));
+        assert_eq!(t.run("lmove missing dst left right"), nil());
+
+        t.run("set s v");
+        assert_eq!(
+            t.run("lmove src s left right"),
+            CommandError::WrongType.into()
+        );
+        assert_eq!(t.run("llen src"), RespFrame::Integer(1));
+    }
+
+    #[test]
+    fn test_lmpop() {
+        let mut t = TestContext::default();
+        t.run("rpush l2 a b c");
+        assert_eq!(
+            t.run("lmpop 2 l1 l2 left"),
+            RespArray::new(vec![bulk("l2"), bulks(&["a"])]).into()
+        );
+        assert_eq!(
+            t.run("lmpop 2 l1 l2 right count 5"),
+            RespArray::new(vec![bulk("l2"), bulks(&["c", "b"])]).into()
+        );
+        assert_eq!(t.run("lmpop 2 l1 l2 right"), RespNullArray.into());
+        assert_eq!(
+            t.run("lmpop 0 l1 left"),
+            CommandError::err("numkeys should be greater than 0").into()
+        );
+        assert_eq!(t.run("lmpop 3 l1 l2 left"), CommandError::Syntax.into());
+        assert_eq!(t.run("lmpop 1 l1 up"), CommandError::Syntax.into());
+        assert_eq!(
+            t.run("lmpop 1 l1 left count 0"),
+            CommandError::err("count should be greater than 0").into()
+        );
+    }
+
+    #[test]
+    fn test_blocking_pop() {
+        let mut t = TestContext::default();
+        t.run("rpush l a");
+        assert_eq!(t.run("blpop missing l 0"), bulks(&["l", "a"]));
+
+        // waiters on a key are served in the order they blocked
+        let mut first = t.block("brpop l 0");
+        t.client = Client::new();
+        let mut second = t.block("blpop other l 1.5");
+        assert_eq!(second.timeout, Some(Duration::from_millis(1500)));
+        t.client = Client::new();
+        assert_eq!(t.run("rpush l x"), RespFrame::Integer(1));
+        assert_eq!(first.rx.try_recv().unwrap(), bulks(&["l", "x"]));
+        assert!(second.rx.try_recv().is_err());
+        assert_eq!(t.run("lpush other y z"), RespFrame::Integer(2));
+        assert_eq!(second.rx.try_recv().unwrap(), bulks(&["other", "z"]));
+        assert_eq!(t.run("lrange other 0 -1"), bulks(&["y"]));
+        assert!(t.backend.blocking.is_empty());
+
+        // a timed out waiter is no longer served
+        let blocked = t.block("blpop l 0");
+        let waiter = t.backend.blocking.unblock(blocked.id).unwrap();
+        assert_eq!(waiter.command.timeout_reply(), RespNullArray.into());
+        t.run("rpush l a");
+        assert_eq!(t.run("llen l"), RespFrame::Integer(1));
+
+        // a key given twice, even apart, is waited on once
+        let blocked = t.block("blpop a b a 0");
+        assert_eq!(t.backend.blocking.queue(0, b"a"), vec![blocked.id]);
+        let waiter = t.backend.blocking.unblock(blocked.id).unwrap();
+        assert_eq!(waiter.keys, vec![b"a".to_vec(), b"b".to_vec()]);
+        assert!(t.backend.blocking.is_empty());
+
+        assert_eq!(
+            t.run("blpop l -1"),
+            CommandError::err("timeout is negative").into()
+        );
+    }
+
+    #[test]
+    fn test_blmove_and_blmpop() {
+        let mut t = TestContext::default();
+        let mut moved = t.block("blmove src dst right left 0");
+        t.client = Client::new();
+        let mut popped = t.block("blmpop 0 2 other dst left count 2");
+        t.client = Client::new();
+        // the element BLMOVE pushes to dst wakes the BLMPOP waiter in turn
+        t.run("rpush src a b");
+        assert_eq!(moved.rx.try_recv().unwrap(), bulk("b"));
+        assert_eq!(
+            popped.rx.try_recv().unwrap(),
+            RespArray::new(vec![bulk("dst"), bulks(&["b"])]).into()
+        );
+        assert_eq!(t.run("lrange src 0 -1"), bulks(&["a"]));
+        assert_eq!(t.run("llen dst"), RespFrame::Integer(0));
+        assert_eq!(t.run("blmove src dst left left 0"), bulk("a"));
+    }
+}
@@ -0,0 +1,985 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::time::Duration;
+
+use enum_dispatch::enum_dispatch;
+use rand::seq::SliceRandom;
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+use crate::backend::{BackendInner, Db};
+use crate::{Client, RespArray, RespFrame, RespNullBulkString, SimpleError, SimpleString};
+
+mod bitmap;
+mod connection;
+mod consumer_group;
+mod expire;
+mod function;
+mod geo;
+mod hash;
+mod hyperloglog;
+mod keyspace;
+mod list;
+mod pubsub;
+mod script;
+mod set;
+mod stream;
+mod string;
+mod transaction;
+mod zset;
+
+pub use bitmap::*;
+pub use connection::*;
+pub use consumer_group::*;
+pub use expire::*;
+pub use function::*;
+pub use geo::*;
+pub use hash::*;
+pub use hyperloglog::*;
+pub use keyspace::*;
+pub use list::*;
+pub use pubsub::*;
+pub use script::*;
+pub use set::*;
+pub use stream::*;
+pub use string::*;
+pub use transaction::*;
+pub use zset::*;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    #[error("ERR unknown command '{0}', with args beginning with: {1}")]
+    UnknownCommand(String, String),
+    #[error("ERR unknown subcommand '{0}'. Try {1} HELP.")]
+    UnknownSubcommand(String, String),
+    #[error("ERR wrong number of arguments for '{0}' command")]
+    WrongArity(String),
+    #[error("ERR Protocol error: {0}")]
+    InvalidCommand(String),
+    #[error("ERR syntax error")]
+    Syntax,
+    #[error("ERR value is not an integer or out of range")]
+    NotInteger,
+    #[error("ERR value is not a valid float")]
+    NotFloat,
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    // a complete error reply, including its error code prefix
+    #[error("{0}")]
+    Reply(String),
+}
+
+impl CommandError {
+    pub fn err(msg: impl AsRef<str>) -> Self {
+        CommandError::Reply(format!("ERR {}", msg.as_ref()))
+    }
+}
+
+impl From<CommandError> for RespFrame {
+    fn from(e: CommandError) -> Self {
+        SimpleError::new(e.to_string()).into()
+    }
+}
+
+// key/value or field/value pairs as they appear on the command line
+pub type Pairs = Vec<(Vec<u8>, Vec<u8>)>;
+
+// everything a command may touch while it runs
+pub struct Context<'a> {
+    pub client: &'a mut Client,
+    pub backend: &'a mut BackendInner,
+    // set by a blocking command that found nothing to serve
+    block: Option<BlockRequest>,
+    // replies that go out ahead of the command's own, for the commands that
+    // answer more than once, like SUBSCRIBE with one reply per channel
+    replies: Vec<RespFrame>,
+    // set by QUIT: the connection closes once the reply is out
+    close: bool,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(client: &'a mut Client, backend: &'a mut BackendInner) -> Self {
+        Context {
+            client,
+            backend,
+            block: None,
+            replies: Vec::new(),
+            close: false,
+        }
+    }
+
+    // close the connection once the command replied
+    pub fn close(&mut self) {
+        self.close = true;
+    }
+
+    // send a reply ahead of the one the command returns
+    pub fn reply(&mut self, frame: RespFrame) {
+        self.replies.push(frame);
+    }
+
+    // the database the client has selected
+    pub fn db(&mut self) -> &mut Db {
+        &mut self.backend.dbs[self.client.db]
+    }
+
+    // check a database index given by a command against the configured count
+    pub fn db_index(&self, index: i64) -> Result<usize, CommandError> {
+        usize::try_from(index)
+            .ok()
+            .filter(|&index| index < self.backend.dbs.len())
+            .ok_or_else(|| CommandError::err("DB index is out of range"))
+    }
+
+    // park the client until `command` can be served or the timeout fires;
+    // the returned frame is only a placeholder, the real reply comes later
+    pub fn block(
+        &mut self,
+        command: impl BlockingCommand + 'static,
+        timeout: Option<Duration>,
+    ) -> Result<RespFrame, CommandError> {
+        let reply = command.timeout_reply();
+        self.block = Some(BlockRequest {
+            command: Box::new(command),
+            timeout,
+        });
+        Ok(reply)
+    }
+}
+
+// a command that can wait for data to arrive: BLPOP and friends
+pub trait BlockingCommand: Debug + Send {
+    // the keys whose writes may let the command through
+    fn keys(&self) -> Vec<Vec<u8>>;
+    // run the command if it can be served now, None to keep waiting
+    fn try_serve(&self, ctx: &mut Context) -> Result<Option<RespFrame>, CommandError>;
+    fn timeout_reply(&self) -> RespFrame;
+}
+
+#[derive(Debug)]
+struct BlockRequest {
+    command: Box<dyn BlockingCommand>,
+    timeout: Option<Duration>,
+}
+
+// the outcome of running one command
+#[derive(Debug)]
+pub enum Reply {
+    Frame(RespFrame),
+    // several replies to one command, in order
+    Frames(Vec<RespFrame>),
+    Blocked(Blocked),
+    // the last reply, after which the connection closes
+    Close(RespFrame),
+}
+
+// a parked client: the reply arrives on rx once a writer serves it, unless
+// the timeout fires first (None waits forever)
+#[derive(Debug)]
+pub struct Blocked {
+    pub id: u64,
+    pub rx: oneshot::Receiver<RespFrame>,
+    pub timeout: Option<Duration>,
+}
+
+// run one command for a client, then wake any clients blocked on the keys it
+// wrote; inside MULTI, commands other than the transaction ones are queued instead
+pub fn run(cmd: Command, client: &mut Client, backend: &mut BackendInner) -> Reply {
+    // a RESP2 connection carries pub/sub messages on the same stream as the
+    // replies, so while it is subscribed it can't run commands that don't fit
+    if !client.is_resp3()
+        && backend.subscriptions.is_subscribed(client.id)
+        && !cmd.allowed_when_subscribed()
+    {
+        return Reply::Frame(
+            CommandError::err(format!(
+                "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                cmd.name()
+            ))
+            .into(),
+        );
+    }
+    if let Some(transaction) = &mut client.transaction {
+        if let Some(e) = cmd.refused_in_transaction() {
+            return Reply::Frame(e.into());
+        }
+        if !cmd.controls_transaction() {
+            transaction.queue(Ok(cmd));
+            return Reply::Frame(SimpleString::new("QUEUED").into());
+        }
+    }
+    let mut ctx = Context::new(client, backend);
+    let result = cmd.execute(&mut ctx);
+    let block = ctx.block.take();
+    let close = ctx.close;
+    let mut replies = std::mem::take(&mut ctx.replies);
+    let reply = match (result, block) {
+        (Ok(_), Some(block)) => {
+            let (id, rx) = backend.blocking.block(client.snapshot(), block.command);
+            Reply::Blocked(Blocked {
+                id,
+                rx,
+                timeout: block.timeout,
+            })
+        }
+        (Ok(frame), None) if close => Reply::Close(frame),
+        (Ok(frame), None) if replies.is_empty() => Reply::Frame(frame),
+        (Ok(frame), None) => {
+            replies.push(frame);
+            Reply::Frames(replies)
+        }
+        (Err(e), _) => Reply::Frame(e.into()),
+    };
+    backend.handle_touched_keys();
+    reply
+}
+
+// the reply to a command that could not be parsed. Inside MULTI, a command
+// that doesn't exist or has the wrong number of arguments dooms the
+// transaction, as EXEC must not run only part of what the client sent; any
+// other bad argument is queued, and its error takes the command's slot at EXEC
+pub fn reject(e: CommandError, client: &mut Client) -> Reply {
+    if let Some(transaction) = &mut client.transaction {
+        match e {
+            CommandError::UnknownCommand(..)
+            | CommandError::UnknownSubcommand(..)
+            | CommandError::WrongArity(_)
+            | CommandError::InvalidCommand(_) => transaction.abort(),
+            e => {
+                transaction.queue(Err(e));
+                return Reply::Frame(SimpleString::new("QUEUED").into());
+            }
+        }
+    }
+    Reply::Frame(e.into())
+}
+
+#[enum_dispatch]
+pub trait CommandExecutor {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError>;
+}
+
+// what the spec of a parsed command says: its name, for the errors that
+// quote it, and whether it writes
+#[enum_dispatch]
+pub trait CommandName {
+    fn name(&self) -> &'static str;
+    fn is_write(&self) -> bool;
+}
+
+impl<T: CommandSpec> CommandName for T {
+    fn name(&self) -> &'static str {
+        T::NAME
+    }
+
+    fn is_write(&self) -> bool {
+        T::WRITE
+    }
+}
+
+// static description of a command: its lowercase name, its arity in the Redis
+// convention (N means exactly N arguments including the name, -N means at least N),
+// whether it may change the dataset, which read-only scripts can't do and
+// after which a script can't be killed, and how to build it from the
+// arguments that follow the name
+pub trait CommandSpec: Sized {
+    const NAME: &'static str;
+    const ARITY: i64;
+    const WRITE: bool = false;
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError>;
+}
+
+#[enum_dispatch(CommandExecutor, CommandName)]
+#[derive(Debug)]
+pub enum Command {
+    Ping(Ping),
+    Echo(Echo),
+    Hello(Hello),
+    Quit(Quit),
+    Reset(Reset),
+    Get(Get),
+    Set(Set),
+    GetEx(GetEx),
+    GetDel(GetDel),
+    SetNx(SetNx),
+    SetEx(SetEx),
+    PSetEx(PSetEx),
+    Append(Append),
+    Strlen(Strlen),
+    GetRange(GetRange),
+    SetRange(SetRange),
+    Incr(Incr),
+    Decr(Decr),
+    IncrBy(IncrBy),
+    DecrBy(DecrBy),
+    IncrByFloat(IncrByFloat),
+    MGet(MGet),
+    MSet(MSet),
+    MSetNx(MSetNx),
+    HSet(HSet),
+    HGet(HGet),
+    HMGet(HMGet),
+    HGetAll(HGetAll),
+    HDel(HDel),
+    HExists(HExists),
+    HLen(HLen),
+    HKeys(HKeys),
+    HVals(HVals),
+    HIncrBy(HIncrBy),
+    HIncrByFloat(HIncrByFloat),
+    HSetNx(HSetNx),
+    HStrlen(HStrlen),
+    HRandField(HRandField),
+    LPush(LPush),
+    RPush(RPush),
+    LPushX(LPushX),
+    RPushX(RPushX),
+    LPop(LPop),
+    RPop(RPop),
+    LRange(LRange),
+    LIndex(LIndex),
+    LSet(LSet),
+    LInsert(LInsert),
+    LRem(LRem),
+    LTrim(LTrim),
+    LLen(LLen),
+    LPos(LPos),
+    LMove(LMove),
+    LMPop(LMPop),
+    BLPop(BLPop),
+    BRPop(BRPop),
+    BLMove(BLMove),
+    BLMPop(BLMPop),
+    SAdd(SAdd),
+    SRem(SRem),
+    SIsMember(SIsMember),
+    SMIsMember(SMIsMember),
+    SMembers(SMembers),
+    SCard(SCard),
+    SPop(SPop),
+    SRandMember(SRandMember),
+    SMove(SMove),
+    SInter(SInter),
+    SUnion(SUnion),
+    SDiff(SDiff),
+    SInterStore(SInterStore),
+    SUnionStore(SUnionStore),
+    SDiffStore(SDiffStore),
+    SInterCard(SInterCard),
+    ZAdd(ZAdd),
+    ZRem(ZRem),
+    ZScore(ZScore),
+    ZMScore(ZMScore),
+    ZIncrBy(ZIncrBy),
+    ZCard(ZCard),
+    ZCount(ZCount),
+    ZRank(ZRank),
+    ZRevRank(ZRevRank),
+    ZRange(ZRange),
+    ZRangeStore(ZRangeStore),
+    ZUnion(ZUnion),
+    ZInter(ZInter),
+    ZDiff(ZDiff),
+    ZUnionStore(ZUnionStore),
+    ZInterStore(ZInterStore),
+    ZDiffStore(ZDiffStore),
+    ZPopMin(ZPopMin),
+    ZPopMax(ZPopMax),
+    ZMPop(ZMPop),
+    BZPopMin(BZPopMin),
+    BZPopMax(BZPopMax),
+    BZMPop(BZMPop),
+    XAdd(XAdd),
+    XRange(XRange),
+    XRevRange(XRevRange),
+    XLen(XLen),
+    XDel(XDel),
+    XTrim(XTrim),
+    XRead(XRead),
+    XGroup(XGroup),
+    XReadGroup(XReadGroup),
+    XAck(XAck),
+    XPending(XPending),
+    XClaim(XClaim),
+    XAutoClaim(XAutoClaim),
+    XInfo(XInfo),
+    SetBit(SetBit),
+    GetBit(GetBit),
+    BitCount(BitCount),
+    BitPos(BitPos),
+    BitOp(BitOp),
+    BitField(BitField),
+    BitFieldRo(BitFieldRo),
+    PfAdd(PfAdd),
+    PfCount(PfCount),
+    PfMerge(PfMerge),
+    GeoAdd(GeoAdd),
+    GeoPos(GeoPos),
+    GeoDist(GeoDist),
+    GeoHash(GeoHash),
+    GeoSearch(GeoSearch),
+    GeoSearchStore(GeoSearchStore),
+    Expire(Expire),
+    PExpire(PExpire),
+    ExpireAt(ExpireAt),
+    PExpireAt(PExpireAt),
+    Ttl(Ttl),
+    PTtl(PTtl),
+    ExpireTime(ExpireTime),
+    PExpireTime(PExpireTime),
+    Persist(Persist),
+    Del(Del),
+    Unlink(Unlink),
+    Exists(Exists),
+    Type(Type),
+    Rename(Rename),
+    RenameNx(RenameNx),
+    Copy(Copy),
+    Touch(Touch),
+    RandomKey(RandomKey),
+    DbSize(DbSize),
+    Keys(Keys),
+    Scan(Scan),
+    HScan(HScan),
+    SScan(SScan),
+    ZScan(ZScan),
+    Select(Select),
+    Move(Move),
+    SwapDb(SwapDb),
+    FlushDb(FlushDb),
+    FlushAll(FlushAll),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Watch(Watch),
+    Unwatch(Unwatch),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    PSubscribe(PSubscribe),
+    PUnsubscribe(PUnsubscribe),
+    SSubscribe(SSubscribe),
+    SUnsubscribe(SUnsubscribe),
+    Publish(Publish),
+    SPublish(SPublish),
+    PubSubInfo(PubSubInfo),
+    Eval(Eval),
+    EvalRo(EvalRo),
+    EvalSha(EvalSha),
+    EvalShaRo(EvalShaRo),
+    Script(Script),
+    FunctionCommand(FunctionCommand),
+    FCall(FCall),
+    FCallRo(FCallRo),
+}
+
+impl TryFrom<RespArray> for Command {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let mut args = VecDeque::with_capacity(value.len());
+        for frame in value.iter() {
+            match frame {
+                RespFrame::BulkString(s) => args.push_back(s.to_vec()),
+                _ => {
+                    return Err(CommandError::InvalidCommand(
+                        "expected array of bulk strings".to_string(),
+                    ))
+                }
+            }
+        }
+        let name = match args.pop_front() {
+            Some(name) => String::from_utf8_lossy(&name).to_ascii_lowercase(),
+            None => return Err(CommandError::InvalidCommand("empty command".to_string())),
+        };
+
+        match name.as_str() {
+            "ping" => parse::<Ping>(args),
+            "echo" => parse::<Echo>(args),
+            "hello" => parse::<Hello>(args),
+            "quit" => parse::<Quit>(args),
+            "reset" => parse::<Reset>(args),
+            "get" => parse::<Get>(args),
+            "set" => parse::<Set>(args),
+            "getex" => parse::<GetEx>(args),
+            "getdel" => parse::<GetDel>(args),
+            "setnx" => parse::<SetNx>(args),
+            "setex" => parse::<SetEx>(args),
+            "psetex" => parse::<PSetEx>(args),
+            "append" => parse::<Append>(args),
+            "strlen" => parse::<Strlen>(args),
+            "getrange" => parse::<GetRange>(args),
+            "setrange" => parse::<SetRange>(args),
+            "incr" => parse::<Incr>(args),
+            "decr" => parse::<Decr>(args),
+            "incrby" => parse::<IncrBy>(args),
+            "decrby" => parse::<DecrBy>(args),
+            "incrbyfloat" => parse::<IncrByFloat>(args),
+            "mget" => parse::<MGet>(args),
+            "mset" => parse::<MSet>(args),
+            "msetnx" => parse::<MSetNx>(args),
+            "hset" => parse::<HSet>(args),
+            "hget" => parse::<HGet>(args),
+            "hmget" => parse::<HMGet>(args),
+            "hgetall" => parse::<HGetAll>(args),
+            "hdel" => parse::<HDel>(args),
+            "hexists" => parse::<HExists>(args),
+            "hlen" => parse::<HLen>(args),
+            "hkeys" => parse::<HKeys>(args),
+            "hvals" => parse::<HVals>(args),
+            "hincrby" => parse::<HIncrBy>(args),
+            "hincrbyfloat" => parse::<HIncrByFloat>(args),
+            "hsetnx" => parse::<HSetNx>(args),
+            "hstrlen" => parse::<HStrlen>(args),
+            "hrandfield" => parse::<HRandField>(args),
+            "lpush" => parse::<LPush>(args),
+            "rpush" => parse::<RPush>(args),
+            "lpushx" => parse::<LPushX>(args),
+            "rpushx" => parse::<RPushX>(args),
+            "lpop" => parse::<LPop>(args),
+            "rpop" => parse::<RPop>(args),
+            "lrange" => parse::<LRange>(args),
+            "lindex" => parse::<LIndex>(args),
+            "lset" => parse::<LSet>(args),
+            "linsert" => parse::<LInsert>(args),
+            "lrem" => parse::<LRem>(args),
+            "ltrim" => parse::<LTrim>(args),
+            "llen" => parse::<LLen>(args),
+            "lpos" => parse::<LPos>(args),
+            "lmove" => parse::<LMove>(args),
+            "lmpop" => parse::<LMPop>(args),
+            "blpop" => parse::<BLPop>(args),
+            "brpop" => parse::<BRPop>(args),
+            "blmove" => parse::<BLMove>(args),
+            "blmpop" => parse::<BLMPop>(args),
+            "sadd" => parse::<SAdd>(args),
+            "srem" => parse::<SRem>(args),
+            "sismember" => parse::<SIsMember>(args),
+            "smismember" => parse::<SMIsMember>(args),
+            "smembers" => parse::<SMembers>(args),
+            "scard" => parse::<SCard>(args),
+            "spop" => parse::<SPop>(args),
+            "srandmember" => parse::<SRandMember>(args),
+            "smove" => parse::<SMove>(args),
+            "sinter" => parse::<SInter>(args),
+            "sunion" => parse::<SUnion>(args),
+            "sdiff" => parse::<SDiff>(args),
+            "sinterstore" => parse::<SInterStore>(args),
+            "sunionstore" => parse::<SUnionStore>(args),
+            "sdiffstore" => parse::<SDiffStore>(args),
+            "sintercard" => parse::<SInterCard>(args),
+            "zadd" => parse::<ZAdd>(args),
+            "zrem" => parse::<ZRem>(args),
+            "zscore" => parse::<ZScore>(args),
+            "zmscore" => parse::<ZMScore>(args),
+            "zincrby" => parse::<ZIncrBy>(args),
+            "zcard" => parse::<ZCard>(args),
+            "zcount" => parse::<ZCount>(args),
+            "zrank" => parse::<ZRank>(args),
+            "zrevrank" => parse::<ZRevRank>(args),
+            "zrange" => parse::<ZRange>(args),
+            "zrangestore" => parse::<ZRangeStore>(args),
+            "zunion" => parse::<ZUnion>(args),
+            "zinter" => parse::<ZInter>(args),
+            "zdiff" => parse::<ZDiff>(args),
+            "zunionstore" => parse::<ZUnionStore>(args),
+            "zinterstore" => parse::<ZInterStore>(args),
+            "zdiffstore" => parse::<ZDiffStore>(args),
+            "zpopmin" => parse::<ZPopMin>(args),
+            "zpopmax" => parse::<ZPopMax>(args),
+            "zmpop" => parse::<ZMPop>(args),
+            "bzpopmin" => parse::<BZPopMin>(args),
+            "bzpopmax" => parse::<BZPopMax>(args),
+            "bzmpop" => parse::<BZMPop>(args),
+            "xadd" => parse::<XAdd>(args),
+            "xrange" => parse::<XRange>(args),
+            "xrevrange" => parse::<XRevRange>(args),
+            "xlen" => parse::<XLen>(args),
+            "xdel" => parse::<XDel>(args),
+            "xtrim" => parse::<XTrim>(args),
+            "xread" => parse::<XRead>(args),
+            "xgroup" => parse::<XGroup>(args),
+            "xreadgroup" => parse::<XReadGroup>(args),
+            "xack" => parse::<XAck>(args),
+            "xpending" => parse::<XPending>(args),
+            "xclaim" => parse::<XClaim>(args),
+            "xautoclaim" => parse::<XAutoClaim>(args),
+            "xinfo" => parse::<XInfo>(args),
+            "setbit" => parse::<SetBit>(args),
+            "getbit" => parse::<GetBit>(args),
+            "bitcount" => parse::<BitCount>(args),
+            "bitpos" => parse::<BitPos>(args),
+            "bitop" => parse::<BitOp>(args),
+            "bitfield" => parse::<BitField>(args),
+            "bitfield_ro" => parse::<BitFieldRo>(args),
+            "pfadd" => parse::<PfAdd>(args),
+            "pfcount" => parse::<PfCount>(args),
+            "pfmerge" => parse::<PfMerge>(args),
+            "geoadd" => parse::<GeoAdd>(args),
+            "geopos" => parse::<GeoPos>(args),
+            "geodist" => parse::<GeoDist>(args),
+            "geohash" => parse::<GeoHash>(args),
+            "geosearch" => parse::<GeoSearch>(args),
+            "geosearchstore" => parse::<GeoSearchStore>(args),
+            "expire" => parse::<Expire>(args),
+            "pexpire" => parse::<PExpire>(args),
+            "expireat" => parse::<ExpireAt>(args),
+            "pexpireat" => parse::<PExpireAt>(args),
+            "ttl" => parse::<Ttl>(args),
+            "pttl" => parse::<PTtl>(args),
+            "expiretime" => parse::<ExpireTime>(args),
+            "pexpiretime" => parse::<PExpireTime>(args),
+            "persist" => parse::<Persist>(args),
+            "del" => parse::<Del>(args),
+            "unlink" => parse::<Unlink>(args),
+            "exists" => parse::<Exists>(args),
+            "type" => parse::<Type>(args),
+            "rename" => parse::<Rename>(args),
+            "renamenx" => parse::<RenameNx>(args),
+            "copy" => parse::<Copy>(args),
+            "touch" => parse::<Touch>(args),
+            "randomkey" => parse::<RandomKey>(args),
+            "dbsize" => parse::<DbSize>(args),
+            "keys" => parse::<Keys>(args),
+            "scan" => parse::<Scan>(args),
+            "hscan" => parse::<HScan>(args),
+            "sscan" => parse::<SScan>(args),
+            "zscan" => parse::<ZScan>(args),
+            "select" => parse::<Select>(args),
+            "move" => parse::<Move>(args),
+            "swapdb" => parse::<SwapDb>(args),
+            "flushdb" => parse::<FlushDb>(args),
+            "flushall" => parse::<FlushAll>(args),
+            "multi" => parse::<Multi>(args),
+            "exec" => parse::<Exec>(args),
+            "discard" => parse::<Discard>(args),
+            "watch" => parse::<Watch>(args),
+            "unwatch" => parse::<Unwatch>(args),
+            "subscribe" => parse::<Subscribe>(args),
+            "unsubscribe" => parse::<Unsubscribe>(args),
+            "psubscribe" => parse::<PSubscribe>(args),
+            "punsubscribe" => parse::<PUnsubscribe>(args),
+            "ssubscribe" => parse::<SSubscribe>(args),
+            "sunsubscribe" => parse::<SUnsubscribe>(args),
+            "publish" => parse::<Publish>(args),
+            "spublish" => parse::<SPublish>(args),
+            "pubsub" => parse::<PubSubInfo>(args),
+            "eval" => parse::<Eval>(args),
+            "eval_ro" => parse::<EvalRo>(args),
+            "evalsha" => parse::<EvalSha>(args),
+            "evalsha_ro" => parse::<EvalShaRo>(args),
+            "script" => parse::<Script>(args),
+            "function" => parse::<FunctionCommand>(args),
+            "fcall" => parse::<FCall>(args),
+            "fcall_ro" => parse::<FCallRo>(args),
+            _ => {
+                let preview = args
+                    .iter()
+                    .map(|arg| format!("'{}' ", String::from_utf8_lossy(arg)))
+                    .collect::<String>();
+                Err(CommandError::UnknownCommand(name, preview))
+            }
+        }
+    }
+}
+
+fn parse<T>(args: VecDeque<Vec<u8>>) -> Result<Command, CommandError>
+where
+    T: CommandSpec + Into<Command>,
+{
+    // arity counts the command name as well
+    let argc = args.len() as i64 + 1;
+    if (T::ARITY >= 0 && argc != T::ARITY) || (T::ARITY < 0 && argc < -T::ARITY) {
+        return Err(CommandError::WrongArity(T::NAME.to_string()));
+    }
+    let mut args = CommandArgs::new(T::NAME, args);
+    T::parse(&mut args).map(Into::into)
+}
+
+// check the arguments left for a subcommand against its arity, which like
+// Redis counts the container command and the subcommand name too
+pub(crate) fn check_subcommand_arity(
+    args: &CommandArgs,
+    name: &str,
+    arity: i64,
+) -> Result<(), CommandError> {
+    let argc = args.len() as i64 + 2;
+    if (arity >= 0 && argc != arity) || (arity < 0 && argc < -arity) {
+        return Err(CommandError::WrongArity(format!(
+            "{}|{}",
+            args.name(),
+            name
+        )));
+    }
+    Ok(())
+}
+
+// the arguments of one command, consumed front to back while parsing
+#[derive(Debug)]
+pub struct CommandArgs {
+    name: &'static str,
+    args: VecDeque<Vec<u8>>,
+}
+
+impl CommandArgs {
+    pub fn new(name: &'static str, args: impl Into<VecDeque<Vec<u8>>>) -> Self {
+        CommandArgs {
+            name,
+            args: args.into(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    pub fn next_bytes(&mut self) -> Result<Vec<u8>, CommandError> {
+        self.args.pop_front().ok_or(CommandError::Syntax)
+    }
+
+    pub fn next_string(&mut self) -> Result<String, CommandError> {
+        Ok(String::from_utf8_lossy(&self.next_bytes()?).into_owned())
+    }
+
+    pub fn next_i64(&mut self) -> Result<i64, CommandError> {
+        parse_i64(&self.next_bytes()?)
+    }
+
+    pub fn next_f64(&mut self) -> Result<f64, CommandError> {
+        parse_f64(&self.next_bytes()?)
+    }
+
+    // the next argument as an uppercase keyword, for option parsing
+    pub fn next_keyword(&mut self) -> Option<String> {
+        self.args
+            .pop_front()
+            .map(|arg| String::from_utf8_lossy(&arg).to_ascii_uppercase())
+    }
+
+    pub fn peek_keyword(&self) -> Option<String> {
+        self.args
+            .front()
+            .map(|arg| String::from_utf8_lossy(arg).to_ascii_uppercase())
+    }
+
+    // take all remaining arguments
+    pub fn rest(&mut self) -> Vec<Vec<u8>> {
+        self.args.drain(..).collect()
+    }
+
+    // fail with a syntax error if anything is left unparsed
+    pub fn finish(&self) -> Result<(), CommandError> {
+        if self.args.is_empty() {
+            Ok(())
+        } else {
+            Err(CommandError::Syntax)
+        }
+    }
+}
+
+// strict integer parsing the way Redis does it: no spaces, no leading '+', no leading zeros
+pub fn parse_i64(buf: &[u8]) -> Result<i64, CommandError> {
+    let s = std::str::from_utf8(buf).map_err(|_| CommandError::NotInteger)?;
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    if digits.is_empty()
+        || !digits.bytes().all(|b| b.is_ascii_digit())
+        || (digits.len() > 1 && digits.starts_with('0'))
+        || s == "-0"
+    {
+        return Err(CommandError::NotInteger);
+    }
+    s.parse().map_err(|_| CommandError::NotInteger)
+}
+
+pub fn parse_f64(buf: &[u8]) -> Result<f64, CommandError> {
+    let s = std::str::from_utf8(buf).map_err(|_| CommandError::NotFloat)?;
+    if s.is_empty() || s.starts_with(char::is_whitespace) || s.ends_with(char::is_whitespace) {
+        return Err(CommandError::NotFloat);
+    }
+    let v = match s.to_ascii_lowercase().as_str() {
+        "inf" | "+inf" | "infinity" | "+infinity" => f64::INFINITY,
+        "-inf" | "-infinity" => f64::NEG_INFINITY,
+        _ => s.parse().map_err(|_| CommandError::NotFloat)?,
+    };
+    if v.is_nan() {
+        return Err(CommandError::NotFloat);
+    }
+    Ok(v)
+}
+
+// the timeout argument of the blocking commands, in seconds; 0 blocks forever
+pub fn parse_timeout(buf: &[u8]) -> Result<Option<Duration>, CommandError> {
+    let secs = parse_f64(buf)
+        .ok()
+        .filter(|s| s.is_finite())
+        .ok_or_else(|| CommandError::err("timeout is not a float or out of range"))?;
+    if secs < 0.0 {
+        return Err(CommandError::err("timeout is negative"));
+    }
+    if secs == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(secs)
+        .map(Some)
+        .map_err(|_| CommandError::err("timeout is not a float or out of range"))
+}
+
+// pick `count` random items the way the *RANDMEMBER family does: a positive
+// count returns distinct items, a negative one may repeat and returns exactly -count
+pub fn random_sample<T: Clone>(items: &[T], count: i64) -> Result<Vec<T>, CommandError> {
+    let mut rng = rand::thread_rng();
+    if count >= 0 {
+        return Ok(items
+            .choose_multiple(&mut rng, count as usize)
+            .cloned()
+            .collect());
+    }
+    if count < -(i64::MAX / 2) {
+        return Err(CommandError::err("value is out of range"));
+    }
+    if items.is_empty() {
+        return Ok(vec![]);
+    }
+    Ok((0..-count)
+        .filter_map(|_| items.choose(&mut rng).cloned())
+        .collect())
+}
+
+// +OK
+pub fn ok() -> RespFrame {
+    SimpleString::new("OK").into()
+}
+
+// $-1, the reply for a missing value
+pub fn nil() -> RespFrame {
+    RespNullBulkString.into()
+}
+
+#[cfg(test)]
+pub(crate) fn resp_cmd(cmd: &str) -> RespArray {
+    RespArray::new(
+        cmd.split_whitespace()
+            .map(|s| crate::BulkString::new(s).into())
+            .collect::<Vec<RespFrame>>(),
+    )
+}
+
+// a backend and a client to run commands against in unit tests
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct TestContext {
+    pub backend: BackendInner,
+    pub client: Client,
+}
+
+#[cfg(test)]
+impl TestContext {
+    pub fn run(&mut self, cmd: &str) -> RespFrame {
+        match self.exec(cmd) {
+            Reply::Frame(frame) => frame,
+            Reply::Frames(_) => panic!("'{}' replied more than once", cmd),
+            Reply::Blocked(_) => panic!("'{}' blocked", cmd),
+            Reply::Close(_) => panic!("'{}' closed the connection", cmd),
+        }
+    }
+
+    // run a command that may reply more than once
+    pub fn run_all(&mut self, cmd: &str) -> Vec<RespFrame> {
+        match self.exec(cmd) {
+            Reply::Frame(frame) => vec![frame],
+            Reply::Frames(frames) => frames,
+            Reply::Blocked(_) => panic!("'{}' blocked", cmd),
+            Reply::Close(_) => panic!("'{}' closed the connection", cmd),
+        }
+    }
+
+    // run a command that is expected to block the client
+    pub fn block(&mut self, cmd: &str) -> Blocked {
+        match self.exec(cmd) {
+            Reply::Blocked(blocked) => blocked,
+            reply => panic!("'{}' did not block: {:?}", cmd, reply),
+        }
+    }
+
+    fn exec(&mut self, cmd: &str) -> Reply {
+        match Command::try_from(resp_cmd(cmd)) {
+            Ok(cmd) => run(cmd, &mut self.client, &mut self.backend),
+            Err(e) => reject(e, &mut self.client),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_dispatch_is_case_insensitive() {
+        let cmd = Command::try_from(resp_cmd("PiNg")).unwrap();
+        assert!(matches!(cmd, Command::Ping(_)));
+    }
+
+    #[test]
+    fn test_command_arity() {
+        let err = Command::try_from(resp_cmd("echo")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ERR wrong number of arguments for 'echo' command"
+        );
+
+        let err = Command::try_from(resp_cmd("ping a b")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ERR wrong number of arguments for 'ping' command"
+        );
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let err = Command::try_from(resp_cmd("foo bar")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ERR unknown command 'foo', with args beginning with: 'bar' "
+        );
+        let frame: RespFrame = err.into();
+        assert!(matches!(frame, RespFrame::Error(_)));
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        assert_eq!(parse_i64(b"-42"), Ok(-42));
+        assert_eq!(parse_i64(b"+42"), Err(CommandError::NotInteger));
+        assert_eq!(parse_i64(b"042"), Err(CommandError::NotInteger));
+        assert_eq!(parse_i64(b" 1"), Err(CommandError::NotInteger));
+        assert_eq!(
+            parse_i64(b"99999999999999999999"),
+            Err(CommandError::NotInteger)
+        );
+
+        assert_eq!(parse_f64(b"1.5"), Ok(1.5));
+        assert_eq!(parse_f64(b"-inf"), Ok(f64::NEG_INFINITY));
+        assert_eq!(parse_f64(b"nan"), Err(CommandError::NotFloat));
+        assert_eq!(parse_f64(b"abc"), Err(CommandError::NotFloat));
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout(b"0"), Ok(None));
+        assert_eq!(parse_timeout(b"0.5"), Ok(Some(Duration::from_millis(500))));
+        assert_eq!(
+            parse_timeout(b"-1").unwrap_err().to_string(),
+            "ERR timeout is negative"
+        );
+        assert_eq!(
+            parse_timeout(b"inf").unwrap_err().to_string(),
+            "ERR timeout is not a float or out of range"
+        );
+    }
+}
@@ -0,0 +1,610 @@
+use crate::backend::{glob_match, Message, SubscriptionKind};
+use crate::{BulkString, RespArray, RespFrame, RespNullBulkString, RespPush};
+
+use super::{
+    check_subcommand_arity, Command, CommandArgs, CommandError, CommandExecutor, CommandSpec,
+    Context,
+};
+
+// SUBSCRIBE channel [channel ...]
+#[derive(Debug)]
+pub struct Subscribe {
+    channels: Vec<Vec<u8>>,
+}
+
+// UNSUBSCRIBE [channel [channel ...]]
+#[derive(Debug)]
+pub struct Unsubscribe {
+    channels: Vec<Vec<u8>>,
+}
+
+// PSUBSCRIBE pattern [pattern ...]
+#[derive(Debug)]
+pub struct PSubscribe {
+    patterns: Vec<Vec<u8>>,
+}
+
+// PUNSUBSCRIBE [pattern [pattern ...]]
+#[derive(Debug)]
+pub struct PUnsubscribe {
+    patterns: Vec<Vec<u8>>,
+}
+
+// SSUBSCRIBE shardchannel [shardchannel ...]
+#[derive(Debug)]
+pub struct SSubscribe {
+    channels: Vec<Vec<u8>>,
+}
+
+// SUNSUBSCRIBE [shardchannel [shardchannel ...]]
+#[derive(Debug)]
+pub struct SUnsubscribe {
+    channels: Vec<Vec<u8>>,
+}
+
+// PUBLISH channel message
+#[derive(Debug)]
+pub struct Publish {
+    channel: Vec<u8>,
+    message: Vec<u8>,
+}
+
+// SPUBLISH shardchannel message
+#[derive(Debug)]
+pub struct SPublish {
+    channel: Vec<u8>,
+    message: Vec<u8>,
+}
+
+// PUBSUB CHANNELS [pattern] | NUMSUB [channel ...] | NUMPAT
+//      | SHARDCHANNELS [pattern] | SHARDNUMSUB [shardchannel ...]
+#[derive(Debug)]
+pub enum PubSubInfo {
+    Channels {
+        kind: SubscriptionKind,
+        pattern: Option<Vec<u8>>,
+    },
+    NumSub {
+        kind: SubscriptionKind,
+        channels: Vec<Vec<u8>>,
+    },
+    NumPat,
+}
+
+impl Command {
+    // what a RESP2 client may still run once it subscribed to something
+    pub fn allowed_when_subscribed(&self) -> bool {
+        matches!(
+            self,
+            Command::Subscribe(_)
+                | Command::Unsubscribe(_)
+                | Command::PSubscribe(_)
+                | Command::PUnsubscribe(_)
+                | Command::SSubscribe(_)
+                | Command::SUnsubscribe(_)
+                | Command::Ping(_)
+                | Command::Quit(_)
+                | Command::Reset(_)
+        )
+    }
+}
+
+// pub/sub data the client did not ask for: a push on RESP3, a plain array on
+// RESP2, where the subscribed mode tells the client what it is
+fn push_frame(resp3: bool, frames: Vec<RespFrame>) -> RespFrame {
+    if resp3 {
+        RespPush::new(frames).into()
+    } else {
+        RespArray::new(frames).into()
+    }
+}
+
+// a published message, the way a subscriber receives it
+pub fn message_frame(message: Message, resp3: bool) -> RespFrame {
+    let mut frames = Vec::with_capacity(4);
+    match message.pattern {
+        Some(pattern) => {
+            frames.push(BulkString::new("pmessage").into());
+            frames.push(BulkString::new(pattern).into());
+        }
+        None if message.sharded => frames.push(BulkString::new("smessage").into()),
+        None => frames.push(BulkString::new("message").into()),
+    }
+    frames.push(BulkString::new(message.channel).into());
+    frames.push(BulkString::new(message.payload).into());
+    push_frame(resp3, frames)
+}
+
+// the confirmation of a (un)subscription: the command name, the channel or
+// pattern and the number of subscriptions of the kind the client is left with
+fn confirmation(
+    ctx: &Context,
+    name: &str,
+    kind: SubscriptionKind,
+    target: Option<Vec<u8>>,
+) -> RespFrame {
+    let target = match target {
+        Some(target) => BulkString::new(target).into(),
+        None => RespNullBulkString.into(),
+    };
+    let count = ctx.backend.subscriptions.count(ctx.client.id, kind) as i64;
+    push_frame(
+        ctx.client.is_resp3(),
+        vec![
+            BulkString::new(name).into(),
+            target,
+            RespFrame::Integer(count),
+        ],
+    )
+}
+
+// answer with one confirmation per target, in order
+fn confirm_each(ctx: &mut Context, mut confirmations: Vec<RespFrame>) -> RespFrame {
+    let last = confirmations.pop().expect("at least one confirmation");
+    for frame in confirmations {
+        ctx.reply(frame);
+    }
+    last
+}
+
+fn subscribe(ctx: &mut Context, kind: SubscriptionKind, targets: Vec<Vec<u8>>) -> RespFrame {
+    let name = match kind {
+        SubscriptionKind::Channel => "subscribe",
+        SubscriptionKind::Pattern => "psubscribe",
+        SubscriptionKind::ShardChannel => "ssubscribe",
+    };
+    let mut confirmations = Vec::with_capacity(targets.len());
+    for target in targets {
+        let id = ctx.client.id;
+        let tx = ctx.client.messages.as_ref();
+        ctx.backend.subscriptions.subscribe(id, tx, kind, &target);
+        confirmations.push(confirmation(ctx, name, kind, Some(target)));
+    }
+    confirm_each(ctx, confirmations)
+}
+
+// unsubscribe from the given targets, or from every one of the kind when
+// none is given; with nothing to unsubscribe from there is still one reply
+fn unsubscribe(ctx: &mut Context, kind: SubscriptionKind, targets: Vec<Vec<u8>>) -> RespFrame {
+    let name = match kind {
+        SubscriptionKind::Channel => "unsubscribe",
+        SubscriptionKind::Pattern => "punsubscribe",
+        SubscriptionKind::ShardChannel => "sunsubscribe",
+    };
+    let id = ctx.client.id;
+    let targets = match targets.is_empty() {
+        true => ctx.backend.subscriptions.client_targets(id, kind),
+        false => targets,
+    };
+    if targets.is_empty() {
+        return confirmation(ctx, name, kind, None);
+    }
+    let mut confirmations = Vec::with_capacity(targets.len());
+    for target in targets {
+        ctx.backend.subscriptions.unsubscribe(id, kind, &target);
+        confirmations.push(confirmation(ctx, name, kind, Some(target)));
+    }
+    confirm_each(ctx, confirmations)
+}
+
+impl CommandSpec for Subscribe {
+    const NAME: &'static str = "subscribe";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Subscribe {
+            channels: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for Subscribe {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(subscribe(ctx, SubscriptionKind::Channel, self.channels))
+    }
+}
+
+impl CommandSpec for Unsubscribe {
+    const NAME: &'static str = "unsubscribe";
+    const ARITY: i64 = -1;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Unsubscribe {
+            channels: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for Unsubscribe {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(unsubscribe(ctx, SubscriptionKind::Channel, self.channels))
+    }
+}
+
+impl CommandSpec for PSubscribe {
+    const NAME: &'static str = "psubscribe";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(PSubscribe {
+            patterns: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for PSubscribe {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(subscribe(ctx, SubscriptionKind::Pattern, self.patterns))
+    }
+}
+
+impl CommandSpec for PUnsubscribe {
+    const NAME: &'static str = "punsubscribe";
+    const ARITY: i64 = -1;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(PUnsubscribe {
+            patterns: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for PUnsubscribe {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(unsubscribe(ctx, SubscriptionKind::Pattern, self.patterns))
+    }
+}
+
+impl CommandSpec for SSubscribe {
+    const NAME: &'static str = "ssubscribe";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SSubscribe {
+            channels: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for SSubscribe {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(subscribe(
+            ctx,
+            SubscriptionKind::ShardChannel,
+            self.channels,
+        ))
+    }
+}
+
+impl CommandSpec for SUnsubscribe {
+    const NAME: &'static str = "sunsubscribe";
+    const ARITY: i64 = -1;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SUnsubscribe {
+            channels: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for SUnsubscribe {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(unsubscribe(
+            ctx,
+            SubscriptionKind::ShardChannel,
+            self.channels,
+        ))
+    }
+}
+
+impl CommandSpec for Publish {
+    const NAME: &'static str = "publish";
+    const ARITY: i64 = 3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Publish {
+            channel: args.next_bytes()?,
+            message: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Publish {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let receivers = ctx
+            .backend
+            .subscriptions
+            .publish(&self.channel, &self.message);
+        Ok(RespFrame::Integer(receivers as i64))
+    }
+}
+
+impl CommandSpec for SPublish {
+    const NAME: &'static str = "spublish";
+    const ARITY: i64 = 3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SPublish {
+            channel: args.next_bytes()?,
+            message: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for SPublish {
+    // only the subscribers found in the channel's slot get the message, which
+    // is all a cluster node would hold for it
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let receivers = ctx
+            .backend
+            .subscriptions
+            .spublish(&self.channel, &self.message);
+        Ok(RespFrame::Integer(receivers as i64))
+    }
+}
+
+impl CommandSpec for PubSubInfo {
+    const NAME: &'static str = "pubsub";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let sub = args.next_string()?.to_ascii_lowercase();
+        let arity = match sub.as_str() {
+            "channels" | "shardchannels" => -2,
+            "numsub" | "shardnumsub" => -2,
+            "numpat" => 2,
+            _ => {
+                return Err(CommandError::UnknownSubcommand(
+                    sub,
+                    Self::NAME.to_uppercase(),
+                ))
+            }
+        };
+        check_subcommand_arity(args, &sub, arity)?;
+        let kind = match sub.starts_with("shard") {
+            true => SubscriptionKind::ShardChannel,
+            false => SubscriptionKind::Channel,
+        };
+        Ok(match sub.as_str() {
+            "channels" | "shardchannels" => {
+                if args.len() > 1 {
+                    return Err(CommandError::WrongArity(format!("{}|{}", Self::NAME, sub)));
+                }
+                PubSubInfo::Channels {
+                    kind,
+                    pattern: args.next_bytes().ok(),
+                }
+            }
+            "numsub" | "shardnumsub" => PubSubInfo::NumSub {
+                kind,
+                channels: args.rest(),
+            },
+            _ => PubSubInfo::NumPat,
+        })
+    }
+}
+
+impl CommandExecutor for PubSubInfo {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let subscriptions = &ctx.backend.subscriptions;
+        Ok(match self {
+            PubSubInfo::Channels { kind, pattern } => {
+                let channels = subscriptions
+                    .channels(kind)
+                    .into_iter()
+                    .filter(|channel| {
+                        pattern
+                            .as_ref()
+                            .is_none_or(|pattern| glob_match(pattern, channel, false))
+                    })
+                    .map(|channel| BulkString::new(channel.clone()).into())
+                    .collect::<Vec<RespFrame>>();
+                RespArray::new(channels).into()
+            }
+            PubSubInfo::NumSub { kind, channels } => {
+                let mut reply = Vec::with_capacity(channels.len() * 2);
+                for channel in channels {
+                    let count = subscriptions.num_subscribers(kind, &channel) as i64;
+                    reply.push(BulkString::new(channel).into());
+                    reply.push(RespFrame::Integer(count));
+                }
+                RespArray::new(reply).into()
+            }
+            PubSubInfo::NumPat => RespFrame::Integer(subscriptions.num_patterns() as i64),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{message_queue, PUBSUB_QUEUE_LIMIT};
+
+    use super::*;
+    use crate::cmd::TestContext;
+    use crate::{Protocol, RespEncode};
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    fn array(frames: Vec<RespFrame>) -> RespFrame {
+        RespArray::new(frames).into()
+    }
+
+    fn confirm(kind: &str, target: &str, count: i64) -> RespFrame {
+        array(vec![bulk(kind), bulk(target), RespFrame::Integer(count)])
+    }
+
+    #[test]
+    fn test_subscribe_and_publish() {
+        let mut t = TestContext::default();
+        let (tx, mut rx) = message_queue(PUBSUB_QUEUE_LIMIT);
+        t.client.messages = Some(tx);
+        assert_eq!(
+            t.run_all("subscribe news sports"),
+            vec![
+                confirm("subscribe", "news", 1),
+                confirm("subscribe", "sports", 2)
+            ]
+        );
+        assert_eq!(
+            t.run_all("psubscribe n*"),
+            vec![confirm("psubscribe", "n*", 3)]
+        );
+
+        let subscriber = std::mem::take(&mut t.client);
+        assert_eq!(t.run("publish news hello"), RespFrame::Integer(2));
+        assert_eq!(t.run("publish weather sunny"), RespFrame::Integer(0));
+        assert_eq!(
+            message_frame(rx.try_recv().unwrap(), false),
+            array(vec![bulk("message"), bulk("news"), bulk("hello")])
+        );
+        assert_eq!(
+            message_frame(rx.try_recv().unwrap(), false),
+            array(vec![
+                bulk("pmessage"),
+                bulk("n*"),
+                bulk("news"),
+                bulk("hello")
+            ])
+        );
+        assert!(rx.try_recv().is_err());
+
+        assert_eq!(t.run("pubsub channels"), t.run("pubsub channels *"));
+        assert_eq!(t.run("pubsub channels s*"), array(vec![bulk("sports")]));
+        assert_eq!(
+            t.run("pubsub numsub news missing"),
+            array(vec![
+                bulk("news"),
+                RespFrame::Integer(1),
+                bulk("missing"),
+                RespFrame::Integer(0),
+            ])
+        );
+        assert_eq!(t.run("pubsub numpat"), RespFrame::Integer(1));
+        assert_eq!(
+            t.run("pubsub frob"),
+            CommandError::UnknownSubcommand("frob".to_string(), "PUBSUB".to_string()).into()
+        );
+        assert_eq!(
+            t.run("pubsub numpat x"),
+            CommandError::WrongArity("pubsub|numpat".to_string()).into()
+        );
+
+        t.client = subscriber;
+        assert_eq!(
+            t.run_all("unsubscribe"),
+            vec![
+                confirm("unsubscribe", "news", 2),
+                confirm("unsubscribe", "sports", 1)
+            ]
+        );
+        assert_eq!(
+            t.run_all("punsubscribe n* other"),
+            vec![
+                confirm("punsubscribe", "n*", 0),
+                confirm("punsubscribe", "other", 0)
+            ]
+        );
+        assert_eq!(
+            t.run("unsubscribe"),
+            array(vec![
+                bulk("unsubscribe"),
+                RespNullBulkString.into(),
+                RespFrame::Integer(0)
+            ])
+        );
+        assert_eq!(t.run("pubsub numpat"), RespFrame::Integer(0));
+    }
+
+    #[test]
+    fn test_subscribed_mode() {
+        let mut t = TestContext::default();
+        t.run("subscribe news");
+        assert_eq!(
+            t.run("get k"),
+            CommandError::err(
+                "Can't execute 'get': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context"
+            )
+            .into()
+        );
+        assert_eq!(t.run("ping"), array(vec![bulk("pong"), bulk("")]));
+        assert_eq!(t.run("ping hi"), array(vec![bulk("pong"), bulk("hi")]));
+        t.run("unsubscribe");
+        assert_eq!(t.run("get k"), RespNullBulkString.into());
+
+        // RESP3 tells replies and pushes apart, so anything goes
+        t.client.protocol = Protocol::Resp3;
+        let confirmation = t.run("subscribe news");
+        assert_eq!(
+            confirmation.encode(),
+            b">3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:+1\r\n".to_vec()
+        );
+        assert_eq!(t.run("get k"), RespNullBulkString.into());
+        assert_eq!(t.run("ping"), crate::SimpleString::new("PONG").into());
+        let message = Message {
+            pattern: None,
+            sharded: false,
+            channel: b"news".to_vec(),
+            payload: b"hi".to_vec(),
+        };
+        assert!(matches!(message_frame(message, true), RespFrame::Push(_)));
+    }
+
+    #[test]
+    fn test_sharded_pubsub() {
+        let mut t = TestContext::default();
+        let (tx, mut rx) = message_queue(PUBSUB_QUEUE_LIMIT);
+        t.client.messages = Some(tx);
+        t.run("subscribe orders");
+        // sharded channels are counted on their own
+        assert_eq!(
+            t.run_all("ssubscribe {shop}.orders {shop}.users"),
+            vec![
+                confirm("ssubscribe", "{shop}.orders", 1),
+                confirm("ssubscribe", "{shop}.users", 2)
+            ]
+        );
+        assert!(matches!(t.run("get k"), RespFrame::Error(_)));
+
+        let subscriber = std::mem::take(&mut t.client);
+        assert_eq!(t.run("spublish {shop}.orders new"), RespFrame::Integer(1));
+        assert_eq!(
+            message_frame(rx.try_recv().unwrap(), false),
+            array(vec![bulk("smessage"), bulk("{shop}.orders"), bulk("new")])
+        );
+        // the two kinds of channels don't see each other's messages
+        assert_eq!(t.run("spublish orders new"), RespFrame::Integer(0));
+        assert_eq!(t.run("publish {shop}.orders new"), RespFrame::Integer(0));
+        assert!(rx.try_recv().is_err());
+
+        assert_eq!(t.run("pubsub channels"), array(vec![bulk("orders")]));
+        assert_eq!(
+            t.run("pubsub shardchannels *.users"),
+            array(vec![bulk("{shop}.users")])
+        );
+        assert_eq!(
+            t.run("pubsub shardnumsub {shop}.orders orders"),
+            array(vec![
+                bulk("{shop}.orders"),
+                RespFrame::Integer(1),
+                bulk("orders"),
+                RespFrame::Integer(0),
+            ])
+        );
+
+        t.client = subscriber;
+        assert_eq!(
+            t.run_all("sunsubscribe"),
+            vec![
+                confirm("sunsubscribe", "{shop}.orders", 1),
+                confirm("sunsubscribe", "{shop}.users", 0)
+            ]
+        );
+        assert_eq!(
+            t.run_all("unsubscribe"),
+            vec![confirm("unsubscribe", "orders", 0)]
+        );
+        assert_eq!(t.run("pubsub shardchannels"), array(vec![]));
+    }
+}
@@ -0,0 +1,884 @@
+use std::cell::RefCell;
+
+use mlua::{ChunkMode, HookTriggers, Lua, Table, Value, Variadic};
+
+use crate::backend::{lua_error_message, redis_table, sandbox, RunningScript};
+use crate::{BulkString, Protocol, RespArray, RespFrame, SimpleError, SimpleString};
+
+use super::{
+    check_subcommand_arity, nil, ok, parse_flush_mode, Command, CommandArgs, CommandError,
+    CommandExecutor, CommandName, CommandSpec, Context,
+};
+
+// how deeply nested a table a script may return, which also stops a table
+// that contains itself
+const MAX_REPLY_DEPTH: usize = 100;
+
+// how many Lua instructions a script runs between two looks at whether it
+// was killed
+const KILL_CHECK_INSTRUCTIONS: u32 = 1000;
+
+// SCRIPT LOAD script | EXISTS sha1 [sha1 ...] | FLUSH [ASYNC|SYNC] | KILL
+#[derive(Debug)]
+pub enum Script {
+    Load { body: Vec<u8> },
+    Exists { shas: Vec<String> },
+    Flush,
+    Kill,
+}
+
+// EVAL and friends: a script given by its body or by its SHA1, the keys it
+// works on and the rest of its arguments; the _RO variants may not write
+macro_rules! eval_command {
+    ($ty:ident, $name:literal, $by_sha:literal, $read_only:literal) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            script: Vec<u8>,
+            args: ScriptArgs,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -3;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                Ok($ty {
+                    script: args.next_bytes()?,
+                    args: ScriptArgs::parse(args)?,
+                })
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                let (sha, body) = match $by_sha {
+                    true => {
+                        let sha = String::from_utf8_lossy(&self.script).to_ascii_lowercase();
+                        let body = ctx.backend.scripts.get(&sha).cloned().ok_or_else(|| {
+                            CommandError::Reply(
+                                "NOSCRIPT No matching script. Please use EVAL.".to_string(),
+                            )
+                        })?;
+                        (sha, body)
+                    }
+                    false => (ctx.backend.scripts.load(self.script.clone()), self.script),
+                };
+                eval(ctx, &sha, &body, self.args, $read_only)
+            }
+        }
+    };
+}
+
+eval_command!(Eval, "eval", false, false);
+eval_command!(EvalRo, "eval_ro", false, true);
+eval_command!(EvalSha, "evalsha", true, false);
+eval_command!(EvalShaRo, "evalsha_ro", true, true);
+
+// the keys a script works on and the rest of its arguments
+#[derive(Debug)]
+pub struct ScriptArgs {
+    pub(crate) keys: Vec<Vec<u8>>,
+    pub(crate) args: Vec<Vec<u8>>,
+}
+
+impl ScriptArgs {
+    // numkeys followed by that many keys, then the arguments, as EVAL and
+    // FCALL take them
+    pub(crate) fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let numkeys = args.next_i64()?;
+        if numkeys < 0 {
+            return Err(CommandError::err("Number of keys can't be negative"));
+        }
+        if numkeys as usize > args.len() {
+            return Err(CommandError::err(
+                "Number of keys can't be greater than number of args",
+            ));
+        }
+        let mut keys = args.rest();
+        let args = keys.split_off(numkeys as usize);
+        Ok(ScriptArgs { keys, args })
+    }
+}
+
+impl Command {
+    // the commands a script can't run with redis.call: the ones that change
+    // how the connection behaves, and scripts themselves
+    fn denied_in_script(&self) -> bool {
+        matches!(
+            self,
+            Command::Hello(_)
+                | Command::Quit(_)
+                | Command::Reset(_)
+                | Command::Multi(_)
+                | Command::Exec(_)
+                | Command::Discard(_)
+                | Command::Watch(_)
+                | Command::Unwatch(_)
+                | Command::Subscribe(_)
+                | Command::Unsubscribe(_)
+                | Command::PSubscribe(_)
+                | Command::PUnsubscribe(_)
+                | Command::SSubscribe(_)
+                | Command::SUnsubscribe(_)
+                | Command::Eval(_)
+                | Command::EvalRo(_)
+                | Command::EvalSha(_)
+                | Command::EvalShaRo(_)
+                | Command::Script(_)
+                | Command::FCall(_)
+                | Command::FCallRo(_)
+                | Command::FunctionCommand(_)
+        )
+    }
+
+    // the commands that may run a script, and so hold the server for long
+    pub fn may_run_script(&self) -> bool {
+        matches!(
+            self,
+            Command::Eval(_)
+                | Command::EvalRo(_)
+                | Command::EvalSha(_)
+                | Command::EvalShaRo(_)
+                | Command::FCall(_)
+                | Command::FCallRo(_)
+                | Command::Exec(_)
+        )
+    }
+}
+
+// run a script body with KEYS and ARGV set for it
+fn eval(
+    ctx: &mut Context,
+    sha: &str,
+    body: &[u8],
+    args: ScriptArgs,
+    read_only: bool,
+) -> Result<RespFrame, CommandError> {
+    let lua = ctx
+        .backend
+        .scripts
+        .take_lua()
+        .map_err(|e| CommandError::err(e.to_string()))?;
+    let name = format!("f_{}", sha);
+    let result = run_script(ctx, &lua, read_only, |lua| {
+        let running = |e: mlua::Error| script_error(&e, &name);
+        let globals = lua.globals();
+        globals
+            .raw_set("KEYS", string_table(lua, &args.keys)?)
+            .map_err(running)?;
+        globals
+            .raw_set("ARGV", string_table(lua, &args.args)?)
+            .map_err(running)?;
+        let function = lua
+            .load(body)
+            .set_name("@user_script")
+            .set_environment(sandbox(lua).map_err(running)?)
+            .set_mode(ChunkMode::Text)
+            .into_function()
+            .map_err(|e| {
+                CommandError::err(format!(
+                    "Error compiling script (new function): {}",
+                    lua_error_message(&e)
+                ))
+            })?;
+        function.call(()).map_err(running)
+    });
+    ctx.backend.scripts.put_lua(lua);
+    result
+}
+
+// run f in lua on behalf of ctx's client, with redis.call and redis.pcall
+// running commands for it, and turn what f returns into a reply. A script
+// sees RESP2 replies whatever its client speaks, and a SELECT inside it
+// does not outlast it. While it runs, SCRIPT KILL from another connection
+// stops it at its next check
+pub(crate) fn run_script<'lua, F>(
+    ctx: &mut Context,
+    lua: &'lua Lua,
+    read_only: bool,
+    f: F,
+) -> Result<RespFrame, CommandError>
+where
+    F: FnOnce(&'lua Lua) -> Result<Value<'lua>, CommandError>,
+{
+    let protocol = std::mem::replace(&mut ctx.client.protocol, Protocol::Resp2);
+    let db = ctx.client.db;
+    let running = ctx.backend.running_script.clone();
+    running.start();
+    let killed = running.clone();
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(KILL_CHECK_INSTRUCTIONS),
+        move |_, _| match killed.is_killed() {
+            true => Err(mlua::Error::RuntimeError(
+                "Script killed by user with SCRIPT KILL...".to_string(),
+            )),
+            false => Ok(()),
+        },
+    );
+    let cell = RefCell::new(&mut *ctx);
+    let result = lua.scope(|scope| {
+        let call = scope.create_function(|lua, args: Variadic<Value>| {
+            let mut ctx = cell.borrow_mut();
+            call_command(lua, &mut ctx, read_only, args)?.map_err(mlua::Error::external)
+        })?;
+        let pcall = scope.create_function(|lua, args: Variadic<Value>| {
+            let mut ctx = cell.borrow_mut();
+            match call_command(lua, &mut ctx, read_only, args)? {
+                Ok(value) => Ok(value),
+                Err(e) => Ok(Value::Table(
+                    lua.create_table_from([("err", e.to_string())])?,
+                )),
+            }
+        })?;
+        let redis = redis_table(lua)?;
+        redis.raw_set("call", call)?;
+        redis.raw_set("pcall", pcall)?;
+        Ok(f(lua)
+            .and_then(|value| lua_to_frame(value, 0).map_err(|e| CommandError::err(e.to_string()))))
+    });
+    lua.remove_hook();
+    running.finish();
+    ctx.client.protocol = protocol;
+    ctx.client.db = db;
+    result.map_err(|e| CommandError::err(e.to_string()))?
+}
+
+// redis.call and redis.pcall: run a command for the script's client. The
+// inner error is the command's error reply, which the two report differently
+fn call_command<'lua>(
+    lua: &'lua Lua,
+    ctx: &mut Context,
+    read_only: bool,
+    args: Variadic<Value<'lua>>,
+) -> mlua::Result<Result<Value<'lua>, CommandError>> {
+    if args.is_empty() {
+        return Ok(Err(CommandError::err(
+            "Please specify at least one argument for this redis lib call",
+        )));
+    }
+    let mut frames = Vec::with_capacity(args.len());
+    for arg in args.iter() {
+        let arg = match arg {
+            Value::String(s) => s.as_bytes().to_vec(),
+            Value::Integer(_) | Value::Number(_) => lua
+                .coerce_string(arg.clone())?
+                .map(|s| s.as_bytes().to_vec())
+                .unwrap_or_default(),
+            _ => {
+                return Ok(Err(CommandError::err(
+                    "Lua redis lib command arguments must be strings or integers",
+                )))
+            }
+        };
+        frames.push(BulkString::new(arg).into());
+    }
+    let cmd = match Command::try_from(RespArray::new(frames)) {
+        Ok(cmd) => cmd,
+        Err(e) => return Ok(Err(e)),
+    };
+    if cmd.denied_in_script() {
+        return Ok(Err(CommandError::err(
+            "This Redis command is not allowed from script",
+        )));
+    }
+    if cmd.is_write() {
+        if read_only {
+            return Ok(Err(CommandError::err(
+                "Write commands are not allowed from read-only scripts.",
+            )));
+        }
+        ctx.backend.running_script.mark_write();
+    }
+    let reply = cmd.execute(ctx).unwrap_or_else(Into::into);
+    // a blocking command answers at once inside a script, as in a transaction
+    ctx.block = None;
+    ctx.replies.clear();
+    match reply {
+        RespFrame::Error(e) => Ok(Err(CommandError::Reply(e.to_string()))),
+        reply => frame_to_lua(lua, &reply).map(Ok),
+    }
+}
+
+// a reply as a script sees it: integers become numbers, bulk strings
+// strings, arrays tables, a status {ok = ...}, an error {err = ...} and a
+// missing value false
+fn frame_to_lua<'lua>(lua: &'lua Lua, frame: &RespFrame) -> mlua::Result<Value<'lua>> {
+    Ok(match frame {
+        RespFrame::SimpleString(s) => Value::Table(lua.create_table_from([("ok", s.as_str())])?),
+        RespFrame::Error(e) => Value::Table(lua.create_table_from([("err", e.as_str())])?),
+        RespFrame::Integer(n) => Value::Integer(*n),
+        RespFrame::BulkString(s) => Value::String(lua.create_string(&s.0)?),
+        RespFrame::NullBulkString(_) | RespFrame::NullArray(_) | RespFrame::Null(_) => {
+            Value::Boolean(false)
+        }
+        RespFrame::Boolean(true) => Value::Integer(1),
+        RespFrame::Boolean(false) => Value::Boolean(false),
+        RespFrame::Double(d) => Value::String(lua.create_string(d.to_string())?),
+        RespFrame::Array(items) => sequence(lua, items.iter())?,
+        RespFrame::Set(items) => sequence(lua, items.iter())?,
+        RespFrame::Push(items) => sequence(lua, items.iter())?,
+        // flattened to field, value, ... as RESP2 sends it
+        RespFrame::Map(map) => {
+            let table = lua.create_table_with_capacity(map.len() * 2, 0)?;
+            for (field, value) in map.iter() {
+                table.raw_push(lua.create_string(field)?)?;
+                table.raw_push(frame_to_lua(lua, value)?)?;
+            }
+            Value::Table(table)
+        }
+    })
+}
+
+fn sequence<'lua, 'a>(
+    lua: &'lua Lua,
+    frames: impl ExactSizeIterator<Item = &'a RespFrame>,
+) -> mlua::Result<Value<'lua>> {
+    let table = lua.create_table_with_capacity(frames.len(), 0)?;
+    for frame in frames {
+        table.raw_push(frame_to_lua(lua, frame)?)?;
+    }
+    Ok(Value::Table(table))
+}
+
+// what a script returns as a reply: numbers are truncated to integers, true
+// is 1, nil and false are a missing value, and a table is an array up to
+// its first nil unless it is an {ok = ...} or {err = ...}
+fn lua_to_frame(value: Value, depth: usize) -> mlua::Result<RespFrame> {
+    Ok(match value {
+        Value::Boolean(true) => RespFrame::Integer(1),
+        Value::Integer(n) => RespFrame::Integer(n),
+        Value::Number(n) => RespFrame::Integer(n as i64),
+        Value::String(s) => BulkString::new(s.as_bytes()).into(),
+        Value::Table(table) => {
+            if depth >= MAX_REPLY_DEPTH {
+                return Ok(SimpleError::new("ERR reached lua stack limit").into());
+            }
+            if let Value::String(e) = table.raw_get("err")? {
+                return Ok(SimpleError::new(e.to_string_lossy()).into());
+            }
+            if let Value::String(s) = table.raw_get("ok")? {
+                return Ok(SimpleString::new(s.to_string_lossy()).into());
+            }
+            let mut items = Vec::new();
+            for i in 1.. {
+                match table.raw_get(i)? {
+                    Value::Nil => break,
+                    value => items.push(lua_to_frame(value, depth + 1)?),
+                }
+            }
+            RespArray::new(items).into()
+        }
+        _ => nil(),
+    })
+}
+
+pub(crate) fn string_table<'lua>(
+    lua: &'lua Lua,
+    items: &[Vec<u8>],
+) -> Result<Table<'lua>, CommandError> {
+    let convert = || {
+        let table = lua.create_table_with_capacity(items.len(), 0)?;
+        for item in items {
+            table.raw_push(lua.create_string(item)?)?;
+        }
+        Ok(table)
+    };
+    convert().map_err(|e: mlua::Error| CommandError::err(e.to_string()))
+}
+
+// the error a script failed with: the error reply of a command redis.call
+// ran is passed on as is, anything else says which script failed
+pub(crate) fn script_error(e: &mlua::Error, name: &str) -> CommandError {
+    match e {
+        mlua::Error::CallbackError { cause, .. } => script_error(cause, name),
+        mlua::Error::ExternalError(external) => match external.downcast_ref::<CommandError>() {
+            Some(e) => CommandError::Reply(e.to_string()),
+            None => running_error(&external.to_string(), name),
+        },
+        e => running_error(&lua_error_message(e), name),
+    }
+}
+
+fn running_error(message: &str, name: &str) -> CommandError {
+    CommandError::err(format!(
+        "Error running script (call to {}): {}",
+        name, message
+    ))
+}
+
+impl CommandSpec for Script {
+    const NAME: &'static str = "script";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let sub = args.next_string()?.to_ascii_lowercase();
+        let arity = match sub.as_str() {
+            "load" => 3,
+            "exists" => -3,
+            "flush" => -2,
+            "kill" => 2,
+            _ => {
+                return Err(CommandError::UnknownSubcommand(
+                    sub,
+                    Self::NAME.to_uppercase(),
+                ))
+            }
+        };
+        check_subcommand_arity(args, &sub, arity)?;
+        Ok(match sub.as_str() {
+            "load" => Script::Load {
+                body: args.next_bytes()?,
+            },
+            "exists" => Script::Exists {
+                shas: args
+                    .rest()
+                    .iter()
+                    .map(|sha| String::from_utf8_lossy(sha).into_owned())
+                    .collect(),
+            },
+            "flush" => {
+                parse_flush_mode(args)?;
+                Script::Flush
+            }
+            _ => Script::Kill,
+        })
+    }
+}
+
+impl CommandExecutor for Script {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let scripts = &mut ctx.backend.scripts;
+        Ok(match self {
+            Script::Load { body } => BulkString::new(scripts.load(body)).into(),
+            Script::Exists { shas } => RespArray::new(
+                shas.iter()
+                    .map(|sha| RespFrame::Integer(scripts.contains(sha) as i64))
+                    .collect::<Vec<_>>(),
+            )
+            .into(),
+            Script::Flush => {
+                scripts.flush();
+                ok()
+            }
+            // SCRIPT KILL sent while a script runs is answered by busy_reply
+            // without the lock; one that got the lock found none running
+            Script::Kill => {
+                return Err(CommandError::Reply(
+                    "NOTBUSY No scripts in execution right now.".to_string(),
+                ))
+            }
+        })
+    }
+}
+
+// the reply to a command sent while a script has held the server for longer
+// than the busy threshold, given without waiting for the lock the script
+// holds: SCRIPT KILL stops the script unless it wrote already, anything else
+// is refused. None when the server is not busy
+pub fn busy_reply(cmd: &Command, running: &RunningScript) -> Option<RespFrame> {
+    if !running.is_busy() {
+        return None;
+    }
+    Some(match cmd {
+        Command::Script(Script::Kill) if running.kill() => ok(),
+        Command::Script(Script::Kill) => CommandError::Reply(
+            "UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command.".to_string(),
+        )
+        .into(),
+        _ => CommandError::Reply(
+            "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE.".to_string(),
+        )
+        .into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::{run, TestContext};
+    use crate::Reply;
+
+    fn int(n: i64) -> RespFrame {
+        RespFrame::Integer(n)
+    }
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    fn array(frames: Vec<RespFrame>) -> RespFrame {
+        RespArray::new(frames).into()
+    }
+
+    // run a command whose arguments may contain spaces
+    fn run_args(t: &mut TestContext, args: &[&str]) -> RespFrame {
+        let args = args.iter().map(|arg| arg.as_bytes()).collect::<Vec<_>>();
+        run_bytes(t, &args)
+    }
+
+    // run a command whose arguments may not even be text
+    fn run_bytes(t: &mut TestContext, args: &[&[u8]]) -> RespFrame {
+        let cmd = RespArray::new(
+            args.iter()
+                .map(|arg| BulkString::new(*arg).into())
+                .collect::<Vec<RespFrame>>(),
+        );
+        match Command::try_from(cmd) {
+            Ok(cmd) => match run(cmd, &mut t.client, &mut t.backend) {
+                Reply::Frame(frame) => frame,
+                reply => panic!("unexpected reply {:?}", reply),
+            },
+            Err(e) => e.into(),
+        }
+    }
+
+    #[test]
+    fn test_eval_conversions() {
+        let mut t = TestContext::default();
+        let mut eval = |script: &str| run_args(&mut t, &["eval", script, "0"]);
+        assert_eq!(eval("return 1"), int(1));
+        assert_eq!(eval("return 3.99"), int(3));
+        assert_eq!(eval("return 'x'"), bulk("x"));
+        assert_eq!(eval("return true"), int(1));
+        assert_eq!(eval("return false"), nil());
+        assert_eq!(eval("return nil"), nil());
+        assert_eq!(
+            eval("return {1, 'a', {2}, nil, 3}"),
+            array(vec![int(1), bulk("a"), array(vec![int(2)])])
+        );
+        assert_eq!(
+            eval("return {ok = 'FINE'}"),
+            SimpleString::new("FINE").into()
+        );
+        assert_eq!(
+            eval("return redis.error_reply('MYERR oops')"),
+            SimpleError::new("MYERR oops").into()
+        );
+        assert_eq!(
+            eval("return {1, {err = 'inner'}}"),
+            array(vec![int(1), SimpleError::new("inner").into()])
+        );
+        // a table holding itself nests as deep as the limit allows
+        assert!(matches!(
+            eval("local t = {} t[1] = t return t"),
+            RespFrame::Array(_)
+        ));
+    }
+
+    #[test]
+    fn test_redis_call() {
+        let mut t = TestContext::default();
+        t.run("set counter 10");
+        t.run("rpush list a b");
+        assert_eq!(
+            run_args(
+                &mut t,
+                &[
+                    "eval",
+                    "redis.call('set', KEYS[1], ARGV[1]) return redis.call('get', KEYS[1])",
+                    "1",
+                    "k",
+                    "v"
+                ]
+            ),
+            bulk("v")
+        );
+        // numbers go in as their Lua string form, replies come back converted
+        assert_eq!(
+            run_args(
+                &mut t,
+                &["eval", "return redis.call('incrby', 'counter', 5)", "0"]
+            ),
+            int(15)
+        );
+        assert_eq!(
+            run_args(
+                &mut t,
+                &["eval", "return redis.call('lrange', 'list', 0, -1)", "0"]
+            ),
+            array(vec![bulk("a"), bulk("b")])
+        );
+        assert_eq!(
+            run_args(
+                &mut t,
+                &["eval", "return redis.call('get', 'missing') == false", "0"]
+            ),
+            int(1)
+        );
+        assert_eq!(
+            run_args(&mut t, &["eval", "return redis.call('ping')", "0"]),
+            SimpleString::new("PONG").into()
+        );
+
+        // redis.call passes a command's error on, redis.pcall hands it over
+        assert_eq!(
+            run_args(&mut t, &["eval", "return redis.call('incr', 'list')", "0"]),
+            CommandError::WrongType.into()
+        );
+        assert_eq!(
+            run_args(
+                &mut t,
+                &[
+                    "eval",
+                    "local r = redis.pcall('incr', 'list') return r.err",
+                    "0"
+                ]
+            ),
+            bulk(&CommandError::WrongType.to_string())
+        );
+        assert_eq!(
+            run_args(&mut t, &["eval", "return redis.call('multi')", "0"]),
+            CommandError::err("This Redis command is not allowed from script").into()
+        );
+        assert_eq!(
+            run_args(&mut t, &["eval", "return redis.call('nosuchcommand')", "0"]),
+            CommandError::UnknownCommand("nosuchcommand".to_string(), String::new()).into()
+        );
+        assert_eq!(
+            run_args(&mut t, &["eval", "return redis.call({})", "0"]),
+            CommandError::err("Lua redis lib command arguments must be strings or integers").into()
+        );
+
+        // a SELECT inside a script stays there
+        assert_eq!(
+            run_args(
+                &mut t,
+                &[
+                    "eval",
+                    "redis.call('select', 1) return redis.call('set', 'k', 'db1')",
+                    "0"
+                ]
+            ),
+            ok()
+        );
+        assert_eq!(t.run("get k"), bulk("v"));
+        assert_eq!(t.client.db, 0);
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        let mut t = TestContext::default();
+        assert_eq!(
+            t.run("eval return 2"),
+            CommandError::err("Number of keys can't be greater than number of args").into()
+        );
+        assert_eq!(
+            t.run("eval return -1"),
+            CommandError::err("Number of keys can't be negative").into()
+        );
+        let RespFrame::Error(e) = run_args(&mut t, &["eval", "return +", "0"]) else {
+            panic!("compiled");
+        };
+        assert!(e.starts_with("ERR Error compiling script (new function): user_script:1:"));
+        let RespFrame::Error(e) = run_args(&mut t, &["eval", "return x", "0"]) else {
+            panic!("ran");
+        };
+        assert!(e.contains("Script attempted to access nonexistent global variable 'x'"));
+        let RespFrame::Error(e) = run_args(&mut t, &["eval", "y = 1", "0"]) else {
+            panic!("ran");
+        };
+        assert!(e.contains("Script attempted to create global variable 'y'"));
+        let RespFrame::Error(e) = run_args(&mut t, &["eval", "error('boom')", "0"]) else {
+            panic!("ran");
+        };
+        assert!(e.starts_with("ERR Error running script (call to f_"));
+        assert!(e.ends_with("user_script:1: boom"));
+    }
+
+    #[test]
+    fn test_eval_cannot_change_the_next_one() {
+        let mut t = TestContext::default();
+        for script in [
+            "string.upper = function() return 'pwned' end",
+            "rawset(_G, 'tonumber', function() return 0 end)",
+            "setmetatable(_G, nil)",
+            "rawset(string, 'upper', nil)",
+            "redis.sha1hex = nil",
+            "table.insert(_G, 'x')",
+        ] {
+            let RespFrame::Error(e) = run_args(&mut t, &["eval", script, "0"]) else {
+                panic!("{} ran", script);
+            };
+            assert!(e.starts_with("ERR Error running script"), "{:?}", e);
+        }
+        assert_eq!(
+            run_args(
+                &mut t,
+                &[
+                    "eval",
+                    "return {string.upper('a'), tonumber('7'), rawget(_G, 1)}",
+                    "0"
+                ]
+            ),
+            array(vec![bulk("A"), int(7)])
+        );
+        assert_eq!(
+            run_args(&mut t, &["eval", "return redis.sha1hex('')", "0"]),
+            bulk("da39a3ee5e6b4b0d3255bfef95601890afd80709")
+        );
+        let RespFrame::Error(e) = run_args(&mut t, &["eval", "return x", "0"]) else {
+            panic!("ran");
+        };
+        assert!(e.contains("nonexistent global variable 'x'"), "{:?}", e);
+    }
+
+    #[test]
+    fn test_eval_refuses_bytecode() {
+        let mut t = TestContext::default();
+        let bytecode = Lua::new()
+            .load("return 99")
+            .into_function()
+            .unwrap()
+            .dump(false);
+        let RespFrame::Error(e) = run_bytes(&mut t, &[b"eval", &bytecode, b"0"]) else {
+            panic!("bytecode ran");
+        };
+        assert!(e.starts_with("ERR Error compiling script (new function):"));
+        // nor can a script compile any at run time
+        for script in [
+            "return loadstring(string.dump(function() return 42 end))()",
+            "return load(function() return nil end)",
+            "return loadstring('return 42')()",
+        ] {
+            let RespFrame::Error(e) = run_args(&mut t, &["eval", script, "0"]) else {
+                panic!("{} ran", script);
+            };
+            assert!(e.contains("nonexistent global variable"), "{:?}", e);
+        }
+        let RespFrame::Error(e) =
+            run_args(&mut t, &["eval", "return string.dump(function() end)", "0"])
+        else {
+            panic!("string.dump ran");
+        };
+        assert!(e.contains("attempt to call field 'dump'"), "{:?}", e);
+    }
+
+    #[test]
+    fn test_evalsha_and_script() {
+        let mut t = TestContext::default();
+        let sha = "e0e1f9fabfc9d4800c877a703b823ac0578ff8db";
+        assert_eq!(
+            t.run(&format!("evalsha {} 0", sha)),
+            CommandError::Reply("NOSCRIPT No matching script. Please use EVAL.".to_string()).into()
+        );
+        assert_eq!(run_args(&mut t, &["script", "load", "return 1"]), bulk(sha));
+        assert_eq!(t.run(&format!("evalsha {} 0", sha.to_uppercase())), int(1));
+        assert_eq!(t.run(&format!("evalsha_ro {} 0", sha)), int(1));
+        // EVAL caches what it runs too
+        run_args(&mut t, &["eval", "return 2", "0"]);
+        assert_eq!(
+            t.run(&format!("script exists {} {} nope", sha, sha1("return 2"))),
+            array(vec![int(1), int(1), int(0)])
+        );
+        assert_eq!(t.run("script flush async"), ok());
+        assert_eq!(
+            t.run(&format!("script exists {}", sha)),
+            array(vec![int(0)])
+        );
+        assert_eq!(t.run("script flush lazy"), CommandError::Syntax.into());
+        assert_eq!(
+            t.run("script kill"),
+            CommandError::Reply("NOTBUSY No scripts in execution right now.".to_string()).into()
+        );
+        assert_eq!(
+            t.run("script frob"),
+            CommandError::UnknownSubcommand("frob".to_string(), "SCRIPT".to_string()).into()
+        );
+    }
+
+    #[test]
+    fn test_eval_ro() {
+        let mut t = TestContext::default();
+        t.run("set k v");
+        assert_eq!(
+            run_args(
+                &mut t,
+                &["eval_ro", "return redis.call('get', KEYS[1])", "1", "k"]
+            ),
+            bulk("v")
+        );
+        assert_eq!(
+            run_args(
+                &mut t,
+                &["eval_ro", "return redis.call('del', KEYS[1])", "1", "k"]
+            ),
+            CommandError::err("Write commands are not allowed from read-only scripts.").into()
+        );
+        assert_eq!(t.run("exists k"), int(1));
+        // PFCOUNT writes too, refreshing the count cached in the key
+        t.run("pfadd hll a b");
+        assert_eq!(
+            run_args(
+                &mut t,
+                &[
+                    "eval_ro",
+                    "return redis.call('pfcount', KEYS[1])",
+                    "1",
+                    "hll"
+                ]
+            ),
+            CommandError::err("Write commands are not allowed from read-only scripts.").into()
+        );
+    }
+
+    #[test]
+    fn test_eval_in_transaction() {
+        let mut t = TestContext::default();
+        t.run("multi");
+        run_args(
+            &mut t,
+            &["eval", "return redis.call('incr', KEYS[1])", "1", "n"],
+        );
+        // a blocking command in a script answers at once
+        run_args(&mut t, &["eval", "return redis.call('blpop', 'l', 0)", "0"]);
+        assert_eq!(t.run("exec"), array(vec![int(1), nil()]));
+        assert!(t.backend.blocking.is_empty());
+    }
+
+    #[test]
+    fn test_script_kill() {
+        let backend = crate::Backend::new(
+            crate::DEFAULT_DATABASES,
+            std::time::Duration::from_millis(50),
+        );
+        let command = |args: &[&str]| {
+            let args = args.iter().map(|arg| BulkString::new(*arg).into());
+            Command::try_from(RespArray::new(args.collect::<Vec<RespFrame>>())).unwrap()
+        };
+        let script = {
+            let backend = backend.clone();
+            let cmd = command(&["eval", "while true do end", "0"]);
+            std::thread::spawn(move || run(cmd, &mut crate::Client::new(), &mut backend.lock()))
+        };
+        // nothing is refused until the script has run past the threshold
+        let ping = command(&["ping"]);
+        let busy = loop {
+            if let Some(reply) = busy_reply(&ping, backend.running_script()) {
+                break reply;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        };
+        assert!(matches!(&busy, RespFrame::Error(e) if e.starts_with("BUSY ")));
+        let kill = command(&["script", "kill"]);
+        assert_eq!(busy_reply(&kill, backend.running_script()), Some(ok()));
+        match script.join().unwrap() {
+            Reply::Frame(RespFrame::Error(e)) => {
+                assert!(
+                    e.contains("Script killed by user with SCRIPT KILL"),
+                    "{:?}",
+                    e
+                )
+            }
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+        assert_eq!(busy_reply(&ping, backend.running_script()), None);
+        assert!(matches!(
+            run(command(&["script", "kill"]), &mut crate::Client::new(), &mut backend.lock()),
+            Reply::Frame(RespFrame::Error(e)) if e.starts_with("NOTBUSY ")
+        ));
+    }
+
+    fn sha1(body: &str) -> String {
+        crate::backend::sha1_hex(body.as_bytes())
+    }
+}
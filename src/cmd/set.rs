@@ -0,0 +1,728 @@
+use crate::backend::{Db, DictSet, Value};
+use crate::{BulkString, RespArray, RespFrame, RespSet};
+
+use super::{
+    nil, parse_numkeys, random_sample, scan_reply, CommandArgs, CommandError, CommandExecutor,
+    CommandSpec, Context, ScanArgs,
+};
+
+// SADD key member [member ...]
+#[derive(Debug)]
+pub struct SAdd {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+}
+
+// SREM key member [member ...]
+#[derive(Debug)]
+pub struct SRem {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+}
+
+// SISMEMBER key member
+#[derive(Debug)]
+pub struct SIsMember {
+    key: Vec<u8>,
+    member: Vec<u8>,
+}
+
+// SMISMEMBER key member [member ...]
+#[derive(Debug)]
+pub struct SMIsMember {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+}
+
+// SMEMBERS key
+#[derive(Debug)]
+pub struct SMembers {
+    key: Vec<u8>,
+}
+
+// SCARD key
+#[derive(Debug)]
+pub struct SCard {
+    key: Vec<u8>,
+}
+
+// SPOP key [count]
+#[derive(Debug)]
+pub struct SPop {
+    key: Vec<u8>,
+    count: Option<usize>,
+}
+
+// SRANDMEMBER key [count]
+#[derive(Debug)]
+pub struct SRandMember {
+    key: Vec<u8>,
+    count: Option<i64>,
+}
+
+// SMOVE source destination member
+#[derive(Debug)]
+pub struct SMove {
+    source: Vec<u8>,
+    destination: Vec<u8>,
+    member: Vec<u8>,
+}
+
+// SINTERCARD numkeys key [key ...] [LIMIT limit]
+#[derive(Debug)]
+pub struct SInterCard {
+    keys: Vec<Vec<u8>>,
+    limit: usize,
+}
+
+// SSCAN key cursor [MATCH pattern] [COUNT count]
+#[derive(Debug)]
+pub struct SScan {
+    key: Vec<u8>,
+    args: ScanArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+// the sets stored at keys, type checked up front; None for a missing key
+fn load_sets<'a>(
+    db: &'a mut Db,
+    keys: &[Vec<u8>],
+) -> Result<Vec<Option<&'a DictSet<Vec<u8>>>>, CommandError> {
+    for key in keys {
+        db.get_set(key)?;
+    }
+    let db = &*db;
+    Ok(keys
+        .iter()
+        .map(|key| match db.peek(key) {
+            Some(Value::Set(set)) => Some(set),
+            _ => None,
+        })
+        .collect())
+}
+
+// the members every set has in common, walking the smallest set and probing
+// the others; a missing key makes the intersection empty
+fn intersect<'a>(
+    sets: Vec<Option<&'a DictSet<Vec<u8>>>>,
+) -> impl Iterator<Item = &'a Vec<u8>> + 'a {
+    let mut sets = sets
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default();
+    sets.sort_by_key(|set| set.len());
+    let smallest = (!sets.is_empty()).then(|| sets.remove(0));
+    smallest
+        .into_iter()
+        .flatten()
+        .filter(move |member| sets.iter().all(|set| set.contains(*member)))
+}
+
+// SINTER, SUNION and SDIFF over the sets at keys
+pub(crate) fn set_op(
+    db: &mut Db,
+    keys: &[Vec<u8>],
+    op: SetOp,
+) -> Result<DictSet<Vec<u8>>, CommandError> {
+    let sets = load_sets(db, keys)?;
+    Ok(match op {
+        SetOp::Inter => intersect(sets).cloned().collect(),
+        SetOp::Union => sets.into_iter().flatten().flatten().cloned().collect(),
+        SetOp::Diff => {
+            let (first, rest) = sets.split_first().ok_or(CommandError::Syntax)?;
+            first
+                .iter()
+                .flat_map(|set| set.iter())
+                .filter(|member| !rest.iter().flatten().any(|set| set.contains(*member)))
+                .cloned()
+                .collect()
+        }
+    })
+}
+
+// a set of members: a RESP3 set, or a plain array on RESP2
+pub(crate) fn set_reply(resp3: bool, members: impl IntoIterator<Item = Vec<u8>>) -> RespFrame {
+    let members = members
+        .into_iter()
+        .map(|member| BulkString::new(member).into())
+        .collect::<Vec<RespFrame>>();
+    if resp3 {
+        RespSet::new(members).into()
+    } else {
+        RespArray::new(members).into()
+    }
+}
+
+impl CommandSpec for SAdd {
+    const NAME: &'static str = "sadd";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SAdd {
+            key: args.next_bytes()?,
+            members: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for SAdd {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let set = ctx.db().get_or_create_set(&self.key)?;
+        let added = self
+            .members
+            .into_iter()
+            .filter(|member| set.insert(member.clone()))
+            .count();
+        if added > 0 {
+            ctx.db().touch(&self.key);
+        }
+        Ok(RespFrame::Integer(added as i64))
+    }
+}
+
+impl CommandSpec for SRem {
+    const NAME: &'static str = "srem";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SRem {
+            key: args.next_bytes()?,
+            members: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for SRem {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let set = match db.get_set_mut(&self.key)? {
+            Some(set) => set,
+            None => return Ok(RespFrame::Integer(0)),
+        };
+        let removed = self
+            .members
+            .iter()
+            .filter(|member| set.remove(*member))
+            .count();
+        if removed > 0 {
+            db.touch(&self.key);
+            db.remove_if_empty(&self.key);
+        }
+        Ok(RespFrame::Integer(removed as i64))
+    }
+}
+
+impl CommandSpec for SIsMember {
+    const NAME: &'static str = "sismember";
+    const ARITY: i64 = 3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SIsMember {
+            key: args.next_bytes()?,
+            member: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for SIsMember {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let set = ctx.db().get_set(&self.key)?;
+        let found = set.is_some_and(|set| set.contains(&self.member));
+        Ok(RespFrame::Integer(found as i64))
+    }
+}
+
+impl CommandSpec for SMIsMember {
+    const NAME: &'static str = "smismember";
+    const ARITY: i64 = -3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SMIsMember {
+            key: args.next_bytes()?,
+            members: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for SMIsMember {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let set = ctx.db().get_set(&self.key)?;
+        let found = self
+            .members
+            .iter()
+            .map(|member| RespFrame::Integer(set.is_some_and(|set| set.contains(member)) as i64))
+            .collect::<Vec<_>>();
+        Ok(RespArray::new(found).into())
+    }
+}
+
+impl CommandSpec for SMembers {
+    const NAME: &'static str = "smembers";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SMembers {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for SMembers {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let members = ctx
+            .db()
+            .get_set(&self.key)?
+            .map(|set| set.iter().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        Ok(set_reply(resp3, members))
+    }
+}
+
+impl CommandSpec for SCard {
+    const NAME: &'static str = "scard";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SCard {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for SCard {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let len = ctx.db().get_set(&self.key)?.map_or(0, |set| set.len());
+        Ok(RespFrame::Integer(len as i64))
+    }
+}
+
+impl CommandSpec for SPop {
+    const NAME: &'static str = "spop";
+    const ARITY: i64 = -2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let count = match args.is_empty() {
+            true => None,
+            false => {
+                let count = args.next_i64()?;
+                if count < 0 {
+                    return Err(CommandError::err("value is out of range, must be positive"));
+                }
+                Some(count as usize)
+            }
+        };
+        if !args.is_empty() {
+            return Err(CommandError::Syntax);
+        }
+        Ok(SPop { key, count })
+    }
+}
+
+impl CommandExecutor for SPop {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let set = match db.get_set_mut(&self.key)? {
+            Some(set) => set,
+            None if self.count.is_some() => return Ok(RespArray::new(vec![]).into()),
+            None => return Ok(nil()),
+        };
+        let members = set.iter().cloned().collect::<Vec<_>>();
+        let popped = random_sample(&members, self.count.unwrap_or(1) as i64)?;
+        for member in &popped {
+            set.remove(member);
+        }
+        if !popped.is_empty() {
+            db.touch(&self.key);
+            db.remove_if_empty(&self.key);
+        }
+        Ok(match self.count {
+            Some(_) => RespArray::new(
+                popped
+                    .into_iter()
+                    .map(|member| BulkString::new(member).into())
+                    .collect::<Vec<RespFrame>>(),
+            )
+            .into(),
+            None => popped
+                .into_iter()
+                .next()
+                .map_or_else(nil, |member| BulkString::new(member).into()),
+        })
+    }
+}
+
+impl CommandSpec for SRandMember {
+    const NAME: &'static str = "srandmember";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let count = match args.is_empty() {
+            true => None,
+            false => Some(args.next_i64()?),
+        };
+        if !args.is_empty() {
+            return Err(CommandError::Syntax);
+        }
+        Ok(SRandMember { key, count })
+    }
+}
+
+impl CommandExecutor for SRandMember {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let members = ctx
+            .db()
+            .get_set(&self.key)?
+            .map(|set| set.iter().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let sample = random_sample(&members, self.count.unwrap_or(1))?;
+        Ok(match self.count {
+            Some(_) => RespArray::new(
+                sample
+                    .into_iter()
+                    .map(|member| BulkString::new(member).into())
+                    .collect::<Vec<RespFrame>>(),
+            )
+            .into(),
+            None => sample
+                .into_iter()
+                .next()
+                .map_or_else(nil, |member| BulkString::new(member).into()),
+        })
+    }
+}
+
+impl CommandSpec for SMove {
+    const NAME: &'static str = "smove";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SMove {
+            source: args.next_bytes()?,
+            destination: args.next_bytes()?,
+            member: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for SMove {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        // check both types before touching anything
+        let found = db
+            .get_set(&self.source)?
+            .is_some_and(|set| set.contains(&self.member));
+        db.get_set(&self.destination)?;
+        if !found {
+            return Ok(RespFrame::Integer(0));
+        }
+        if self.source != self.destination {
+            if let Some(set) = db.get_set_mut(&self.source)? {
+                set.remove(&self.member);
+            }
+            db.touch(&self.source);
+            db.remove_if_empty(&self.source);
+            db.get_or_create_set(&self.destination)?.insert(self.member);
+            db.touch(&self.destination);
+        }
+        Ok(RespFrame::Integer(1))
+    }
+}
+
+// SINTER, SUNION and SDIFF: reply with the result
+macro_rules! set_op_command {
+    ($ty:ident, $name:literal, $op:expr) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            keys: Vec<Vec<u8>>,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -2;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                Ok($ty { keys: args.rest() })
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                let resp3 = ctx.client.is_resp3();
+                let result = set_op(ctx.db(), &self.keys, $op)?;
+                Ok(set_reply(resp3, result))
+            }
+        }
+    };
+}
+
+// SINTERSTORE, SUNIONSTORE and SDIFFSTORE: store the result at destination,
+// deleting it when the result is empty, and reply with its size
+macro_rules! set_store_command {
+    ($ty:ident, $name:literal, $op:expr) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            destination: Vec<u8>,
+            keys: Vec<Vec<u8>>,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -3;
+            const WRITE: bool = true;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                Ok($ty {
+                    destination: args.next_bytes()?,
+                    keys: args.rest(),
+                })
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                let db = ctx.db();
+                let result = set_op(db, &self.keys, $op)?;
+                let len = result.len();
+                if result.is_empty() {
+                    db.remove(&self.destination);
+                } else {
+                    db.insert(self.destination, Value::Set(result));
+                }
+                Ok(RespFrame::Integer(len as i64))
+            }
+        }
+    };
+}
+
+set_op_command!(SInter, "sinter", SetOp::Inter);
+set_op_command!(SUnion, "sunion", SetOp::Union);
+set_op_command!(SDiff, "sdiff", SetOp::Diff);
+set_store_command!(SInterStore, "sinterstore", SetOp::Inter);
+set_store_command!(SUnionStore, "sunionstore", SetOp::Union);
+set_store_command!(SDiffStore, "sdiffstore", SetOp::Diff);
+
+impl CommandSpec for SInterCard {
+    const NAME: &'static str = "sintercard";
+    const ARITY: i64 = -3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let keys = parse_numkeys(args).map_err(|e| match e {
+            CommandError::Syntax => {
+                CommandError::err("Number of keys can't be greater than number of args")
+            }
+            e => e,
+        })?;
+        let mut limit = 0;
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                "LIMIT" => {
+                    let n = args
+                        .next_i64()
+                        .map_err(|_| CommandError::err("LIMIT can't be negative"))?;
+                    if n < 0 {
+                        return Err(CommandError::err("LIMIT can't be negative"));
+                    }
+                    limit = n as usize;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok(SInterCard { keys, limit })
+    }
+}
+
+impl CommandExecutor for SInterCard {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let sets = load_sets(ctx.db(), &self.keys)?;
+        // LIMIT 0 means no limit; otherwise stop counting once it is reached
+        let limit = if self.limit == 0 {
+            usize::MAX
+        } else {
+            self.limit
+        };
+        let count = intersect(sets).take(limit).count();
+        Ok(RespFrame::Integer(count as i64))
+    }
+}
+
+impl CommandSpec for SScan {
+    const NAME: &'static str = "sscan";
+    const ARITY: i64 = -3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SScan {
+            key: args.next_bytes()?,
+            args: ScanArgs::parse(args, false)?,
+        })
+    }
+}
+
+impl CommandExecutor for SScan {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let set = match ctx.db().get_set(&self.key)? {
+            Some(set) => set,
+            None => return Ok(scan_reply(0, Vec::new())),
+        };
+        let args = &self.args;
+        let (cursor, found) = args.walk(|cursor, found| {
+            set.scan(cursor, |member| {
+                if args.matches(member) {
+                    found.push(member.clone());
+                }
+            })
+        });
+        Ok(scan_reply(cursor, found))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Protocol;
+    use crate::cmd::TestContext;
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    // the members of a set reply, sorted, since set order is unspecified
+    fn members(frame: RespFrame) -> Vec<String> {
+        let items: &[RespFrame] = match &frame {
+            RespFrame::Array(arr) => arr,
+            RespFrame::Set(set) => set,
+            frame => panic!("not a set reply: {:?}", frame),
+        };
+        let mut members = items
+            .iter()
+            .map(|item| match item {
+                RespFrame::BulkString(s) => String::from_utf8_lossy(s).into_owned(),
+                item => panic!("not a bulk string: {:?}", item),
+            })
+            .collect::<Vec<_>>();
+        members.sort();
+        members
+    }
+
+    #[test]
+    fn test_sadd_srem() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("sadd s a b c a"), RespFrame::Integer(3));
+        assert_eq!(t.run("sadd s c d"), RespFrame::Integer(1));
+        assert_eq!(t.run("scard s"), RespFrame::Integer(4));
+        assert_eq!(t.run("sismember s a"), RespFrame::Integer(1));
+        assert_eq!(
+            t.run("smismember s a x d"),
+            RespArray::new(vec![
+                RespFrame::Integer(1),
+                RespFrame::Integer(0),
+                RespFrame::Integer(1)
+            ])
+            .into()
+        );
+        assert_eq!(t.run("srem s a x"), RespFrame::Integer(1));
+        assert_eq!(members(t.run("smembers s")), ["b", "c", "d"]);
+        assert_eq!(t.run("srem s b c d"), RespFrame::Integer(3));
+        assert_eq!(t.run("scard s"), RespFrame::Integer(0));
+        assert!(t.backend.dbs[0].is_empty());
+
+        t.run("set str v");
+        assert_eq!(t.run("sadd str a"), CommandError::WrongType.into());
+    }
+
+    #[test]
+    fn test_smembers_by_protocol() {
+        let mut t = TestContext::default();
+        t.run("sadd s a");
+        assert_eq!(t.run("smembers s"), RespArray::new(vec![bulk("a")]).into());
+        t.client.protocol = Protocol::Resp3;
+        assert_eq!(t.run("smembers s"), RespSet::new(vec![bulk("a")]).into());
+    }
+
+    #[test]
+    fn test_spop_srandmember() {
+        let mut t = TestContext::default();
+        t.run("sadd s a b c");
+        assert_eq!(members(t.run("srandmember s 5")), ["a", "b", "c"]);
+        match t.run("srandmember s -5") {
+            RespFrame::Array(arr) => assert_eq!(arr.len(), 5),
+            frame => panic!("unexpected reply {:?}", frame),
+        }
+        assert_eq!(members(t.run("spop s 2")).len(), 2);
+        assert!(matches!(t.run("spop s"), RespFrame::BulkString(_)));
+        assert_eq!(t.run("spop s"), nil());
+        assert_eq!(t.run("spop s 1"), RespArray::new(vec![]).into());
+        assert_eq!(
+            t.run("spop s -1"),
+            CommandError::err("value is out of range, must be positive").into()
+        );
+        assert!(t.backend.dbs[0].is_empty());
+    }
+
+    #[test]
+    fn test_smove() {
+        let mut t = TestContext::default();
+        t.run("sadd src a b");
+        assert_eq!(t.run("smove src dst a"), RespFrame::Integer(1));
+        assert_eq!(t.run("smove src dst x"), RespFrame::Integer(0));
+        assert_eq!(t.run("smove src src b"), RespFrame::Integer(1));
+        assert_eq!(members(t.run("smembers dst")), ["a"]);
+        t.run("set str v");
+        assert_eq!(t.run("smove src str b"), CommandError::WrongType.into());
+        assert_eq!(members(t.run("smembers src")), ["b"]);
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let mut t = TestContext::default();
+        t.run("sadd a 1 2 3 4");
+        t.run("sadd b 3 4 5");
+        t.run("sadd c 4 6");
+        assert_eq!(members(t.run("sinter a b c")), ["4"]);
+        assert_eq!(members(t.run("sinter a missing")), Vec::<String>::new());
+        assert_eq!(
+            members(t.run("sunion a b c")),
+            ["1", "2", "3", "4", "5", "6"]
+        );
+        assert_eq!(members(t.run("sdiff a b c")), ["1", "2"]);
+
+        assert_eq!(t.run("sinterstore dst a b"), RespFrame::Integer(2));
+        assert_eq!(members(t.run("smembers dst")), ["3", "4"]);
+        assert_eq!(t.run("sdiffstore dst a a"), RespFrame::Integer(0));
+        assert_eq!(t.run("scard dst"), RespFrame::Integer(0));
+        assert_eq!(t.run("sunionstore dst b c"), RespFrame::Integer(4));
+
+        t.run("set str v");
+        assert_eq!(t.run("sunion a str"), CommandError::WrongType.into());
+    }
+
+    #[test]
+    fn test_sintercard() {
+        let mut t = TestContext::default();
+        t.run("sadd a 1 2 3 4");
+        t.run("sadd b 1 2 3 5");
+        assert_eq!(t.run("sintercard 2 a b"), RespFrame::Integer(3));
+        assert_eq!(t.run("sintercard 2 a b limit 2"), RespFrame::Integer(2));
+        assert_eq!(t.run("sintercard 2 a b limit 0"), RespFrame::Integer(3));
+        assert_eq!(
+            t.run("sintercard 3 a b"),
+            CommandError::err("Number of keys can't be greater than number of args").into()
+        );
+        assert_eq!(
+            t.run("sintercard 2 a b limit -1"),
+            CommandError::err("LIMIT can't be negative").into()
+        );
+    }
+}
@@ -0,0 +1,810 @@
+use std::time::Duration;
+
+use crate::backend::{now_ms, Db, Stream, StreamFields, StreamId, TrimStrategy};
+use crate::{BulkString, RespArray, RespFrame, RespMap, RespNullArray};
+
+use super::{
+    nil, BlockingCommand, CommandArgs, CommandError, CommandExecutor, CommandSpec, Context,
+};
+
+// approximate trims stop after this many entries unless LIMIT says otherwise,
+// 100 times the entries of a chunk like Redis
+const DEFAULT_TRIM_LIMIT: usize = 100 * 100;
+
+type Keys = Vec<Vec<u8>>;
+
+// XADD key [NOMKSTREAM] [MAXLEN | MINID [= | ~] threshold [LIMIT count]] * | id field value [field value ...]
+#[derive(Debug)]
+pub struct XAdd {
+    key: Vec<u8>,
+    no_mkstream: bool,
+    trim: Option<Trim>,
+    id: AddId,
+    fields: StreamFields,
+}
+
+// the id argument of XADD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AddId {
+    // *
+    Auto,
+    // ms-*
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+// MAXLEN | MINID [= | ~] threshold [LIMIT count]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Trim {
+    strategy: TrimStrategy,
+    approx: bool,
+    limit: usize,
+}
+
+// XRANGE key start end [COUNT count]
+#[derive(Debug)]
+pub struct XRange {
+    key: Vec<u8>,
+    start: StreamId,
+    end: StreamId,
+    count: Option<usize>,
+}
+
+// XREVRANGE key end start [COUNT count]
+#[derive(Debug)]
+pub struct XRevRange {
+    key: Vec<u8>,
+    start: StreamId,
+    end: StreamId,
+    count: Option<usize>,
+}
+
+// XLEN key
+#[derive(Debug)]
+pub struct XLen {
+    key: Vec<u8>,
+}
+
+// XDEL key id [id ...]
+#[derive(Debug)]
+pub struct XDel {
+    key: Vec<u8>,
+    ids: Vec<StreamId>,
+}
+
+// XTRIM key MAXLEN | MINID [= | ~] threshold [LIMIT count]
+#[derive(Debug)]
+pub struct XTrim {
+    key: Vec<u8>,
+    trim: Trim,
+}
+
+// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+#[derive(Debug)]
+pub struct XRead {
+    count: Option<usize>,
+    // Some when BLOCK was given, holding None to wait forever
+    block: Option<Option<Duration>>,
+    keys: Vec<Vec<u8>>,
+    from: Vec<ReadFrom>,
+    // from, resolved against the streams when the command runs
+    after: Vec<StreamId>,
+}
+
+// where XREAD starts reading: after an id, after the current last id ($),
+// or from the last entry (+)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReadFrom {
+    After(StreamId),
+    Last,
+    LastEntry,
+}
+
+pub(crate) fn invalid_id() -> CommandError {
+    CommandError::err("Invalid stream ID specified as stream command argument")
+}
+
+// ms-seq, or just ms with the sequence defaulting to default_seq
+pub(crate) fn parse_id(buf: &[u8], default_seq: u64) -> Result<StreamId, CommandError> {
+    let s = std::str::from_utf8(buf).map_err(|_| invalid_id())?;
+    let (ms, seq) = match s.split_once('-') {
+        Some((ms, seq)) => (ms, Some(seq)),
+        None => (s, None),
+    };
+    let number = |s: &str| -> Result<u64, CommandError> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid_id());
+        }
+        s.parse().map_err(|_| invalid_id())
+    };
+    Ok(StreamId::new(
+        number(ms)?,
+        seq.map(number).transpose()?.unwrap_or(default_seq),
+    ))
+}
+
+// one end of an XRANGE interval: - and + for the open ends, a partial id
+// covering its whole millisecond, or an exclusive id prefixed by (
+pub(crate) fn parse_range_id(buf: &[u8], is_start: bool) -> Result<StreamId, CommandError> {
+    match buf {
+        b"-" => return Ok(StreamId::MIN),
+        b"+" => return Ok(StreamId::MAX),
+        _ => {}
+    }
+    let default_seq = if is_start { 0 } else { u64::MAX };
+    match buf.strip_prefix(b"(") {
+        Some(id) => {
+            let id = parse_id(id, default_seq)?;
+            match is_start {
+                true => id
+                    .next()
+                    .ok_or_else(|| CommandError::err("invalid start ID for the interval")),
+                false => id
+                    .prev()
+                    .ok_or_else(|| CommandError::err("invalid end ID for the interval")),
+            }
+        }
+        None => parse_id(buf, default_seq),
+    }
+}
+
+// an entry as [id, [field, value, ...]]
+pub(crate) fn entry_reply(id: StreamId, fields: StreamFields) -> RespFrame {
+    let fields = fields
+        .into_iter()
+        .flat_map(|(field, value)| [BulkString::new(field).into(), BulkString::new(value).into()])
+        .collect::<Vec<RespFrame>>();
+    RespArray::new(vec![
+        BulkString::new(id.to_string()).into(),
+        RespArray::new(fields).into(),
+    ])
+    .into()
+}
+
+pub(crate) fn entries_reply(entries: Vec<(StreamId, StreamFields)>) -> RespFrame {
+    RespArray::new(
+        entries
+            .into_iter()
+            .map(|(id, fields)| entry_reply(id, fields))
+            .collect::<Vec<_>>(),
+    )
+    .into()
+}
+
+// the COUNT count option of the range and read commands; 0 or less reads nothing
+fn parse_count(args: &mut CommandArgs) -> Result<usize, CommandError> {
+    Ok(args.next_i64()?.max(0) as usize)
+}
+
+impl Trim {
+    // parse the trim options, the strategy keyword having been consumed already
+    fn parse(strategy: &str, args: &mut CommandArgs) -> Result<Trim, CommandError> {
+        let approx = match args.peek_keyword().as_deref() {
+            Some("~") => true,
+            Some("=") => false,
+            _ => {
+                return Self::parse_threshold(strategy, args, false);
+            }
+        };
+        args.next_bytes()?;
+        Self::parse_threshold(strategy, args, approx)
+    }
+
+    fn parse_threshold(
+        strategy: &str,
+        args: &mut CommandArgs,
+        approx: bool,
+    ) -> Result<Trim, CommandError> {
+        let strategy = match strategy {
+            "MAXLEN" => {
+                let max = args.next_i64()?;
+                if max < 0 {
+                    return Err(CommandError::err("The MAXLEN argument must be >= 0."));
+                }
+                TrimStrategy::MaxLen(max as usize)
+            }
+            _ => TrimStrategy::MinId(parse_id(&args.next_bytes()?, 0)?),
+        };
+        let mut limit = if approx { DEFAULT_TRIM_LIMIT } else { 0 };
+        if args.peek_keyword().as_deref() == Some("LIMIT") {
+            args.next_bytes()?;
+            let n = args.next_i64()?;
+            if n < 0 {
+                return Err(CommandError::err("The LIMIT argument must be >= 0."));
+            }
+            if !approx {
+                return Err(CommandError::err(
+                    "syntax error, LIMIT cannot be used without the special ~ option",
+                ));
+            }
+            limit = n as usize;
+        }
+        Ok(Trim {
+            strategy,
+            approx,
+            limit,
+        })
+    }
+
+    fn apply(&self, stream: &mut Stream) -> usize {
+        stream.trim(self.strategy, self.approx, self.limit)
+    }
+}
+
+impl AddId {
+    fn parse(buf: &[u8]) -> Result<AddId, CommandError> {
+        if buf == b"*" {
+            return Ok(AddId::Auto);
+        }
+        if let Some(ms) = buf.strip_suffix(b"-*") {
+            return Ok(AddId::AutoSeq(parse_id(ms, 0)?.ms));
+        }
+        Ok(AddId::Explicit(parse_id(buf, 0)?))
+    }
+
+    // the id of the new entry, which has to come after everything in the stream
+    fn resolve(self, stream: &Stream) -> Result<StreamId, CommandError> {
+        let last = stream.last_id();
+        let too_small = || {
+            CommandError::err(
+                "The ID specified in XADD is equal or smaller than the target stream top item",
+            )
+        };
+        let id = match self {
+            AddId::Auto => stream.next_id(now_ms()).ok_or_else(too_small)?,
+            AddId::AutoSeq(ms) if ms < last.ms => return Err(too_small()),
+            AddId::AutoSeq(ms) if ms == last.ms && last != StreamId::MIN => last
+                .seq
+                .checked_add(1)
+                .map(|seq| StreamId::new(ms, seq))
+                .ok_or_else(too_small)?,
+            // 0-* starts at 0-1, the first valid id
+            AddId::AutoSeq(ms) => StreamId::new(ms, if ms == 0 { 1 } else { 0 }),
+            AddId::Explicit(id) => id,
+        };
+        if id == StreamId::MIN {
+            return Err(CommandError::err(
+                "The ID specified in XADD must be greater than 0-0",
+            ));
+        }
+        if id <= last {
+            return Err(too_small());
+        }
+        Ok(id)
+    }
+}
+
+impl CommandSpec for XAdd {
+    const NAME: &'static str = "xadd";
+    const ARITY: i64 = -5;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let mut no_mkstream = false;
+        let mut trim = None;
+        while let Some(opt) = args.peek_keyword() {
+            match opt.as_str() {
+                "NOMKSTREAM" => {
+                    args.next_bytes()?;
+                    no_mkstream = true;
+                }
+                "MAXLEN" | "MINID" => {
+                    args.next_bytes()?;
+                    trim = Some(Trim::parse(&opt, args)?);
+                }
+                _ => break,
+            }
+        }
+        let id = AddId::parse(&args.next_bytes()?)?;
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err(CommandError::WrongArity(Self::NAME.to_string()));
+        }
+        let mut fields = Vec::with_capacity(args.len() / 2);
+        while !args.is_empty() {
+            fields.push((args.next_bytes()?, args.next_bytes()?));
+        }
+        Ok(XAdd {
+            key,
+            no_mkstream,
+            trim,
+            id,
+            fields,
+        })
+    }
+}
+
+impl CommandExecutor for XAdd {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        if self.no_mkstream && db.get_stream(&self.key)?.is_none() {
+            return Ok(nil());
+        }
+        // check the id before creating the stream, so a bad one leaves no empty key
+        let id = match db.get_stream(&self.key)? {
+            Some(stream) => self.id.resolve(stream)?,
+            None => self.id.resolve(&Stream::new())?,
+        };
+        let stream = db.get_or_create_stream(&self.key)?;
+        stream.add(id, self.fields);
+        if let Some(trim) = self.trim {
+            trim.apply(stream);
+        }
+        db.touch(&self.key);
+        Ok(BulkString::new(id.to_string()).into())
+    }
+}
+
+// XRANGE and XREVRANGE
+fn stream_range(
+    db: &mut Db,
+    key: &[u8],
+    start: StreamId,
+    end: StreamId,
+    count: Option<usize>,
+    rev: bool,
+) -> Result<RespFrame, CommandError> {
+    let entries = db
+        .get_stream(key)?
+        .map(|stream| stream.range(start, end, count, rev))
+        .unwrap_or_default();
+    Ok(entries_reply(entries))
+}
+
+// the trailing [COUNT count] of XRANGE and XREVRANGE
+fn parse_range_count(args: &mut CommandArgs) -> Result<Option<usize>, CommandError> {
+    let count = match args.next_keyword().as_deref() {
+        Some("COUNT") => Some(parse_count(args)?),
+        Some(_) => return Err(CommandError::Syntax),
+        None => None,
+    };
+    args.finish()?;
+    Ok(count)
+}
+
+impl CommandSpec for XRange {
+    const NAME: &'static str = "xrange";
+    const ARITY: i64 = -4;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let start = parse_range_id(&args.next_bytes()?, true)?;
+        let end = parse_range_id(&args.next_bytes()?, false)?;
+        let count = parse_range_count(args)?;
+        Ok(XRange {
+            key,
+            start,
+            end,
+            count,
+        })
+    }
+}
+
+impl CommandExecutor for XRange {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        stream_range(ctx.db(), &self.key, self.start, self.end, self.count, false)
+    }
+}
+
+impl CommandSpec for XRevRange {
+    const NAME: &'static str = "xrevrange";
+    const ARITY: i64 = -4;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let end = parse_range_id(&args.next_bytes()?, false)?;
+        let start = parse_range_id(&args.next_bytes()?, true)?;
+        let count = parse_range_count(args)?;
+        Ok(XRevRange {
+            key,
+            start,
+            end,
+            count,
+        })
+    }
+}
+
+impl CommandExecutor for XRevRange {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        stream_range(ctx.db(), &self.key, self.start, self.end, self.count, true)
+    }
+}
+
+impl CommandSpec for XLen {
+    const NAME: &'static str = "xlen";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(XLen {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for XLen {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let len = ctx.db().get_stream(&self.key)?.map_or(0, |s| s.len());
+        Ok(RespFrame::Integer(len as i64))
+    }
+}
+
+impl CommandSpec for XDel {
+    const NAME: &'static str = "xdel";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let ids = args
+            .rest()
+            .iter()
+            .map(|id| parse_id(id, 0))
+            .collect::<Result<_, _>>()?;
+        Ok(XDel { key, ids })
+    }
+}
+
+impl CommandExecutor for XDel {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        // an emptied stream stays around, keeping its last id
+        let deleted = match ctx.db().get_stream_mut(&self.key)? {
+            Some(stream) => self.ids.iter().filter(|id| stream.delete(**id)).count(),
+            None => 0,
+        };
+        if deleted > 0 {
+            ctx.db().touch(&self.key);
+        }
+        Ok(RespFrame::Integer(deleted as i64))
+    }
+}
+
+impl CommandSpec for XTrim {
+    const NAME: &'static str = "xtrim";
+    const ARITY: i64 = -4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let trim = match args.next_keyword() {
+            Some(strategy) if strategy == "MAXLEN" || strategy == "MINID" => {
+                Trim::parse(&strategy, args)?
+            }
+            _ => return Err(CommandError::Syntax),
+        };
+        args.finish()?;
+        Ok(XTrim { key, trim })
+    }
+}
+
+impl CommandExecutor for XTrim {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let removed = match ctx.db().get_stream_mut(&self.key)? {
+            Some(stream) => self.trim.apply(stream),
+            None => 0,
+        };
+        if removed > 0 {
+            ctx.db().touch(&self.key);
+        }
+        Ok(RespFrame::Integer(removed as i64))
+    }
+}
+
+// the BLOCK argument of XREAD and XREADGROUP, in milliseconds; 0 blocks forever
+pub(crate) fn parse_block(args: &mut CommandArgs) -> Result<Option<Duration>, CommandError> {
+    let ms = args
+        .next_i64()
+        .map_err(|_| CommandError::err("timeout is not an integer or out of range"))?;
+    if ms < 0 {
+        return Err(CommandError::err("timeout is negative"));
+    }
+    Ok((ms > 0).then(|| Duration::from_millis(ms as u64)))
+}
+
+// the STREAMS key [key ...] id [id ...] tail of XREAD and XREADGROUP
+pub(crate) fn parse_streams(args: &mut CommandArgs) -> Result<(Keys, Vec<Vec<u8>>), CommandError> {
+    let mut rest = args.rest();
+    if rest.is_empty() || !rest.len().is_multiple_of(2) {
+        return Err(CommandError::err(format!(
+            "Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be specified.",
+            args.name(),
+            if args.name() == "xread" { "$" } else { ">" }
+        )));
+    }
+    let ids = rest.split_off(rest.len() / 2);
+    Ok((rest, ids))
+}
+
+// one reply per stream with new entries: an array of [key, entries] on RESP2,
+// a map from key to entries on RESP3; None when no stream had any
+pub(crate) fn streams_reply(resp3: bool, found: Vec<(Vec<u8>, RespFrame)>) -> Option<RespFrame> {
+    if found.is_empty() {
+        return None;
+    }
+    Some(if resp3 {
+        let mut map = RespMap::new();
+        for (key, entries) in found {
+            map.insert(key, entries);
+        }
+        map.into()
+    } else {
+        RespArray::new(
+            found
+                .into_iter()
+                .map(|(key, entries)| {
+                    RespArray::new(vec![BulkString::new(key).into(), entries]).into()
+                })
+                .collect::<Vec<RespFrame>>(),
+        )
+        .into()
+    })
+}
+
+impl CommandSpec for XRead {
+    const NAME: &'static str = "xread";
+    const ARITY: i64 = -4;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let mut count = None;
+        let mut block = None;
+        loop {
+            match args.next_keyword().as_deref() {
+                Some("COUNT") => count = Some(parse_count(args)?),
+                Some("BLOCK") => block = Some(parse_block(args)?),
+                Some("STREAMS") => break,
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        let (keys, ids) = parse_streams(args)?;
+        let from = ids
+            .iter()
+            .map(|id| match id.as_slice() {
+                b"$" => Ok(ReadFrom::Last),
+                b"+" => Ok(ReadFrom::LastEntry),
+                id => parse_id(id, 0).map(ReadFrom::After),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(XRead {
+            count,
+            block,
+            keys,
+            from,
+            after: vec![],
+        })
+    }
+}
+
+impl BlockingCommand for XRead {
+    fn keys(&self) -> Vec<Vec<u8>> {
+        self.keys.clone()
+    }
+
+    fn try_serve(&self, ctx: &mut Context) -> Result<Option<RespFrame>, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let mut found = Vec::new();
+        for (key, after) in self.keys.iter().zip(&self.after) {
+            let stream = match ctx.db().get_stream(key)? {
+                Some(stream) => stream,
+                None => continue,
+            };
+            let entries = match after.next() {
+                Some(start) => stream.range(start, StreamId::MAX, self.count, false),
+                None => vec![],
+            };
+            if !entries.is_empty() {
+                found.push((key.clone(), entries_reply(entries)));
+            }
+        }
+        Ok(streams_reply(resp3, found))
+    }
+
+    fn timeout_reply(&self) -> RespFrame {
+        RespNullArray.into()
+    }
+}
+
+impl CommandExecutor for XRead {
+    fn execute(mut self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        // pin $ and + to the streams as they are now, so that a blocked read
+        // only sees what is added later
+        let mut after = Vec::with_capacity(self.keys.len());
+        for (key, from) in self.keys.iter().zip(&self.from) {
+            let stream = ctx.db().get_stream(key)?;
+            after.push(match (from, stream) {
+                (ReadFrom::After(id), _) => *id,
+                (_, None) => StreamId::MIN,
+                (ReadFrom::Last, Some(stream)) => stream.last_id(),
+                (ReadFrom::LastEntry, Some(stream)) => match stream.last_entry() {
+                    Some((id, _)) => id.prev().unwrap_or(StreamId::MIN),
+                    None => stream.last_id(),
+                },
+            });
+        }
+        self.after = after;
+
+        if let Some(reply) = self.try_serve(ctx)? {
+            return Ok(reply);
+        }
+        match self.block {
+            Some(timeout) => ctx.block(self, timeout),
+            None => Ok(RespNullArray.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Protocol;
+    use crate::cmd::TestContext;
+    use crate::Client;
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    fn entry(id: &str, fields: &[&str]) -> RespFrame {
+        RespArray::new(vec![
+            bulk(id),
+            RespArray::new(fields.iter().map(|f| bulk(f)).collect::<Vec<_>>()).into(),
+        ])
+        .into()
+    }
+
+    fn entries(items: Vec<RespFrame>) -> RespFrame {
+        RespArray::new(items).into()
+    }
+
+    #[test]
+    fn test_parse_ids() {
+        assert_eq!(parse_id(b"5-3", 0), Ok(StreamId::new(5, 3)));
+        assert_eq!(parse_id(b"5", 7), Ok(StreamId::new(5, 7)));
+        assert_eq!(parse_id(b"5-", 0), Err(invalid_id()));
+        assert_eq!(parse_id(b"-1", 0), Err(invalid_id()));
+        assert_eq!(parse_range_id(b"(5-3", true), Ok(StreamId::new(5, 4)));
+        assert_eq!(
+            parse_range_id(b"(5", false),
+            Ok(StreamId::new(5, u64::MAX - 1))
+        );
+        assert_eq!(parse_range_id(b"5", false), Ok(StreamId::new(5, u64::MAX)));
+        assert_eq!(parse_range_id(b"+", false), Ok(StreamId::MAX));
+    }
+
+    #[test]
+    fn test_xadd_ids() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("xadd s 1-1 a 1"), bulk("1-1"));
+        assert_eq!(t.run("xadd s 1-* a 2"), bulk("1-2"));
+        assert_eq!(t.run("xadd s 5 a 3"), bulk("5-0"));
+        assert_eq!(
+            t.run("xadd s 5-0 a 4"),
+            CommandError::err(
+                "The ID specified in XADD is equal or smaller than the target stream top item"
+            )
+            .into()
+        );
+        assert_eq!(
+            t.run("xadd new 0-0 a 1"),
+            CommandError::err("The ID specified in XADD must be greater than 0-0").into()
+        );
+        assert_eq!(t.run("xlen new"), RespFrame::Integer(0));
+        assert_eq!(t.run("xadd new 0-* a 1"), bulk("0-1"));
+        assert!(matches!(t.run("xadd s * a 5"), RespFrame::BulkString(_)));
+        assert_eq!(t.run("xlen s"), RespFrame::Integer(4));
+        assert_eq!(t.run("xadd other nomkstream * a 1"), nil());
+        assert_eq!(
+            t.run("xadd s * a"),
+            CommandError::WrongArity("xadd".to_string()).into()
+        );
+        assert_eq!(t.run("xadd s x-1 a 1"), invalid_id().into());
+    }
+
+    #[test]
+    fn test_xrange_xdel_xtrim() {
+        let mut t = TestContext::default();
+        for i in 1..=5 {
+            t.run(&format!("xadd s {}-0 n {}", i, i));
+        }
+        assert_eq!(
+            t.run("xrange s - + count 2"),
+            entries(vec![entry("1-0", &["n", "1"]), entry("2-0", &["n", "2"])])
+        );
+        assert_eq!(
+            t.run("xrange s (2 4"),
+            entries(vec![entry("3-0", &["n", "3"]), entry("4-0", &["n", "4"])])
+        );
+        assert_eq!(
+            t.run("xrevrange s + (4 count 5"),
+            entries(vec![entry("5-0", &["n", "5"])])
+        );
+        assert_eq!(t.run("xdel s 2-0 9-0 3"), RespFrame::Integer(2));
+        assert_eq!(t.run("xlen s"), RespFrame::Integer(3));
+        assert_eq!(t.run("xtrim s maxlen 2"), RespFrame::Integer(1));
+        assert_eq!(
+            t.run("xrange s - +"),
+            entries(vec![entry("4-0", &["n", "4"]), entry("5-0", &["n", "5"])])
+        );
+        assert_eq!(t.run("xtrim s minid 5"), RespFrame::Integer(1));
+        assert_eq!(t.run("xadd s maxlen = 0 6 n 6"), bulk("6-0"));
+        // an emptied stream keeps its key and its last id
+        assert_eq!(t.run("xlen s"), RespFrame::Integer(0));
+        assert!(!t.backend.dbs[0].is_empty());
+
+        assert_eq!(
+            t.run("xtrim s maxlen 1 limit 10"),
+            CommandError::err("syntax error, LIMIT cannot be used without the special ~ option")
+                .into()
+        );
+        assert_eq!(t.run("xtrim s maxlen ~ 1 limit 10"), RespFrame::Integer(0));
+        assert_eq!(
+            t.run("xtrim s maxlen -1"),
+            CommandError::err("The MAXLEN argument must be >= 0.").into()
+        );
+    }
+
+    #[test]
+    fn test_xread() {
+        let mut t = TestContext::default();
+        t.run("xadd a 1-0 f 1");
+        t.run("xadd a 2-0 f 2");
+        t.run("xadd b 1-0 g 1");
+        assert_eq!(
+            t.run("xread count 1 streams a b 1-0 0"),
+            RespArray::new(vec![
+                RespArray::new(vec![bulk("a"), entries(vec![entry("2-0", &["f", "2"])])]).into(),
+                RespArray::new(vec![bulk("b"), entries(vec![entry("1-0", &["g", "1"])])]).into(),
+            ])
+            .into()
+        );
+        assert_eq!(t.run("xread streams a $"), RespNullArray.into());
+        assert_eq!(
+            t.run("xread streams a +"),
+            RespArray::new(vec![RespArray::new(vec![
+                bulk("a"),
+                entries(vec![entry("2-0", &["f", "2"])])
+            ])
+            .into()])
+            .into()
+        );
+        assert_eq!(
+            t.run("xread streams a b 0"),
+            CommandError::err(
+                "Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified."
+            )
+            .into()
+        );
+
+        t.client.protocol = Protocol::Resp3;
+        let mut map = RespMap::new();
+        map.insert(b"b".to_vec(), entries(vec![entry("1-0", &["g", "1"])]));
+        assert_eq!(t.run("xread streams b 0-0"), map.into());
+    }
+
+    #[test]
+    fn test_xread_block() {
+        let mut t = TestContext::default();
+        t.run("xadd s 1-0 f 1");
+        let mut blocked = t.block("xread block 0 streams s $");
+        assert_eq!(blocked.timeout, None);
+        t.client = Client::new();
+        // deleting does not wake the reader, adding does
+        t.run("xdel s 1-0");
+        assert!(blocked.rx.try_recv().is_err());
+        t.run("xadd s 2-0 f 2");
+        assert_eq!(
+            blocked.rx.try_recv().unwrap(),
+            RespArray::new(vec![RespArray::new(vec![
+                bulk("s"),
+                entries(vec![entry("2-0", &["f", "2"])])
+            ])
+            .into()])
+            .into()
+        );
+
+        let blocked = t.block("xread block 100 streams missing 0");
+        assert_eq!(blocked.timeout, Some(Duration::from_millis(100)));
+        assert_eq!(
+            t.run("xread block -1 streams s 0"),
+            CommandError::err("timeout is negative").into()
+        );
+    }
+}
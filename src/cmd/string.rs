@@ -0,0 +1,1171 @@
+use crate::backend::{now_ms, Value};
+use crate::{BulkString, RespArray, RespFrame};
+
+use super::{
+    nil, ok, parse_f64, parse_i64, CommandArgs, CommandError, CommandExecutor, CommandSpec,
+    Context, Pairs,
+};
+
+// strings may not grow beyond proto-max-bulk-len
+pub(crate) const MAX_STRING_SIZE: usize = 512 * 1024 * 1024;
+
+// GET key
+#[derive(Debug)]
+pub struct Get {
+    key: Vec<u8>,
+}
+
+// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds |
+//   EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]
+#[derive(Debug)]
+pub struct Set {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    condition: Condition,
+    get: bool,
+    expiration: Expiration,
+}
+
+// GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+//   PXAT unix-time-milliseconds | PERSIST]
+#[derive(Debug)]
+pub struct GetEx {
+    key: Vec<u8>,
+    expiration: Expiration,
+}
+
+// GETDEL key
+#[derive(Debug)]
+pub struct GetDel {
+    key: Vec<u8>,
+}
+
+// SETNX key value
+#[derive(Debug)]
+pub struct SetNx {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+// SETEX key seconds value
+#[derive(Debug)]
+pub struct SetEx {
+    key: Vec<u8>,
+    expiration: Expiration,
+    value: Vec<u8>,
+}
+
+// PSETEX key milliseconds value
+#[derive(Debug)]
+pub struct PSetEx {
+    key: Vec<u8>,
+    expiration: Expiration,
+    value: Vec<u8>,
+}
+
+// APPEND key value
+#[derive(Debug)]
+pub struct Append {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+// STRLEN key
+#[derive(Debug)]
+pub struct Strlen {
+    key: Vec<u8>,
+}
+
+// GETRANGE key start end
+#[derive(Debug)]
+pub struct GetRange {
+    key: Vec<u8>,
+    start: i64,
+    end: i64,
+}
+
+// SETRANGE key offset value
+#[derive(Debug)]
+pub struct SetRange {
+    key: Vec<u8>,
+    offset: i64,
+    value: Vec<u8>,
+}
+
+// INCR key
+#[derive(Debug)]
+pub struct Incr {
+    key: Vec<u8>,
+}
+
+// DECR key
+#[derive(Debug)]
+pub struct Decr {
+    key: Vec<u8>,
+}
+
+// INCRBY key increment
+#[derive(Debug)]
+pub struct IncrBy {
+    key: Vec<u8>,
+    increment: i64,
+}
+
+// DECRBY key decrement
+#[derive(Debug)]
+pub struct DecrBy {
+    key: Vec<u8>,
+    decrement: i64,
+}
+
+// INCRBYFLOAT key increment
+#[derive(Debug)]
+pub struct IncrByFloat {
+    key: Vec<u8>,
+    increment: f64,
+    // as given, for float_sum to add exactly
+    increment_text: Vec<u8>,
+}
+
+// MGET key [key ...]
+#[derive(Debug)]
+pub struct MGet {
+    keys: Vec<Vec<u8>>,
+}
+
+// MSET key value [key value ...]
+#[derive(Debug)]
+pub struct MSet {
+    pairs: Pairs,
+}
+
+// MSETNX key value [key value ...]
+#[derive(Debug)]
+pub struct MSetNx {
+    pairs: Pairs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Condition {
+    Always,
+    IfNotExists,
+    IfExists,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Expiration {
+    // leave the TTL alone (GETEX without options)
+    Unchanged,
+    // drop any TTL (plain SET)
+    None,
+    KeepTtl,
+    Persist,
+    // relative to the time the command runs, in milliseconds
+    In(u64),
+    // absolute unix time in milliseconds
+    At(u64),
+}
+
+impl Expiration {
+    // parse the argument of EX/PX/EXAT/PXAT, rejecting the same values Redis rejects
+    pub(crate) fn parse(
+        option: &str,
+        args: &mut CommandArgs,
+        cmd: &str,
+    ) -> Result<Expiration, CommandError> {
+        let value = args.next_i64()?;
+        let invalid = || CommandError::err(format!("invalid expire time in '{}' command", cmd));
+        if value <= 0 {
+            return Err(invalid());
+        }
+        let value = value as u64;
+        let ms = match option {
+            "EX" | "EXAT" => value.checked_mul(1000).ok_or_else(invalid)?,
+            _ => value,
+        };
+        match option {
+            "EX" | "PX" => {
+                now_ms()
+                    .checked_add(ms)
+                    .filter(|&at| at <= i64::MAX as u64)
+                    .ok_or_else(invalid)?;
+                Ok(Expiration::In(ms))
+            }
+            _ => Ok(Expiration::At(ms)),
+        }
+    }
+
+    // the absolute deadline this expiration asks for, if any
+    pub(crate) fn deadline(&self) -> Option<u64> {
+        match self {
+            Expiration::In(ms) => Some(now_ms().saturating_add(*ms)),
+            Expiration::At(at) => Some(*at),
+            _ => None,
+        }
+    }
+}
+
+fn bulk_or_nil(value: Option<Vec<u8>>) -> RespFrame {
+    match value {
+        Some(v) => BulkString::new(v).into(),
+        None => nil(),
+    }
+}
+
+impl CommandSpec for Get {
+    const NAME: &'static str = "get";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Get {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Get {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let value = ctx.db().get_string(&self.key)?.cloned();
+        Ok(bulk_or_nil(value))
+    }
+}
+
+impl CommandSpec for Set {
+    const NAME: &'static str = "set";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let mut cmd = Set {
+            key: args.next_bytes()?,
+            value: args.next_bytes()?,
+            condition: Condition::Always,
+            get: false,
+            expiration: Expiration::None,
+        };
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                "NX" if cmd.condition != Condition::IfExists => {
+                    cmd.condition = Condition::IfNotExists
+                }
+                "XX" if cmd.condition != Condition::IfNotExists => {
+                    cmd.condition = Condition::IfExists
+                }
+                "GET" => cmd.get = true,
+                "KEEPTTL" if cmd.expiration == Expiration::None => {
+                    cmd.expiration = Expiration::KeepTtl
+                }
+                "EX" | "PX" | "EXAT" | "PXAT" if cmd.expiration == Expiration::None => {
+                    cmd.expiration = Expiration::parse(&opt, args, Self::NAME)?;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok(cmd)
+    }
+}
+
+impl CommandExecutor for Set {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let old = if self.get {
+            db.get_string(&self.key)?.cloned()
+        } else {
+            None
+        };
+
+        let exists = db.contains_key(&self.key);
+        let skip = match self.condition {
+            Condition::Always => false,
+            Condition::IfNotExists => exists,
+            Condition::IfExists => !exists,
+        };
+        if !skip {
+            let ttl = match self.expiration {
+                Expiration::KeepTtl => db.expire_at(&self.key),
+                _ => self.expiration.deadline(),
+            };
+            db.insert(self.key.clone(), Value::String(self.value));
+            if let Some(at) = ttl {
+                db.set_expire(&self.key, at);
+            }
+        }
+
+        Ok(match (self.get, skip) {
+            (true, _) => bulk_or_nil(old),
+            (false, true) => nil(),
+            (false, false) => ok(),
+        })
+    }
+}
+
+impl CommandSpec for GetEx {
+    const NAME: &'static str = "getex";
+    const ARITY: i64 = -2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let expiration = match args.next_keyword() {
+            None => Expiration::Unchanged,
+            Some(opt) => match opt.as_str() {
+                "PERSIST" => Expiration::Persist,
+                "EX" | "PX" | "EXAT" | "PXAT" => Expiration::parse(&opt, args, Self::NAME)?,
+                _ => return Err(CommandError::Syntax),
+            },
+        };
+        args.finish()?;
+        Ok(GetEx { key, expiration })
+    }
+}
+
+impl CommandExecutor for GetEx {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let value = db.get_string(&self.key)?.cloned();
+        if value.is_some() {
+            match self.expiration {
+                Expiration::Persist => {
+                    db.persist(&self.key);
+                }
+                _ => {
+                    if let Some(at) = self.expiration.deadline() {
+                        db.set_expire(&self.key, at);
+                    }
+                }
+            }
+        }
+        Ok(bulk_or_nil(value))
+    }
+}
+
+impl CommandSpec for GetDel {
+    const NAME: &'static str = "getdel";
+    const ARITY: i64 = 2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(GetDel {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for GetDel {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let value = db.get_string(&self.key)?.cloned();
+        if value.is_some() {
+            db.remove(&self.key);
+        }
+        Ok(bulk_or_nil(value))
+    }
+}
+
+impl CommandSpec for SetNx {
+    const NAME: &'static str = "setnx";
+    const ARITY: i64 = 3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SetNx {
+            key: args.next_bytes()?,
+            value: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for SetNx {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        if db.contains_key(&self.key) {
+            return Ok(RespFrame::Integer(0));
+        }
+        db.insert(self.key, Value::String(self.value));
+        Ok(RespFrame::Integer(1))
+    }
+}
+
+impl CommandSpec for SetEx {
+    const NAME: &'static str = "setex";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SetEx {
+            key: args.next_bytes()?,
+            expiration: Expiration::parse("EX", args, Self::NAME)?,
+            value: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for SetEx {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        set_with_expiration(ctx, self.key, self.value, self.expiration)
+    }
+}
+
+impl CommandSpec for PSetEx {
+    const NAME: &'static str = "psetex";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(PSetEx {
+            key: args.next_bytes()?,
+            expiration: Expiration::parse("PX", args, Self::NAME)?,
+            value: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for PSetEx {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        set_with_expiration(ctx, self.key, self.value, self.expiration)
+    }
+}
+
+fn set_with_expiration(
+    ctx: &mut Context,
+    key: Vec<u8>,
+    value: Vec<u8>,
+    expiration: Expiration,
+) -> Result<RespFrame, CommandError> {
+    let db = ctx.db();
+    db.insert(key.clone(), Value::String(value));
+    if let Some(at) = expiration.deadline() {
+        db.set_expire(&key, at);
+    }
+    Ok(ok())
+}
+
+impl CommandSpec for Append {
+    const NAME: &'static str = "append";
+    const ARITY: i64 = 3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Append {
+            key: args.next_bytes()?,
+            value: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Append {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let current = db
+            .get_string(&self.key)?
+            .map(|v| v.len())
+            .unwrap_or_default();
+        check_string_size(current + self.value.len())?;
+        let s = db.get_or_create_string(&self.key)?;
+        s.extend_from_slice(&self.value);
+        let len = s.len();
+        db.touch(&self.key);
+        Ok(RespFrame::Integer(len as i64))
+    }
+}
+
+impl CommandSpec for Strlen {
+    const NAME: &'static str = "strlen";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Strlen {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Strlen {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let len = ctx
+            .db()
+            .get_string(&self.key)?
+            .map(|v| v.len())
+            .unwrap_or_default();
+        Ok(RespFrame::Integer(len as i64))
+    }
+}
+
+impl CommandSpec for GetRange {
+    const NAME: &'static str = "getrange";
+    const ARITY: i64 = 4;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(GetRange {
+            key: args.next_bytes()?,
+            start: args.next_i64()?,
+            end: args.next_i64()?,
+        })
+    }
+}
+
+impl CommandExecutor for GetRange {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let value = match ctx.db().get_string(&self.key)? {
+            Some(v) => v,
+            None => return Ok(BulkString::new(vec![]).into()),
+        };
+        let range = match string_range(value.len(), self.start, self.end) {
+            Some((start, end)) => value[start..=end].to_vec(),
+            None => vec![],
+        };
+        Ok(BulkString::new(range).into())
+    }
+}
+
+// resolve an inclusive [start, end] byte range with negative offsets counted
+// from the end, returning None when the range is empty
+pub(crate) fn string_range(len: usize, start: i64, end: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    if len == 0 || (start < 0 && end < 0 && start > end) {
+        return None;
+    }
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 {
+        (len + end).max(0)
+    } else {
+        end.min(len - 1)
+    };
+    if start > end {
+        return None;
+    }
+    Some((start as usize, end as usize))
+}
+
+impl CommandSpec for SetRange {
+    const NAME: &'static str = "setrange";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(SetRange {
+            key: args.next_bytes()?,
+            offset: args.next_i64()?,
+            value: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for SetRange {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        if self.offset < 0 {
+            return Err(CommandError::err("offset is out of range"));
+        }
+        let offset = self.offset as usize;
+        let db = ctx.db();
+        let current = db.get_string(&self.key)?.map(|v| v.len());
+        if self.value.is_empty() {
+            return Ok(RespFrame::Integer(current.unwrap_or_default() as i64));
+        }
+        check_string_size(offset.saturating_add(self.value.len()))?;
+
+        let s = db.get_or_create_string(&self.key)?;
+        let end = offset + self.value.len();
+        if s.len() < end {
+            s.resize(end, 0);
+        }
+        s[offset..end].copy_from_slice(&self.value);
+        let len = s.len();
+        db.touch(&self.key);
+        Ok(RespFrame::Integer(len as i64))
+    }
+}
+
+pub(crate) fn check_string_size(len: usize) -> Result<(), CommandError> {
+    if len > MAX_STRING_SIZE {
+        return Err(CommandError::err(
+            "string exceeds maximum allowed size (proto-max-bulk-len)",
+        ));
+    }
+    Ok(())
+}
+
+impl CommandSpec for Incr {
+    const NAME: &'static str = "incr";
+    const ARITY: i64 = 2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Incr {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Incr {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        incr_by(ctx, &self.key, 1)
+    }
+}
+
+impl CommandSpec for Decr {
+    const NAME: &'static str = "decr";
+    const ARITY: i64 = 2;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Decr {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for Decr {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        incr_by(ctx, &self.key, -1)
+    }
+}
+
+impl CommandSpec for IncrBy {
+    const NAME: &'static str = "incrby";
+    const ARITY: i64 = 3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(IncrBy {
+            key: args.next_bytes()?,
+            increment: args.next_i64()?,
+        })
+    }
+}
+
+impl CommandExecutor for IncrBy {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        incr_by(ctx, &self.key, self.increment)
+    }
+}
+
+impl CommandSpec for DecrBy {
+    const NAME: &'static str = "decrby";
+    const ARITY: i64 = 3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(DecrBy {
+            key: args.next_bytes()?,
+            decrement: args.next_i64()?,
+        })
+    }
+}
+
+impl CommandExecutor for DecrBy {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let increment = self
+            .decrement
+            .checked_neg()
+            .ok_or_else(|| CommandError::err("decrement would overflow"))?;
+        incr_by(ctx, &self.key, increment)
+    }
+}
+
+// add to the integer stored at key, creating it at 0 when missing; the TTL is kept
+fn incr_by(ctx: &mut Context, key: &[u8], increment: i64) -> Result<RespFrame, CommandError> {
+    let db = ctx.db();
+    let current = match db.get_string(key)? {
+        Some(v) => parse_i64(v)?,
+        None => 0,
+    };
+    let value = current
+        .checked_add(increment)
+        .ok_or_else(|| CommandError::err("increment or decrement would overflow"))?;
+    *db.get_or_create_string(key)? = value.to_string().into_bytes();
+    db.touch(key);
+    Ok(RespFrame::Integer(value))
+}
+
+impl CommandSpec for IncrByFloat {
+    const NAME: &'static str = "incrbyfloat";
+    const ARITY: i64 = 3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let increment_text = args.next_bytes()?;
+        Ok(IncrByFloat {
+            key,
+            increment: parse_f64(&increment_text)?,
+            increment_text,
+        })
+    }
+}
+
+impl CommandExecutor for IncrByFloat {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let (current, current_text) = match db.get_string(&self.key)? {
+            Some(v) => (parse_f64(v)?, v.as_slice()),
+            None => (0.0, &b"0"[..]),
+        };
+        let value = current + self.increment;
+        if !value.is_finite() {
+            return Err(CommandError::err("increment would produce NaN or Infinity"));
+        }
+        let formatted = float_sum(current_text, &self.increment_text, value).into_bytes();
+        *db.get_or_create_string(&self.key)? = formatted.clone();
+        db.touch(&self.key);
+        Ok(BulkString::new(formatted).into())
+    }
+}
+
+// a score as RESP2 replies give it: the shortest decimal that reads back as
+// the same double, without exponent
+pub(crate) fn format_float(value: f64) -> String {
+    if value == 0.0 {
+        // drop the sign of negative zero
+        return "0".to_string();
+    }
+    format!("{}", value)
+}
+
+// the sum INCRBYFLOAT and HINCRBYFLOAT store and reply with, given the two
+// numbers as text and their sum as a double. Redis adds in long double
+// precision and keeps 17 significant digits, so that adding decimals gives
+// the decimal one expects, 0.1 plus 0.2 making 0.3, where the double sum is
+// 0.30000000000000004. Here two decimals that fit are added exactly, and
+// any other sum comes from the double
+pub(crate) fn float_sum(a: &[u8], b: &[u8], sum: f64) -> String {
+    Decimal::parse(a)
+        .zip(Decimal::parse(b))
+        .and_then(|(a, b)| a.checked_add(b))
+        .or_else(|| Decimal::parse(format!("{:e}", sum).as_bytes()))
+        .map(|sum| sum.to_human_string())
+        .unwrap_or_else(|| format_float(sum))
+}
+
+// mantissa * 10^exponent, exact
+#[derive(Debug, Clone, Copy)]
+struct Decimal {
+    mantissa: i128,
+    exponent: i32,
+}
+
+// how many significant digits a float_sum keeps, as Redis does
+const FLOAT_SUM_DIGITS: usize = 17;
+
+impl Decimal {
+    // a number such as -12.5 or 5.0e3; None for anything else, and for
+    // digits that don't fit
+    fn parse(text: &[u8]) -> Option<Decimal> {
+        let text = std::str::from_utf8(text).ok()?;
+        let (number, exponent) = match text.find(['e', 'E']) {
+            Some(at) => (&text[..at], text[at + 1..].parse::<i32>().ok()?),
+            None => (text, 0),
+        };
+        let (negative, number) = match number.strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, number.strip_prefix('+').unwrap_or(number)),
+        };
+        let (int, frac) = number.split_once('.').unwrap_or((number, ""));
+        if int.len() + frac.len() == 0 || exponent.abs() > 400 {
+            return None;
+        }
+        let mut mantissa: i128 = 0;
+        for digit in int.bytes().chain(frac.bytes()) {
+            if !digit.is_ascii_digit() {
+                return None;
+            }
+            mantissa = mantissa
+                .checked_mul(10)?
+                .checked_add(i128::from(digit - b'0'))?;
+        }
+        Some(Decimal {
+            mantissa: if negative { -mantissa } else { mantissa },
+            exponent: exponent - frac.len() as i32,
+        })
+    }
+
+    fn checked_add(self, other: Decimal) -> Option<Decimal> {
+        let exponent = self.exponent.min(other.exponent);
+        let scale = |d: Decimal| {
+            10i128
+                .checked_pow((d.exponent - exponent) as u32)
+                .and_then(|scale| d.mantissa.checked_mul(scale))
+        };
+        Some(Decimal {
+            mantissa: scale(self)?.checked_add(scale(other)?)?,
+            exponent,
+        })
+    }
+
+    // plain decimal notation, rounded to FLOAT_SUM_DIGITS significant
+    // digits, without exponent or trailing zeros
+    fn to_human_string(self) -> String {
+        let mut digits = self.mantissa.unsigned_abs();
+        let mut exponent = self.exponent;
+        let len = digits.to_string().len();
+        if len > FLOAT_SUM_DIGITS {
+            let scale = 10u128.pow((len - FLOAT_SUM_DIGITS) as u32);
+            let rest = digits % scale;
+            digits /= scale;
+            if rest >= scale - rest {
+                digits += 1;
+            }
+            exponent += (len - FLOAT_SUM_DIGITS) as i32;
+        }
+        if digits == 0 {
+            return "0".to_string();
+        }
+        let digits = digits.to_string();
+        let mut text = match exponent {
+            0.. => digits + &"0".repeat(exponent as usize),
+            _ => {
+                let point = digits.len() as i32 + exponent;
+                let number = match point {
+                    ..=0 => format!("0.{}{}", "0".repeat(-point as usize), digits),
+                    _ => format!(
+                        "{}.{}",
+                        &digits[..point as usize],
+                        &digits[point as usize..]
+                    ),
+                };
+                number
+                    .trim_end_matches('0')
+                    .trim_end_matches('.')
+                    .to_string()
+            }
+        };
+        if self.mantissa < 0 {
+            text.insert(0, '-');
+        }
+        text
+    }
+}
+
+impl CommandSpec for MGet {
+    const NAME: &'static str = "mget";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(MGet { keys: args.rest() })
+    }
+}
+
+impl CommandExecutor for MGet {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        // keys holding other types read as missing rather than failing the batch
+        let values = self
+            .keys
+            .iter()
+            .map(|key| match db.get(key) {
+                Some(Value::String(v)) => BulkString::new(v.clone()).into(),
+                _ => nil(),
+            })
+            .collect::<Vec<RespFrame>>();
+        Ok(RespArray::new(values).into())
+    }
+}
+
+// the key/value pairs of MSET and MSETNX
+fn parse_pairs(args: &mut CommandArgs) -> Result<Pairs, CommandError> {
+    if !args.len().is_multiple_of(2) {
+        return Err(CommandError::WrongArity(args.name().to_string()));
+    }
+    let mut pairs = Vec::with_capacity(args.len() / 2);
+    while !args.is_empty() {
+        pairs.push((args.next_bytes()?, args.next_bytes()?));
+    }
+    Ok(pairs)
+}
+
+impl CommandSpec for MSet {
+    const NAME: &'static str = "mset";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(MSet {
+            pairs: parse_pairs(args)?,
+        })
+    }
+}
+
+impl CommandExecutor for MSet {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        for (key, value) in self.pairs {
+            db.insert(key, Value::String(value));
+        }
+        Ok(ok())
+    }
+}
+
+impl CommandSpec for MSetNx {
+    const NAME: &'static str = "msetnx";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(MSetNx {
+            pairs: parse_pairs(args)?,
+        })
+    }
+}
+
+impl CommandExecutor for MSetNx {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        // all or nothing: a single existing key vetoes the whole batch
+        if self.pairs.iter().any(|(key, _)| db.contains_key(key)) {
+            return Ok(RespFrame::Integer(0));
+        }
+        for (key, value) in self.pairs {
+            db.insert(key, Value::String(value));
+        }
+        Ok(RespFrame::Integer(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::TestContext;
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    #[test]
+    fn test_get_set() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("get k"), nil());
+        assert_eq!(t.run("set k v"), ok());
+        assert_eq!(t.run("GET k"), bulk("v"));
+        assert_eq!(t.run("set k v2 get"), bulk("v"));
+        assert_eq!(t.run("get k"), bulk("v2"));
+    }
+
+    #[test]
+    fn test_set_conditions() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("set k v xx"), nil());
+        assert_eq!(t.run("set k v nx px 10000"), ok());
+        assert_eq!(t.run("set k v2 nx px 10000"), nil());
+        assert_eq!(t.run("set k v2 nx get"), bulk("v"));
+        assert_eq!(t.run("set k v3 xx"), ok());
+        assert_eq!(t.run("get k"), bulk("v3"));
+        assert_eq!(t.run("set k v nx xx"), CommandError::Syntax.into());
+        assert_eq!(t.run("set k v ex 10 px 10"), CommandError::Syntax.into());
+        assert_eq!(t.run("set k v keepttl ex 10"), CommandError::Syntax.into());
+    }
+
+    #[test]
+    fn test_set_expiration() {
+        let mut t = TestContext::default();
+        t.run("set k v ex 100");
+        let at = t.backend.dbs[0].expire_at(b"k").unwrap();
+        assert!(at > now_ms() + 99_000);
+
+        t.run("set k v2 keepttl");
+        assert_eq!(t.backend.dbs[0].expire_at(b"k"), Some(at));
+
+        t.run("set k v3");
+        assert_eq!(t.backend.dbs[0].expire_at(b"k"), None);
+
+        assert_eq!(
+            t.run("set k v ex 0"),
+            CommandError::err("invalid expire time in 'set' command").into()
+        );
+        assert_eq!(t.run("set k v ex abc"), CommandError::NotInteger.into());
+
+        // an absolute time in the past deletes the key right away
+        assert_eq!(t.run("set k v pxat 1"), ok());
+        assert_eq!(t.run("get k"), nil());
+    }
+
+    #[test]
+    fn test_getex_getdel() {
+        let mut t = TestContext::default();
+        t.run("set k v");
+        assert_eq!(t.run("getex k px 100000"), bulk("v"));
+        assert!(t.backend.dbs[0].expire_at(b"k").is_some());
+        assert_eq!(t.run("getex k persist"), bulk("v"));
+        assert_eq!(t.backend.dbs[0].expire_at(b"k"), None);
+        assert_eq!(t.run("getex k persist ex 1"), CommandError::Syntax.into());
+
+        assert_eq!(t.run("getdel k"), bulk("v"));
+        assert_eq!(t.run("getdel k"), nil());
+    }
+
+    #[test]
+    fn test_setnx_setex() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("setnx k v"), RespFrame::Integer(1));
+        assert_eq!(t.run("setnx k v2"), RespFrame::Integer(0));
+        assert_eq!(t.run("setex k 100 v3"), ok());
+        assert_eq!(t.run("get k"), bulk("v3"));
+        assert!(t.backend.dbs[0].expire_at(b"k").is_some());
+        assert_eq!(
+            t.run("psetex k -1 v"),
+            CommandError::err("invalid expire time in 'psetex' command").into()
+        );
+    }
+
+    #[test]
+    fn test_append_strlen() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("append k hello"), RespFrame::Integer(5));
+        assert_eq!(t.run("append k world"), RespFrame::Integer(10));
+        assert_eq!(t.run("strlen k"), RespFrame::Integer(10));
+        assert_eq!(t.run("strlen missing"), RespFrame::Integer(0));
+    }
+
+    #[test]
+    fn test_getrange_setrange() {
+        let mut t = TestContext::default();
+        t.run("set k hello");
+        assert_eq!(t.run("getrange k 0 -1"), bulk("hello"));
+        assert_eq!(t.run("getrange k -3 -1"), bulk("llo"));
+        assert_eq!(t.run("getrange k 1 100"), bulk("ello"));
+        assert_eq!(t.run("getrange k -1 -3"), bulk(""));
+        assert_eq!(t.run("getrange missing 0 -1"), bulk(""));
+
+        assert_eq!(t.run("setrange k 1 ipp"), RespFrame::Integer(5));
+        assert_eq!(t.run("get k"), bulk("hippo"));
+        assert_eq!(t.run("setrange n 2 ab"), RespFrame::Integer(4));
+        assert_eq!(t.run("get n"), BulkString::new(b"\0\0ab".to_vec()).into());
+        assert_eq!(
+            t.run("setrange k -1 x"),
+            CommandError::err("offset is out of range").into()
+        );
+    }
+
+    #[test]
+    fn test_incr_decr() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("incr c"), RespFrame::Integer(1));
+        assert_eq!(t.run("incrby c 10"), RespFrame::Integer(11));
+        assert_eq!(t.run("decr c"), RespFrame::Integer(10));
+        assert_eq!(t.run("decrby c 20"), RespFrame::Integer(-10));
+        assert_eq!(t.run("get c"), bulk("-10"));
+
+        t.run("set c 9223372036854775807");
+        assert_eq!(
+            t.run("incr c"),
+            CommandError::err("increment or decrement would overflow").into()
+        );
+        assert_eq!(
+            t.run("decrby c -9223372036854775808"),
+            CommandError::err("decrement would overflow").into()
+        );
+
+        t.run("set s abc");
+        assert_eq!(t.run("incr s"), CommandError::NotInteger.into());
+        t.run("set s \x201");
+        assert_eq!(t.run("incrby c x"), CommandError::NotInteger.into());
+    }
+
+    #[test]
+    fn test_incr_keeps_ttl() {
+        let mut t = TestContext::default();
+        t.run("set c 1 ex 100");
+        t.run("incr c");
+        assert!(t.backend.dbs[0].expire_at(b"c").is_some());
+    }
+
+    #[test]
+    fn test_incrbyfloat() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("incrbyfloat f 10.5"), bulk("10.5"));
+        assert_eq!(t.run("incrbyfloat f 0.1"), bulk("10.6"));
+        assert_eq!(t.run("incrbyfloat f -5"), bulk("5.6"));
+        assert_eq!(t.run("incrbyfloat f 2.0e2"), bulk("205.6"));
+        assert_eq!(t.run("incrbyfloat f -205.6"), bulk("0"));
+        assert_eq!(t.run("incrbyfloat g 5.0e3"), bulk("5000"));
+        assert_eq!(t.run("incrbyfloat g abc"), CommandError::NotFloat.into());
+        assert_eq!(
+            t.run("incrbyfloat g inf"),
+            CommandError::err("increment would produce NaN or Infinity").into()
+        );
+        t.run("set s abc");
+        assert_eq!(t.run("incrbyfloat s 1"), CommandError::NotFloat.into());
+
+        // decimals add up as decimals, not as doubles do
+        assert_eq!(t.run("incrbyfloat d 0.1"), bulk("0.1"));
+        assert_eq!(t.run("incrbyfloat d 0.2"), bulk("0.3"));
+        assert_eq!(t.run("get d"), bulk("0.3"));
+        assert_eq!(
+            t.run("incrbyfloat d -0.30000000000000004"),
+            bulk("-0.00000000000000004")
+        );
+        t.run("set d 1.2345678901234567891");
+        assert_eq!(t.run("incrbyfloat d 0"), bulk("1.2345678901234568"));
+        t.run("set d 1e300");
+        assert_eq!(
+            t.run("incrbyfloat d 1e-300"),
+            bulk(&format!("1{}", "0".repeat(300)))
+        );
+    }
+
+    #[test]
+    fn test_float_sum() {
+        assert_eq!(float_sum(b"0.1", b"0.2", 0.1 + 0.2), "0.3");
+        assert_eq!(float_sum(b"5.0e3", b"2.0E2", 5200.0), "5200");
+        assert_eq!(float_sum(b"-1.5", b"+1.5", 0.0), "0");
+        assert_eq!(float_sum(b"0.5", b"-0.75", -0.25), "-0.25");
+        assert_eq!(
+            float_sum(b"99999999999999999", b"1", 1e17),
+            "100000000000000000"
+        );
+        assert_eq!(
+            float_sum(b"0.99999999999999999", b"0", 1.0),
+            "0.99999999999999999"
+        );
+        assert_eq!(float_sum(b"0.999999999999999999", b"0", 1.0), "1");
+        // past what a decimal holds, the double sum is all there is
+        assert_eq!(float_sum(b"inf", b"1", 1.0), "1");
+        assert_eq!(
+            float_sum(b"1e300", b"1e-300", 1e300),
+            format!("1{}", "0".repeat(300))
+        );
+    }
+
+    #[test]
+    fn test_mget_mset() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("mset a 1 b 2"), ok());
+        t.backend.dbs[0]
+            .get_or_create_list(b"l")
+            .unwrap()
+            .push_back(b"x".to_vec());
+        assert_eq!(
+            t.run("mget a missing b l"),
+            RespArray::new(vec![bulk("1"), nil(), bulk("2"), nil()]).into()
+        );
+        assert_eq!(
+            t.run("mset a 1 b"),
+            CommandError::WrongArity("mset".to_string()).into()
+        );
+    }
+
+    #[test]
+    fn test_msetnx() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("msetnx a 1 b 2"), RespFrame::Integer(1));
+        assert_eq!(t.run("msetnx c 3 a 4"), RespFrame::Integer(0));
+        assert_eq!(t.run("get c"), nil());
+        assert_eq!(t.run("get a"), bulk("1"));
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut t = TestContext::default();
+        t.backend.dbs[0]
+            .get_or_create_list(b"l")
+            .unwrap()
+            .push_back(b"a".to_vec());
+        assert_eq!(t.run("get l"), CommandError::WrongType.into());
+        assert_eq!(t.run("append l x"), CommandError::WrongType.into());
+        assert_eq!(t.run("set l v get"), CommandError::WrongType.into());
+        assert_eq!(t.run("set l v"), ok());
+    }
+}
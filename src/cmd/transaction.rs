@@ -0,0 +1,426 @@
+use crate::{RespArray, RespFrame, RespNullArray};
+
+use super::{ok, Command, CommandArgs, CommandError, CommandExecutor, CommandSpec, Context};
+
+// MULTI
+#[derive(Debug)]
+pub struct Multi;
+
+// EXEC
+#[derive(Debug)]
+pub struct Exec;
+
+// DISCARD
+#[derive(Debug)]
+pub struct Discard;
+
+// WATCH key [key ...]
+#[derive(Debug)]
+pub struct Watch {
+    keys: Vec<Vec<u8>>,
+}
+
+// UNWATCH
+#[derive(Debug)]
+pub struct Unwatch;
+
+// the commands a client queued since MULTI, run all at once by EXEC; a
+// command whose arguments did not parse is queued as its error
+#[derive(Debug, Default)]
+pub struct Transaction {
+    commands: Vec<Result<Command, CommandError>>,
+    // a command was rejected while queueing, EXEC will refuse to run the rest
+    aborted: bool,
+}
+
+impl Transaction {
+    pub fn queue(&mut self, cmd: Result<Command, CommandError>) {
+        self.commands.push(cmd);
+    }
+
+    pub fn abort(&mut self) {
+        self.aborted = true;
+    }
+}
+
+impl Command {
+    // the commands that run right away inside MULTI instead of being queued:
+    // the ones ending the transaction, and the ones ending the connection's
+    // state along with it
+    pub fn controls_transaction(&self) -> bool {
+        matches!(
+            self,
+            Command::Exec(_) | Command::Discard(_) | Command::Quit(_) | Command::Reset(_)
+        )
+    }
+
+    // the error of a command that can't be sent inside MULTI at all; the
+    // transaction stays open, as in Redis
+    pub fn refused_in_transaction(&self) -> Option<CommandError> {
+        match self {
+            Command::Multi(_) => Some(CommandError::err("MULTI calls can not be nested")),
+            Command::Watch(_) => Some(CommandError::err("WATCH inside MULTI is not allowed")),
+            _ => None,
+        }
+    }
+}
+
+impl CommandSpec for Multi {
+    const NAME: &'static str = "multi";
+    const ARITY: i64 = 1;
+
+    fn parse(_args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Multi)
+    }
+}
+
+impl CommandExecutor for Multi {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        ctx.client.transaction = Some(Transaction::default());
+        Ok(ok())
+    }
+}
+
+impl CommandSpec for Exec {
+    const NAME: &'static str = "exec";
+    const ARITY: i64 = 1;
+
+    fn parse(_args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Exec)
+    }
+}
+
+impl CommandExecutor for Exec {
+    // the queued commands run back to back under the lock, so no other client
+    // sees the keyspace halfway through; a command failing does not stop the
+    // ones after it, its error just takes its place in the reply
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let transaction = ctx
+            .client
+            .transaction
+            .take()
+            .ok_or_else(|| CommandError::err("EXEC without MULTI"))?;
+        let watch_broken = watch_broken(ctx);
+        ctx.backend.watches.unwatch_all(ctx.client.id);
+        if transaction.aborted {
+            return Err(CommandError::Reply(
+                "EXECABORT Transaction discarded because of previous errors.".to_string(),
+            ));
+        }
+        if watch_broken {
+            return Ok(RespNullArray.into());
+        }
+        let mut replies = Vec::with_capacity(transaction.commands.len());
+        for cmd in transaction.commands {
+            let reply = cmd
+                .and_then(|cmd| cmd.execute(ctx))
+                .unwrap_or_else(Into::into);
+            // a blocking command does not wait inside a transaction, it
+            // answers as if it timed out right away
+            ctx.block = None;
+            // the extra replies of a command like SUBSCRIBE take their own slots
+            replies.append(&mut ctx.replies);
+            replies.push(reply);
+        }
+        Ok(RespArray::new(replies).into())
+    }
+}
+
+// whether a key the client watches was written since it was watched; a key
+// that expired meanwhile counts as written, so look at them all first
+fn watch_broken(ctx: &mut Context) -> bool {
+    let id = ctx.client.id;
+    let keys = ctx.backend.watches.keys(id).to_vec();
+    for (db, key) in &keys {
+        ctx.backend.dbs[*db].contains_key(key);
+    }
+    ctx.backend.handle_touched_keys();
+    ctx.backend.watches.is_dirty(id)
+}
+
+impl CommandSpec for Discard {
+    const NAME: &'static str = "discard";
+    const ARITY: i64 = 1;
+
+    fn parse(_args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Discard)
+    }
+}
+
+impl CommandExecutor for Discard {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        if ctx.client.transaction.take().is_none() {
+            return Err(CommandError::err("DISCARD without MULTI"));
+        }
+        ctx.backend.watches.unwatch_all(ctx.client.id);
+        Ok(ok())
+    }
+}
+
+impl CommandSpec for Watch {
+    const NAME: &'static str = "watch";
+    const ARITY: i64 = -2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Watch { keys: args.rest() })
+    }
+}
+
+impl CommandExecutor for Watch {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        // expire what is already due first, so that the deletion does not
+        // count against the watch about to start
+        for key in &self.keys {
+            ctx.db().contains_key(key);
+        }
+        ctx.backend.handle_touched_keys();
+        let (id, db) = (ctx.client.id, ctx.client.db);
+        for key in self.keys {
+            ctx.backend.watches.watch(id, db, key);
+        }
+        Ok(ok())
+    }
+}
+
+impl CommandSpec for Unwatch {
+    const NAME: &'static str = "unwatch";
+    const ARITY: i64 = 1;
+
+    fn parse(_args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(Unwatch)
+    }
+}
+
+impl CommandExecutor for Unwatch {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        ctx.backend.watches.unwatch_all(ctx.client.id);
+        Ok(ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::TestContext;
+    use crate::{BulkString, Client, SimpleString};
+
+    fn int(n: i64) -> RespFrame {
+        RespFrame::Integer(n)
+    }
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    fn queued() -> RespFrame {
+        SimpleString::new("QUEUED").into()
+    }
+
+    #[test]
+    fn test_multi_exec() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("multi"), ok());
+        assert_eq!(t.run("set a 1"), queued());
+        assert_eq!(t.run("incr a"), queued());
+        assert_eq!(t.run("lpush a x"), queued());
+        assert_eq!(t.run("get a"), queued());
+        assert_eq!(
+            t.run("multi"),
+            CommandError::err("MULTI calls can not be nested").into()
+        );
+        // nothing ran yet
+        assert_eq!(t.backend.dbs[0].len(), 0);
+        assert_eq!(
+            t.run("exec"),
+            RespArray::new(vec![
+                ok(),
+                int(2),
+                CommandError::WrongType.into(),
+                bulk("2"),
+            ])
+            .into()
+        );
+        assert_eq!(
+            t.run("exec"),
+            CommandError::err("EXEC without MULTI").into()
+        );
+        assert_eq!(t.run("multi"), ok());
+        assert_eq!(t.run("exec"), RespArray::new(vec![]).into());
+
+        // WATCH is refused before it runs, and the transaction goes on
+        t.run("multi");
+        assert_eq!(
+            t.run("watch a"),
+            CommandError::err("WATCH inside MULTI is not allowed").into()
+        );
+        assert!(t.backend.watches.is_empty());
+        assert_eq!(t.run("get a"), queued());
+        assert_eq!(t.run("exec"), RespArray::new(vec![bulk("2")]).into());
+
+        // a blocking command answers at once instead of waiting
+        t.run("multi");
+        t.run("blpop l 0");
+        assert_eq!(
+            t.run("exec"),
+            RespArray::new(vec![RespNullArray.into()]).into()
+        );
+        assert!(t.backend.blocking.is_empty());
+    }
+
+    #[test]
+    fn test_discard_and_execabort() {
+        let mut t = TestContext::default();
+        assert_eq!(
+            t.run("discard"),
+            CommandError::err("DISCARD without MULTI").into()
+        );
+        t.run("multi");
+        t.run("set a 1");
+        assert_eq!(t.run("discard"), ok());
+        assert_eq!(t.run("exists a"), int(0));
+
+        t.run("multi");
+        t.run("set a 1");
+        assert_eq!(
+            t.run("set a"),
+            CommandError::WrongArity("set".to_string()).into()
+        );
+        assert!(matches!(t.run("nosuchcommand"), RespFrame::Error(_)));
+        assert_eq!(
+            t.run("exec"),
+            CommandError::Reply(
+                "EXECABORT Transaction discarded because of previous errors.".to_string()
+            )
+            .into()
+        );
+        assert_eq!(t.run("exists a"), int(0));
+        assert!(t.client.transaction.is_none());
+    }
+
+    #[test]
+    fn test_bad_arguments_queued() {
+        let mut t = TestContext::default();
+        t.run("multi");
+        assert_eq!(t.run("set a 1"), queued());
+        // arguments that don't parse only fail their own command
+        assert_eq!(t.run("set k v ex notanumber"), queued());
+        assert_eq!(t.run("incrby a x"), queued());
+        assert_eq!(t.run("incr a"), queued());
+        assert_eq!(
+            t.run("exec"),
+            RespArray::new(vec![
+                ok(),
+                CommandError::NotInteger.into(),
+                CommandError::NotInteger.into(),
+                int(2),
+            ])
+            .into()
+        );
+        assert_eq!(t.run("exists k"), int(0));
+    }
+
+    #[test]
+    fn test_watch() {
+        let mut t = TestContext::default();
+        let mut other = Client::new();
+        t.run("set balance 10");
+        assert_eq!(t.run("watch balance"), ok());
+        t.run("multi");
+        t.run("decrby balance 3");
+        assert_eq!(
+            t.run("watch balance"),
+            CommandError::err("WATCH inside MULTI is not allowed").into()
+        );
+        std::mem::swap(&mut t.client, &mut other);
+        t.run("incr balance");
+        std::mem::swap(&mut t.client, &mut other);
+        assert_eq!(t.run("exec"), RespNullArray.into());
+        assert_eq!(t.run("get balance"), bulk("11"));
+
+        // EXEC forgets the watch, whether it ran or not
+        t.run("multi");
+        t.run("decrby balance 3");
+        assert_eq!(t.run("exec"), RespArray::new(vec![int(8)]).into());
+        assert!(t.backend.watches.is_empty());
+
+        // a write to the same key in another database leaves the watch alone
+        t.run("watch balance");
+        t.run("select 1");
+        t.run("set balance 0");
+        t.run("select 0");
+        t.run("multi");
+        t.run("get balance");
+        assert_eq!(t.run("exec"), RespArray::new(vec![bulk("8")]).into());
+
+        // the client's own writes break it like anyone else's
+        t.run("watch balance");
+        t.run("set balance 1");
+        t.run("multi");
+        t.run("get balance");
+        assert_eq!(t.run("exec"), RespNullArray.into());
+
+        // UNWATCH drops the watch, a missing key that stays missing is fine
+        t.run("watch balance missing");
+        t.run("set balance 2");
+        assert_eq!(t.run("unwatch"), ok());
+        t.run("watch missing");
+        t.run("multi");
+        t.run("get balance");
+        assert_eq!(t.run("exec"), RespArray::new(vec![bulk("2")]).into());
+
+        // a key flushed away is a key written
+        t.run("watch balance");
+        t.run("flushdb");
+        t.run("multi");
+        assert_eq!(t.run("exec"), RespNullArray.into());
+    }
+
+    #[test]
+    fn test_watch_failed_and_noop_writes() {
+        let mut t = TestContext::default();
+        let mut other = Client::new();
+        t.run("hset h f v");
+        t.run("rpush l a b");
+        t.run("watch h l");
+        // writes that fail or change nothing leave the watched keys alone
+        std::mem::swap(&mut t.client, &mut other);
+        assert_eq!(t.run("lpush h x"), CommandError::WrongType.into());
+        assert_eq!(t.run("hdel h missing"), int(0));
+        assert_eq!(t.run("linsert l before nopivot x"), int(-1));
+        assert_eq!(t.run("lrem l 0 nomatch"), int(0));
+        assert_eq!(t.run("ltrim l 0 -1"), ok());
+        std::mem::swap(&mut t.client, &mut other);
+        t.run("multi");
+        t.run("hget h f");
+        assert_eq!(t.run("exec"), RespArray::new(vec![bulk("v")]).into());
+
+        // a real one still aborts
+        t.run("watch l");
+        std::mem::swap(&mut t.client, &mut other);
+        assert_eq!(t.run("lrem l 0 a"), int(1));
+        std::mem::swap(&mut t.client, &mut other);
+        t.run("multi");
+        t.run("llen l");
+        assert_eq!(t.run("exec"), RespNullArray.into());
+    }
+
+    #[test]
+    fn test_watch_expired_key() {
+        let mut t = TestContext::default();
+        t.run("set k v");
+        t.run("pexpire k 1");
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        // already expired when watched: still missing at EXEC, nothing changed
+        t.run("watch k");
+        t.run("multi");
+        assert_eq!(t.run("exec"), RespArray::new(vec![]).into());
+
+        // expiring between WATCH and EXEC counts as a change
+        t.run("set k v");
+        t.run("pexpire k 20");
+        t.run("watch k");
+        std::thread::sleep(std::time::Duration::from_millis(30));
+        t.run("multi");
+        assert_eq!(t.run("exec"), RespNullArray.into());
+    }
+}
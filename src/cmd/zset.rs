@@ -0,0 +1,1539 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::backend::{Db, DictSet, LexBound, LexRange, ScoreRange, Value, ZSet};
+use crate::{BulkString, RespArray, RespFrame, RespNullArray};
+
+use super::{
+    format_float, list_range, nil, parse_f64, parse_mpop_count, parse_numkeys, parse_timeout,
+    scan_reply, BlockingCommand, CommandArgs, CommandError, CommandExecutor, CommandSpec, Context,
+    ScanArgs, SetOp,
+};
+
+// members with their scores, in reply order
+pub type ScoredMembers = Vec<(Vec<u8>, f64)>;
+
+// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+#[derive(Debug)]
+pub struct ZAdd {
+    key: Vec<u8>,
+    flags: ZAddFlags,
+    ch: bool,
+    incr: bool,
+    pairs: Vec<(f64, Vec<u8>)>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ZAddFlags {
+    pub(crate) nx: bool,
+    pub(crate) xx: bool,
+    gt: bool,
+    lt: bool,
+}
+
+// ZREM key member [member ...]
+#[derive(Debug)]
+pub struct ZRem {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+}
+
+// ZSCORE key member
+#[derive(Debug)]
+pub struct ZScore {
+    key: Vec<u8>,
+    member: Vec<u8>,
+}
+
+// ZMSCORE key member [member ...]
+#[derive(Debug)]
+pub struct ZMScore {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+}
+
+// ZINCRBY key increment member
+#[derive(Debug)]
+pub struct ZIncrBy {
+    key: Vec<u8>,
+    increment: f64,
+    member: Vec<u8>,
+}
+
+// ZCARD key
+#[derive(Debug)]
+pub struct ZCard {
+    key: Vec<u8>,
+}
+
+// ZCOUNT key min max
+#[derive(Debug)]
+pub struct ZCount {
+    key: Vec<u8>,
+    range: ScoreRange,
+}
+
+// ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES]
+#[derive(Debug)]
+pub struct ZRange {
+    key: Vec<u8>,
+    range: RangeSpec,
+    with_scores: bool,
+}
+
+// ZRANGESTORE dst src min max [BYSCORE | BYLEX] [REV] [LIMIT offset count]
+#[derive(Debug)]
+pub struct ZRangeStore {
+    destination: Vec<u8>,
+    source: Vec<u8>,
+    range: RangeSpec,
+}
+
+// ZMPOP numkeys key [key ...] MIN | MAX [COUNT count]
+#[derive(Debug)]
+pub struct ZMPop {
+    keys: Vec<Vec<u8>>,
+    max: bool,
+    count: usize,
+}
+
+// BZMPOP timeout numkeys key [key ...] MIN | MAX [COUNT count]
+#[derive(Debug)]
+pub struct BZMPop {
+    inner: ZMPop,
+    timeout: Option<Duration>,
+}
+
+// ZSCAN key cursor [MATCH pattern] [COUNT count]
+#[derive(Debug)]
+pub struct ZScan {
+    key: Vec<u8>,
+    args: ScanArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+// the inputs of ZUNION, ZINTER, ZDIFF and their STORE variants
+#[derive(Debug)]
+pub(crate) struct ZSetOpArgs {
+    keys: Vec<Vec<u8>>,
+    weights: Vec<f64>,
+    aggregate: Aggregate,
+}
+
+// what a ZRANGE start and stop mean
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum RangeBy {
+    Rank(i64, i64),
+    Score(ScoreRange),
+    Lex(LexRange),
+}
+
+// the range options shared by ZRANGE and ZRANGESTORE
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RangeSpec {
+    by: RangeBy,
+    rev: bool,
+    limit: Option<(i64, i64)>,
+}
+
+// a score: a double on RESP3, a bulk string on RESP2
+pub(crate) fn score_reply(resp3: bool, score: f64) -> RespFrame {
+    if resp3 {
+        RespFrame::Double(score)
+    } else {
+        BulkString::new(format_float(score)).into()
+    }
+}
+
+// members, optionally with their scores: interleaved on RESP2, as
+// [member, score] pairs on RESP3
+pub(crate) fn scored_reply(resp3: bool, with_scores: bool, items: ScoredMembers) -> RespFrame {
+    let mut arr = Vec::with_capacity(items.len());
+    for (member, score) in items {
+        let member: RespFrame = BulkString::new(member).into();
+        match (with_scores, resp3) {
+            (false, _) => arr.push(member),
+            (true, true) => arr.push(RespArray::new(vec![member, score_reply(true, score)]).into()),
+            (true, false) => {
+                arr.push(member);
+                arr.push(score_reply(false, score));
+            }
+        }
+    }
+    RespArray::new(arr).into()
+}
+
+// one end of a score range: a float, -inf/+inf, or exclusive when prefixed by (
+fn parse_score_bound(buf: &[u8]) -> Result<(f64, bool), CommandError> {
+    let err = || CommandError::err("min or max is not a float");
+    match buf.strip_prefix(b"(") {
+        Some(rest) => parse_f64(rest).map(|v| (v, true)).map_err(|_| err()),
+        None => parse_f64(buf).map(|v| (v, false)).map_err(|_| err()),
+    }
+}
+
+pub(crate) fn parse_score_range(min: &[u8], max: &[u8]) -> Result<ScoreRange, CommandError> {
+    let (min, min_exclusive) = parse_score_bound(min)?;
+    let (max, max_exclusive) = parse_score_bound(max)?;
+    Ok(ScoreRange {
+        min,
+        min_exclusive,
+        max,
+        max_exclusive,
+    })
+}
+
+// one end of a lex range: - and + for the open ends, else [member or (member
+fn parse_lex_bound(buf: &[u8]) -> Result<LexBound, CommandError> {
+    match buf.split_first() {
+        Some((b'-', [])) => Ok(LexBound::NegInf),
+        Some((b'+', [])) => Ok(LexBound::PosInf),
+        Some((b'[', rest)) => Ok(LexBound::Inclusive(rest.to_vec())),
+        Some((b'(', rest)) => Ok(LexBound::Exclusive(rest.to_vec())),
+        _ => Err(CommandError::err("min or max not valid string range item")),
+    }
+}
+
+pub(crate) fn parse_lex_range(min: &[u8], max: &[u8]) -> Result<LexRange, CommandError> {
+    Ok(LexRange {
+        min: parse_lex_bound(min)?,
+        max: parse_lex_bound(max)?,
+    })
+}
+
+impl RangeSpec {
+    // parse start stop and the options after them, returning whether
+    // WITHSCORES was given
+    pub(crate) fn parse(args: &mut CommandArgs) -> Result<(RangeSpec, bool), CommandError> {
+        let start = args.next_bytes()?;
+        let stop = args.next_bytes()?;
+        let (mut by_score, mut by_lex, mut rev, mut with_scores) = (false, false, false, false);
+        let mut limit = None;
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                "BYSCORE" => by_score = true,
+                "BYLEX" => by_lex = true,
+                "REV" => rev = true,
+                "WITHSCORES" => with_scores = true,
+                "LIMIT" => limit = Some((args.next_i64()?, args.next_i64()?)),
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        if by_score && by_lex {
+            return Err(CommandError::Syntax);
+        }
+        if limit.is_some() && !by_score && !by_lex {
+            return Err(CommandError::err(
+                "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+            ));
+        }
+        if with_scores && by_lex {
+            return Err(CommandError::err(
+                "syntax error, WITHSCORES not supported in combination with BYLEX",
+            ));
+        }
+        // with REV the score and lex ranges are given from max to min
+        let (min, max) = if rev {
+            (&stop, &start)
+        } else {
+            (&start, &stop)
+        };
+        let by = if by_score {
+            RangeBy::Score(parse_score_range(min, max)?)
+        } else if by_lex {
+            RangeBy::Lex(parse_lex_range(min, max)?)
+        } else {
+            let start = super::parse_i64(&start)?;
+            let stop = super::parse_i64(&stop)?;
+            RangeBy::Rank(start, stop)
+        };
+        Ok((RangeSpec { by, rev, limit }, with_scores))
+    }
+
+    // the selected members and their scores, in reply order
+    pub(crate) fn select(&self, zset: &ZSet) -> ScoredMembers {
+        // the rank to start from, in the direction of the range, and how many to take
+        let (rank, count) = match &self.by {
+            RangeBy::Rank(start, stop) => match list_range(zset.len(), *start, *stop) {
+                Some((start, stop)) => (start, stop - start + 1),
+                None => return vec![],
+            },
+            RangeBy::Score(range) => match zset.score_range(range, self.rev) {
+                Some(found) => found,
+                None => return vec![],
+            },
+            RangeBy::Lex(range) => match zset.lex_range(range, self.rev) {
+                Some(found) => found,
+                None => return vec![],
+            },
+        };
+        // LIMIT skips by rank arithmetic, so offsets cost nothing; a negative
+        // count means all the rest
+        let (rank, count) = match self.limit {
+            None => (rank, count),
+            Some((offset, _)) if offset < 0 || offset as usize >= count => return vec![],
+            Some((offset, limit)) => {
+                let rest = count - offset as usize;
+                let take = if limit < 0 {
+                    rest
+                } else {
+                    rest.min(limit as usize)
+                };
+                (rank + offset as usize, take)
+            }
+        };
+        zset.iter_from_rank(rank, self.rev)
+            .take(count)
+            .map(|(member, score)| (member.to_vec(), score))
+            .collect()
+    }
+}
+
+impl ZAddFlags {
+    fn check(&self) -> Result<(), CommandError> {
+        if self.nx && self.xx {
+            return Err(CommandError::err(
+                "XX and NX options at the same time are not compatible",
+            ));
+        }
+        if (self.gt && self.lt) || (self.nx && (self.gt || self.lt)) {
+            return Err(CommandError::err(
+                "GT, LT, and/or NX options at the same time are not compatible",
+            ));
+        }
+        Ok(())
+    }
+}
+
+// the outcome of adding one member under the ZADD flags
+enum Added {
+    New(f64),
+    Updated(f64),
+    Unchanged(f64),
+    Skipped,
+}
+
+// add or update one member the way ZADD does, incrementing its score when incr
+fn zadd_member(
+    zset: &mut ZSet,
+    flags: ZAddFlags,
+    incr: bool,
+    score: f64,
+    member: &[u8],
+) -> Result<Added, CommandError> {
+    match zset.score(member) {
+        Some(current) => {
+            if flags.nx {
+                return Ok(Added::Skipped);
+            }
+            let new = if incr { current + score } else { score };
+            if new.is_nan() {
+                return Err(CommandError::err("resulting score is not a number (NaN)"));
+            }
+            if (flags.gt && new <= current) || (flags.lt && new >= current) {
+                return Ok(Added::Skipped);
+            }
+            if new == current {
+                return Ok(Added::Unchanged(new));
+            }
+            zset.insert(member.to_vec(), new);
+            Ok(Added::Updated(new))
+        }
+        None if flags.xx => Ok(Added::Skipped),
+        None => {
+            zset.insert(member.to_vec(), score);
+            Ok(Added::New(score))
+        }
+    }
+}
+
+// run a ZADD against key, creating the set only if something gets added;
+// returns how many members were added and changed, and the last resulting score
+pub(crate) fn zadd(
+    db: &mut Db,
+    key: &[u8],
+    flags: ZAddFlags,
+    incr: bool,
+    pairs: &[(f64, Vec<u8>)],
+) -> Result<(usize, usize, Option<f64>), CommandError> {
+    if db.get_zset(key)?.is_none() && flags.xx {
+        return Ok((0, 0, None));
+    }
+    let zset = db.get_or_create_zset(key)?;
+    let (mut added, mut changed, mut last) = (0, 0, None);
+    let mut result = Ok(());
+    for (score, member) in pairs {
+        match zadd_member(zset, flags, incr, *score, member) {
+            Ok(Added::New(score)) => {
+                added += 1;
+                last = Some(score);
+            }
+            Ok(Added::Updated(score)) => {
+                changed += 1;
+                last = Some(score);
+            }
+            Ok(Added::Unchanged(score)) => last = Some(score),
+            Ok(Added::Skipped) => last = None,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+    if added + changed > 0 {
+        db.touch(key);
+    }
+    db.remove_if_empty(key);
+    result.map(|_| (added, changed, last))
+}
+
+impl CommandSpec for ZAdd {
+    const NAME: &'static str = "zadd";
+    const ARITY: i64 = -4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let mut flags = ZAddFlags::default();
+        let (mut ch, mut incr) = (false, false);
+        while let Some(opt) = args.peek_keyword() {
+            match opt.as_str() {
+                "NX" => flags.nx = true,
+                "XX" => flags.xx = true,
+                "GT" => flags.gt = true,
+                "LT" => flags.lt = true,
+                "CH" => ch = true,
+                "INCR" => incr = true,
+                _ => break,
+            }
+            args.next_bytes()?;
+        }
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err(CommandError::Syntax);
+        }
+        flags.check()?;
+        if incr && args.len() > 2 {
+            return Err(CommandError::err(
+                "INCR option supports a single increment-element pair",
+            ));
+        }
+        let mut pairs = Vec::with_capacity(args.len() / 2);
+        while !args.is_empty() {
+            pairs.push((args.next_f64()?, args.next_bytes()?));
+        }
+        Ok(ZAdd {
+            key,
+            flags,
+            ch,
+            incr,
+            pairs,
+        })
+    }
+}
+
+impl CommandExecutor for ZAdd {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let (added, changed, last) = zadd(ctx.db(), &self.key, self.flags, self.incr, &self.pairs)?;
+        if self.incr {
+            return Ok(last.map_or_else(nil, |score| score_reply(resp3, score)));
+        }
+        let count = if self.ch { added + changed } else { added };
+        Ok(RespFrame::Integer(count as i64))
+    }
+}
+
+impl CommandSpec for ZRem {
+    const NAME: &'static str = "zrem";
+    const ARITY: i64 = -3;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(ZRem {
+            key: args.next_bytes()?,
+            members: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for ZRem {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let zset = match db.get_zset_mut(&self.key)? {
+            Some(zset) => zset,
+            None => return Ok(RespFrame::Integer(0)),
+        };
+        let removed = self
+            .members
+            .iter()
+            .filter(|member| zset.remove(member).is_some())
+            .count();
+        if removed > 0 {
+            db.touch(&self.key);
+            db.remove_if_empty(&self.key);
+        }
+        Ok(RespFrame::Integer(removed as i64))
+    }
+}
+
+impl CommandSpec for ZScore {
+    const NAME: &'static str = "zscore";
+    const ARITY: i64 = 3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(ZScore {
+            key: args.next_bytes()?,
+            member: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for ZScore {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let score = ctx
+            .db()
+            .get_zset(&self.key)?
+            .and_then(|zset| zset.score(&self.member));
+        Ok(score.map_or_else(nil, |score| score_reply(resp3, score)))
+    }
+}
+
+impl CommandSpec for ZMScore {
+    const NAME: &'static str = "zmscore";
+    const ARITY: i64 = -3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(ZMScore {
+            key: args.next_bytes()?,
+            members: args.rest(),
+        })
+    }
+}
+
+impl CommandExecutor for ZMScore {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let zset = ctx.db().get_zset(&self.key)?;
+        let scores = self
+            .members
+            .iter()
+            .map(|member| {
+                zset.and_then(|zset| zset.score(member))
+                    .map_or_else(nil, |score| score_reply(resp3, score))
+            })
+            .collect::<Vec<_>>();
+        Ok(RespArray::new(scores).into())
+    }
+}
+
+impl CommandSpec for ZIncrBy {
+    const NAME: &'static str = "zincrby";
+    const ARITY: i64 = 4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(ZIncrBy {
+            key: args.next_bytes()?,
+            increment: args.next_f64()?,
+            member: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for ZIncrBy {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let pairs = [(self.increment, self.member)];
+        let (_, _, last) = zadd(ctx.db(), &self.key, ZAddFlags::default(), true, &pairs)?;
+        Ok(last.map_or_else(nil, |score| score_reply(resp3, score)))
+    }
+}
+
+impl CommandSpec for ZCard {
+    const NAME: &'static str = "zcard";
+    const ARITY: i64 = 2;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(ZCard {
+            key: args.next_bytes()?,
+        })
+    }
+}
+
+impl CommandExecutor for ZCard {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let len = ctx.db().get_zset(&self.key)?.map_or(0, |zset| zset.len());
+        Ok(RespFrame::Integer(len as i64))
+    }
+}
+
+impl CommandSpec for ZCount {
+    const NAME: &'static str = "zcount";
+    const ARITY: i64 = 4;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let range = parse_score_range(&args.next_bytes()?, &args.next_bytes()?)?;
+        Ok(ZCount { key, range })
+    }
+}
+
+impl CommandExecutor for ZCount {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let count = ctx
+            .db()
+            .get_zset(&self.key)?
+            .map_or(0, |zset| zset.count_in_score_range(&self.range));
+        Ok(RespFrame::Integer(count as i64))
+    }
+}
+
+// ZRANK and ZREVRANK: the rank of a member, optionally with its score
+macro_rules! rank_command {
+    ($ty:ident, $name:literal, $rev:literal) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            key: Vec<u8>,
+            member: Vec<u8>,
+            with_score: bool,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -3;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                let key = args.next_bytes()?;
+                let member = args.next_bytes()?;
+                let with_score = match args.next_keyword() {
+                    Some(opt) if opt == "WITHSCORE" => true,
+                    Some(_) => return Err(CommandError::Syntax),
+                    None => false,
+                };
+                args.finish()?;
+                Ok($ty {
+                    key,
+                    member,
+                    with_score,
+                })
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                let resp3 = ctx.client.is_resp3();
+                let zset = ctx.db().get_zset(&self.key)?;
+                let found = zset.and_then(|zset| {
+                    let rank = zset.rank(&self.member, $rev)?;
+                    Some((rank, zset.score(&self.member)?))
+                });
+                Ok(match (found, self.with_score) {
+                    (Some((rank, _)), false) => RespFrame::Integer(rank as i64),
+                    (Some((rank, score)), true) => RespArray::new(vec![
+                        RespFrame::Integer(rank as i64),
+                        score_reply(resp3, score),
+                    ])
+                    .into(),
+                    (None, false) => nil(),
+                    (None, true) => RespNullArray.into(),
+                })
+            }
+        }
+    };
+}
+
+rank_command!(ZRank, "zrank", false);
+rank_command!(ZRevRank, "zrevrank", true);
+
+impl CommandSpec for ZRange {
+    const NAME: &'static str = "zrange";
+    const ARITY: i64 = -4;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let key = args.next_bytes()?;
+        let (range, with_scores) = RangeSpec::parse(args)?;
+        Ok(ZRange {
+            key,
+            range,
+            with_scores,
+        })
+    }
+}
+
+impl CommandExecutor for ZRange {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        let items = ctx
+            .db()
+            .get_zset(&self.key)?
+            .map(|zset| self.range.select(zset))
+            .unwrap_or_default();
+        Ok(scored_reply(resp3, self.with_scores, items))
+    }
+}
+
+impl CommandSpec for ZRangeStore {
+    const NAME: &'static str = "zrangestore";
+    const ARITY: i64 = -5;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let destination = args.next_bytes()?;
+        let source = args.next_bytes()?;
+        let (range, with_scores) = RangeSpec::parse(args)?;
+        if with_scores {
+            return Err(CommandError::Syntax);
+        }
+        Ok(ZRangeStore {
+            destination,
+            source,
+            range,
+        })
+    }
+}
+
+impl CommandExecutor for ZRangeStore {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let db = ctx.db();
+        let items = db
+            .get_zset(&self.source)?
+            .map(|zset| self.range.select(zset))
+            .unwrap_or_default();
+        let len = items.len();
+        store_zset(db, self.destination, items);
+        Ok(RespFrame::Integer(len as i64))
+    }
+}
+
+// store the members at destination as a new sorted set, deleting it when there are none
+pub(crate) fn store_zset(db: &mut Db, destination: Vec<u8>, items: ScoredMembers) {
+    if items.is_empty() {
+        db.remove(&destination);
+        return;
+    }
+    let mut zset = ZSet::new();
+    for (member, score) in items {
+        zset.insert(member, score);
+    }
+    db.insert(destination, Value::ZSet(zset));
+}
+
+// a sorted set operation input: sorted sets, or plain sets whose members all score 1
+enum Input<'a> {
+    ZSet(&'a ZSet),
+    Set(&'a DictSet<Vec<u8>>),
+}
+
+impl Input<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Input::ZSet(zset) => zset.len(),
+            Input::Set(set) => set.len(),
+        }
+    }
+
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        match self {
+            Input::ZSet(zset) => zset.score(member),
+            Input::Set(set) => set.contains(member).then_some(1.0),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&[u8], f64)> + '_> {
+        match self {
+            Input::ZSet(zset) => Box::new(zset.iter()),
+            Input::Set(set) => Box::new(set.iter().map(|member| (member.as_slice(), 1.0))),
+        }
+    }
+}
+
+impl Aggregate {
+    fn apply(self, acc: f64, score: f64) -> f64 {
+        match self {
+            // inf + -inf counts as 0, like Redis does
+            Aggregate::Sum => Some(acc + score).filter(|v| !v.is_nan()).unwrap_or(0.0),
+            Aggregate::Min => acc.min(score),
+            Aggregate::Max => acc.max(score),
+        }
+    }
+}
+
+impl ZSetOpArgs {
+    // numkeys key [key ...] [WEIGHTS weight ...] [AGGREGATE SUM | MIN | MAX],
+    // returning whether WITHSCORES was given; ZDIFF takes neither WEIGHTS nor AGGREGATE
+    fn parse(
+        args: &mut CommandArgs,
+        op: SetOp,
+        allow_with_scores: bool,
+    ) -> Result<(ZSetOpArgs, bool), CommandError> {
+        let numkeys = args.next_i64()?;
+        if numkeys <= 0 {
+            return Err(CommandError::err(format!(
+                "at least 1 input key is needed for '{}' command",
+                args.name()
+            )));
+        }
+        if numkeys as usize > args.len() {
+            return Err(CommandError::Syntax);
+        }
+        let keys = (0..numkeys)
+            .map(|_| args.next_bytes())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut weights = vec![1.0; keys.len()];
+        let mut aggregate = Aggregate::Sum;
+        let mut with_scores = false;
+        while let Some(opt) = args.next_keyword() {
+            match opt.as_str() {
+                "WEIGHTS" if op != SetOp::Diff => {
+                    for weight in weights.iter_mut() {
+                        *weight = args
+                            .next_bytes()
+                            .and_then(|w| parse_f64(&w))
+                            .map_err(|_| CommandError::err("weight value is not a float"))?;
+                    }
+                }
+                "AGGREGATE" if op != SetOp::Diff => {
+                    aggregate = match args.next_keyword().as_deref() {
+                        Some("SUM") => Aggregate::Sum,
+                        Some("MIN") => Aggregate::Min,
+                        Some("MAX") => Aggregate::Max,
+                        _ => return Err(CommandError::Syntax),
+                    }
+                }
+                "WITHSCORES" if allow_with_scores => with_scores = true,
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok((
+            ZSetOpArgs {
+                keys,
+                weights,
+                aggregate,
+            },
+            with_scores,
+        ))
+    }
+
+    // the resulting members and scores, in no particular order
+    fn compute(&self, db: &mut Db, op: SetOp) -> Result<ScoredMembers, CommandError> {
+        for key in &self.keys {
+            match db.get(key) {
+                None | Some(Value::ZSet(_)) | Some(Value::Set(_)) => {}
+                Some(_) => return Err(CommandError::WrongType),
+            }
+        }
+        let db = &*db;
+        let inputs = self
+            .keys
+            .iter()
+            .map(|key| match db.peek(key) {
+                Some(Value::ZSet(zset)) => Some(Input::ZSet(zset)),
+                Some(Value::Set(set)) => Some(Input::Set(set)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // inf * 0 counts as 0
+        let weigh =
+            |score: f64, weight: f64| Some(score * weight).filter(|v| !v.is_nan()).unwrap_or(0.0);
+
+        Ok(match op {
+            SetOp::Union => {
+                let mut result: HashMap<&[u8], f64> = HashMap::new();
+                for (input, &weight) in inputs.iter().zip(&self.weights) {
+                    for (member, score) in input.iter().flat_map(|input| input.iter()) {
+                        let score = weigh(score, weight);
+                        result
+                            .entry(member)
+                            .and_modify(|acc| *acc = self.aggregate.apply(*acc, score))
+                            .or_insert(score);
+                    }
+                }
+                result
+                    .into_iter()
+                    .map(|(member, score)| (member.to_vec(), score))
+                    .collect()
+            }
+            SetOp::Inter => {
+                // a missing key makes the intersection empty
+                let mut inputs = match inputs.into_iter().collect::<Option<Vec<_>>>() {
+                    Some(inputs) => inputs.into_iter().zip(&self.weights).collect::<Vec<_>>(),
+                    None => return Ok(vec![]),
+                };
+                inputs.sort_by_key(|(input, _)| input.len());
+                let ((first, first_weight), rest) = match inputs.split_first() {
+                    Some(split) => split,
+                    None => return Ok(vec![]),
+                };
+                first
+                    .iter()
+                    .filter_map(|(member, score)| {
+                        let mut acc = weigh(score, **first_weight);
+                        for (input, &weight) in rest {
+                            let score = weigh(input.score(member)?, weight);
+                            acc = self.aggregate.apply(acc, score);
+                        }
+                        Some((member.to_vec(), acc))
+                    })
+                    .collect()
+            }
+            SetOp::Diff => {
+                let (first, rest) = match inputs.split_first() {
+                    Some((Some(first), rest)) => (first, rest),
+                    _ => return Ok(vec![]),
+                };
+                first
+                    .iter()
+                    .filter(|(member, _)| {
+                        !rest
+                            .iter()
+                            .flatten()
+                            .any(|input| input.score(member).is_some())
+                    })
+                    .map(|(member, score)| (member.to_vec(), score))
+                    .collect()
+            }
+        })
+    }
+}
+
+// ZUNION, ZINTER and ZDIFF: reply with the result, sorted by score
+macro_rules! zset_op_command {
+    ($ty:ident, $name:literal, $op:expr) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            inputs: ZSetOpArgs,
+            with_scores: bool,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -3;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                let (inputs, with_scores) = ZSetOpArgs::parse(args, $op, true)?;
+                Ok($ty {
+                    inputs,
+                    with_scores,
+                })
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                let resp3 = ctx.client.is_resp3();
+                let mut items = self.inputs.compute(ctx.db(), $op)?;
+                items.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+                Ok(scored_reply(resp3, self.with_scores, items))
+            }
+        }
+    };
+}
+
+// ZUNIONSTORE, ZINTERSTORE and ZDIFFSTORE: store the result at destination
+// and reply with its size
+macro_rules! zset_store_command {
+    ($ty:ident, $name:literal, $op:expr) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            destination: Vec<u8>,
+            inputs: ZSetOpArgs,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -4;
+            const WRITE: bool = true;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                let destination = args.next_bytes()?;
+                let (inputs, _) = ZSetOpArgs::parse(args, $op, false)?;
+                Ok($ty {
+                    destination,
+                    inputs,
+                })
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                let db = ctx.db();
+                let items = self.inputs.compute(db, $op)?;
+                let len = items.len();
+                store_zset(db, self.destination, items);
+                Ok(RespFrame::Integer(len as i64))
+            }
+        }
+    };
+}
+
+zset_op_command!(ZUnion, "zunion", SetOp::Union);
+zset_op_command!(ZInter, "zinter", SetOp::Inter);
+zset_op_command!(ZDiff, "zdiff", SetOp::Diff);
+zset_store_command!(ZUnionStore, "zunionstore", SetOp::Union);
+zset_store_command!(ZInterStore, "zinterstore", SetOp::Inter);
+zset_store_command!(ZDiffStore, "zdiffstore", SetOp::Diff);
+
+// pop up to count members with the lowest or, with max, the highest scores,
+// dropping the key once it is empty; None when the key does not exist
+pub(crate) fn pop_scored(
+    db: &mut Db,
+    key: &[u8],
+    max: bool,
+    count: usize,
+) -> Result<Option<ScoredMembers>, CommandError> {
+    let zset = match db.get_zset_mut(key)? {
+        Some(zset) => zset,
+        None => return Ok(None),
+    };
+    let popped = match max {
+        false => zset.iter().take(count),
+        true => zset.iter_rev().take(count),
+    }
+    .map(|(member, score)| (member.to_vec(), score))
+    .collect::<Vec<_>>();
+    for (member, _) in &popped {
+        zset.remove(member);
+    }
+    if !popped.is_empty() {
+        db.touch(key);
+        db.remove_if_empty(key);
+    }
+    Ok(Some(popped))
+}
+
+// [[member, score], ...], the element layout of ZMPOP and BZMPOP on both protocols
+fn scored_pairs(resp3: bool, items: ScoredMembers) -> RespFrame {
+    RespArray::new(
+        items
+            .into_iter()
+            .map(|(member, score)| {
+                RespArray::new(vec![
+                    BulkString::new(member).into(),
+                    score_reply(resp3, score),
+                ])
+                .into()
+            })
+            .collect::<Vec<RespFrame>>(),
+    )
+    .into()
+}
+
+// MIN | MAX
+fn parse_min_max(args: &mut CommandArgs) -> Result<bool, CommandError> {
+    match args.next_keyword().as_deref() {
+        Some("MIN") => Ok(false),
+        Some("MAX") => Ok(true),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+// ZPOPMIN and ZPOPMAX
+macro_rules! zpop_command {
+    ($ty:ident, $name:literal, $max:literal) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            key: Vec<u8>,
+            count: Option<usize>,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -2;
+            const WRITE: bool = true;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                let key = args.next_bytes()?;
+                let count = match args.is_empty() {
+                    true => None,
+                    false => {
+                        let count = args.next_i64()?;
+                        if count < 0 {
+                            return Err(CommandError::err(
+                                "value is out of range, must be positive",
+                            ));
+                        }
+                        Some(count as usize)
+                    }
+                };
+                if !args.is_empty() {
+                    return Err(CommandError::Syntax);
+                }
+                Ok($ty { key, count })
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                let resp3 = ctx.client.is_resp3();
+                let popped = pop_scored(ctx.db(), &self.key, $max, self.count.unwrap_or(1))?
+                    .unwrap_or_default();
+                // RESP3 groups each member with its score when a count was given
+                if resp3 && self.count.is_some() {
+                    return Ok(scored_pairs(true, popped));
+                }
+                let mut arr = Vec::with_capacity(popped.len() * 2);
+                for (member, score) in popped {
+                    arr.push(BulkString::new(member).into());
+                    arr.push(score_reply(resp3, score));
+                }
+                Ok(RespArray::new(arr).into())
+            }
+        }
+    };
+}
+
+zpop_command!(ZPopMin, "zpopmin", false);
+zpop_command!(ZPopMax, "zpopmax", true);
+
+// BZPOPMIN and BZPOPMAX: pop from the first non-empty sorted set, or wait for one
+macro_rules! blocking_zpop_command {
+    ($ty:ident, $name:literal, $max:literal) => {
+        #[derive(Debug)]
+        pub struct $ty {
+            keys: Vec<Vec<u8>>,
+            timeout: Option<Duration>,
+        }
+
+        impl CommandSpec for $ty {
+            const NAME: &'static str = $name;
+            const ARITY: i64 = -3;
+            const WRITE: bool = true;
+
+            fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+                let mut keys = args.rest();
+                let timeout = parse_timeout(&keys.pop().unwrap_or_default())?;
+                Ok($ty { keys, timeout })
+            }
+        }
+
+        impl BlockingCommand for $ty {
+            fn keys(&self) -> Vec<Vec<u8>> {
+                self.keys.clone()
+            }
+
+            fn try_serve(&self, ctx: &mut Context) -> Result<Option<RespFrame>, CommandError> {
+                let resp3 = ctx.client.is_resp3();
+                for key in &self.keys {
+                    if let Some(popped) = pop_scored(ctx.db(), key, $max, 1)? {
+                        if let Some((member, score)) = popped.into_iter().next() {
+                            return Ok(Some(
+                                RespArray::new(vec![
+                                    BulkString::new(key.clone()).into(),
+                                    BulkString::new(member).into(),
+                                    score_reply(resp3, score),
+                                ])
+                                .into(),
+                            ));
+                        }
+                    }
+                }
+                Ok(None)
+            }
+
+            fn timeout_reply(&self) -> RespFrame {
+                RespNullArray.into()
+            }
+        }
+
+        impl CommandExecutor for $ty {
+            fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+                match self.try_serve(ctx)? {
+                    Some(reply) => Ok(reply),
+                    None => {
+                        let timeout = self.timeout;
+                        ctx.block(self, timeout)
+                    }
+                }
+            }
+        }
+    };
+}
+
+blocking_zpop_command!(BZPopMin, "bzpopmin", false);
+blocking_zpop_command!(BZPopMax, "bzpopmax", true);
+
+impl ZMPop {
+    // pop from the first non-empty sorted set, replying [key, [[member, score], ...]]
+    fn try_pop(&self, ctx: &mut Context) -> Result<Option<RespFrame>, CommandError> {
+        let resp3 = ctx.client.is_resp3();
+        for key in &self.keys {
+            if let Some(popped) = pop_scored(ctx.db(), key, self.max, self.count)? {
+                return Ok(Some(
+                    RespArray::new(vec![
+                        BulkString::new(key.clone()).into(),
+                        scored_pairs(resp3, popped),
+                    ])
+                    .into(),
+                ));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl CommandSpec for ZMPop {
+    const NAME: &'static str = "zmpop";
+    const ARITY: i64 = -4;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let keys = parse_numkeys(args)?;
+        let max = parse_min_max(args)?;
+        let count = parse_mpop_count(args)?;
+        Ok(ZMPop { keys, max, count })
+    }
+}
+
+impl CommandExecutor for ZMPop {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        Ok(self.try_pop(ctx)?.unwrap_or_else(|| RespNullArray.into()))
+    }
+}
+
+impl CommandSpec for BZMPop {
+    const NAME: &'static str = "bzmpop";
+    const ARITY: i64 = -5;
+    const WRITE: bool = true;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        let timeout = parse_timeout(&args.next_bytes()?)?;
+        let inner = ZMPop::parse(args)?;
+        Ok(BZMPop { inner, timeout })
+    }
+}
+
+impl BlockingCommand for BZMPop {
+    fn keys(&self) -> Vec<Vec<u8>> {
+        self.inner.keys.clone()
+    }
+
+    fn try_serve(&self, ctx: &mut Context) -> Result<Option<RespFrame>, CommandError> {
+        self.inner.try_pop(ctx)
+    }
+
+    fn timeout_reply(&self) -> RespFrame {
+        RespNullArray.into()
+    }
+}
+
+impl CommandExecutor for BZMPop {
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        match self.try_serve(ctx)? {
+            Some(reply) => Ok(reply),
+            None => {
+                let timeout = self.timeout;
+                ctx.block(self, timeout)
+            }
+        }
+    }
+}
+
+impl CommandSpec for ZScan {
+    const NAME: &'static str = "zscan";
+    const ARITY: i64 = -3;
+
+    fn parse(args: &mut CommandArgs) -> Result<Self, CommandError> {
+        Ok(ZScan {
+            key: args.next_bytes()?,
+            args: ScanArgs::parse(args, false)?,
+        })
+    }
+}
+
+impl CommandExecutor for ZScan {
+    // members and scores, interleaved; the scores are strings on both protocols
+    fn execute(self, ctx: &mut Context) -> Result<RespFrame, CommandError> {
+        let zset = match ctx.db().get_zset(&self.key)? {
+            Some(zset) => zset,
+            None => return Ok(scan_reply(0, Vec::new())),
+        };
+        let args = &self.args;
+        let (cursor, found) = args.walk(|cursor, found| {
+            zset.scan(cursor, |member, score| {
+                if args.matches(member) {
+                    found.push(member.to_vec());
+                    found.push(format_float(score).into_bytes());
+                }
+            })
+        });
+        Ok(scan_reply(cursor, found))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Protocol;
+    use crate::cmd::TestContext;
+    use crate::Client;
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    fn bulks(items: &[&str]) -> RespFrame {
+        RespArray::new(items.iter().map(|s| bulk(s)).collect::<Vec<_>>()).into()
+    }
+
+    #[test]
+    fn test_zadd_flags() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("zadd z 1 a 2 b 3 c"), RespFrame::Integer(3));
+        assert_eq!(t.run("zadd z nx 10 a 4 d"), RespFrame::Integer(1));
+        assert_eq!(t.run("zscore z a"), bulk("1"));
+        assert_eq!(t.run("zadd z xx ch 10 a 5 e"), RespFrame::Integer(1));
+        assert_eq!(t.run("zscore z e"), nil());
+        assert_eq!(t.run("zadd z gt ch 5 a 5 b"), RespFrame::Integer(1));
+        assert_eq!(t.run("zscore z a"), bulk("10"));
+        assert_eq!(t.run("zadd z lt ch 1 b"), RespFrame::Integer(1));
+        assert_eq!(t.run("zadd z incr 2.5 b"), bulk("3.5"));
+        assert_eq!(t.run("zadd z nx incr 1 b"), nil());
+        assert_eq!(t.run("zadd missing xx 1 a"), RespFrame::Integer(0));
+        assert_eq!(t.run("zcard missing"), RespFrame::Integer(0));
+        assert_eq!(t.run("zcard z"), RespFrame::Integer(4));
+
+        assert_eq!(
+            t.run("zadd z nx xx 1 a"),
+            CommandError::err("XX and NX options at the same time are not compatible").into()
+        );
+        assert_eq!(
+            t.run("zadd z gt lt 1 a"),
+            CommandError::err("GT, LT, and/or NX options at the same time are not compatible")
+                .into()
+        );
+        assert_eq!(
+            t.run("zadd z incr 1 a 2 b"),
+            CommandError::err("INCR option supports a single increment-element pair").into()
+        );
+        assert_eq!(t.run("zadd z 1 a 2"), CommandError::Syntax.into());
+        assert_eq!(t.run("zadd z x a"), CommandError::NotFloat.into());
+        assert_eq!(t.run("zadd z nan a"), CommandError::NotFloat.into());
+    }
+
+    #[test]
+    fn test_zincrby_and_scores() {
+        let mut t = TestContext::default();
+        assert_eq!(t.run("zincrby z 1.5 a"), bulk("1.5"));
+        assert_eq!(t.run("zincrby z inf a"), bulk("inf"));
+        assert_eq!(
+            t.run("zincrby z -inf a"),
+            CommandError::err("resulting score is not a number (NaN)").into()
+        );
+        assert_eq!(
+            t.run("zmscore z a missing"),
+            RespArray::new(vec![bulk("inf"), nil()]).into()
+        );
+        t.client.protocol = Protocol::Resp3;
+        assert_eq!(t.run("zscore z a"), RespFrame::Double(f64::INFINITY));
+        assert_eq!(t.run("zrem z a b"), RespFrame::Integer(1));
+        assert!(t.backend.dbs[0].is_empty());
+    }
+
+    #[test]
+    fn test_zrank() {
+        let mut t = TestContext::default();
+        t.run("zadd z 1 a 2 b 3 c");
+        assert_eq!(t.run("zrank z a"), RespFrame::Integer(0));
+        assert_eq!(t.run("zrevrank z a"), RespFrame::Integer(2));
+        assert_eq!(
+            t.run("zrank z c withscore"),
+            RespArray::new(vec![RespFrame::Integer(2), bulk("3")]).into()
+        );
+        assert_eq!(t.run("zrank z x"), nil());
+        assert_eq!(t.run("zrank z x withscore"), RespNullArray.into());
+        assert_eq!(t.run("zcount z (1 +inf"), RespFrame::Integer(2));
+        assert_eq!(
+            t.run("zcount z a 1"),
+            CommandError::err("min or max is not a float").into()
+        );
+    }
+
+    #[test]
+    fn test_zrange() {
+        let mut t = TestContext::default();
+        t.run("zadd z 1 a 2 b 3 c 4 d 5 e");
+        assert_eq!(t.run("zrange z 0 1"), bulks(&["a", "b"]));
+        assert_eq!(t.run("zrange z -2 -1 rev"), bulks(&["b", "a"]));
+        assert_eq!(t.run("zrange z 0 0 withscores"), bulks(&["a", "1"]));
+        assert_eq!(t.run("zrange z (1 3 byscore"), bulks(&["b", "c"]));
+        assert_eq!(t.run("zrange z +inf 4 byscore rev"), bulks(&["e", "d"]));
+        assert_eq!(
+            t.run("zrange z -inf +inf byscore limit 1 2"),
+            bulks(&["b", "c"])
+        );
+        assert_eq!(
+            t.run("zrange z +inf -inf byscore rev limit 3 -1"),
+            bulks(&["b", "a"])
+        );
+        assert_eq!(t.run("zrange z 0 -1 byscore limit 9 1"), bulks(&[]));
+
+        t.run("zadd l 0 a 0 b 0 c 0 d");
+        assert_eq!(t.run("zrange l [b (d bylex"), bulks(&["b", "c"]));
+        assert_eq!(
+            t.run("zrange l + - bylex rev limit 0 2"),
+            bulks(&["d", "c"])
+        );
+        assert_eq!(
+            t.run("zrange l b d bylex"),
+            CommandError::err("min or max not valid string range item").into()
+        );
+        assert_eq!(
+            t.run("zrange z 0 1 limit 0 1"),
+            CommandError::err(
+                "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+            )
+            .into()
+        );
+        assert_eq!(
+            t.run("zrange l - + bylex withscores"),
+            CommandError::err("syntax error, WITHSCORES not supported in combination with BYLEX")
+                .into()
+        );
+
+        t.client.protocol = Protocol::Resp3;
+        assert_eq!(
+            t.run("zrange z 0 0 withscores"),
+            RespArray::new(vec![RespArray::new(vec![
+                bulk("a"),
+                RespFrame::Double(1.0)
+            ])
+            .into()])
+            .into()
+        );
+    }
+
+    #[test]
+    fn test_zunion_zinter_zdiff() {
+        let mut t = TestContext::default();
+        t.run("zadd a 1 x 2 y 3 z");
+        t.run("zadd b 10 y 20 z 30 w");
+        t.run("sadd s x y");
+        assert_eq!(
+            t.run("zunion 2 a b withscores"),
+            bulks(&["x", "1", "y", "12", "z", "23", "w", "30"])
+        );
+        assert_eq!(
+            t.run("zinter 2 a b weights 2 1 aggregate max withscores"),
+            bulks(&["y", "10", "z", "20"])
+        );
+        assert_eq!(t.run("zinter 3 a b s withscores"), bulks(&["y", "13"]));
+        assert_eq!(t.run("zinter 2 a missing"), bulks(&[]));
+        assert_eq!(t.run("zdiff 2 a b withscores"), bulks(&["x", "1"]));
+        assert_eq!(t.run("zdiff 2 a s"), bulks(&["z"]));
+
+        assert_eq!(
+            t.run("zunionstore out 2 a b aggregate min"),
+            RespFrame::Integer(4)
+        );
+        assert_eq!(
+            t.run("zrange out 0 -1 withscores"),
+            bulks(&["x", "1", "y", "2", "z", "3", "w", "30"])
+        );
+        assert_eq!(t.run("zinterstore out 2 a missing"), RespFrame::Integer(0));
+        assert_eq!(t.run("zcard out"), RespFrame::Integer(0));
+        assert_eq!(t.run("zdiffstore out 1 b"), RespFrame::Integer(3));
+
+        assert_eq!(
+            t.run("zunion 0 a"),
+            CommandError::err("at least 1 input key is needed for 'zunion' command").into()
+        );
+        assert_eq!(t.run("zunion 3 a b"), CommandError::Syntax.into());
+        assert_eq!(
+            t.run("zunion 2 a b weights 1 x"),
+            CommandError::err("weight value is not a float").into()
+        );
+        assert_eq!(
+            t.run("zdiff 2 a b weights 1 2"),
+            CommandError::Syntax.into()
+        );
+        assert_eq!(
+            t.run("zunionstore out 1 a withscores"),
+            CommandError::Syntax.into()
+        );
+        t.run("set str v");
+        assert_eq!(t.run("zunion 2 a str"), CommandError::WrongType.into());
+    }
+
+    #[test]
+    fn test_zrangestore() {
+        let mut t = TestContext::default();
+        t.run("zadd z 1 a 2 b 3 c");
+        assert_eq!(
+            t.run("zrangestore dst z 2 +inf byscore"),
+            RespFrame::Integer(2)
+        );
+        assert_eq!(
+            t.run("zrange dst 0 -1 withscores"),
+            bulks(&["b", "2", "c", "3"])
+        );
+        assert_eq!(t.run("zrangestore dst z 5 10"), RespFrame::Integer(0));
+        assert_eq!(t.run("zcard dst"), RespFrame::Integer(0));
+    }
+
+    #[test]
+    fn test_zpop_and_zmpop() {
+        let mut t = TestContext::default();
+        t.run("zadd z 1 a 2 b 3 c 4 d");
+        assert_eq!(t.run("zpopmin z"), bulks(&["a", "1"]));
+        assert_eq!(t.run("zpopmax z 2"), bulks(&["d", "4", "c", "3"]));
+        assert_eq!(t.run("zpopmax missing"), bulks(&[]));
+        assert_eq!(
+            t.run("zmpop 2 missing z max count 5"),
+            RespArray::new(vec![
+                bulk("z"),
+                RespArray::new(vec![bulks(&["b", "2"])]).into()
+            ])
+            .into()
+        );
+        assert!(t.backend.dbs[0].is_empty());
+        assert_eq!(t.run("zmpop 1 z min"), RespNullArray.into());
+        assert_eq!(t.run("zmpop 1 z up"), CommandError::Syntax.into());
+
+        t.client.protocol = Protocol::Resp3;
+        t.run("zadd z 1 a 2 b");
+        assert_eq!(
+            t.run("zpopmin z"),
+            RespArray::new(vec![bulk("a"), RespFrame::Double(1.0)]).into()
+        );
+        assert_eq!(
+            t.run("zpopmin z 1"),
+            RespArray::new(vec![RespArray::new(vec![
+                bulk("b"),
+                RespFrame::Double(2.0)
+            ])
+            .into()])
+            .into()
+        );
+    }
+
+    #[test]
+    fn test_blocking_zpop() {
+        let mut t = TestContext::default();
+        t.run("zadd z 1 a 2 b");
+        assert_eq!(t.run("bzpopmax other z 0"), bulks(&["z", "b", "2"]));
+
+        t.run("zpopmin z");
+        let mut min = t.block("bzpopmin z 0");
+        t.client = Client::new();
+        let mut mpop = t.block("bzmpop 0 1 z max count 2");
+        t.client = Client::new();
+        t.run("zadd z 5 x 6 y 7 z");
+        assert_eq!(min.rx.try_recv().unwrap(), bulks(&["z", "x", "5"]));
+        assert_eq!(
+            mpop.rx.try_recv().unwrap(),
+            RespArray::new(vec![
+                bulk("z"),
+                RespArray::new(vec![bulks(&["z", "7"]), bulks(&["y", "6"])]).into()
+            ])
+            .into()
+        );
+        assert!(t.backend.dbs[0].is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use crate::{DEFAULT_BUSY_REPLY_THRESHOLD, DEFAULT_DATABASES};
+
+const DEFAULT_ADDR: &str = "0.0.0.0:6379";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    pub addr: String,
+    pub databases: usize,
+    pub busy_reply_threshold: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            addr: DEFAULT_ADDR.to_string(),
+            databases: DEFAULT_DATABASES,
+            busy_reply_threshold: DEFAULT_BUSY_REPLY_THRESHOLD,
+        }
+    }
+}
+
+impl ServerConfig {
+    // supported flags:
+    //   --addr <host:port>   full listen address
+    //   --bind <host>        listen host, keeps the current port
+    //   --port <port>        listen port, keeps the current host
+    //   --databases <n>      number of databases SELECT can pick from
+    //   --busy-reply-threshold <ms>
+    //                        how long a script runs before other clients get BUSY
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut config = ServerConfig::default();
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value for {}", flag))
+            };
+            match flag.as_str() {
+                "--addr" => config.addr = value()?,
+                "--bind" => {
+                    let host = value()?;
+                    let (_, port) = config.split_addr();
+                    config.addr = format!("{}:{}", host, port);
+                }
+                "--port" => {
+                    let port: u16 = value()?.parse()?;
+                    let (host, _) = config.split_addr();
+                    config.addr = format!("{}:{}", host, port);
+                }
+                "--databases" => {
+                    config.databases = value()?.parse()?;
+                    if config.databases == 0 {
+                        return Err(anyhow!("--databases must be at least 1"));
+                    }
+                }
+                "--busy-reply-threshold" => {
+                    config.busy_reply_threshold = Duration::from_millis(value()?.parse()?);
+                }
+                _ => return Err(anyhow!("unknown argument: {}", flag)),
+            }
+        }
+        Ok(config)
+    }
+
+    fn split_addr(&self) -> (String, String) {
+        match self.addr.rsplit_once(':') {
+            Some((host, port)) => (host.to_string(), port.to_string()),
+            None => (self.addr.clone(), "6379".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_config_from_args() {
+        let config = ServerConfig::from_args(args("")).unwrap();
+        assert_eq!(config.addr, "0.0.0.0:6379");
+        assert_eq!(config.databases, 16);
+
+        let config = ServerConfig::from_args(args("--bind 127.0.0.1 --port 7000")).unwrap();
+        assert_eq!(config.addr, "127.0.0.1:7000");
+
+        let config = ServerConfig::from_args(args("--addr localhost:6380")).unwrap();
+        assert_eq!(config.addr, "localhost:6380");
+
+        let config = ServerConfig::from_args(args("--databases 4")).unwrap();
+        assert_eq!(config.databases, 4);
+
+        let config = ServerConfig::from_args(args("--busy-reply-threshold 100")).unwrap();
+        assert_eq!(config.busy_reply_threshold, Duration::from_millis(100));
+        assert_eq!(
+            ServerConfig::default().busy_reply_threshold,
+            Duration::from_secs(5)
+        );
+
+        assert!(ServerConfig::from_args(args("--port")).is_err());
+        assert!(ServerConfig::from_args(args("--databases 0")).is_err());
+        assert!(ServerConfig::from_args(args("--verbose")).is_err());
+    }
+}
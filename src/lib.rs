@@ -1,5 +1,13 @@
+mod backend;
+mod client;
+mod cmd;
+mod config;
+mod network;
 mod resp;
 
-
-
-pub use resp::*; // Export the module resp
\ No newline at end of file
+pub use backend::*;
+pub use client::*;
+pub use cmd::*;
+pub use config::*;
+pub use network::*;
+pub use resp::*; // Export the module resp
@@ -1,3 +1,27 @@
-fn main() {
-    println!("Hello, world!");
+use anyhow::Result;
+use template::{active_expire, stream_handler, Backend, ServerConfig};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let config = ServerConfig::from_args(std::env::args().skip(1))?;
+    let listener = TcpListener::bind(&config.addr).await?;
+    info!("Redis server listening on {}", config.addr);
+
+    let backend = Backend::new(config.databases, config.busy_reply_threshold);
+    tokio::spawn(active_expire(backend.clone()));
+    loop {
+        let (stream, raddr) = listener.accept().await?;
+        info!("Accepted connection from: {}", raddr);
+        let backend = backend.clone();
+        tokio::spawn(async move {
+            match stream_handler(stream, backend).await {
+                Ok(_) => info!("Connection from {} exited", raddr),
+                Err(e) => warn!("handle error for {}: {:?}", raddr, e),
+            }
+        });
+    }
 }
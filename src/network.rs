@@ -0,0 +1,201 @@
+use anyhow::Result;
+use bytes::BytesMut;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+use crate::backend::{message_queue, Backend, MessageReceiver, PUBSUB_QUEUE_LIMIT};
+use crate::cmd::{self, Blocked, Command, Reply};
+use crate::{Client, RespDecode, RespDecodeError, RespEncode, RespFrame, SimpleError};
+
+const READ_BUF_CAP: usize = 16 * 1024;
+
+// serve one client connection: keep decoding frames from the per-connection buffer,
+// answer every complete request and flush the replies of a pipelined batch together
+pub async fn stream_handler(stream: TcpStream, backend: Backend) -> Result<()> {
+    let (tx, messages) = message_queue(PUBSUB_QUEUE_LIMIT);
+    let mut client = Client::new();
+    client.messages = Some(tx);
+    let guard = DisconnectGuard {
+        backend: &backend,
+        client: Some(client.id),
+    };
+    let result = serve_client(stream, &mut client, messages, &backend).await;
+    guard.disconnect().await;
+    result
+}
+
+// forgets a client however serving it ended: a panic or a dropped task still
+// drops the guard, which then disconnects the client while blocking on the
+// lock. A normal end disconnects without blocking, through disconnect()
+struct DisconnectGuard<'a> {
+    backend: &'a Backend,
+    client: Option<u64>,
+}
+
+impl DisconnectGuard<'_> {
+    async fn disconnect(mut self) {
+        let mut inner = self.backend.lock_async().await;
+        if let Some(client) = self.client.take() {
+            inner.disconnect(client);
+        }
+    }
+}
+
+impl Drop for DisconnectGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.backend.lock().disconnect(client);
+        }
+    }
+}
+
+// besides requests, a subscribed client gets the messages published to it,
+// written out between replies
+async fn serve_client(
+    mut stream: TcpStream,
+    client: &mut Client,
+    mut messages: MessageReceiver,
+    backend: &Backend,
+) -> Result<()> {
+    let mut buf = BytesMut::with_capacity(READ_BUF_CAP);
+    let mut out = Vec::with_capacity(READ_BUF_CAP);
+    loop {
+        loop {
+            match RespFrame::decode(&mut buf) {
+                Ok(frame) => {
+                    let reply = match request_handler(frame, client, backend).await {
+                        Reply::Frame(reply) => reply,
+                        Reply::Close(reply) => {
+                            out.extend_from_slice(&reply.encode());
+                            stream.write_all(&out).await?;
+                            return Ok(());
+                        }
+                        Reply::Frames(replies) => {
+                            for reply in replies {
+                                out.extend_from_slice(&reply.encode());
+                            }
+                            continue;
+                        }
+                        Reply::Blocked(blocked) => {
+                            // the replies queued so far go out before the client waits
+                            if !out.is_empty() {
+                                stream.write_all(&out).await?;
+                                out.clear();
+                            }
+                            match wait_blocked(&mut stream, &mut buf, backend, blocked).await? {
+                                Some(reply) => reply,
+                                None => {
+                                    info!("client closed the connection while blocked");
+                                    return Ok(());
+                                }
+                            }
+                        }
+                    };
+                    out.extend_from_slice(&reply.encode());
+                }
+                Err(RespDecodeError::NotComplete) => break,
+                Err(e) => {
+                    warn!("protocol error: {}", e);
+                    let reply: RespFrame =
+                        SimpleError::new(format!("ERR Protocol error: {}", e)).into();
+                    out.extend_from_slice(&reply.encode());
+                    stream.write_all(&out).await?;
+                    return Ok(());
+                }
+            }
+        }
+        if !out.is_empty() {
+            stream.write_all(&out).await?;
+            out.clear();
+        }
+        tokio::select! {
+            n = stream.read_buf(&mut buf) => {
+                if n? == 0 {
+                    info!("client closed the connection");
+                    return Ok(());
+                }
+            }
+            message = messages.recv() => {
+                let Some(message) = message else {
+                    warn!("closing a subscriber whose pub/sub messages went unread");
+                    return Ok(());
+                };
+                out.extend_from_slice(&cmd::message_frame(message, client.is_resp3()).encode());
+            }
+        }
+    }
+}
+
+async fn request_handler(frame: RespFrame, client: &mut Client, backend: &Backend) -> Reply {
+    let cmd = match frame {
+        RespFrame::Array(args) => Command::try_from(args),
+        _ => {
+            return Reply::Frame(
+                SimpleError::new("ERR Protocol error: expected array of bulk strings").into(),
+            )
+        }
+    };
+    let cmd = match cmd {
+        Ok(cmd) => cmd,
+        Err(e) => return cmd::reject(e, client),
+    };
+    // while a script holds the lock, wait for it without tying up a runtime
+    // thread, until the script is done or has run for long enough that the
+    // server answers BUSY instead
+    loop {
+        if let Some(reply) = cmd::busy_reply(&cmd, backend.running_script()) {
+            return Reply::Frame(reply);
+        }
+        if let Some(mut inner) = backend.try_lock() {
+            // a script runs on a thread of its own, so that the runtime
+            // keeps serving the connections that may want to kill it
+            return match cmd.may_run_script() {
+                true => tokio::task::block_in_place(|| cmd::run(cmd, client, &mut inner)),
+                false => cmd::run(cmd, client, &mut inner),
+            };
+        }
+        backend.wait_lock().await;
+    }
+}
+
+// wait for a blocked command to be served or to time out; whatever the client
+// pipelines meanwhile stays buffered until then. None when the client disconnects
+async fn wait_blocked(
+    stream: &mut TcpStream,
+    buf: &mut BytesMut,
+    backend: &Backend,
+    mut blocked: Blocked,
+) -> Result<Option<RespFrame>> {
+    let deadline = blocked.timeout.map(|t| tokio::time::Instant::now() + t);
+    let timer = async {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(timer);
+    loop {
+        tokio::select! {
+            reply = &mut blocked.rx => {
+                if let Ok(reply) = reply {
+                    return Ok(Some(reply));
+                }
+            }
+            _ = &mut timer => {}
+            n = stream.read_buf(buf) => {
+                if n? != 0 {
+                    continue;
+                }
+                backend.lock_async().await.blocking.unblock(blocked.id);
+                return Ok(None);
+            }
+        }
+        // timed out, unless a writer served the client right before we got the lock
+        let mut inner = backend.lock_async().await;
+        return Ok(Some(match inner.blocking.unblock(blocked.id) {
+            Some(waiter) => waiter.command.timeout_reply(),
+            None => blocked.rx.try_recv().unwrap_or_else(|_| cmd::nil()),
+        }));
+    }
+}
@@ -2,8 +2,6 @@ use std::ops::{Deref, DerefMut};
 
 use bytes::{Buf, BytesMut};
 
-use crate::resp::extract_simple_frame_data;
-
 use super::{calc_total_length, extract_fixed_data, parse_length, RespDecode, RespDecodeError, RespEncode, RespFrame, BUF_CAP, CRLF_LEN};
 
 
@@ -14,7 +12,7 @@ pub struct RespArray(Vec<RespFrame>);
 pub struct RespNullArray;
 
 impl RespArray {
-    fn new(value:impl Into<Vec<RespFrame>> ) -> Self {
+    pub fn new(value:impl Into<Vec<RespFrame>> ) -> Self {
         RespArray(value.into())
     }
 }
@@ -69,6 +67,11 @@ impl RespDecode for RespArray {
         }
         Ok(RespArray::new(frame))
     }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        calc_total_length(buf, end, len, Self::PREFIX)
+    }
 }
 
 impl RespEncode for RespNullArray {
@@ -82,7 +85,14 @@ impl RespDecode for RespNullArray {
     fn decode(buf:&mut BytesMut) -> Result<Self,RespDecodeError> {
         extract_fixed_data(buf, "*-1\r\n", "NullArray")?;
         Ok(RespNullArray)
-    }    
+    }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        if buf.len() < 5 {
+            return Err(RespDecodeError::NotComplete);
+        }
+        Ok(5)
+    }
 }
 
 
@@ -119,6 +129,14 @@ mod tese{
 
         let frame = RespArray::decode(&mut buf).unwrap();
         assert_eq!(frame, RespArray::new([b"set".into(), b"hello".into()]));
+
+        // a bulk string cut in the middle must not consume the array header
+        buf.extend_from_slice(b"*1\r\n$5\r\nhel");
+        let ret = RespArray::decode(&mut buf);
+        assert_eq!(ret.unwrap_err(), RespDecodeError::NotComplete);
+        buf.extend_from_slice(b"lo\r\n");
+        let frame = RespArray::decode(&mut buf).unwrap();
+        assert_eq!(frame, RespArray::new([b"hello".into()]));
     }
 
 
@@ -26,10 +26,16 @@ impl RespDecode for bool {
         }
     }
 
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        if buf.len() < 4 {
+            return Err(RespDecodeError::NotComplete);
+        }
+        Ok(4)
+    }
+
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
 
@@ -46,14 +52,14 @@ mod tests {
     fn test_bool_decode() {
         let mut buf = BytesMut::from("#t\r\n");
         let frame = bool::decode(&mut buf).unwrap();
-        assert_eq!(frame, true);
+        assert!(frame);
 
         let mut buf = BytesMut::from("#f\r\n");
         let frame = bool::decode(&mut buf).unwrap();
-        assert_eq!(frame, false);
+        assert!(!frame);
 
         let mut buf = BytesMut::from("invalid\r\n");
-        let frame = bool::decode(&mut buf).unwrap_err();
+        assert!(bool::decode(&mut buf).is_err());
     }
 }
 
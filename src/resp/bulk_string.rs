@@ -1,8 +1,8 @@
-use std::{ops::Deref, str::FromStr};
+use std::ops::Deref;
 
 use bytes::{Buf, BytesMut};
 
-use super::{extract_fixed_data, extract_simple_frame_data, parse_length, RespDecode, RespDecodeError, RespEncode};
+use super::{extract_fixed_data, parse_length, RespDecode, RespDecodeError, RespEncode, CRLF_LEN, MAX_BULK_LEN};
 
 
 
@@ -19,6 +19,18 @@ impl BulkString {
     }
 }
 
+// the length of a whole bulk string frame whose length line ends at end;
+// a length past MAX_BULK_LEN is refused before anything is added to it
+fn total_length(end: usize, len: usize) -> Result<usize, RespDecodeError> {
+    if len > MAX_BULK_LEN {
+        return Err(RespDecodeError::InvalidBulkLength);
+    }
+    end.checked_add(CRLF_LEN)
+        .and_then(|total| total.checked_add(len))
+        .and_then(|total| total.checked_add(CRLF_LEN))
+        .ok_or(RespDecodeError::InvalidBulkLength)
+}
+
 impl  Deref for BulkString {
     type Target = Vec<u8>;
     fn deref(&self) -> &Self::Target {
@@ -82,14 +94,22 @@ impl RespDecode for BulkString {
     const PREFIX: &'static str="$";
     fn decode(buf:&mut BytesMut) -> Result<Self,RespDecodeError> {
         let (end,len)=parse_length(buf, Self::PREFIX)?;
-        let remained = &buf[end + 2..];
-        if remained.len() < len + 2 {
+        if buf.len() < total_length(end, len)? {
             return Err(RespDecodeError::NotComplete);
         }
-        buf.advance(end + 2);
-        let data = buf.split_to(len + 2);
+        buf.advance(end + CRLF_LEN);
+        let data = buf.split_to(len + CRLF_LEN);
         Ok(BulkString::new(data[..len].to_vec()))
     }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        let total = total_length(end, len)?;
+        if buf.len() < total {
+            return Err(RespDecodeError::NotComplete);
+        }
+        Ok(total)
+    }
 }
 
 // - null bulk string: "$-1\r\n"
@@ -99,10 +119,16 @@ impl RespDecode for RespNullBulkString {
         extract_fixed_data(buf, "$-1\r\n", "NullBulkString")?;
         Ok(RespNullBulkString)
     }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        if buf.len() < 5 {
+            return Err(RespDecodeError::NotComplete);
+        }
+        Ok(5)
+    }
 }
 
 #[cfg(test)]
-
 mod tests {
     use crate::RespFrame;
 
@@ -123,4 +149,18 @@ mod tests {
         let frame = BulkString::decode(&mut buf).unwrap();
         assert_eq!(frame.as_ref(), b"Hello, World!");
     }
+
+    #[test]
+    fn test_bulk_string_length_limit() {
+        for frame in [&b"$18446744073709551615\r\n"[..], b"$536870913\r\n"] {
+            let mut buf = BytesMut::from(frame);
+            assert_eq!(BulkString::decode(&mut buf), Err(RespDecodeError::InvalidBulkLength));
+            assert_eq!(RespFrame::expect_length(frame), Err(RespDecodeError::InvalidBulkLength));
+        }
+        // as long as it may be, it just waits for the rest
+        let mut buf = BytesMut::from(&b"$536870912\r\nabc"[..]);
+        assert_eq!(BulkString::decode(&mut buf), Err(RespDecodeError::NotComplete));
+        let mut buf = BytesMut::from(&b"*1\r\n$18446744073709551615\r\n"[..]);
+        assert_eq!(RespFrame::decode(&mut buf), Err(RespDecodeError::InvalidBulkLength));
+    }
 }
\ No newline at end of file
@@ -6,7 +6,11 @@ use crate::resp::{extract_simple_frame_data, CRLF_LEN};
 impl RespEncode for f64{
     fn encode(self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(2048);
-        let ret = if self.abs()>1e+8 || self.abs()<1e-8{
+        let ret = if !self.is_finite() {
+            // the spec spells these out as inf, -inf and nan
+            let s = if self.is_nan() { "nan" } else if self > 0.0 { "inf" } else { "-inf" };
+            format!(",{}\r\n", s)
+        } else if self.abs()>1e+8 || self.abs()<1e-8{
             format!(",{:+e}\r\n",self)
         }else {
             let sign = if self < 0.0 { "" } else { "+" };
@@ -28,10 +32,16 @@ impl RespDecode for f64 {
         let s = String::from_utf8_lossy(&data[Self::PREFIX.len()..end]);
         Ok(s.parse()?)
     }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        let end = extract_simple_frame_data(buf, Self::PREFIX)?;
+        Ok(end + CRLF_LEN)
+    }
 }
 
 
 #[cfg(test)]
+#[allow(clippy::approx_constant)]
 mod tests {
     use bytes::BytesMut;
     use crate::{RespDecode, RespEncode, RespFrame};
@@ -43,6 +53,9 @@ mod tests {
 
         let frame: RespFrame = (-1.23456e-9).into();
         assert_eq!(&frame.encode(), b",-1.23456e-9\r\n");
+
+        let frame: RespFrame = f64::NEG_INFINITY.into();
+        assert_eq!(&frame.encode(), b",-inf\r\n");
     }
 
 
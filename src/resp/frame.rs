@@ -2,6 +2,7 @@ use enum_dispatch::enum_dispatch;
 use crate::resp::map::RespMap;
 use crate::resp::null::RespNull;
 use crate::resp::set::RespSet;
+use crate::resp::push::RespPush;
 use crate::RespDecodeError;
 use super::{BulkString, RespArray, RespDecode, RespNullArray, RespNullBulkString, SimpleError, SimpleString};
 
@@ -22,6 +23,7 @@ pub enum RespFrame {
     Double(f64),
     Map(RespMap),
     Set(RespSet),
+    Push(RespPush),
 }
 impl From<&str> for RespFrame {
     fn from(s: &str) -> Self {
@@ -103,6 +105,34 @@ impl RespDecode for RespFrame {
                 let frame = RespSet::decode(buf)?;
                 Ok(frame.into())
             }
+            Some(b'>') => {
+                let frame = RespPush::decode(buf)?;
+                Ok(frame.into())
+            }
+            None => Err(RespDecodeError::NotComplete),
+            _ => Err(RespDecodeError::InvalidFrameType(format!(
+                "expect_length: unknown frame type: {:?}",
+                buf
+            ))),
+        }
+    }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        let mut iter = buf.iter().peekable();
+        match iter.peek() {
+            Some(b'*') if buf.starts_with(b"*-1") => RespNullArray::expect_length(buf),
+            Some(b'*') => RespArray::expect_length(buf),
+            Some(b'~') => RespSet::expect_length(buf),
+            Some(b'>') => RespPush::expect_length(buf),
+            Some(b'%') => RespMap::expect_length(buf),
+            Some(b'$') if buf.starts_with(b"$-1") => RespNullBulkString::expect_length(buf),
+            Some(b'$') => BulkString::expect_length(buf),
+            Some(b':') => i64::expect_length(buf),
+            Some(b'+') => SimpleString::expect_length(buf),
+            Some(b'-') => SimpleError::expect_length(buf),
+            Some(b'#') => bool::expect_length(buf),
+            Some(b',') => f64::expect_length(buf),
+            Some(b'_') => RespNull::expect_length(buf),
             None => Err(RespDecodeError::NotComplete),
             _ => Err(RespDecodeError::InvalidFrameType(format!(
                 "expect_length: unknown frame type: {:?}",
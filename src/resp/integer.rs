@@ -27,6 +27,11 @@ impl RespDecode for i64{
         let s = String::from_utf8_lossy(&data[Self::PREFIX.len()..end]);
         Ok(s.parse()?)
     }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        let end = extract_simple_frame_data(buf, Self::PREFIX)?;
+        Ok(end + CRLF_LEN)
+    }
 }
 
 
@@ -1,14 +1,14 @@
 use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
 use bytes::{Buf, BytesMut};
-use crate::{RespDecode, RespDecodeError, RespEncode, RespFrame, SimpleString};
+use crate::{BulkString, RespDecode, RespDecodeError, RespEncode, RespFrame};
 use crate::resp::{calc_total_length, parse_length, CRLF_LEN};
 
-#[derive(Debug, PartialEq)]
-pub struct RespMap(HashMap<String,RespFrame>);
+#[derive(Debug, PartialEq, Default)]
+pub struct RespMap(HashMap<Vec<u8>,RespFrame>);
 
 impl Deref for RespMap {
-    type Target=HashMap<String,RespFrame>;
+    type Target=HashMap<Vec<u8>,RespFrame>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -22,7 +22,7 @@ impl DerefMut for RespMap {
 }
 
 impl RespMap {
-    fn new() -> Self {
+    pub fn new() -> Self {
         RespMap(HashMap::new())
     }
 }
@@ -30,12 +30,13 @@ impl RespMap {
 impl RespEncode for RespMap {
 
     // - map: "%<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n>"
-    // we only support string key which encode to SimpleString
+    // keys are binary, like the hash fields and stream keys they carry, so they
+    // go out as BulkString
     fn encode(self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(4096);
         buf.extend_from_slice(&format!("%{}\r\n", self.len()).into_bytes());
         for (k, v) in self.0 {
-            buf.extend_from_slice(&SimpleString::new(k).encode());
+            buf.extend_from_slice(&BulkString::new(k).encode());
             buf.extend_from_slice(&v.encode())
         }
         buf
@@ -54,10 +55,44 @@ impl RespDecode for RespMap {
         buf.advance(end+CRLF_LEN);
         let mut frames = RespMap::new();
         for _ in 0..len {
-            let key = SimpleString::decode(buf)?;
+            let key = match RespFrame::decode(buf)? {
+                RespFrame::BulkString(key) => key.0,
+                RespFrame::SimpleString(key) => key.0.into_bytes(),
+                key => return Err(RespDecodeError::InvalidFrameType(format!("map key: {:?}", key))),
+            };
             let value = RespFrame::decode(buf)?;
-            frames.insert(key.0, value);
+            frames.insert(key, value);
         }
         Ok(frames)
     }
-}
\ No newline at end of file
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        calc_total_length(buf, end, len, Self::PREFIX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_map_encode() {
+        let mut map = RespMap::new();
+        map.insert(b"a\r\nb".to_vec(), BulkString::new("v").into());
+        let frame: RespFrame = map.into();
+        assert_eq!(frame.encode(), b"%1\r\n$4\r\na\r\nb\r\n$1\r\nv\r\n".to_vec());
+    }
+
+    #[test]
+    fn test_map_decode() {
+        let mut buf = BytesMut::from(&b"%2\r\n+a\r\n:1\r\n$3\r\n\xff\r\n\r\n:2\r\n"[..]);
+        let decoded = RespMap::decode(&mut buf).unwrap();
+        assert_eq!(decoded.get(b"a".as_slice()), Some(&RespFrame::Integer(1)));
+        assert_eq!(decoded.get(b"\xff\r\n".as_slice()), Some(&RespFrame::Integer(2)));
+
+        let mut buf = BytesMut::from("%1\r\n:1\r\n:2\r\n");
+        assert!(RespMap::decode(&mut buf).is_err());
+    }
+}
@@ -1,8 +1,6 @@
-use std::{collections:: HashMap, ops::DerefMut};
 use bytes::{Buf, BytesMut};
 use thiserror::Error;
 use enum_dispatch::enum_dispatch;
-use std::ops::Deref;
 mod array;
 mod simple_string;
 mod simple_error;
@@ -14,20 +12,23 @@ mod integer;
 mod map;
 mod null;
 mod set;
+mod push;
 
 pub use array::*;
 pub use simple_string::*;
 pub use simple_error::*;
-pub use bool::*;
 pub use bulk_string::*;
 pub use frame::*;
 pub use null::*;
 pub use set::*;
 pub use map::*;
+pub use push::*;
 
 const BUF_CAP: usize = 4096;
 const CRLF: &[u8] = b"\r\n";
 const CRLF_LEN: usize = CRLF.len();
+// the longest bulk string a client may send, Redis' default proto-max-bulk-len
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
 
 
 #[derive(Error, Debug,PartialEq, Eq)]
@@ -40,6 +41,8 @@ pub enum RespDecodeError {
     InvalidFrameLength(isize),
     #[error("Frame is not complete")]
     NotComplete,
+    #[error("invalid bulk length")]
+    InvalidBulkLength,
     #[error("Parse error: {0}")]
     ParseIntError(#[from] std::num::ParseIntError),
     #[error("Utf8 error: {0}")]
@@ -57,6 +60,8 @@ pub trait RespEncode {
 pub trait RespDecode:Sized {
     const PREFIX: &'static str;
     fn decode(buf:&mut BytesMut) -> Result<Self,RespDecodeError>;
+    // length of the whole frame at the head of buf, or NotComplete if it has not fully arrived
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError>;
 }
 fn extract_simple_frame_data(buf: &[u8], prefix: &str) -> Result<usize, RespDecodeError> {
     if buf.len() < 3 {
@@ -110,11 +115,32 @@ fn parse_length(buf: &[u8], prefix: &str) -> Result<(usize, usize), RespDecodeEr
     Ok((end, s.parse()?))
 }
 
+// total length of an aggregate frame, walking every nested element so that
+// a partially received frame is reported as NotComplete before anything is consumed
 fn calc_total_length(buf: &[u8], end: usize, len: usize, prefix: &str) -> Result<usize, RespDecodeError> {
-    let (end,len)=parse_length(buf, prefix)?;
-    if end + 2 + len > buf.len() {
-        return Err(RespDecodeError::NotComplete);
-    }
-    Ok(end + 2 + len)
+    let mut total = end + CRLF_LEN;
+    let mut data = &buf[total..];
+    match prefix {
+        "*" | "~" | ">" => {
+            for _ in 0..len {
+                let len = RespFrame::expect_length(data)?;
+                data = &data[len..];
+                total += len;
+            }
+            Ok(total)
+        }
+        "%" => {
+            for _ in 0..len {
+                let len = RespFrame::expect_length(data)?;
+                data = &data[len..];
+                total += len;
 
-}
\ No newline at end of file
+                let len = RespFrame::expect_length(data)?;
+                data = &data[len..];
+                total += len;
+            }
+            Ok(total)
+        }
+        _ => Ok(len + CRLF_LEN),
+    }
+}
@@ -18,5 +18,12 @@ impl RespDecode for RespNull {
         extract_fixed_data(buf,"_\r\n","Null")?;
         Ok(RespNull)
     }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        if buf.len() < 3 {
+            return Err(RespDecodeError::NotComplete);
+        }
+        Ok(3)
+    }
 }
 
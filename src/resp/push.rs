@@ -0,0 +1,92 @@
+use std::ops::Deref;
+
+use bytes::{Buf, BytesMut};
+
+use super::{calc_total_length, parse_length, RespDecode, RespDecodeError, RespEncode, RespFrame, BUF_CAP, CRLF_LEN};
+
+// RESP3 out-of-band data, such as pub/sub messages: an array the client did not ask for
+#[derive(Debug, PartialEq)]
+pub struct RespPush(Vec<RespFrame>);
+
+impl RespPush {
+    pub fn new(value: impl Into<Vec<RespFrame>>) -> Self {
+        RespPush(value.into())
+    }
+}
+
+impl Deref for RespPush {
+    type Target = Vec<RespFrame>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// ><number-of-elements>\r\n<element-1>...<element-n>
+impl RespEncode for RespPush {
+    fn encode(self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(BUF_CAP);
+        buf.extend_from_slice(&format!(">{}\r\n", self.len()).into_bytes());
+        for item in self.0 {
+            buf.extend_from_slice(&item.encode());
+        }
+        buf
+    }
+}
+
+impl RespDecode for RespPush {
+    const PREFIX: &'static str = ">";
+
+    fn decode(buf: &mut BytesMut) -> Result<Self, RespDecodeError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        let total_len = calc_total_length(buf, end, len, Self::PREFIX)?;
+
+        if buf.len() < total_len {
+            return Err(RespDecodeError::NotComplete);
+        }
+
+        buf.advance(end + CRLF_LEN);
+
+        let mut frames = Vec::with_capacity(len);
+        for _ in 0..len {
+            frames.push(RespFrame::decode(buf)?);
+        }
+        Ok(RespPush::new(frames))
+    }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        calc_total_length(buf, end, len, Self::PREFIX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BulkString;
+
+    #[test]
+    fn test_push_encode() {
+        let frame = RespPush::new(vec![
+            BulkString::new("message").into(),
+            BulkString::new("news").into(),
+            BulkString::new("hi").into(),
+        ]);
+        assert_eq!(
+            frame.encode(),
+            b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_push_decode() {
+        let mut buf = BytesMut::from(">2\r\n$7\r\nmessage\r\n:1\r\n");
+        let frame = RespFrame::decode(&mut buf).unwrap();
+        assert_eq!(
+            frame,
+            RespPush::new(vec![BulkString::new("message").into(), RespFrame::Integer(1)]).into()
+        );
+
+        let mut buf = BytesMut::from(">2\r\n$7\r\nmessage\r\n");
+        assert_eq!(RespFrame::decode(&mut buf), Err(RespDecodeError::NotComplete));
+    }
+}
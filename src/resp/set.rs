@@ -18,7 +18,7 @@ impl Deref for RespSet {
 
 
 impl RespSet {
-    fn new(s:impl Into<Vec<RespFrame>>) -> Self {
+    pub fn new(s:impl Into<Vec<RespFrame>>) -> Self {
         RespSet(s.into())
     }
 }
@@ -36,7 +36,7 @@ impl RespEncode for RespSet {
 }
 
 impl RespDecode for RespSet {
-    const PREFIX: &'static str = "";
+    const PREFIX: &'static str = "~";
 
     fn decode(buf: &mut BytesMut) -> Result<Self, RespDecodeError> {
         let (end, len) = parse_length(buf, Self::PREFIX)?;
@@ -55,4 +55,9 @@ impl RespDecode for RespSet {
         }
         Ok(RespSet::new(frames))
     }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        calc_total_length(buf, end, len, Self::PREFIX)
+    }
 }
\ No newline at end of file
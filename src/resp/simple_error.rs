@@ -2,7 +2,7 @@ use std::ops::Deref;
 
 use bytes::BytesMut;
 
-use super::{extract_simple_frame_data, RespDecode, RespDecodeError, RespEncode};
+use super::{extract_simple_frame_data, RespDecode, RespDecodeError, RespEncode, CRLF_LEN};
 
 
 
@@ -10,7 +10,7 @@ use super::{extract_simple_frame_data, RespDecode, RespDecodeError, RespEncode};
 pub struct SimpleError(String);
 
 impl SimpleError {
-    fn new(s: impl Into<String>) -> Self {
+    pub fn new(s: impl Into<String>) -> Self {
         SimpleError(s.into())
     }
 }
@@ -37,15 +37,17 @@ impl RespDecode for SimpleError {
         let end = extract_simple_frame_data(buf, Self::PREFIX)?;
         let data =buf.split_to(end+2);
         let ret = String::from_utf8_lossy(&data[1..end]);
-        return Ok(SimpleError::new(ret.to_string()));
+        Ok(SimpleError::new(ret.to_string()))
     }
-    
 
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        let end = extract_simple_frame_data(buf, Self::PREFIX)?;
+        Ok(end + CRLF_LEN)
+    }
 }
 
 
 #[cfg(test)]
-
 mod tests {
     use crate::RespFrame;
 
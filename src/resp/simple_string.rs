@@ -2,7 +2,7 @@ use std::ops::Deref;
 
 use bytes::BytesMut;
 
-use super::{extract_simple_frame_data, RespDecode, RespDecodeError, RespEncode};
+use super::{extract_simple_frame_data, RespDecode, RespDecodeError, RespEncode, CRLF_LEN};
 
 
 #[derive(Debug, PartialEq, Eq)]
@@ -42,7 +42,12 @@ impl RespDecode for SimpleString {
         let end = extract_simple_frame_data(buf, Self::PREFIX)?;
         let data =buf.split_to(end+2);
         let ret = String::from_utf8_lossy(&data[1..end]);
-        return Ok(SimpleString::new(ret.to_string()));
+        Ok(SimpleString::new(ret.to_string()))
+    }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespDecodeError> {
+        let end = extract_simple_frame_data(buf, Self::PREFIX)?;
+        Ok(end + CRLF_LEN)
     }
 }
 